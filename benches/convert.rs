@@ -0,0 +1,133 @@
+//! Benchmarks for converting plists to and from Nu values.
+//!
+//! Run with `cargo bench`. The fixtures are generated so no files need to be
+//! checked in.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nu_plugin_plist::{
+    convert_nu_value, convert_plist_value, decode_archive, encode_archive, into_nu_value,
+    parse_plist, plist_bytes, DecodeOptions, PlistFormat,
+};
+use nu_protocol::{Record, Span, Value as NuValue};
+use plist::{Dictionary, Value as PlistValue};
+
+/// A track entry like those in an iTunes library.
+fn track(id: i64) -> PlistValue {
+    let mut dict = Dictionary::new();
+    dict.insert("Track ID".to_string(), PlistValue::Integer(id.into()));
+    dict.insert(
+        "Name".to_string(),
+        PlistValue::String(format!("Track {id}")),
+    );
+    dict.insert("Artist".to_string(), PlistValue::String("Artist".into()));
+    dict.insert(
+        "Total Time".to_string(),
+        PlistValue::Integer(215_000.into()),
+    );
+    dict.insert("Rating".to_string(), PlistValue::Real(4.5));
+    dict.insert("Compilation".to_string(), PlistValue::Boolean(false));
+    dict.insert("Artwork".to_string(), PlistValue::Data(vec![0xab; 64]));
+    PlistValue::Dictionary(dict)
+}
+
+fn small() -> PlistValue {
+    let mut dict = Dictionary::new();
+    dict.insert(
+        "CFBundleIdentifier".to_string(),
+        PlistValue::String("com.example.app".into()),
+    );
+    dict.insert(
+        "CFBundleShortVersionString".to_string(),
+        PlistValue::String("1.0".into()),
+    );
+    dict.insert("LSRequiresIPhoneOS".to_string(), PlistValue::Boolean(true));
+    dict.insert(
+        "UIDeviceFamily".to_string(),
+        PlistValue::Array(vec![1.into(), 2.into()]),
+    );
+    PlistValue::Dictionary(dict)
+}
+
+fn large() -> PlistValue {
+    PlistValue::Array((0..10_000).map(track).collect())
+}
+
+fn archive_root(span: Span) -> NuValue {
+    let tracks = (0..1_000)
+        .map(|id| {
+            let mut record = Record::new();
+            record.push("$class", NuValue::string("Track", span));
+            record.push("id", NuValue::int(id, span));
+            record.push("name", NuValue::string(format!("Track {id}"), span));
+            NuValue::record(record, span)
+        })
+        .collect();
+    NuValue::list(tracks, span)
+}
+
+fn fixture(plist_val: &PlistValue, format: PlistFormat) -> Vec<u8> {
+    plist_bytes(plist_val, format, Span::unknown()).expect("failed to write fixture")
+}
+
+fn from_plist(c: &mut Criterion) {
+    let span = Span::unknown();
+    let fixtures = [
+        ("small xml", fixture(&small(), PlistFormat::Xml)),
+        ("large xml", fixture(&large(), PlistFormat::Xml)),
+        ("large binary", fixture(&large(), PlistFormat::Binary)),
+    ];
+    let mut group = c.benchmark_group("from plist");
+    for (name, bytes) in &fixtures {
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                let (plist_val, _) = parse_plist(black_box(bytes), span).expect("failed to parse");
+                into_nu_value(plist_val, span).expect("failed to convert")
+            })
+        });
+    }
+    let plist_val = large();
+    group.bench_function("large borrowed", |b| {
+        b.iter(|| convert_plist_value(black_box(&plist_val), span).expect("failed to convert"))
+    });
+    let archive = encode_archive(&archive_root(span)).expect("failed to encode archive");
+    let bytes = fixture(&archive, PlistFormat::Binary);
+    group.bench_function("keyed archive", |b| {
+        b.iter(|| {
+            let (plist_val, _) = parse_plist(black_box(&bytes), span).expect("failed to parse");
+            decode_archive(&plist_val, &DecodeOptions::default(), None, span)
+                .expect("failed to decode")
+        })
+    });
+    group.finish();
+}
+
+fn to_plist(c: &mut Criterion) {
+    let span = Span::unknown();
+    let small = convert_plist_value(&small(), span).expect("failed to convert");
+    let large = convert_plist_value(&large(), span).expect("failed to convert");
+    let cases = [
+        ("small xml", &small, PlistFormat::Xml),
+        ("large xml", &large, PlistFormat::Xml),
+        ("large binary", &large, PlistFormat::Binary),
+    ];
+    let mut group = c.benchmark_group("to plist");
+    for (name, value, format) in cases {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let plist_val = convert_nu_value(black_box(value)).expect("failed to convert");
+                plist_bytes(&plist_val, format, span).expect("failed to write")
+            })
+        });
+    }
+    let root = archive_root(span);
+    group.bench_function("keyed archive", |b| {
+        b.iter(|| {
+            let archive = encode_archive(black_box(&root)).expect("failed to encode");
+            plist_bytes(&archive, PlistFormat::Binary, span).expect("failed to write")
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, from_plist, to_plist);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+//! Decodes arbitrary bytes as the objects of a keyed archive, as
+//! `from nskeyedarchive` and `from plist --decode-archives` do.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nu_plugin_plist::{decode_archive, parse_plist, DecodeOptions};
+use nu_protocol::Span;
+use plist::{Dictionary, Uid, Value as PlistValue};
+
+fuzz_target!(|data: &[u8]| {
+    let bytes = [b"bplist00", data].concat();
+    let span = Span::unknown();
+    let Ok((plist_val, _)) = parse_plist(&bytes, span) else {
+        return;
+    };
+    // Random bytes are rarely a whole archive, so an array is taken as the
+    // `$objects` of one whose root is its second object
+    let archive = match plist_val {
+        PlistValue::Array(objects) => {
+            let mut top = Dictionary::new();
+            top.insert("root".to_string(), PlistValue::Uid(Uid::new(1)));
+            let mut dict = Dictionary::new();
+            dict.insert(
+                "$archiver".to_string(),
+                PlistValue::String("NSKeyedArchiver".to_string()),
+            );
+            dict.insert("$top".to_string(), PlistValue::Dictionary(top));
+            dict.insert("$objects".to_string(), PlistValue::Array(objects));
+            PlistValue::Dictionary(dict)
+        }
+        other => other,
+    };
+    let _ = decode_archive(&archive, &DecodeOptions::default(), None, span);
+});
@@ -0,0 +1,20 @@
+//! Decodes arbitrary bytes as a binary plist, converting whatever parses to
+//! Nu and back to XML.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nu_plugin_plist::{convert_nu_value, into_nu_value, parse_plist, plist_bytes, PlistFormat};
+use nu_protocol::Span;
+
+fuzz_target!(|data: &[u8]| {
+    // Start every input with the magic so none is wasted on format detection
+    let bytes = [b"bplist00", data].concat();
+    let span = Span::unknown();
+    if let Ok((plist_val, _)) = parse_plist(&bytes, span) {
+        let _ = plist_bytes(&plist_val, PlistFormat::Xml, span);
+        if let Ok(nu_val) = into_nu_value(plist_val, span) {
+            let _ = convert_nu_value(&nu_val);
+        }
+    }
+});
@@ -0,0 +1,20 @@
+//! Decodes arbitrary text as an XML plist, converting whatever parses to Nu
+//! and back to binary.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nu_plugin_plist::{convert_nu_value, into_nu_value, parse_plist, plist_bytes, PlistFormat};
+use nu_protocol::Span;
+
+fuzz_target!(|data: &[u8]| {
+    // Inputs that don't look like XML are read as OpenStep, which is fuzzed
+    // along the way
+    let span = Span::unknown();
+    if let Ok((plist_val, _)) = parse_plist(data, span) {
+        let _ = plist_bytes(&plist_val, PlistFormat::Binary, span);
+        if let Ok(nu_val) = into_nu_value(plist_val, span) {
+            let _ = convert_nu_value(&nu_val);
+        }
+    }
+});
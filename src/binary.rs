@@ -0,0 +1,1038 @@
+//! Low-level access to the structure of binary plists.
+//!
+//! A binary plist is the `bplist00` header, the object table, an offset table
+//! holding the position of every object, and a 32 byte trailer describing the
+//! sizes of the offset table entries and object references.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+use plist::{Date, Integer, Uid, Value as PlistValue};
+use serde::{Deserialize, Serialize};
+
+use crate::nu_plist::{
+    apple_epoch, apple_seconds, apple_timestamp, convert_date, plist_date, MAX_DEPTH,
+};
+
+/// Length of the `bplistNN` header.
+const HEADER_LEN: usize = 8;
+/// Length of the trailer at the end of the document.
+const TRAILER_LEN: usize = 32;
+
+/// The trailer of a binary plist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trailer {
+    /// Width in bytes of each offset table entry.
+    pub offset_size: u8,
+    /// Width in bytes of references between objects.
+    pub ref_size: u8,
+    pub object_count: u64,
+    pub root_object: u64,
+    pub offset_table_offset: u64,
+}
+
+/// The two character version following the `bplist` magic.
+pub fn version(bytes: &[u8]) -> Option<&str> {
+    bytes
+        .strip_prefix(b"bplist")
+        .and_then(|rest| rest.get(..2))
+        .and_then(|v| std::str::from_utf8(v).ok())
+}
+
+/// Reads the trailer, checking that the offset table it describes lies within
+/// the document.
+pub fn read_trailer(bytes: &[u8]) -> Option<Trailer> {
+    check_trailer(bytes).ok()
+}
+
+/// Like [`read_trailer`], describing what is wrong with an invalid trailer.
+fn check_trailer(bytes: &[u8]) -> Result<Trailer, String> {
+    if version(bytes).is_none() {
+        return Err("the document has no bplist header".to_string());
+    }
+    if bytes.len() < HEADER_LEN + TRAILER_LEN {
+        return Err(format!(
+            "the document is {} bytes long, too short to hold a trailer",
+            bytes.len()
+        ));
+    }
+    let trailer = &bytes[bytes.len() - TRAILER_LEN..];
+    let field = |range: std::ops::Range<usize>| read_uint(&trailer[range]).unwrap_or_default();
+    let trailer = Trailer {
+        offset_size: trailer[6],
+        ref_size: trailer[7],
+        object_count: field(8..16),
+        root_object: field(16..24),
+        offset_table_offset: field(24..32),
+    };
+
+    for (name, size) in [
+        ("offset", trailer.offset_size),
+        ("reference", trailer.ref_size),
+    ] {
+        if !(1..=8).contains(&size) {
+            return Err(format!("the trailer has an invalid {name} size of {size}"));
+        }
+    }
+    let table_end = trailer
+        .object_count
+        .checked_mul(u64::from(trailer.offset_size))
+        .and_then(|len| trailer.offset_table_offset.checked_add(len))
+        .unwrap_or(u64::MAX);
+    if trailer.offset_table_offset < HEADER_LEN as u64
+        || table_end > (bytes.len() - TRAILER_LEN) as u64
+    {
+        return Err(format!(
+            "the trailer places an offset table of {} objects at offset {}, outside the document",
+            trailer.object_count, trailer.offset_table_offset
+        ));
+    }
+    Ok(trailer)
+}
+
+/// What a binary plist that failed to parse looks like around the failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnosis {
+    /// The object holding the offset the parser stopped at.
+    pub object: Option<u64>,
+    /// A problem with the trailer or offset table, which would explain the
+    /// failure.
+    pub problem: Option<String>,
+}
+
+/// Looks for the object that failed to parse at `offset` and checks the
+/// trailer and offset table, which are the usual victims of truncation.
+pub fn diagnose(bytes: &[u8], offset: Option<u64>) -> Diagnosis {
+    let trailer = match check_trailer(bytes) {
+        Ok(trailer) => trailer,
+        Err(problem) => {
+            return Diagnosis {
+                object: None,
+                problem: Some(problem),
+            }
+        }
+    };
+    let mut diagnosis = Diagnosis {
+        object: None,
+        problem: (trailer.root_object >= trailer.object_count).then(|| {
+            format!(
+                "the root object {} is beyond the {} objects of the offset table",
+                trailer.root_object, trailer.object_count
+            )
+        }),
+    };
+    // The object starting closest before the offset is the one being read
+    let mut closest = None;
+    for idx in 0..trailer.object_count {
+        let start = usize::try_from(trailer.offset_table_offset).unwrap_or(usize::MAX)
+            + idx as usize * usize::from(trailer.offset_size);
+        let entry = bytes
+            .get(start..start + usize::from(trailer.offset_size))
+            .and_then(read_uint);
+        match entry {
+            Some(entry) if entry >= HEADER_LEN as u64 && entry < trailer.offset_table_offset => {
+                if offset.is_some_and(|offset| entry <= offset)
+                    && entry > closest.map_or(0, |(_, closest)| closest)
+                {
+                    closest = Some((idx, entry));
+                }
+            }
+            entry => {
+                diagnosis.problem.get_or_insert_with(|| {
+                    format!(
+                        "offset table entry {idx} points to {}, outside the object table",
+                        entry.unwrap_or_default()
+                    )
+                });
+            }
+        }
+    }
+    diagnosis.object = closest.map(|(idx, _)| idx);
+    diagnosis
+}
+
+/// The byte offset of an object, read from the offset table.
+pub fn object_offset(bytes: &[u8], trailer: &Trailer, idx: u64) -> Option<usize> {
+    if idx >= trailer.object_count {
+        return None;
+    }
+    let size = usize::from(trailer.offset_size);
+    let start =
+        usize::try_from(trailer.offset_table_offset).ok()? + usize::try_from(idx).ok()? * size;
+    let offset = usize::try_from(read_uint(bytes.get(start..start + size)?)?).ok()?;
+    (offset < bytes.len()).then_some(offset)
+}
+
+/// Reads the marker byte of the object at `offset` along with the length
+/// encoded in it, following the extended length integer if present.
+pub fn object_header(bytes: &[u8], offset: usize) -> Option<(u8, u64, usize)> {
+    let marker = *bytes.get(offset)?;
+    let low = marker & 0x0f;
+    if low != 0x0f || marker >> 4 == 0 {
+        return Some((marker, u64::from(low), offset + 1));
+    }
+    // Extended length: an integer object follows the marker
+    let int_marker = *bytes.get(offset + 1)?;
+    if int_marker >> 4 != 0x1 {
+        return None;
+    }
+    let width = 1usize << (int_marker & 0x0f);
+    let start = offset + 2;
+    let len = read_uint(bytes.get(start..start + width)?)?;
+    Some((marker, len, start + width))
+}
+
+/// Reads the keys of the dictionary object `idx` without decoding its
+/// values. Returns `None` if the object is not a dictionary or is corrupt.
+pub fn dict_keys(bytes: &[u8], trailer: &Trailer, idx: u64) -> Option<Vec<String>> {
+    let offset = object_offset(bytes, trailer, idx)?;
+    let (marker, len, start) = object_header(bytes, offset)?;
+    if marker >> 4 != 0xd {
+        return None;
+    }
+    let ref_size = usize::from(trailer.ref_size);
+    let len = usize::try_from(len).ok()?;
+    // The key references come first, followed by the value references
+    let refs = bytes.get(start..start.checked_add(len.checked_mul(ref_size)?)?)?;
+    refs.chunks(ref_size)
+        .map(|key_ref| read_string(bytes, trailer, read_uint(key_ref)?))
+        .collect()
+}
+
+/// Reads the string object `idx`.
+pub fn read_string(bytes: &[u8], trailer: &Trailer, idx: u64) -> Option<String> {
+    let offset = object_offset(bytes, trailer, idx)?;
+    let (marker, len, start) = object_header(bytes, offset)?;
+    let len = usize::try_from(len).ok()?;
+    match marker >> 4 {
+        0x5 => {
+            // ASCII strings
+            let data = bytes.get(start..start.checked_add(len)?)?;
+            Some(data.iter().map(|b| char::from(*b)).collect())
+        }
+        0x6 => {
+            let data = bytes.get(start..start.checked_add(len.checked_mul(2)?)?)?;
+            let units: Vec<u16> = data
+                .chunks(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16(&units).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Limits on the value a binary plist decodes to. Objects can be referenced
+/// from several places and are decoded once for each, so a small hostile file
+/// can decode to an enormous value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    /// Values in the decoded document, counting shared objects once per use.
+    pub max_objects: u64,
+    /// Bytes of string and data content in the decoded document.
+    pub max_bytes: u64,
+    /// Levels of arrays and dictionaries. The parser decodes them recursively,
+    /// so a deeply nested document would overflow the stack.
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_objects: 20_000_000,
+            max_bytes: 1 << 30,
+            max_depth: MAX_DEPTH,
+        }
+    }
+}
+
+/// Checks the size of the value a binary plist decodes to against `limits`
+/// without decoding it. Corrupt documents pass, leaving the parser to report
+/// what is wrong with them.
+pub fn check_limits(bytes: &[u8], limits: &Limits) -> Result<(), String> {
+    let Some(trailer) = read_trailer(bytes) else {
+        return Ok(());
+    };
+    if trailer.object_count > limits.max_objects {
+        return Err(format!(
+            "Binary plist declares {} objects, more than the limit of {}",
+            trailer.object_count, limits.max_objects
+        ));
+    }
+    match decoded_size(bytes, &trailer, limits) {
+        Some(Err(e)) => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Rounds the dates of a parsed binary plist to the precision of the floats
+/// they were stored as. The parser truncates the fraction, so a date written
+/// as `.123456` would otherwise read as `.123455999`.
+pub fn round_dates(value: &mut PlistValue) {
+    match value {
+        PlistValue::Array(items) => items.iter_mut().for_each(round_dates),
+        PlistValue::Dictionary(dict) => dict.values_mut().for_each(round_dates),
+        PlistValue::Date(date) => {
+            let rounded = convert_date(date)
+                .and_then(|d| apple_timestamp(apple_seconds(&d)))
+                .and_then(|d| plist_date(&d));
+            if let Some(rounded) = rounded {
+                *date = rounded;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The number of values and content bytes the root object decodes to,
+/// failing as soon as either goes over its limit. `None` for corrupt
+/// documents.
+fn decoded_size(
+    bytes: &[u8],
+    trailer: &Trailer,
+    limits: &Limits,
+) -> Option<Result<(u64, u64), String>> {
+    /// A collection being measured.
+    struct Frame {
+        idx: u64,
+        children: Vec<u64>,
+        next: usize,
+        objects: u64,
+        bytes: u64,
+    }
+
+    impl Frame {
+        fn new(idx: u64, children: Vec<u64>) -> Frame {
+            Frame {
+                idx,
+                children,
+                next: 0,
+                objects: 1,
+                bytes: 0,
+            }
+        }
+
+        fn add(&mut self, (objects, bytes): (u64, u64)) {
+            self.objects = self.objects.saturating_add(objects);
+            self.bytes = self.bytes.saturating_add(bytes);
+        }
+    }
+
+    enum Object {
+        /// The values and content bytes of a scalar.
+        Scalar(u64, u64),
+        /// The objects a collection refers to.
+        Collection(Vec<u64>),
+    }
+
+    let ref_size = usize::from(trailer.ref_size);
+    // Sizes of the collections already measured, as they may be shared
+    let mut known: HashMap<u64, (u64, u64)> = HashMap::new();
+    let mut open: HashSet<u64> = HashSet::new();
+    let read = |idx: u64| -> Option<Object> {
+        let offset = object_offset(bytes, trailer, idx)?;
+        let (marker, len, start) = object_header(bytes, offset)?;
+        let refs = match marker >> 4 {
+            // Data and ASCII strings
+            0x4 | 0x5 => return Some(Object::Scalar(1, len)),
+            // UTF-16 strings
+            0x6 => return Some(Object::Scalar(1, len.checked_mul(2)?)),
+            // Arrays and sets
+            0xa | 0xc => len,
+            // Dictionaries, with a key and a value reference per entry
+            0xd => len.checked_mul(2)?,
+            _ => return Some(Object::Scalar(1, 0)),
+        };
+        let refs = usize::try_from(refs).ok()?;
+        let data = bytes.get(start..start.checked_add(refs.checked_mul(ref_size)?)?)?;
+        Some(Object::Collection(
+            data.chunks(ref_size).filter_map(read_uint).collect(),
+        ))
+    };
+
+    let root = trailer.root_object;
+    let mut stack = match read(root)? {
+        Object::Scalar(objects, bytes) => return Some(Ok((objects, bytes))),
+        Object::Collection(children) => vec![Frame::new(root, children)],
+    };
+    open.insert(root);
+    loop {
+        let frame = stack.last_mut()?;
+        if frame.objects > limits.max_objects {
+            return Some(Err(format!(
+                "Binary plist decodes to more than {} objects",
+                limits.max_objects
+            )));
+        }
+        if frame.bytes > limits.max_bytes {
+            return Some(Err(format!(
+                "Binary plist decodes to more than {} bytes of strings and data",
+                limits.max_bytes
+            )));
+        }
+        let Some(&child) = frame.children.get(frame.next) else {
+            let done = stack.pop()?;
+            open.remove(&done.idx);
+            known.insert(done.idx, (done.objects, done.bytes));
+            match stack.last_mut() {
+                Some(parent) => parent.add((done.objects, done.bytes)),
+                None => return Some(Ok((done.objects, done.bytes))),
+            }
+            continue;
+        };
+        frame.next += 1;
+        let object = match known.get(&child) {
+            Some(&(objects, bytes)) => Object::Scalar(objects, bytes),
+            None => read(child)?,
+        };
+        match object {
+            Object::Scalar(objects, bytes) => frame.add((objects, bytes)),
+            Object::Collection(children) => {
+                if !open.insert(child) {
+                    // A cycle, which the parser rejects
+                    return None;
+                }
+                if stack.len() >= limits.max_depth {
+                    return Some(Err(format!(
+                        "Binary plist is nested more than {} levels deep",
+                        limits.max_depth
+                    )));
+                }
+                stack.push(Frame::new(child, children));
+            }
+        }
+    }
+}
+
+/// Reads a big-endian unsigned integer of up to eight bytes.
+pub fn read_uint(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() > 8 {
+        return None;
+    }
+    Some(bytes.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b)))
+}
+
+/// Options for writing binary plists.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// Width in bytes of offset table entries and object references. The
+    /// narrowest width that fits the document is used when unset.
+    pub offset_size: Option<u8>,
+    /// Store equal arrays and dictionaries once, as well as equal scalars.
+    pub compact: bool,
+}
+
+/// Writes a binary plist.
+///
+/// Objects are numbered in the order they are reached, dictionary keys before
+/// values, and equal strings, numbers, dates and data are stored once. This is
+/// the same layout the plist crate writes. With `compact` equal collections
+/// are shared too, each stored where it is first reached.
+pub fn write(value: &PlistValue, options: &WriteOptions, writer: impl Write) -> io::Result<()> {
+    let mut scalars = HashMap::new();
+    let mut collections = count_objects(value, &mut scalars)?;
+    let mut shapes = Shapes::default();
+    if options.compact {
+        shapes.number(value)?;
+        collections = shapes.distinct;
+    }
+    let object_count = collections + scalars.len();
+    let ref_size = entry_size(options, (object_count - 1) as u64, "object references")?;
+
+    let mut writer = BinaryWriter {
+        writer,
+        pos: 0,
+        scalars,
+        shapes: shapes.collections,
+        collections: HashMap::new(),
+        next_id: 0,
+        offsets: vec![0; object_count],
+        ref_size,
+    };
+    writer.write_all(b"bplist00")?;
+    match Scalar::new(value) {
+        Some(scalar) => {
+            writer.assign(scalar);
+            writer.write_scalar(scalar)?;
+        }
+        None => {
+            let id = writer.assign_collection(value);
+            writer.write_collection(value, id)?
+        }
+    }
+
+    let table_offset = writer.pos;
+    let offset_size = entry_size(options, table_offset as u64, "offsets")?;
+    for offset in std::mem::take(&mut writer.offsets) {
+        writer.write_uint(offset as u64, offset_size)?;
+    }
+    let mut trailer = [0; TRAILER_LEN];
+    trailer[6] = offset_size;
+    trailer[7] = ref_size;
+    trailer[8..16].copy_from_slice(&(object_count as u64).to_be_bytes());
+    trailer[24..32].copy_from_slice(&(table_offset as u64).to_be_bytes());
+    writer.write_all(&trailer)?;
+    writer.writer.flush()
+}
+
+/// The width of entries able to hold `max`, or the forced width if it fits.
+fn entry_size(options: &WriteOptions, max: u64, what: &str) -> io::Result<u8> {
+    let needed = match max {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xffff_ffff => 4,
+        _ => 8,
+    };
+    match options.offset_size {
+        Some(size) if size < needed => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The document needs {needed} byte {what}, {size} bytes is too small"),
+        )),
+        Some(size) => Ok(size),
+        None => Ok(needed),
+    }
+}
+
+/// Counts the collections in a value and collects its distinct scalars.
+fn count_objects<'a>(
+    value: &'a PlistValue,
+    scalars: &mut HashMap<Scalar<'a>, Slot>,
+) -> io::Result<usize> {
+    match value {
+        PlistValue::Array(items) => items
+            .iter()
+            .try_fold(1, |count, item| Ok(count + count_objects(item, scalars)?)),
+        PlistValue::Dictionary(dict) => dict.iter().try_fold(1, |count, (key, value)| {
+            scalars.entry(Scalar::String(key)).or_default();
+            Ok(count + count_objects(value, scalars)?)
+        }),
+        _ => {
+            let scalar = Scalar::new(value).ok_or_else(|| unsupported(value))?;
+            scalars.entry(scalar).or_default();
+            Ok(0)
+        }
+    }
+}
+
+fn unsupported(value: &PlistValue) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{value:?} can't be written to a binary plist"),
+    )
+}
+
+/// Numbers the distinct subtrees of a document, so that equal collections can
+/// be stored once.
+#[derive(Default)]
+struct Shapes<'a> {
+    ids: HashMap<Shape<'a>, usize>,
+    /// The number of each array and dictionary in the document.
+    collections: HashMap<*const PlistValue, usize>,
+    /// How many distinct collections there are.
+    distinct: usize,
+}
+
+/// A subtree, with its children replaced by their numbers.
+#[derive(PartialEq, Eq, Hash)]
+enum Shape<'a> {
+    Scalar(Scalar<'a>),
+    Array(Vec<usize>),
+    Dictionary(Vec<(&'a str, usize)>),
+}
+
+impl<'a> Shapes<'a> {
+    fn number(&mut self, value: &'a PlistValue) -> io::Result<usize> {
+        let shape = match value {
+            PlistValue::Array(items) => Shape::Array(
+                items
+                    .iter()
+                    .map(|item| self.number(item))
+                    .collect::<io::Result<_>>()?,
+            ),
+            PlistValue::Dictionary(dict) => Shape::Dictionary(
+                dict.iter()
+                    .map(|(key, value)| Ok((key.as_str(), self.number(value)?)))
+                    .collect::<io::Result<_>>()?,
+            ),
+            _ => Shape::Scalar(Scalar::new(value).ok_or_else(|| unsupported(value))?),
+        };
+        let is_collection = !matches!(shape, Shape::Scalar(_));
+        let next = self.ids.len();
+        let id = *self.ids.entry(shape).or_insert(next);
+        if is_collection {
+            if id == next {
+                self.distinct += 1;
+            }
+            self.collections.insert(value, id);
+        }
+        Ok(id)
+    }
+}
+
+/// A value stored once however often it occurs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Scalar<'a> {
+    Boolean(bool),
+    Data(&'a [u8]),
+    Date(Date),
+    Integer(Integer),
+    /// Reals are compared by their bits.
+    Real(u64),
+    String(&'a str),
+    Uid(Uid),
+}
+
+impl<'a> Scalar<'a> {
+    fn new(value: &'a PlistValue) -> Option<Scalar<'a>> {
+        match value {
+            PlistValue::Boolean(b) => Some(Scalar::Boolean(*b)),
+            PlistValue::Data(data) => Some(Scalar::Data(data)),
+            PlistValue::Date(date) => Some(Scalar::Date(*date)),
+            PlistValue::Integer(int) => Some(Scalar::Integer(*int)),
+            PlistValue::Real(real) => Some(Scalar::Real(real.to_bits())),
+            PlistValue::String(string) => Some(Scalar::String(string)),
+            PlistValue::Uid(uid) => Some(Scalar::Uid(*uid)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    id: Option<usize>,
+    written: bool,
+}
+
+impl Slot {
+    /// The object's id, numbering it if it hasn't been reached before.
+    fn id(&mut self, next_id: &mut usize) -> usize {
+        *self.id.get_or_insert_with(|| {
+            *next_id += 1;
+            *next_id - 1
+        })
+    }
+}
+
+struct BinaryWriter<'a, W> {
+    writer: W,
+    pos: usize,
+    scalars: HashMap<Scalar<'a>, Slot>,
+    /// The subtree number of each collection when writing compactly.
+    shapes: HashMap<*const PlistValue, usize>,
+    /// Shared collections by subtree number.
+    collections: HashMap<usize, Slot>,
+    next_id: usize,
+    offsets: Vec<usize>,
+    ref_size: u8,
+}
+
+impl<'a, W: Write> BinaryWriter<'a, W> {
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.pos += bytes.len();
+        Ok(())
+    }
+
+    fn write_uint(&mut self, value: u64, size: u8) -> io::Result<()> {
+        self.write_all(&value.to_be_bytes()[8 - size as usize..])
+    }
+
+    /// Writes an object marker with its length, which is stored in a following
+    /// integer object when it doesn't fit the marker.
+    fn write_marker(&mut self, marker: u8, len: usize) -> io::Result<()> {
+        if len < 0xf {
+            return self.write_all(&[marker | len as u8]);
+        }
+        self.write_all(&[marker | 0xf])?;
+        match len as u64 {
+            len @ 0..=0xff => self.write_all(&[0x10, len as u8]),
+            len @ 0x100..=0xffff => {
+                self.write_all(&[0x11])?;
+                self.write_uint(len, 2)
+            }
+            len @ 0x1_0000..=0xffff_ffff => {
+                self.write_all(&[0x12])?;
+                self.write_uint(len, 4)
+            }
+            len => {
+                self.write_all(&[0x13])?;
+                self.write_uint(len, 8)
+            }
+        }
+    }
+
+    /// The id of a scalar, numbering it if it hasn't been reached before.
+    fn assign(&mut self, scalar: Scalar<'a>) -> usize {
+        self.scalars
+            .entry(scalar)
+            .or_default()
+            .id(&mut self.next_id)
+    }
+
+    /// The id of a collection, which is new unless it is shared.
+    fn assign_collection(&mut self, value: &PlistValue) -> usize {
+        match self.shapes.get(&(value as *const PlistValue)) {
+            Some(shape) => self
+                .collections
+                .entry(*shape)
+                .or_default()
+                .id(&mut self.next_id),
+            None => {
+                self.next_id += 1;
+                self.next_id - 1
+            }
+        }
+    }
+
+    /// Writes an array or dictionary, numbering its children, then the
+    /// children themselves in order.
+    fn write_collection(&mut self, value: &'a PlistValue, id: usize) -> io::Result<()> {
+        if let Some(shape) = self.shapes.get(&(value as *const PlistValue)) {
+            let slot = self.collections.entry(*shape).or_default();
+            if slot.written {
+                return Ok(());
+            }
+            slot.written = true;
+        }
+        self.offsets[id] = self.pos;
+        let (marker, keys, values): (_, Vec<&'a str>, Vec<&'a PlistValue>) = match value {
+            PlistValue::Array(items) => (0xa0, Vec::new(), items.iter().collect()),
+            PlistValue::Dictionary(dict) => (
+                0xd0,
+                dict.keys().map(String::as_str).collect(),
+                dict.values().collect(),
+            ),
+            _ => return Ok(()),
+        };
+        self.write_marker(marker, values.len())?;
+
+        for key in &keys {
+            let key_id = self.assign(Scalar::String(key));
+            self.write_uint(key_id as u64, self.ref_size)?;
+        }
+        let mut children = Vec::with_capacity(values.len());
+        for value in values {
+            let child_id = match Scalar::new(value) {
+                Some(scalar) => self.assign(scalar),
+                None => self.assign_collection(value),
+            };
+            self.write_uint(child_id as u64, self.ref_size)?;
+            children.push((value, child_id));
+        }
+
+        for key in keys {
+            self.write_scalar(Scalar::String(key))?;
+        }
+        for (value, child_id) in children {
+            match Scalar::new(value) {
+                Some(scalar) => self.write_scalar(scalar)?,
+                None => self.write_collection(value, child_id)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a scalar unless it has already been written.
+    fn write_scalar(&mut self, scalar: Scalar<'a>) -> io::Result<()> {
+        let Some(slot) = self.scalars.get_mut(&scalar) else {
+            return Ok(());
+        };
+        let Some(id) = slot.id.filter(|_| !slot.written) else {
+            return Ok(());
+        };
+        slot.written = true;
+        self.offsets[id] = self.pos;
+
+        match scalar {
+            Scalar::Boolean(b) => self.write_all(&[if b { 0x09 } else { 0x08 }]),
+            Scalar::Data(data) => {
+                self.write_marker(0x40, data.len())?;
+                self.write_all(data)
+            }
+            Scalar::Date(date) => {
+                let time = SystemTime::from(date);
+                let seconds = match time.duration_since(apple_epoch()) {
+                    Ok(duration) => duration.as_secs_f64(),
+                    Err(e) => -e.duration().as_secs_f64(),
+                };
+                self.write_all(&[0x33])?;
+                self.write_all(&seconds.to_bits().to_be_bytes())
+            }
+            Scalar::Integer(int) => match (int.as_signed(), int.as_unsigned()) {
+                (Some(int @ 0..=0xff), _) => self.write_all(&[0x10, int as u8]),
+                (Some(int @ 0x100..=0xffff), _) => {
+                    self.write_all(&[0x11])?;
+                    self.write_uint(int as u64, 2)
+                }
+                (Some(int @ 0x1_0000..=0xffff_ffff), _) => {
+                    self.write_all(&[0x12])?;
+                    self.write_uint(int as u64, 4)
+                }
+                (Some(int), _) => {
+                    self.write_all(&[0x13])?;
+                    self.write_all(&int.to_be_bytes())
+                }
+                // Unsigned integers past i64::MAX are stored in 16 bytes
+                (None, Some(int)) => {
+                    self.write_all(&[0x14])?;
+                    self.write_all(&i128::from(int).to_be_bytes())
+                }
+                (None, None) => Ok(()),
+            },
+            Scalar::Real(bits) => {
+                self.write_all(&[0x23])?;
+                self.write_all(&bits.to_be_bytes())
+            }
+            Scalar::String(string) if string.is_ascii() => {
+                self.write_marker(0x50, string.len())?;
+                self.write_all(string.as_bytes())
+            }
+            Scalar::String(string) => {
+                self.write_marker(0x60, string.encode_utf16().count())?;
+                let units: Vec<u8> = string
+                    .encode_utf16()
+                    .flat_map(|unit| unit.to_be_bytes())
+                    .collect();
+                self.write_all(&units)
+            }
+            Scalar::Uid(uid) => match uid.get() {
+                uid @ 0..=0xff => self.write_all(&[0x80, uid as u8]),
+                uid @ 0x100..=0xffff => {
+                    self.write_all(&[0x81])?;
+                    self.write_uint(uid, 2)
+                }
+                uid @ 0x1_0000..=0xffff_ffff => {
+                    self.write_all(&[0x83])?;
+                    self.write_uint(uid, 4)
+                }
+                uid => {
+                    self.write_all(&[0x87])?;
+                    self.write_uint(uid, 8)
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plist::Value as PlistValue;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn encode(value: &PlistValue) -> Vec<u8> {
+        let mut out = Vec::new();
+        plist::to_writer_binary(&mut out, value).expect("failed to write");
+        out
+    }
+
+    #[test]
+    fn test_read_trailer() {
+        let bytes = encode(&PlistValue::Array(vec![
+            PlistValue::String("a".to_string()),
+            PlistValue::Boolean(true),
+        ]));
+        assert_eq!(version(&bytes), Some("00"));
+        let trailer = read_trailer(&bytes).expect("missing trailer");
+        assert_eq!(trailer.object_count, 3);
+        assert_eq!(trailer.offset_size, 1);
+
+        let root = object_offset(&bytes, &trailer, trailer.root_object).expect("missing root");
+        let (marker, len, _) = object_header(&bytes, root).expect("missing header");
+        assert_eq!(marker >> 4, 0xa);
+        assert_eq!(len, 2);
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let mut bytes = encode(&PlistValue::Array(vec![
+            PlistValue::String("a".to_string()),
+            PlistValue::Boolean(true),
+        ]));
+        let trailer = read_trailer(&bytes).expect("missing trailer");
+        let string = object_offset(&bytes, &trailer, 1).expect("missing string");
+        assert_eq!(
+            diagnose(&bytes, Some(string as u64 + 1)),
+            Diagnosis {
+                object: Some(1),
+                problem: None
+            }
+        );
+
+        let truncated = diagnose(&bytes[..bytes.len() - 4], None);
+        assert!(truncated.problem.is_some());
+
+        // Point the last offset table entry into the trailer
+        let last = bytes.len() - TRAILER_LEN - 1;
+        bytes[last] = 0xff;
+        let diagnosis = diagnose(&bytes, None);
+        assert!(
+            diagnosis
+                .problem
+                .as_deref()
+                .is_some_and(|p| p.contains("entry 2")),
+            "{diagnosis:?}"
+        );
+    }
+
+    #[test]
+    fn test_dict_keys() {
+        let mut dict = plist::Dictionary::new();
+        dict.insert("first".to_string(), PlistValue::Boolean(true));
+        dict.insert("sëcond".to_string(), PlistValue::Array(vec![]));
+        let bytes = encode(&PlistValue::Dictionary(dict));
+        let trailer = read_trailer(&bytes).expect("missing trailer");
+        assert_eq!(
+            dict_keys(&bytes, &trailer, trailer.root_object),
+            Some(vec!["first".to_string(), "sëcond".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_truncated_trailer() {
+        let bytes = encode(&PlistValue::Boolean(true));
+        assert!(read_trailer(&bytes[..bytes.len() - 1]).is_none());
+        assert!(read_trailer(b"bplist00").is_none());
+    }
+
+    fn sample() -> PlistValue {
+        let mut dict = plist::Dictionary::new();
+        dict.insert("name".to_string(), PlistValue::String("name".to_string()));
+        dict.insert(
+            "ünïcode".to_string(),
+            PlistValue::String("日本".to_string()),
+        );
+        dict.insert("negative".to_string(), PlistValue::Integer((-3).into()));
+        dict.insert("wide".to_string(), PlistValue::Integer(u64::MAX.into()));
+        dict.insert("real".to_string(), PlistValue::Real(1.5));
+        dict.insert(
+            "date".to_string(),
+            PlistValue::Date((UNIX_EPOCH + Duration::from_millis(1_500)).into()),
+        );
+        dict.insert("uid".to_string(), PlistValue::Uid(Uid::new(70_000)));
+        dict.insert("data".to_string(), PlistValue::Data(vec![7; 300]));
+        dict.insert(
+            "items".to_string(),
+            PlistValue::Array((0..20).map(|i| PlistValue::Integer(i.into())).collect()),
+        );
+        dict.insert(
+            "nested".to_string(),
+            PlistValue::Array(vec![PlistValue::Dictionary(plist::Dictionary::new())]),
+        );
+        PlistValue::Dictionary(dict)
+    }
+
+    #[test]
+    fn test_write_matches_plist_crate() {
+        for value in [sample(), PlistValue::Boolean(true)] {
+            let mut out = Vec::new();
+            write(&value, &WriteOptions::default(), &mut out).expect("failed to write");
+            assert_eq!(out, encode(&value));
+        }
+    }
+
+    #[test]
+    fn test_write_offset_size() {
+        let value = sample();
+        let options = WriteOptions {
+            offset_size: Some(8),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write(&value, &options, &mut out).expect("failed to write");
+        let trailer = read_trailer(&out).expect("missing trailer");
+        assert_eq!((trailer.offset_size, trailer.ref_size), (8, 8));
+        assert_eq!(
+            PlistValue::from_reader(io::Cursor::new(out)).ok(),
+            Some(value)
+        );
+
+        let options = WriteOptions {
+            offset_size: Some(1),
+            ..Default::default()
+        };
+        assert!(write(&sample(), &options, io::sink()).is_err());
+    }
+
+    #[test]
+    fn test_write_compact() {
+        let track = |name: &str| {
+            let mut dict = plist::Dictionary::new();
+            dict.insert("Name".to_string(), PlistValue::String(name.to_string()));
+            dict.insert("Tags".to_string(), PlistValue::Array(vec![]));
+            PlistValue::Dictionary(dict)
+        };
+        let value = PlistValue::Array(vec![track("a"), track("b"), track("a")]);
+        let options = WriteOptions {
+            compact: true,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write(&value, &options, &mut out).expect("failed to write");
+        assert!(out.len() < encode(&value).len());
+
+        // the root, two tracks, one shared empty array and the strings
+        let trailer = read_trailer(&out).expect("missing trailer");
+        assert_eq!(trailer.object_count, 8);
+        assert_eq!(
+            PlistValue::from_reader(io::Cursor::new(out)).ok(),
+            Some(value)
+        );
+    }
+
+    /// A binary plist of nested arrays, each referring to the one below ten
+    /// times, so it decodes to 10^depth strings.
+    fn shared_arrays(depth: u8) -> Vec<u8> {
+        let mut out = b"bplist00".to_vec();
+        let mut offsets = vec![out.len() as u8];
+        out.extend_from_slice(&[0x51, b'x']);
+        for idx in 0..depth {
+            offsets.push(out.len() as u8);
+            out.push(0xaa);
+            out.extend_from_slice(&[idx; 10]);
+        }
+        let table = out.len() as u64;
+        out.extend_from_slice(&offsets);
+        out.extend_from_slice(&[0; 6]);
+        out.extend_from_slice(&[1, 1]);
+        out.extend_from_slice(&(u64::from(depth) + 1).to_be_bytes());
+        out.extend_from_slice(&u64::from(depth).to_be_bytes());
+        out.extend_from_slice(&table.to_be_bytes());
+        out
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let small = shared_arrays(2);
+        let value = plist::from_bytes::<PlistValue>(&small).expect("failed to parse");
+        assert_eq!(value.as_array().map(Vec::len), Some(10));
+        assert_eq!(check_limits(&small, &Limits::default()), Ok(()));
+
+        let huge = shared_arrays(9);
+        assert!(huge.len() < 200);
+        let err = check_limits(&huge, &Limits::default()).expect_err("should be over the limit");
+        assert!(err.contains("objects"), "{err}");
+
+        let limits = Limits {
+            max_bytes: 50,
+            ..Default::default()
+        };
+        let err = check_limits(&small, &limits).expect_err("should be over the limit");
+        assert!(err.contains("bytes"), "{err}");
+
+        let limits = Limits {
+            max_objects: 2,
+            ..Default::default()
+        };
+        assert!(check_limits(&small, &limits).is_err());
+
+        let limits = Limits {
+            max_depth: 5,
+            ..Default::default()
+        };
+        let err = check_limits(&shared_arrays(6), &limits).expect_err("should be too deep");
+        assert!(err.contains("nested more than 5 levels"), "{err}");
+        assert_eq!(check_limits(&shared_arrays(5), &limits), Ok(()));
+    }
+}
@@ -0,0 +1,487 @@
+//! Structural comparison of plist documents.
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{
+    ast::{CellPath, PathMember},
+    Category, Example, LabeledError, Record, Signature, Span, SyntaxShape, Value as NuValue,
+};
+use plist::Value as PlistValue;
+
+use crate::format::{parse_input, parse_operand, write_plist};
+use crate::nu_plist::{
+    convert_nu_value, into_nu_value, into_nu_value_with, plist_error, ConvertOptions, DataAs,
+    DatesAs, ErrorKind, NuPlistPlugin,
+};
+use crate::path;
+
+pub struct PlistDiff;
+pub struct PlistPatch;
+
+impl SimplePluginCommand for PlistDiff {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist diff"
+    }
+
+    fn usage(&self) -> &str {
+        "Compare two plist documents and list the paths that differ"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"The comparison is structural, so documents in different formats or with
+dictionary keys in a different order compare equal. Each row of the output has
+the path, the kind of change (added, removed or changed) and the old and new
+values.
+
+With --patch the differences are output as a patch instead: a list of add,
+remove and replace operations that `plist patch` can apply to a document."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "open --raw a.plist | plist diff (open --raw b.plist)",
+            description: "Compare two preference files",
+            result: None,
+        },
+        Example {
+            example: "open --raw a.plist | plist diff --patch (open --raw b.plist) | save changes.json",
+            description: "Save the changes between two documents as a patch",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required(
+                "other",
+                SyntaxShape::Any,
+                "The plist to compare the input against",
+            )
+            .switch(
+                "patch",
+                "Output a list of patch operations instead of a table of differences",
+                Some('p'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let other: NuValue = call.req(0)?;
+        let other = parse_operand(&other)?;
+        let (plist, _) = parse_input(input, call.head)?;
+
+        let mut differences = Vec::new();
+        diff(&plist, &other, &mut Vec::new(), &mut differences);
+        let as_patch = call.has_flag("patch")?;
+        let rows = differences
+            .into_iter()
+            .map(|difference| {
+                if as_patch {
+                    difference.into_operation(call.head)
+                } else {
+                    difference.into_value(call.head)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(NuValue::list(rows, call.head))
+    }
+}
+
+impl SimplePluginCommand for PlistPatch {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist patch"
+    }
+
+    fn usage(&self) -> &str {
+        "Apply a list of patch operations to a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Each operation is a record with an `op` of add, remove or replace, a `path`
+given as a cell path, dotted string or list of keys and indices, and for add and
+replace the new `value`. Patches are produced by `plist diff --patch`, and keep
+their paths through `to json` and `from json`. The operations are applied in
+order and the document is written back in the format it was read in."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "open --raw c.plist | plist patch (open changes.json)",
+                description: "Replay saved changes on another document",
+                result: None,
+            },
+            Example {
+                example: "open --raw Info.plist | plist patch [{ op: replace, path: CFBundleVersion, value: \"2.1\" }]",
+                description: "Apply a hand-written patch",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required(
+                "patch",
+                SyntaxShape::List(Box::new(SyntaxShape::Record(vec![]))),
+                "The operations to apply",
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let operations: Vec<NuValue> = call.req(0)?;
+        let (mut plist, format) = parse_input(input, call.head)?;
+        for operation in &operations {
+            apply_operation(&mut plist, operation)?;
+        }
+        write_plist(&plist, format, call.head)
+    }
+}
+
+/// Applies a single `{op, path, value}` patch operation.
+pub fn apply_operation(plist: &mut PlistValue, operation: &NuValue) -> Result<(), LabeledError> {
+    let span = operation.span();
+    let record = operation.as_record()?;
+    let field = |name: &str| {
+        record.get(name).ok_or_else(|| {
+            plist_error(
+                ErrorKind::General,
+                format!("Patch operation is missing {name}"),
+                span,
+            )
+        })
+    };
+    let op = field("op")?;
+    let members = path::path_members(field("path")?)?;
+
+    match op.as_str()? {
+        "add" => {
+            let value = convert_nu_value(field("value")?)?;
+            match members.last() {
+                Some(PathMember::Int { .. }) => path::insert(plist, &members, value, span),
+                _ => path::set(plist, &members, value),
+            }
+        }
+        "remove" => path::remove(plist, &members, span).map(|_| ()),
+        "replace" => {
+            *path::follow_mut(plist, &members)? = convert_nu_value(field("value")?)?;
+            Ok(())
+        }
+        other => Err(plist_error(
+            ErrorKind::General,
+            format!("Unknown patch operation {other}, expected add, remove or replace"),
+            op.span(),
+        )),
+    }
+}
+
+/// A single path at which two documents differ. `old` is `None` for added
+/// values and `new` is `None` for removed values.
+#[derive(Debug, PartialEq)]
+pub struct Difference {
+    pub path: Vec<PathMember>,
+    pub old: Option<PlistValue>,
+    pub new: Option<PlistValue>,
+}
+
+impl Difference {
+    pub fn kind(&self) -> &'static str {
+        match (&self.old, &self.new) {
+            (None, _) => "added",
+            (_, None) => "removed",
+            _ => "changed",
+        }
+    }
+
+    fn into_operation(self, span: Span) -> Result<NuValue, LabeledError> {
+        let mut record = Record::new();
+        let op = match self.kind() {
+            "added" => "add",
+            "removed" => "remove",
+            _ => "replace",
+        };
+        record.push("op", NuValue::string(op, span));
+        record.push(
+            "path",
+            NuValue::cell_path(CellPath { members: self.path }, span),
+        );
+        if let Some(new) = self.new {
+            // Tagged records keep data, dates and large unsigned integers
+            // intact when the patch goes through JSON or YAML
+            let options = ConvertOptions {
+                data_as: DataAs::Tagged,
+                dates_as: DatesAs::Tagged,
+                ..ConvertOptions::default()
+            };
+            record.push("value", into_nu_value_with(new, &options, span)?);
+        }
+        Ok(NuValue::record(record, span))
+    }
+
+    fn into_value(self, span: Span) -> Result<NuValue, LabeledError> {
+        let convert = |value: Option<PlistValue>| match value {
+            Some(value) => into_nu_value(value, span),
+            None => Ok(NuValue::nothing(span)),
+        };
+        let mut record = Record::new();
+        record.push("change", NuValue::string(self.kind(), span));
+        record.push(
+            "path",
+            NuValue::cell_path(CellPath { members: self.path }, span),
+        );
+        record.push("old", convert(self.old)?);
+        record.push("new", convert(self.new)?);
+        Ok(NuValue::record(record, span))
+    }
+}
+
+/// Collects the differences between two values. Dictionaries are compared
+/// key by key and arrays element by element, any other change is reported
+/// for the whole value.
+pub fn diff(
+    old: &PlistValue,
+    new: &PlistValue,
+    path: &mut Vec<PathMember>,
+    differences: &mut Vec<Difference>,
+) {
+    match (old, new) {
+        (PlistValue::Dictionary(old), PlistValue::Dictionary(new)) => {
+            for (key, old_value) in old {
+                path.push(PathMember::string(key.clone(), false, Span::unknown()));
+                match new.get(key) {
+                    Some(new_value) => diff(old_value, new_value, path, differences),
+                    None => differences.push(Difference {
+                        path: path.clone(),
+                        old: Some(old_value.clone()),
+                        new: None,
+                    }),
+                }
+                path.pop();
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    path.push(PathMember::string(key.clone(), false, Span::unknown()));
+                    differences.push(Difference {
+                        path: path.clone(),
+                        old: None,
+                        new: Some(new_value.clone()),
+                    });
+                    path.pop();
+                }
+            }
+        }
+        (PlistValue::Array(old), PlistValue::Array(new)) => {
+            let common = old.len().min(new.len());
+            for idx in 0..common {
+                path.push(PathMember::int(idx, false, Span::unknown()));
+                diff(&old[idx], &new[idx], path, differences);
+                path.pop();
+            }
+            for (idx, new_value) in new.iter().enumerate().skip(common) {
+                path.push(PathMember::int(idx, false, Span::unknown()));
+                differences.push(Difference {
+                    path: path.clone(),
+                    old: None,
+                    new: Some(new_value.clone()),
+                });
+                path.pop();
+            }
+            // Trailing removals are listed from the end so that applying them
+            // in order as a patch doesn't shift the remaining indices.
+            for (idx, old_value) in old.iter().enumerate().skip(common).rev() {
+                path.push(PathMember::int(idx, false, Span::unknown()));
+                differences.push(Difference {
+                    path: path.clone(),
+                    old: Some(old_value.clone()),
+                    new: None,
+                });
+                path.pop();
+            }
+        }
+        (old, new) if old != new => differences.push(Difference {
+            path: path.clone(),
+            old: Some(old.clone()),
+            new: Some(new.clone()),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plist::{Date as PlistDate, Dictionary};
+
+    fn dict(entries: Vec<(&str, PlistValue)>) -> PlistValue {
+        PlistValue::Dictionary(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect::<Dictionary>(),
+        )
+    }
+
+    fn differences(old: &PlistValue, new: &PlistValue) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        diff(old, new, &mut Vec::new(), &mut differences);
+        differences
+    }
+
+    #[test]
+    fn test_diff_ignores_key_order() {
+        let a = dict(vec![
+            ("a", PlistValue::Integer(1.into())),
+            ("b", PlistValue::Boolean(true)),
+        ]);
+        let b = dict(vec![
+            ("b", PlistValue::Boolean(true)),
+            ("a", PlistValue::Integer(1.into())),
+        ]);
+        assert!(differences(&a, &b).is_empty());
+    }
+
+    #[test]
+    fn test_diff_changes() {
+        let old = dict(vec![
+            ("changed", PlistValue::Integer(1.into())),
+            ("removed", PlistValue::Boolean(true)),
+            (
+                "list",
+                PlistValue::Array(vec![PlistValue::Integer(1.into())]),
+            ),
+        ]);
+        let new = dict(vec![
+            ("changed", PlistValue::Integer(2.into())),
+            (
+                "list",
+                PlistValue::Array(vec![
+                    PlistValue::Integer(1.into()),
+                    PlistValue::Integer(2.into()),
+                ]),
+            ),
+            ("added", PlistValue::String("x".to_string())),
+        ]);
+        let kinds: Vec<_> = differences(&old, &new)
+            .iter()
+            .map(|d| (d.kind(), d.path.len()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![("changed", 1), ("removed", 1), ("added", 2), ("added", 1)]
+        );
+    }
+
+    #[test]
+    fn test_patch_round_trip() {
+        let old = dict(vec![
+            ("changed", PlistValue::Integer(1.into())),
+            ("removed", PlistValue::Boolean(true)),
+            (
+                "list",
+                PlistValue::Array(vec![
+                    PlistValue::Integer(1.into()),
+                    PlistValue::Integer(2.into()),
+                    PlistValue::Integer(3.into()),
+                ]),
+            ),
+        ]);
+        let new = dict(vec![
+            ("changed", PlistValue::String("one".to_string())),
+            (
+                "list",
+                PlistValue::Array(vec![PlistValue::Integer(1.into())]),
+            ),
+            ("added", dict(vec![("x", PlistValue::Boolean(false))])),
+        ]);
+
+        let mut patched = old.clone();
+        for difference in differences(&old, &new) {
+            let operation = difference
+                .into_operation(Span::test_data())
+                .expect("failed to build operation");
+            apply_operation(&mut patched, &operation).expect("failed to apply");
+        }
+        assert!(differences(&patched, &new).is_empty());
+    }
+
+    #[test]
+    fn test_patch_json_round_trip() {
+        let span = Span::test_data();
+        let old = dict(vec![(
+            "com.apple.Safari",
+            dict(vec![
+                ("Version", PlistValue::Integer(16.into())),
+                (
+                    "Tabs",
+                    PlistValue::Array(vec![PlistValue::String("a".to_string())]),
+                ),
+                ("Icon", PlistValue::Data(vec![1, 2, 3])),
+            ]),
+        )]);
+        let new = dict(vec![(
+            "com.apple.Safari",
+            dict(vec![
+                ("Version", PlistValue::Integer(17.into())),
+                (
+                    "Tabs",
+                    PlistValue::Array(vec![
+                        PlistValue::String("a".to_string()),
+                        PlistValue::String("b".to_string()),
+                    ]),
+                ),
+                ("Icon", PlistValue::Data(vec![4, 5, 6])),
+                (
+                    "LastOpened",
+                    PlistValue::Date(
+                        PlistDate::from_xml_format("2024-02-29T12:30:00Z").expect("valid date"),
+                    ),
+                ),
+            ]),
+        )]);
+
+        let mut patched = old.clone();
+        for difference in differences(&old, &new) {
+            let operation = difference
+                .into_operation(span)
+                .expect("failed to build operation");
+            // `to json | from json` turns the cell path into a list of keys
+            // and indices
+            let mut record = operation.into_record().expect("operation is a record");
+            let Some(NuValue::CellPath { val, .. }) = record.get("path") else {
+                panic!("operation has no cell path");
+            };
+            let members = val.members.clone();
+            let path = members
+                .into_iter()
+                .map(|member| match member {
+                    PathMember::String { val, .. } => NuValue::string(val, span),
+                    PathMember::Int { val, .. } => NuValue::int(val as i64, span),
+                })
+                .collect();
+            record.insert("path", NuValue::list(path, span));
+            // JSON has no binary or date type
+            assert!(!matches!(
+                record.get("value"),
+                Some(NuValue::Binary { .. } | NuValue::Date { .. })
+            ));
+            apply_operation(&mut patched, &NuValue::record(record, span)).expect("failed to apply");
+        }
+        assert!(differences(&patched, &new).is_empty());
+    }
+}
@@ -0,0 +1,369 @@
+//! A lazily converted plist document. `from plist --lazy` keeps the parsed
+//! document in the plugin and gives Nu a custom value referring to it, so cell
+//! path accesses only convert the values they reach.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::SystemTime;
+
+use nu_protocol::{ast::PathMember, CustomValue, LabeledError, ShellError, Span, Value as NuValue};
+use plist::Value as PlistValue;
+use serde::{Deserialize, Serialize};
+
+use crate::file::open_file;
+use crate::format::PlistFormat;
+use crate::nu_plist::{convert_plist_value_with, plist_error, ConvertOptions, ErrorKind};
+use crate::path::follow;
+
+/// Documents held for lazy access, keyed by id.
+static DOCUMENTS: OnceLock<Mutex<Documents>> = OnceLock::new();
+
+#[derive(Default)]
+struct Documents {
+    next_id: u64,
+    /// Each document with the number of custom values referring to it.
+    entries: HashMap<u64, (Arc<PlistValue>, usize)>,
+}
+
+fn documents() -> MutexGuard<'static, Documents> {
+    DOCUMENTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The most files `plist open --cached` keeps parsed at once.
+const MAX_CACHED_FILES: usize = 64;
+
+/// Files parsed by `plist open --cached`.
+static FILES: OnceLock<Mutex<CachedFiles>> = OnceLock::new();
+
+#[derive(Default)]
+struct CachedFiles {
+    /// Counts lookups, so the least recently used file can be evicted.
+    clock: u64,
+    entries: HashMap<PathBuf, CachedFile>,
+}
+
+struct CachedFile {
+    /// The modification time and length of the file when it was read.
+    modified: SystemTime,
+    len: u64,
+    last_used: u64,
+    format: PlistFormat,
+    value: Arc<PlistValue>,
+}
+
+fn cached_files() -> MutexGuard<'static, CachedFiles> {
+    FILES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Whether any parsed files are cached.
+pub fn has_cached_files() -> bool {
+    !cached_files().entries.is_empty()
+}
+
+/// Parses a file, reusing the previous result if the file hasn't been modified
+/// since. Only the most recently used files are kept.
+pub fn open_cached(
+    path: &Path,
+    span: Span,
+) -> Result<(PlistFormat, Arc<PlistValue>), LabeledError> {
+    let metadata =
+        fs::metadata(path).and_then(|metadata| Ok((metadata.modified()?, metadata.len())));
+    let (modified, len) = match metadata {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            cached_files().entries.remove(path);
+            return Err(plist_error(
+                ErrorKind::Io,
+                format!("Failed to read {}: {e}", path.display()),
+                span,
+            ));
+        }
+    };
+
+    {
+        let mut files = cached_files();
+        files.clock += 1;
+        let clock = files.clock;
+        if let Some(cached) = files.entries.get_mut(path) {
+            if cached.modified == modified && cached.len == len {
+                cached.last_used = clock;
+                return Ok((cached.format, cached.value.clone()));
+            }
+        }
+    }
+    let (format, plist) = match open_file(path, span) {
+        Ok(opened) => opened,
+        Err(e) => {
+            cached_files().entries.remove(path);
+            return Err(e);
+        }
+    };
+    let value = Arc::new(plist);
+
+    let mut files = cached_files();
+    files.entries.remove(path);
+    if files.entries.len() >= MAX_CACHED_FILES {
+        let oldest = files
+            .entries
+            .iter()
+            .min_by_key(|(_, cached)| cached.last_used)
+            .map(|(path, _)| path.clone());
+        if let Some(oldest) = oldest {
+            files.entries.remove(&oldest);
+        }
+    }
+    let last_used = files.clock;
+    files.entries.insert(
+        path.to_path_buf(),
+        CachedFile {
+            modified,
+            len,
+            last_used,
+            format,
+            value: value.clone(),
+        },
+    );
+    Ok((format, value))
+}
+
+/// A reference to a value inside a document held by the plugin.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlistDocument {
+    id: u64,
+    path: Vec<PathMember>,
+    options: ConvertOptions,
+}
+
+impl PlistDocument {
+    /// Stores a parsed document and returns a reference to its root.
+    pub fn new(value: PlistValue) -> PlistDocument {
+        PlistDocument::shared(Arc::new(value))
+    }
+
+    /// Like [`PlistDocument::new`] for a document that is also held elsewhere,
+    /// such as in the file cache.
+    pub fn shared(value: Arc<PlistValue>) -> PlistDocument {
+        let mut documents = documents();
+        let id = documents.next_id;
+        documents.next_id += 1;
+        documents.entries.insert(id, (value, 1));
+        PlistDocument {
+            id,
+            path: Vec::new(),
+            options: ConvertOptions::default(),
+        }
+    }
+
+    /// Sets how values without a direct Nu equivalent are converted.
+    pub fn options(mut self, options: ConvertOptions) -> PlistDocument {
+        self.options = options;
+        self
+    }
+
+    /// Another reference into the same document.
+    fn reference(&self, path: Vec<PathMember>) -> PlistDocument {
+        if let Some((_, count)) = documents().entries.get_mut(&self.id) {
+            *count += 1;
+        }
+        PlistDocument {
+            id: self.id,
+            path,
+            options: self.options.clone(),
+        }
+    }
+
+    /// Drops this reference, freeing the document along with the last one.
+    pub fn release(&self) {
+        let mut documents = documents();
+        if let Some((_, count)) = documents.entries.get_mut(&self.id) {
+            *count -= 1;
+            if *count == 0 {
+                documents.entries.remove(&self.id);
+            }
+        }
+    }
+
+    fn document(&self, span: Span) -> Result<Arc<PlistValue>, ShellError> {
+        documents()
+            .entries
+            .get(&self.id)
+            .map(|(document, _)| document.clone())
+            .ok_or_else(|| {
+                plist_error(
+                    ErrorKind::NotFound,
+                    "The plist document is no longer loaded".into(),
+                    span,
+                )
+                .into()
+            })
+    }
+
+    /// The plist value this refers to, without converting it to Nu first.
+    pub fn plist_value(&self, span: Span) -> Result<Option<PlistValue>, ShellError> {
+        let document = self.document(span)?;
+        Ok(follow(&document, &self.path)?.cloned())
+    }
+
+    /// Follows one more member. Containers stay lazy, other values are
+    /// converted.
+    fn follow_member(&self, member: PathMember, span: Span) -> Result<NuValue, ShellError> {
+        let document = self.document(span)?;
+        let mut path = self.path.clone();
+        path.push(member);
+
+        match follow(&document, &path)? {
+            None => Ok(NuValue::nothing(span)),
+            Some(PlistValue::Dictionary(_) | PlistValue::Array(_)) => {
+                Ok(NuValue::custom(Box::new(self.reference(path)), span))
+            }
+            Some(value) => Ok(convert_plist_value_with(value, &self.options, span)?),
+        }
+    }
+}
+
+#[typetag::serde]
+impl CustomValue for PlistDocument {
+    fn clone_value(&self, span: Span) -> NuValue {
+        NuValue::custom(Box::new(self.reference(self.path.clone())), span)
+    }
+
+    fn type_name(&self) -> String {
+        "PlistDocument".into()
+    }
+
+    fn to_base_value(&self, span: Span) -> Result<NuValue, ShellError> {
+        let document = self.document(span)?;
+        match follow(&document, &self.path)? {
+            Some(value) => Ok(convert_plist_value_with(value, &self.options, span)?),
+            None => Ok(NuValue::nothing(span)),
+        }
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn follow_path_int(
+        &self,
+        _self_span: Span,
+        index: usize,
+        path_span: Span,
+    ) -> Result<NuValue, ShellError> {
+        self.follow_member(PathMember::int(index, false, path_span), path_span)
+    }
+
+    fn follow_path_string(
+        &self,
+        _self_span: Span,
+        column_name: String,
+        path_span: Span,
+    ) -> Result<NuValue, ShellError> {
+        self.follow_member(PathMember::string(column_name, false, path_span), path_span)
+    }
+
+    fn notify_plugin_on_drop(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plist::Dictionary;
+
+    fn library() -> PlistDocument {
+        let mut track = Dictionary::new();
+        track.insert("Name".to_string(), PlistValue::String("One".to_string()));
+        let mut tracks = Dictionary::new();
+        tracks.insert("1234".to_string(), PlistValue::Dictionary(track));
+        let mut root = Dictionary::new();
+        root.insert("Tracks".to_string(), PlistValue::Dictionary(tracks));
+        PlistDocument::new(PlistValue::Dictionary(root))
+    }
+
+    #[test]
+    fn test_follow_lazily() {
+        let span = Span::test_data();
+        let document = library();
+        let tracks = document
+            .follow_path_string(span, "Tracks".into(), span)
+            .expect("failed to follow");
+        let NuValue::Custom { val, .. } = &tracks else {
+            panic!("expected a lazy dictionary");
+        };
+        let track = val
+            .follow_path_int(span, 1234, span)
+            .expect("failed to follow");
+        let NuValue::Custom { val, .. } = &track else {
+            panic!("expected a lazy dictionary");
+        };
+        assert_eq!(
+            val.follow_path_string(span, "Name".into(), span)
+                .expect("failed to follow"),
+            NuValue::test_string("One")
+        );
+    }
+
+    #[test]
+    fn test_open_cached() {
+        let span = Span::test_data();
+        let path =
+            std::env::temp_dir().join(format!("nu_plugin_plist_cache_{}", std::process::id()));
+        fs::write(&path, "{ a = 1; }").expect("failed to write");
+        let (format, first) = open_cached(&path, span).expect("failed to open");
+        assert_eq!(format, PlistFormat::OpenStep);
+        let (_, second) = open_cached(&path, span).expect("failed to open");
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(has_cached_files());
+
+        // A change that keeps the modification time still changes the length
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .expect("failed to stat");
+        fs::write(&path, "{ a = 12; }").expect("failed to write");
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(modified))
+            .expect("failed to set modification time");
+        let (_, third) = open_cached(&path, span).expect("failed to open");
+        assert!(!Arc::ptr_eq(&first, &third));
+
+        fs::remove_file(&path).expect("failed to clean up");
+        assert!(open_cached(&path, span).is_err());
+        assert!(!cached_files().entries.contains_key(&path));
+    }
+
+    #[test]
+    fn test_release() {
+        let span = Span::test_data();
+        let document = library();
+        let NuValue::Custom { val, .. } = document
+            .follow_path_string(span, "Tracks".into(), span)
+            .expect("failed to follow")
+        else {
+            panic!("expected a lazy dictionary");
+        };
+        document.release();
+        assert!(val.to_base_value(span).is_ok());
+
+        let tracks = val
+            .as_any()
+            .downcast_ref::<PlistDocument>()
+            .expect("expected a document");
+        tracks.release();
+        assert!(tracks.to_base_value(span).is_err());
+    }
+}
@@ -0,0 +1,611 @@
+//! Commands that modify a plist document in place and re-serialize it in
+//! the format it was read in.
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+
+use chrono::DateTime;
+use nu_protocol::{
+    ast::CellPath, engine::Closure, Category, Example, LabeledError, Signature, Span, Spanned,
+    SyntaxShape, Value as NuValue,
+};
+use plist::{Dictionary, Value as PlistValue};
+
+use crate::format::{parse_input, write_plist};
+use crate::nu_plist::{
+    convert_nu_value, into_nu_value, plist_date, plist_error, ErrorKind, NuPlistPlugin,
+};
+use crate::path;
+
+pub struct PlistSet;
+pub struct PlistDelete;
+pub struct PlistInsert;
+pub struct PlistUpdate;
+pub struct PlistCopy;
+pub struct PlistRenameKey;
+
+impl SimplePluginCommand for PlistSet {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist set"
+    }
+
+    fn usage(&self) -> &str {
+        "Set the value at a cell path in a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"The document is written back in the format it was read in, so XML stays XML and
+binary stays binary. The path may use `*` and `**` wildcards to set every
+matching value."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example:
+                "open --raw prefs.plist | plist set CFBundleVersion \"2.1\" | save -f prefs.plist",
+            description: "Update a single key in a preferences file",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required("path", SyntaxShape::CellPath, "The cell path to set")
+            .required("value", SyntaxShape::Any, "The new value")
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let cell_path: CellPath = call.req(0)?;
+        let value: NuValue = call.req(1)?;
+        let new_value = convert_nu_value(&value)?;
+        let (mut plist, format) = parse_input(input, call.head)?;
+
+        if path::has_wildcards(&cell_path.members) {
+            // A literal final member may name a key that doesn't exist yet,
+            // so only the parents are expanded in that case
+            let members = &cell_path.members;
+            let targets = match members.split_last() {
+                Some((last, parents)) if !path::has_wildcards(std::slice::from_ref(last)) => {
+                    path::expand(&plist, parents)
+                        .into_iter()
+                        .map(|mut parent| {
+                            parent.push(last.clone());
+                            parent
+                        })
+                        .collect()
+                }
+                _ => path::expand(&plist, members),
+            };
+            for target in targets {
+                path::set(&mut plist, &target, new_value.clone())?;
+            }
+        } else {
+            path::set(&mut plist, &cell_path.members, new_value)?;
+        }
+        write_plist(&plist, format, call.head)
+    }
+}
+
+impl SimplePluginCommand for PlistDelete {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist delete"
+    }
+
+    fn usage(&self) -> &str {
+        "Remove a dictionary key or array element from a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        "The paths may use `*` and `**` wildcards to remove every matching entry."
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "open --raw prefs.plist | plist delete AccountToken | save -f shared.plist",
+            description: "Strip a sensitive key before sharing a preferences file",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .rest(
+                "paths",
+                SyntaxShape::CellPath,
+                "The cell paths of the entries to remove",
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let cell_paths: Vec<CellPath> = call.rest(0)?;
+        let (mut plist, format) = parse_input(input, call.head)?;
+        for cell_path in cell_paths {
+            if path::has_wildcards(&cell_path.members) {
+                // Removing from the end of the document first keeps the
+                // indices of the remaining matches valid
+                for target in path::expand(&plist, &cell_path.members).iter().rev() {
+                    path::remove(&mut plist, target, call.head)?;
+                }
+            } else {
+                path::remove(&mut plist, &cell_path.members, call.head)?;
+            }
+        }
+        write_plist(&plist, format, call.head)
+    }
+}
+
+impl SimplePluginCommand for PlistInsert {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist insert"
+    }
+
+    fn usage(&self) -> &str {
+        "Insert a new entry into a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Inserting an existing dictionary key is an error, use `plist set` to replace values.
+Inserting into an array shifts the following elements along.
+
+With --type the value is converted to that plist type instead of the type Nu
+inferred for it. The value may be left out for dict and array, which inserts an
+empty container."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "open --raw Info.plist | plist insert CFBundleVersion 42 --type string",
+                description: "Insert a number as a plist string",
+                result: None,
+            },
+            Example {
+                example: "open --raw Info.plist | plist insert LSEnvironment --type dict",
+                description: "Insert an empty dictionary",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required("path", SyntaxShape::CellPath, "The cell path to insert at")
+            .optional("value", SyntaxShape::Any, "The value to insert")
+            .named(
+                "type",
+                SyntaxShape::String,
+                "The plist type of the value: string, int, real, bool, date, data, dict or array",
+                Some('t'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let cell_path: CellPath = call.req(0)?;
+        let value: Option<NuValue> = call.opt(1)?;
+        let new_value = match call.get_flag::<Spanned<String>>("type")? {
+            Some(type_name) => coerce_value(value.as_ref(), &type_name)?,
+            None => match value {
+                Some(value) => convert_nu_value(&value)?,
+                None => {
+                    return Err(plist_error(
+                        ErrorKind::General,
+                        "A value is required unless --type is dict or array".into(),
+                        call.head,
+                    ))
+                }
+            },
+        };
+        let (mut plist, format) = parse_input(input, call.head)?;
+        path::insert(&mut plist, &cell_path.members, new_value, call.head)?;
+        write_plist(&plist, format, call.head)
+    }
+}
+
+impl SimplePluginCommand for PlistUpdate {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist update"
+    }
+
+    fn usage(&self) -> &str {
+        "Update the value at a cell path in a plist document with a closure"
+    }
+
+    fn extra_usage(&self) -> &str {
+        "The closure receives the current value both as its argument and as pipeline input, and its result replaces that value."
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "open --raw Info.plist | plist update CFBundleVersion {|v| $v + 1 }",
+                description: "Increment a build number",
+                result: None,
+            },
+            Example {
+                example: "open --raw prefs.plist | plist update RecentFiles { append ~/new.txt }",
+                description: "Append to an array",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required("path", SyntaxShape::CellPath, "The cell path to update")
+            .required(
+                "closure",
+                SyntaxShape::Closure(Some(vec![SyntaxShape::Any])),
+                "The closure computing the new value",
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let cell_path: CellPath = call.req(0)?;
+        let closure: Spanned<Closure> = call.req(1)?;
+        let (mut plist, format) = parse_input(input, call.head)?;
+
+        let target = path::follow_mut(&mut plist, &cell_path.members)?;
+        // The old value is replaced, so it can be moved rather than copied
+        let current = into_nu_value(
+            std::mem::replace(target, PlistValue::Boolean(false)),
+            call.head,
+        )?;
+        let updated = engine.eval_closure(&closure, vec![current.clone()], Some(current))?;
+        *target = convert_nu_value(&updated)?;
+
+        write_plist(&plist, format, call.head)
+    }
+}
+
+impl SimplePluginCommand for PlistCopy {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist copy"
+    }
+
+    fn usage(&self) -> &str {
+        "Copy the entry at one cell path to another within a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        "Like `plist insert`, the destination must be a new dictionary key or an array index up to the array length."
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "open --raw prefs.plist | plist copy Profiles.0 Profiles.1",
+            description: "Duplicate the first profile",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required(
+                "source",
+                SyntaxShape::CellPath,
+                "The cell path to copy from",
+            )
+            .required(
+                "destination",
+                SyntaxShape::CellPath,
+                "The cell path to copy to",
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let source: CellPath = call.req(0)?;
+        let destination: CellPath = call.req(1)?;
+        let (mut plist, format) = parse_input(input, call.head)?;
+
+        copy(&mut plist, &source, &destination, call.head)?;
+        write_plist(&plist, format, call.head)
+    }
+}
+
+/// Copies the entry at `source` to the new entry at `destination`.
+fn copy(
+    plist: &mut PlistValue,
+    source: &CellPath,
+    destination: &CellPath,
+    span: Span,
+) -> Result<(), LabeledError> {
+    let copied = path::follow_mut(plist, &source.members)?.clone();
+    path::insert(plist, &destination.members, copied, span)
+}
+
+impl SimplePluginCommand for PlistRenameKey {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist rename-key"
+    }
+
+    fn usage(&self) -> &str {
+        "Rename a dictionary key in a plist document, keeping its position"
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "open --raw Info.plist | plist rename-key LSEnvironment.PATH SEARCH_PATH",
+            description: "Rename a nested key",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required("path", SyntaxShape::CellPath, "The cell path of the key")
+            .required("new-name", SyntaxShape::String, "The new name of the key")
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let cell_path: CellPath = call.req(0)?;
+        let new_name: Spanned<String> = call.req(1)?;
+        let (mut plist, format) = parse_input(input, call.head)?;
+        path::rename_key(&mut plist, &cell_path.members, &new_name)?;
+        write_plist(&plist, format, call.head)
+    }
+}
+
+/// Converts a Nu value into a specific plist type, as PlistBuddy does for
+/// typed entries.
+fn coerce_value(
+    value: Option<&NuValue>,
+    type_name: &Spanned<String>,
+) -> Result<PlistValue, LabeledError> {
+    let span = type_name.span;
+    let Some(value) = value else {
+        return match type_name.item.as_str() {
+            "dict" => Ok(PlistValue::Dictionary(Dictionary::new())),
+            "array" => Ok(PlistValue::Array(Vec::new())),
+            other => Err(plist_error(
+                ErrorKind::General,
+                format!("A value is required for type {other}"),
+                span,
+            )),
+        };
+    };
+    let invalid = || {
+        plist_error(
+            ErrorKind::UnsupportedType,
+            format!("Cannot convert {} to {}", value.get_type(), type_name.item),
+            value.span(),
+        )
+    };
+
+    match type_name.item.as_str() {
+        "string" => match value {
+            NuValue::Date { val, .. } => Ok(PlistValue::String(val.to_rfc3339())),
+            _ => Ok(PlistValue::String(
+                value.coerce_str().map_err(|_| invalid())?.into_owned(),
+            )),
+        },
+        "int" | "integer" => match value {
+            NuValue::Int { val, .. } => Ok(PlistValue::Integer((*val).into())),
+            NuValue::Float { val, .. } if val.fract() == 0.0 => {
+                Ok(PlistValue::Integer((*val as i64).into()))
+            }
+            NuValue::Bool { val, .. } => Ok(PlistValue::Integer(i64::from(*val).into())),
+            NuValue::String { val, .. } => val
+                .trim()
+                .parse::<i64>()
+                .map(|i| PlistValue::Integer(i.into()))
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        },
+        "real" | "float" => match value {
+            NuValue::Int { val, .. } => Ok(PlistValue::Real(*val as f64)),
+            NuValue::Float { val, .. } => Ok(PlistValue::Real(*val)),
+            NuValue::String { val, .. } => val
+                .trim()
+                .parse::<f64>()
+                .map(PlistValue::Real)
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        },
+        "bool" | "boolean" => match value {
+            NuValue::Bool { val, .. } => Ok(PlistValue::Boolean(*val)),
+            NuValue::Int { val: 0, .. } => Ok(PlistValue::Boolean(false)),
+            NuValue::Int { val: 1, .. } => Ok(PlistValue::Boolean(true)),
+            NuValue::String { val, .. } => match val.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "1" => Ok(PlistValue::Boolean(true)),
+                "false" | "no" | "0" => Ok(PlistValue::Boolean(false)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        },
+        "date" => match value {
+            NuValue::Date { val, .. } => plist_date(val).map(PlistValue::Date).ok_or_else(invalid),
+            NuValue::String { val, .. } => DateTime::parse_from_rfc3339(val.trim())
+                .ok()
+                .and_then(|d| plist_date(&d))
+                .map(PlistValue::Date)
+                .ok_or_else(invalid),
+            _ => Err(invalid()),
+        },
+        "data" => match value {
+            NuValue::Binary { val, .. } => Ok(PlistValue::Data(val.clone())),
+            NuValue::String { val, .. } => Ok(PlistValue::Data(val.as_bytes().to_vec())),
+            _ => Err(invalid()),
+        },
+        "dict" | "dictionary" => match value {
+            NuValue::Record { .. } => convert_nu_value(value),
+            _ => Err(invalid()),
+        },
+        "array" => match value {
+            NuValue::List { .. } => convert_nu_value(value),
+            _ => Ok(PlistValue::Array(vec![convert_nu_value(value)?])),
+        },
+        other => Err(plist_error(
+            ErrorKind::General,
+            format!(
+                "Unknown type {other}, expected string, int, real, bool, date, data, dict or array"
+            ),
+            span,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nu_protocol::ast::PathMember;
+
+    fn typed(value: NuValue, type_name: &str) -> Result<PlistValue, LabeledError> {
+        coerce_value(
+            Some(&value),
+            &Spanned {
+                item: type_name.to_string(),
+                span: Span::test_data(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_coerce_value() {
+        assert_eq!(
+            typed(NuValue::test_int(42), "string").expect("failed to coerce"),
+            PlistValue::String("42".to_string())
+        );
+        assert_eq!(
+            typed(NuValue::test_string("7"), "int").expect("failed to coerce"),
+            PlistValue::Integer(7.into())
+        );
+        assert_eq!(
+            typed(NuValue::test_int(2), "real").expect("failed to coerce"),
+            PlistValue::Real(2.0)
+        );
+        assert_eq!(
+            typed(NuValue::test_string("YES"), "bool").expect("failed to coerce"),
+            PlistValue::Boolean(true)
+        );
+        assert_eq!(
+            typed(NuValue::test_string("abc"), "data").expect("failed to coerce"),
+            PlistValue::Data(b"abc".to_vec())
+        );
+        assert!(typed(NuValue::test_string("abc"), "int").is_err());
+        assert!(typed(NuValue::test_int(1), "bogus").is_err());
+    }
+
+    fn cell_path(members: &[&str]) -> CellPath {
+        CellPath {
+            members: members
+                .iter()
+                .map(|member| match member.parse::<usize>() {
+                    Ok(index) => PathMember::int(index, false, Span::test_data()),
+                    Err(_) => PathMember::string(member.to_string(), false, Span::test_data()),
+                })
+                .collect(),
+        }
+    }
+
+    fn profiles() -> PlistValue {
+        let mut profile = Dictionary::new();
+        profile.insert("Name".to_string(), PlistValue::String("Work".to_string()));
+        let mut root = Dictionary::new();
+        root.insert(
+            "Profiles".to_string(),
+            PlistValue::Array(vec![PlistValue::Dictionary(profile)]),
+        );
+        PlistValue::Dictionary(root)
+    }
+
+    #[test]
+    fn test_copy() {
+        let span = Span::test_data();
+        let mut plist = profiles();
+        copy(
+            &mut plist,
+            &cell_path(&["Profiles", "0"]),
+            &cell_path(&["Profiles", "1"]),
+            span,
+        )
+        .expect("failed to copy");
+        let profiles = plist
+            .as_dictionary()
+            .and_then(|root| root.get("Profiles"))
+            .and_then(PlistValue::as_array)
+            .expect("expected an array");
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0], profiles[1]);
+    }
+
+    #[test]
+    fn test_copy_missing_parent() {
+        let span = Span::test_data();
+        let mut plist = profiles();
+        let error = copy(
+            &mut plist,
+            &cell_path(&["Profiles", "0"]),
+            &cell_path(&["Backup", "Profile"]),
+            span,
+        )
+        .expect_err("expected a missing parent error");
+        assert_eq!(error.code.as_deref(), Some("nu_plugin_plist::not_found"));
+        assert!(error.labels[0].text.contains("Key Backup not found"));
+        assert_eq!(plist, profiles());
+    }
+
+    #[test]
+    fn test_coerce_empty_container() {
+        let dict = Spanned {
+            item: "dict".to_string(),
+            span: Span::test_data(),
+        };
+        assert_eq!(
+            coerce_value(None, &dict).expect("failed to coerce"),
+            PlistValue::Dictionary(Dictionary::new())
+        );
+    }
+}
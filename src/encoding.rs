@@ -0,0 +1,234 @@
+//! Transcoding text plists that aren't UTF-8 before they are parsed. Older
+//! Apple tools write some XML plists and most `.strings` files as UTF-16, and
+//! XML plists may declare a legacy single byte encoding in their prolog.
+
+use std::borrow::Cow;
+
+/// The byte order mark some editors start UTF-8 files with.
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// How far into a document the XML declaration is looked for.
+const DECLARATION_LEN: usize = 256;
+
+/// The characters Mac OS Roman maps bytes 0x80 to 0xff to.
+const MAC_ROMAN: [char; 128] = [
+    'Ä', 'Å', 'Ç', 'É', 'Ñ', 'Ö', 'Ü', 'á', 'à', 'â', 'ä', 'ã', 'å', 'ç', 'é', 'è', 'ê', 'ë', 'í',
+    'ì', 'î', 'ï', 'ñ', 'ó', 'ò', 'ô', 'ö', 'õ', 'ú', 'ù', 'û', 'ü', '†', '°', '¢', '£', '§', '•',
+    '¶', 'ß', '®', '©', '™', '´', '¨', '≠', 'Æ', 'Ø', '∞', '±', '≤', '≥', '¥', 'µ', '∂', '∑', '∏',
+    'π', '∫', 'ª', 'º', 'Ω', 'æ', 'ø', '¿', '¡', '¬', '√', 'ƒ', '≈', '∆', '«', '»', '…', '\u{a0}',
+    'À', 'Ã', 'Õ', 'Œ', 'œ', '–', '—', '“', '”', '‘', '’', '÷', '◊', 'ÿ', 'Ÿ', '⁄', '€', '‹', '›',
+    'ﬁ', 'ﬂ', '‡', '·', '‚', '„', '‰', 'Â', 'Ê', 'Á', 'Ë', 'È', 'Í', 'Î', 'Ï', 'Ì', 'Ó', 'Ô',
+    '\u{f8ff}', 'Ò', 'Ú', 'Û', 'Ù', 'ı', 'ˆ', '˜', '¯', '˘', '˙', '˚', '¸', '˝', '˛', 'ˇ',
+];
+
+/// The characters Windows-1252 maps bytes 0x80 to 0x9f to. The five unassigned
+/// bytes map to the control characters of the same value.
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// The text encodings plists are read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+    MacRoman,
+}
+
+impl Encoding {
+    /// Looks up an encoding by the name used in XML declarations.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "us-ascii" | "ascii" => Some(Encoding::Utf8),
+            "utf-16" | "utf16" | "utf-16be" => Some(Encoding::Utf16Be),
+            "utf-16le" => Some(Encoding::Utf16Le),
+            "iso-8859-1" | "iso_8859-1" | "latin1" | "l1" => Some(Encoding::Latin1),
+            "windows-1252" | "cp1252" => Some(Encoding::Windows1252),
+            "macintosh" | "macroman" | "x-mac-roman" | "mac" => Some(Encoding::MacRoman),
+            _ => None,
+        }
+    }
+
+    /// Detects the encoding of a document from its byte order mark, the zero
+    /// bytes around its first characters, or the encoding named in its XML
+    /// declaration.
+    pub fn detect(bytes: &[u8]) -> Result<Encoding, String> {
+        if let Some(utf16) = utf16_order(bytes) {
+            return Ok(utf16);
+        }
+        let Some(name) = declared_encoding(strip_bom(bytes)) else {
+            return Ok(Encoding::Utf8);
+        };
+        match Encoding::from_name(name) {
+            // The document isn't UTF-16 after all, so the declaration is stale
+            Some(Encoding::Utf16Le | Encoding::Utf16Be) => Ok(Encoding::Utf8),
+            Some(encoding) => Ok(encoding),
+            None => Err(format!("Unsupported encoding {name}")),
+        }
+    }
+}
+
+/// The byte order of a UTF-16 document, if it is one.
+fn utf16_order(bytes: &[u8]) -> Option<Encoding> {
+    match bytes {
+        [0xff, 0xfe, ..] => Some(Encoding::Utf16Le),
+        [0xfe, 0xff, ..] => Some(Encoding::Utf16Be),
+        [a, 0, b, 0, ..] if *a != 0 && *b != 0 => Some(Encoding::Utf16Le),
+        [0, a, 0, b, ..] if *a != 0 && *b != 0 => Some(Encoding::Utf16Be),
+        _ => None,
+    }
+}
+
+/// The value of the `encoding` attribute of an XML declaration.
+fn declared_encoding(bytes: &[u8]) -> Option<&str> {
+    let head = &bytes[..bytes.len().min(DECLARATION_LEN)];
+    let head = head.strip_prefix(b"<?xml")?;
+    let end = head.windows(2).position(|w| w == b"?>")?;
+    let declaration = std::str::from_utf8(&head[..end]).ok()?;
+    let rest = declaration[declaration.find("encoding")? + "encoding".len()..]
+        .trim_start()
+        .strip_prefix('=')?
+        .trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &rest[1..];
+    Some(&value[..value.find(quote)?])
+}
+
+/// Drops a UTF-8 byte order mark from the start of a document.
+pub fn strip_bom(bytes: &[u8]) -> &[u8] {
+    bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes)
+}
+
+/// Converts a document to UTF-8 without a byte order mark. Documents that
+/// already are UTF-8 are borrowed rather than copied.
+pub fn to_utf8(bytes: &[u8]) -> Result<Cow<[u8]>, String> {
+    match Encoding::detect(bytes)? {
+        Encoding::Utf8 => Ok(Cow::Borrowed(strip_bom(bytes))),
+        encoding => decode(bytes, encoding).map(|text| Cow::Owned(text.into_bytes())),
+    }
+}
+
+/// Decodes a document in the given encoding, dropping any byte order mark.
+pub fn decode(bytes: &[u8], encoding: Encoding) -> Result<String, String> {
+    // Bytes below the table are ASCII
+    let high = |table: &[char], b: u8| match b.checked_sub(0x80) {
+        Some(i) if usize::from(i) < table.len() => table[usize::from(i)],
+        _ => char::from(b),
+    };
+    let text = match encoding {
+        Encoding::Utf8 => String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())?,
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let pairs = bytes.chunks_exact(2);
+            if !pairs.remainder().is_empty() {
+                return Err("UTF-16 document has an odd number of bytes".to_string());
+            }
+            let units = pairs.map(|pair| match encoding {
+                Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                _ => u16::from_be_bytes([pair[0], pair[1]]),
+            });
+            char::decode_utf16(units)
+                .collect::<Result<String, _>>()
+                .map_err(|e| {
+                    format!(
+                        "Invalid UTF-16, unpaired surrogate {:#x}",
+                        e.unpaired_surrogate()
+                    )
+                })?
+        }
+        Encoding::Latin1 => bytes.iter().map(|b| char::from(*b)).collect(),
+        Encoding::Windows1252 => bytes.iter().map(|b| high(&WINDOWS_1252, *b)).collect(),
+        Encoding::MacRoman => bytes.iter().map(|b| high(&MAC_ROMAN, *b)).collect(),
+    };
+    match text.strip_prefix('\u{feff}') {
+        Some(rest) => Ok(rest.to_string()),
+        None => Ok(text),
+    }
+}
+
+/// The start of a UTF-16 document as UTF-8, enough to detect its format.
+pub fn utf16_head(bytes: &[u8]) -> Option<Vec<u8>> {
+    let encoding = utf16_order(bytes)?;
+    let head = &bytes[..bytes.len().min(DECLARATION_LEN) & !1];
+    let units = head.chunks(2).map(|pair| match encoding {
+        Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+        _ => u16::from_be_bytes([pair[0], pair[1]]),
+    });
+    let text: String = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    Some(text.trim_start_matches('\u{feff}').as_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn utf16(text: &str, bom: bool, little_endian: bool) -> Vec<u8> {
+        let bom = bom.then_some('\u{feff}');
+        bom.into_iter()
+            .chain(text.chars())
+            .collect::<String>()
+            .encode_utf16()
+            .flat_map(|unit| match little_endian {
+                true => unit.to_le_bytes(),
+                false => unit.to_be_bytes(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-16"?><plist/>"#;
+        assert_eq!(
+            Encoding::detect(&utf16(xml, true, true)),
+            Ok(Encoding::Utf16Le)
+        );
+        assert_eq!(
+            Encoding::detect(&utf16(xml, false, false)),
+            Ok(Encoding::Utf16Be)
+        );
+        // Declared UTF-16 but written in 8 bits
+        assert_eq!(Encoding::detect(xml.as_bytes()), Ok(Encoding::Utf8));
+        assert_eq!(
+            Encoding::detect(b"<?xml version='1.0' encoding='macintosh'?>"),
+            Ok(Encoding::MacRoman)
+        );
+        assert_eq!(Encoding::detect(b"<plist/>"), Ok(Encoding::Utf8));
+        assert!(Encoding::detect(b"<?xml version=\"1.0\" encoding=\"EBCDIC\"?>").is_err());
+    }
+
+    #[test]
+    fn test_to_utf8() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-16\"?><string>Grüße</string>";
+        let bytes = utf16(xml, true, false);
+        let utf8 = to_utf8(&bytes).expect("failed to decode");
+        assert_eq!(utf8.as_ref(), xml.as_bytes());
+
+        let mut latin1 = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><string>".to_vec();
+        latin1.extend_from_slice(b"caf\xe9</string>");
+        let utf8 = to_utf8(&latin1).expect("failed to decode");
+        assert!(String::from_utf8_lossy(&utf8).contains("café"));
+
+        let mut mac = b"<?xml version=\"1.0\" encoding=\"macintosh\"?><string>".to_vec();
+        mac.extend_from_slice(b"\x8e\xa5\xf0</string>");
+        let utf8 = to_utf8(&mac).expect("failed to decode");
+        assert!(String::from_utf8_lossy(&utf8).contains("é•\u{f8ff}"));
+
+        assert!(matches!(to_utf8(b"<plist/>"), Ok(Cow::Borrowed(_))));
+        assert_eq!(
+            to_utf8(b"\xef\xbb\xbf<plist/>").as_deref(),
+            Ok(&b"<plist/>"[..])
+        );
+    }
+
+    #[test]
+    fn test_utf16_head() {
+        let head = utf16_head(&utf16("\"key\" = \"value\";", true, true));
+        assert_eq!(head.as_deref(), Some(&b"\"key\" = \"value\";"[..]));
+        assert_eq!(utf16_head(b"<plist/>"), None);
+    }
+}
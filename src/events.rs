@@ -0,0 +1,791 @@
+//! Reading XML plists from the `plist` event stream, converting straight to
+//! Nu values instead of materializing a `plist::Value` tree first. This keeps
+//! very large documents such as an iTunes `Library.xml` fast to read, and lets
+//! `plist get` and `plist keys` skip everything they don't need.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+use std::sync::{Arc, Mutex};
+
+use nu_protocol::{ast::PathMember, LabeledError, Record, Span, Value as NuValue};
+use plist::stream::{Event, OwnedEvent, XmlReader};
+use plist::Value as PlistValue;
+
+use crate::binary::Limits;
+use crate::encoding::{strip_bom, to_utf8, Encoding};
+use crate::format::{parse_plist_with, LineIndex, ParseError, PlistFormat};
+use crate::nu_plist::{convert_data, plist_error, ConvertOptions, ErrorKind, OnDuplicate};
+use crate::path::{describe, key_member, member_optional, member_span};
+
+/// How much of a stream is read up front to detect its format.
+const DETECT_LEN: usize = 256;
+
+/// A document ready to be converted. XML is kept as an event stream and
+/// converted as it is read, the other formats are parsed up front.
+pub enum Document {
+    Events(Events<Box<dyn BufRead + Send>>),
+    Value(PlistValue),
+}
+
+impl Document {
+    /// Reads a document that is already in memory. Binary plists are checked
+    /// against `limits` before they are parsed.
+    pub fn from_bytes(
+        bytes: impl AsRef<[u8]> + Send + 'static,
+        limits: &Limits,
+        span: Span,
+    ) -> Result<Document, LabeledError> {
+        if PlistFormat::detect(bytes.as_ref()) == PlistFormat::Xml {
+            let reader: Box<dyn BufRead + Send> = match to_utf8(bytes.as_ref()) {
+                Ok(Cow::Borrowed(utf8)) => {
+                    let bom = bytes.as_ref().len() - utf8.len();
+                    let mut cursor = Cursor::new(bytes);
+                    cursor.set_position(bom as u64);
+                    Box::new(cursor)
+                }
+                Ok(Cow::Owned(utf8)) => Box::new(Cursor::new(utf8)),
+                Err(e) => return Err(plist_error(ErrorKind::Parse, e, span)),
+            };
+            return Ok(Document::Events(Events::new(reader, span)));
+        }
+        Ok(Document::Value(
+            parse_plist_with(bytes.as_ref(), limits, span)?.0,
+        ))
+    }
+
+    /// Reads a document from a stream, only buffering it when it isn't XML.
+    pub fn from_reader(
+        mut reader: impl Read + Send + 'static,
+        limits: &Limits,
+        span: Span,
+    ) -> Result<Document, LabeledError> {
+        let io_error = |e: std::io::Error| plist_error(ErrorKind::Io, format!("{}", e), span);
+        let mut head = Vec::with_capacity(DETECT_LEN);
+        (&mut reader)
+            .take(DETECT_LEN as u64)
+            .read_to_end(&mut head)
+            .map_err(io_error)?;
+
+        let utf8 = Encoding::detect(&head) == Ok(Encoding::Utf8);
+        if PlistFormat::detect(&head) == PlistFormat::Xml && utf8 {
+            let bom = head.len() - strip_bom(&head).len();
+            head.drain(..bom);
+            let reader = BufReader::new(Cursor::new(head).chain(reader));
+            return Ok(Document::Events(Events::new(Box::new(reader), span)));
+        }
+        // Other encodings are transcoded as a whole
+        let mut bytes = head;
+        reader.read_to_end(&mut bytes).map_err(io_error)?;
+        if PlistFormat::detect(&bytes) == PlistFormat::Xml {
+            return Document::from_bytes(bytes, limits, span);
+        }
+        Ok(Document::Value(parse_plist_with(&bytes, limits, span)?.0))
+    }
+}
+
+/// A reader noting the lines of what the XML parser consumes, so its errors
+/// can be located without holding on to the document.
+struct Tracked<R> {
+    inner: R,
+    lines: Arc<Mutex<LineIndex>>,
+}
+
+impl<R: BufRead> Read for Tracked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for Tracked<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // The buffer is already filled, so this doesn't read anything
+        if let Ok(buf) = self.inner.fill_buf() {
+            let mut lines = self
+                .lines
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            lines.push(&buf[..amt.min(buf.len())]);
+        }
+        self.inner.consume(amt)
+    }
+}
+
+/// The events of an XML plist document.
+pub struct Events<R: BufRead> {
+    reader: XmlReader<Tracked<R>>,
+    lines: Arc<Mutex<LineIndex>>,
+    span: Span,
+    /// Boxed so that `Root::Rows` stays close to the size of a value.
+    options: Box<ConvertOptions>,
+    /// Problems passed over in lenient mode.
+    skipped: Vec<String>,
+    /// The error that ended a lenient read, after which every collection reads
+    /// as closed.
+    truncated: Option<String>,
+    /// How many arrays and dictionaries enclose the current event.
+    depth: usize,
+}
+
+impl<'a> Events<Cursor<Cow<'a, [u8]>>> {
+    /// Reads an XML document that is already in memory, transcoding it to
+    /// UTF-8 first if it is in another encoding or starts with a BOM.
+    pub fn from_xml(bytes: &'a [u8], span: Span) -> Result<Self, LabeledError> {
+        let utf8 = to_utf8(bytes).map_err(|e| plist_error(ErrorKind::Parse, e, span))?;
+        Ok(Events::new(Cursor::new(utf8), span))
+    }
+}
+
+impl<R: BufRead> Events<R> {
+    pub fn new(reader: R, span: Span) -> Self {
+        let lines = Arc::new(Mutex::new(LineIndex::default()));
+        let reader = Tracked {
+            inner: reader,
+            lines: lines.clone(),
+        };
+        Events {
+            reader: XmlReader::new(reader),
+            lines,
+            span,
+            options: Box::default(),
+            skipped: Vec::new(),
+            truncated: None,
+            depth: 0,
+        }
+    }
+
+    /// Sets how values without a direct Nu equivalent are converted.
+    pub fn options(mut self, options: ConvertOptions) -> Self {
+        self.options = Box::new(options);
+        self
+    }
+
+    fn next_event(&mut self) -> Result<OwnedEvent, LabeledError> {
+        if self.truncated.is_some() {
+            return Ok(Event::EndCollection);
+        }
+        let message = match self.reader.next() {
+            Some(Ok(event)) => return Ok(event),
+            Some(Err(e)) => format!("{}", self.parse_error(&e)),
+            None => "Unexpected end of document".to_string(),
+        };
+        if self.options.lenient {
+            self.skipped
+                .push(format!("{message}, skipped the rest of the document"));
+            self.truncated = Some(message);
+            return Ok(Event::EndCollection);
+        }
+        Err(plist_error(ErrorKind::Parse, message, self.span))
+    }
+
+    fn parse_error(&self, error: &plist::Error) -> ParseError {
+        let mut error = ParseError::from_plist(error);
+        let lines = self
+            .lines
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        lines.locate(&mut error);
+        error
+    }
+
+    /// Checks that nothing follows the root value, in strict and lenient
+    /// modes.
+    fn end(&mut self) -> Result<(), LabeledError> {
+        if !(self.options.strict || self.options.lenient) || self.truncated.is_some() {
+            return Ok(());
+        }
+        let message = match self.reader.next() {
+            None => return Ok(()),
+            Some(Ok(event)) => format!("Unexpected {} after the root value", event_type(&event)),
+            Some(Err(e)) => format!("{} after the root value", self.parse_error(&e)),
+        };
+        if self.options.lenient {
+            self.skipped.push(format!("{message}, ignored"));
+            return Ok(());
+        }
+        Err(plist_error(ErrorKind::Parse, message, self.span))
+    }
+
+    fn convert_event(&mut self, event: OwnedEvent, head: Span) -> Result<NuValue, LabeledError> {
+        match event {
+            Event::StartArray(_) => {
+                self.enter()?;
+                let mut values = Vec::new();
+                loop {
+                    match self.next_event()? {
+                        Event::EndCollection => break,
+                        event => values.push(self.convert_event(event, head)?),
+                    }
+                }
+                self.depth -= 1;
+                Ok(NuValue::list(values, head))
+            }
+            Event::StartDictionary(_) => {
+                self.enter()?;
+                let mut record = Record::new();
+                let mut collected = HashSet::new();
+                loop {
+                    match self.next_event()? {
+                        Event::EndCollection => break,
+                        Event::String(key) => {
+                            let event = self.next_event()?;
+                            if self.truncated.is_some() {
+                                // The key's value couldn't be read
+                                break;
+                            }
+                            let value = self.convert_event(event, head)?;
+                            self.insert(&mut record, &mut collected, key.into_owned(), value)?;
+                        }
+                        _ => return Err(self.invalid_key()),
+                    }
+                }
+                self.depth -= 1;
+                Ok(NuValue::record(record, head))
+            }
+            Event::EndCollection => Err(plist_error(
+                ErrorKind::Parse,
+                "Unexpected end of collection".into(),
+                self.span,
+            )),
+            Event::Boolean(b) => Ok(NuValue::bool(b, head)),
+            Event::Data(data) => convert_data(
+                data,
+                &self.options,
+                self.options.max_depth - self.depth,
+                head,
+            ),
+            Event::Date(date) => self
+                .options
+                .dates_as
+                .convert(&date, self.options.timezone, head),
+            Event::Integer(i) => Ok(self.options.uint_as.convert(i, head)),
+            Event::Real(r) => Ok(NuValue::float(r, head)),
+            Event::String(s) => Ok(NuValue::string(s.into_owned(), head)),
+            Event::Uid(uid) => self.options.uid_as.convert(uid, head),
+            _ => Ok(NuValue::nothing(head)),
+        }
+    }
+
+    /// Enters an array or dictionary, failing when that is nested too deeply.
+    fn enter(&mut self) -> Result<(), LabeledError> {
+        if self.depth >= self.options.max_depth {
+            return Err(plist_error(
+                ErrorKind::Overflow,
+                "The plist is nested too deeply, --max-depth raises the limit".into(),
+                self.span,
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Adds an entry to a dictionary's record, following the duplicate key
+    /// policy. `collected` holds the keys whose values were already gathered
+    /// into a list.
+    fn insert(
+        &mut self,
+        record: &mut Record,
+        collected: &mut HashSet<String>,
+        key: String,
+        value: NuValue,
+    ) -> Result<(), LabeledError> {
+        let Some(existing) = record.get_mut(&key) else {
+            record.push(key, value);
+            return Ok(());
+        };
+        if self.options.lenient && self.options.on_duplicate != OnDuplicate::Collect {
+            self.skipped
+                .push(format!("Duplicate key {key} in dictionary"));
+        }
+        match self.options.on_duplicate {
+            OnDuplicate::First => {}
+            OnDuplicate::Last => *existing = value,
+            OnDuplicate::Error => {
+                return Err(plist_error(
+                    ErrorKind::Parse,
+                    format!("Duplicate key {key} in dictionary"),
+                    self.span,
+                ))
+            }
+            OnDuplicate::Collect => match existing {
+                NuValue::List { vals, .. } if collected.contains(&key) => vals.push(value),
+                _ => {
+                    let span = value.span();
+                    let first = std::mem::replace(existing, NuValue::nothing(span));
+                    *existing = NuValue::list(vec![first, value], span);
+                    collected.insert(key);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Converts the document, or when its root is an array returns an
+    /// iterator converting one row at a time.
+    pub fn into_rows(mut self, head: Span) -> Result<Root<R>, LabeledError> {
+        match self.next_event()? {
+            Event::StartArray(_) => {
+                self.enter()?;
+                Ok(Root::Rows(Rows {
+                    events: self,
+                    head,
+                    done: false,
+                }))
+            }
+            event => {
+                let value = self.convert_event(event, head)?;
+                self.end()?;
+                Ok(Root::Value(value))
+            }
+        }
+    }
+
+    /// Converts the whole document in lenient mode, returning what was passed
+    /// over along with the value.
+    pub fn into_value_lenient(
+        mut self,
+        head: Span,
+    ) -> Result<(NuValue, Vec<String>), LabeledError> {
+        self.options.lenient = true;
+        let event = self.next_event()?;
+        if let Some(message) = self.truncated {
+            // Nothing could be read at all
+            return Err(plist_error(ErrorKind::Parse, message, self.span));
+        }
+        let value = self.convert_event(event, head)?;
+        self.end()?;
+        Ok((value, self.skipped))
+    }
+
+    /// Skips the rest of a value whose first event has been read.
+    fn skip_event(&mut self, event: &OwnedEvent) -> Result<(), LabeledError> {
+        if !matches!(event, Event::StartArray(_) | Event::StartDictionary(_)) {
+            return Ok(());
+        }
+        let mut depth = 1usize;
+        while depth > 0 {
+            match self.next_event()? {
+                Event::StartArray(_) | Event::StartDictionary(_) => depth += 1,
+                Event::EndCollection => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Follows a path through the document, converting only the value it
+    /// leads to. Missing members are errors unless they are optional.
+    pub fn follow(
+        mut self,
+        members: &[PathMember],
+        head: Span,
+    ) -> Result<Option<NuValue>, LabeledError> {
+        let mut event = self.next_event()?;
+        for member in members {
+            match self.step(event, member)? {
+                Some(next) => event = next,
+                None => return Ok(None),
+            }
+        }
+        self.convert_event(event, head).map(Some)
+    }
+
+    /// Moves from the first event of a container to the first event of the
+    /// child named by `member`.
+    fn step(
+        &mut self,
+        event: OwnedEvent,
+        member: &PathMember,
+    ) -> Result<Option<OwnedEvent>, LabeledError> {
+        let missing = |message: String| {
+            if member_optional(member) {
+                Ok(None)
+            } else {
+                Err(plist_error(
+                    ErrorKind::NotFound,
+                    message,
+                    member_span(member),
+                ))
+            }
+        };
+        let member = match event {
+            Event::StartDictionary(_) => key_member(member),
+            _ => Cow::Borrowed(member),
+        };
+        match (&*member, event) {
+            (PathMember::String { val, .. }, Event::StartDictionary(_)) => loop {
+                match self.next_event()? {
+                    Event::EndCollection => return missing(format!("Key {val} not found")),
+                    Event::String(key) => {
+                        let value = self.next_event()?;
+                        if key == val.as_str() {
+                            return Ok(Some(value));
+                        }
+                        self.skip_event(&value)?;
+                    }
+                    _ => return Err(self.invalid_key()),
+                }
+            },
+            (PathMember::Int { val, .. }, Event::StartArray(_)) => {
+                let mut index: usize = 0;
+                loop {
+                    match self.next_event()? {
+                        Event::EndCollection => {
+                            return missing(format!(
+                                "Index {val} out of range, array has {index} elements"
+                            ))
+                        }
+                        value if index == *val => return Ok(Some(value)),
+                        value => self.skip_event(&value)?,
+                    }
+                    index += 1;
+                }
+            }
+            (member, event) => missing(format!(
+                "Cannot access {} on a {}",
+                describe(member),
+                event_type(&event)
+            )),
+        }
+    }
+
+    /// The keys of the root dictionary, skipping over all of its values.
+    pub fn root_keys(mut self) -> Result<Vec<String>, LabeledError> {
+        let event = self.next_event()?;
+        if !matches!(event, Event::StartDictionary(_)) {
+            return Err(plist_error(
+                ErrorKind::UnsupportedType,
+                format!("Root of the plist is a {}, not a dict", event_type(&event)),
+                self.span,
+            ));
+        }
+        let mut keys = Vec::new();
+        loop {
+            match self.next_event()? {
+                Event::EndCollection => return Ok(keys),
+                Event::String(key) => {
+                    keys.push(key.into_owned());
+                    let value = self.next_event()?;
+                    self.skip_event(&value)?;
+                }
+                _ => return Err(self.invalid_key()),
+            }
+        }
+    }
+
+    fn invalid_key(&self) -> LabeledError {
+        plist_error(
+            ErrorKind::Parse,
+            "Dictionary keys must be strings".into(),
+            self.span,
+        )
+    }
+}
+
+/// A converted document, see [`Events::into_rows`].
+pub enum Root<R: BufRead> {
+    Value(NuValue),
+    Rows(Rows<R>),
+}
+
+/// The rows of a root array, converted as they are read. A parse error ends
+/// the rows with an error value.
+pub struct Rows<R: BufRead> {
+    events: Events<R>,
+    head: Span,
+    done: bool,
+}
+
+impl<R: BufRead> Iterator for Rows<R> {
+    type Item = NuValue;
+
+    fn next(&mut self) -> Option<NuValue> {
+        if self.done {
+            return None;
+        }
+        let row = match self.events.next_event() {
+            Ok(Event::EndCollection) => self.events.end().err().map(Err),
+            Ok(event) => Some(self.events.convert_event(event, self.head)),
+            Err(e) => Some(Err(e)),
+        };
+        match row {
+            Some(Ok(value)) => Some(value),
+            Some(Err(e)) => {
+                self.done = true;
+                Some(NuValue::error(e.into(), self.head))
+            }
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+/// The plist type name of the value an event starts.
+fn event_type(event: &OwnedEvent) -> &'static str {
+    match event {
+        Event::StartArray(_) => "array",
+        Event::StartDictionary(_) => "dict",
+        Event::Boolean(_) => "bool",
+        Event::Data(_) => "data",
+        Event::Date(_) => "date",
+        Event::Integer(_) => "int",
+        Event::Real(_) => "real",
+        Event::String(_) => "string",
+        Event::Uid(_) => "uid",
+        _ => "value",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nu_plist::convert_plist_value;
+
+    const DOC: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Tracks</key>
+    <dict>
+        <key>1</key>
+        <dict><key>Name</key><string>One</string></dict>
+        <key>2</key>
+        <dict><key>Name</key><string>Two</string></dict>
+    </dict>
+    <key>Playlists</key>
+    <array><integer>1</integer><integer>2</integer></array>
+</dict>
+</plist>"#;
+
+    fn events() -> Events<&'static [u8]> {
+        Events::new(DOC, Span::test_data())
+    }
+
+    fn key(name: &str) -> PathMember {
+        PathMember::string(name.to_string(), false, Span::test_data())
+    }
+
+    #[test]
+    fn test_convert_events() {
+        let Root::Value(value) = events()
+            .into_rows(Span::test_data())
+            .expect("failed to convert")
+        else {
+            panic!("expected a value");
+        };
+        let plist = PlistValue::from_reader_xml(DOC).expect("failed to parse");
+        assert_eq!(
+            value,
+            convert_plist_value(&plist, Span::test_data()).expect("failed to convert")
+        );
+    }
+
+    #[test]
+    fn test_follow_events() {
+        let found = events()
+            .follow(&[key("Tracks"), key("2"), key("Name")], Span::test_data())
+            .expect("failed to follow");
+        assert_eq!(found, Some(NuValue::test_string("Two")));
+
+        let found = events()
+            .follow(
+                &[
+                    key("Playlists"),
+                    PathMember::int(1, false, Span::test_data()),
+                ],
+                Span::test_data(),
+            )
+            .expect("failed to follow");
+        assert_eq!(found, Some(NuValue::test_int(2)));
+
+        assert!(events()
+            .follow(&[key("Missing")], Span::test_data())
+            .is_err());
+    }
+
+    #[test]
+    fn test_document_from_reader() {
+        let document = Document::from_reader(DOC, &Limits::default(), Span::test_data())
+            .expect("failed to read");
+        assert!(matches!(document, Document::Events(_)));
+
+        let bom = [b"\xef\xbb\xbf", DOC].concat();
+        for document in [
+            Document::from_reader(
+                Cursor::new(bom.clone()),
+                &Limits::default(),
+                Span::test_data(),
+            ),
+            Document::from_bytes(bom, &Limits::default(), Span::test_data()),
+        ] {
+            let Ok(Document::Events(events)) = document else {
+                panic!("expected events");
+            };
+            assert!(events.into_value_lenient(Span::test_data()).is_ok());
+        }
+
+        let document =
+            Document::from_reader(&b"{ a = 1; }"[..], &Limits::default(), Span::test_data())
+                .expect("failed to read");
+        assert!(matches!(
+            document,
+            Document::Value(PlistValue::Dictionary(_))
+        ));
+    }
+
+    #[test]
+    fn test_rows() {
+        let doc = b"<plist><array><integer>1</integer><string>a</string></array></plist>";
+        let Root::Rows(rows) = Events::new(&doc[..], Span::test_data())
+            .into_rows(Span::test_data())
+            .expect("failed to convert")
+        else {
+            panic!("expected rows");
+        };
+        assert_eq!(
+            rows.collect::<Vec<_>>(),
+            vec![NuValue::test_int(1), NuValue::test_string("a")]
+        );
+    }
+
+    #[test]
+    fn test_root_keys() {
+        assert_eq!(
+            events().root_keys().expect("failed to read keys"),
+            vec!["Tracks".to_string(), "Playlists".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let doc: &[u8] = b"<plist><dict><key>a</key><integer>1</integer><key>b</key><true/>\
+            <key>a</key><integer>2</integer><key>a</key><integer>3</integer></dict></plist>";
+        let convert = |on_duplicate| {
+            let options = ConvertOptions {
+                on_duplicate,
+                ..Default::default()
+            };
+            match Events::new(doc, Span::test_data())
+                .options(options)
+                .into_rows(Span::test_data())?
+            {
+                Root::Value(value) => Ok(value),
+                Root::Rows(_) => panic!("expected a value"),
+            }
+        };
+        let a = |on_duplicate| {
+            let value: Result<NuValue, LabeledError> = convert(on_duplicate);
+            let record = value
+                .expect("failed to convert")
+                .into_record()
+                .expect("not a record");
+            assert_eq!(record.columns().collect::<Vec<_>>(), ["a", "b"]);
+            record.get("a").cloned()
+        };
+        assert_eq!(a(OnDuplicate::Last), Some(NuValue::test_int(3)));
+        assert_eq!(a(OnDuplicate::First), Some(NuValue::test_int(1)));
+        assert_eq!(
+            a(OnDuplicate::Collect),
+            Some(NuValue::test_list(vec![
+                NuValue::test_int(1),
+                NuValue::test_int(2),
+                NuValue::test_int(3)
+            ]))
+        );
+        assert!(convert(OnDuplicate::Error).is_err());
+    }
+
+    #[test]
+    fn test_error_location() {
+        let doc: &[u8] =
+            b"<plist>\n<dict>\n  <key>a</key>\n  <integer>x</integer>\n</dict>\n</plist>";
+        let error = Events::new(doc, Span::test_data())
+            .into_rows(Span::test_data())
+            .map(|_| ())
+            .expect_err("expected a parse error");
+        let text = &error.labels[0].text;
+        assert!(
+            text.contains("line 4") && text.contains("<integer>"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let strict = |doc: &'static [u8]| {
+            let options = ConvertOptions {
+                strict: true,
+                ..Default::default()
+            };
+            Events::new(doc, Span::test_data())
+                .options(options)
+                .into_rows(Span::test_data())
+                .map(|_| ())
+        };
+        assert!(strict(DOC).is_ok());
+        assert!(strict(b"<plist><string>a</string></plist><string>b</string>").is_err());
+        assert!(strict(b"<plist><string>a</string></plist>trailing").is_err());
+    }
+
+    #[test]
+    fn test_lenient() {
+        let doc: &[u8] = b"<plist><dict><key>a</key><integer>1</integer>\
+            <key>b</key><array><true/><date>yesterday</date></array>\
+            <key>c</key><integer>3</integer></dict></plist>";
+        let (value, skipped) = Events::new(doc, Span::test_data())
+            .into_value_lenient(Span::test_data())
+            .expect("failed to convert");
+        let mut record = Record::new();
+        record.push("a", NuValue::test_int(1));
+        record.push("b", NuValue::test_list(vec![NuValue::test_bool(true)]));
+        assert_eq!(value, NuValue::test_record(record));
+        assert_eq!(skipped.len(), 1);
+
+        let doc: &[u8] = b"<plist><dict><key>a</key><true/><key>a</key><false/></dict></plist>x";
+        let (_, skipped) = Events::new(doc, Span::test_data())
+            .into_value_lenient(Span::test_data())
+            .expect("failed to convert");
+        assert_eq!(skipped.len(), 2);
+
+        assert!(
+            Events::new(&b"<plist><date>x</date></plist>"[..], Span::test_data())
+                .into_value_lenient(Span::test_data())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let doc = format!(
+            "<plist>{}<true/>{}</plist>",
+            "<array>".repeat(100_000),
+            "</array>".repeat(100_000)
+        );
+        let result =
+            Events::new(doc.as_bytes(), Span::test_data()).into_value_lenient(Span::test_data());
+        assert!(result.is_err());
+
+        let options = ConvertOptions {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let convert = |doc: &'static [u8]| {
+            Events::new(doc, Span::test_data())
+                .options(options.clone())
+                .into_value_lenient(Span::test_data())
+        };
+        assert!(convert(b"<plist><dict><key>a</key><array/></dict></plist>").is_ok());
+        assert!(
+            convert(b"<plist><dict><key>a</key><array><array/></array></dict></plist>").is_err()
+        );
+    }
+}
@@ -0,0 +1,498 @@
+//! Reading and writing plist files directly, so large documents don't have to
+//! pass through the Nu pipeline.
+
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use memmap2::Mmap;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, Record, Signature, Span, Spanned, SyntaxShape,
+    Value as NuValue,
+};
+use plist::Value as PlistValue;
+
+use crate::document::{self, PlistDocument};
+use crate::format::{parse_plist, serialize, try_parse, PlistFormat};
+use crate::nu_plist::{into_nu_value, plist_error, ErrorKind, NuPlistPlugin};
+
+pub struct PlistConvert;
+pub struct PlistOpen;
+
+impl SimplePluginCommand for PlistConvert {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist convert"
+    }
+
+    fn usage(&self) -> &str {
+        "Convert a plist file to another format"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"The file is read and written by the plugin, so the document never passes through
+the pipeline. The output is written to a temporary file that replaces the
+destination once it is complete. With --backup the previous contents of the
+destination are kept as <file>.bak."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "plist convert Info.plist --to binary -o Info.bplist",
+                description: "Write a binary copy of an XML plist",
+                result: None,
+            },
+            Example {
+                example: "plist convert prefs.plist --to xml --in-place --backup",
+                description: "Convert a preferences file to XML, keeping the original",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required("input", SyntaxShape::Filepath, "The plist file to convert")
+            .required_named(
+                "to",
+                SyntaxShape::String,
+                "Output format: xml, binary, openstep or gnustep",
+                Some('t'),
+            )
+            .named(
+                "output",
+                SyntaxShape::Filepath,
+                "The file to write the converted document to",
+                Some('o'),
+            )
+            .switch("in-place", "Replace the input file", Some('i'))
+            .switch(
+                "backup",
+                "Keep the previous contents of the destination as <file>.bak",
+                Some('b'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let input: Spanned<String> = call.req(0)?;
+        let format = format_flag(call.get_flag("to")?, call.head)?;
+        let output: Option<Spanned<String>> = call.get_flag("output")?;
+        let in_place = call.has_flag("in-place")?;
+        let backup = call.has_flag("backup")?;
+
+        let source = resolve_path(engine, &input)?;
+        let destination = match (output, in_place) {
+            (Some(output), false) => resolve_path(engine, &output)?,
+            (None, true) => source.clone(),
+            (Some(output), true) => {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    "--output and --in-place can't be used together".into(),
+                    output.span,
+                ))
+            }
+            (None, false) => {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    "Either --output or --in-place is required".into(),
+                    call.head,
+                ))
+            }
+        };
+
+        // The map is dropped before writing, since the destination may be the
+        // same file
+        let (plist, _) = parse_plist(&map_file(&source, input.span)?, input.span)?;
+        write_atomic(&destination, backup, call.head, |writer| {
+            serialize(&plist, format, writer, call.head)
+        })?;
+        Ok(NuValue::nothing(call.head))
+    }
+}
+
+impl SimplePluginCommand for PlistOpen {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist open"
+    }
+
+    fn usage(&self) -> &str {
+        "Parse every plist file matching one or more glob patterns"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Returns a table with the name, format and decoded value of each file.
+
+With --cached the parsed documents are kept in the plugin, keyed by path,
+modification time and length, and each value is returned lazily as with
+`from plist --lazy`. Opening an unchanged file again reuses the parsed document
+instead of reading it again. Only the 64 most recently used files are kept."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "plist open ~/Library/LaunchAgents/*.plist | select name value.Label",
+                description: "List the labels of every launch agent",
+                result: None,
+            },
+            Example {
+                example: "plist open --cached Library.xml | get 0.value.Tracks.1234.Name",
+                description: "Look up a track, reusing the parsed library on later calls",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .rest(
+                "files",
+                SyntaxShape::GlobPattern,
+                "The files or glob patterns to open",
+            )
+            .switch(
+                "cached",
+                "Keep the parsed documents in the plugin for later calls",
+                Some('c'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let patterns: Vec<NuValue> = call.rest(0)?;
+        let cached = call.has_flag("cached")?;
+        let rows = self.open(engine, call, &patterns, cached);
+        if cached {
+            // The cache only helps while the plugin stays running, so keep it
+            // running while the cache holds any files
+            engine.set_gc_disabled(document::has_cached_files())?;
+        }
+        rows
+    }
+}
+
+impl PlistOpen {
+    fn open(
+        &self,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        patterns: &[NuValue],
+        cached: bool,
+    ) -> Result<NuValue, LabeledError> {
+        let cwd = PathBuf::from(engine.get_current_dir()?);
+        let mut rows = Vec::new();
+        for pattern in patterns {
+            let span = pattern.span();
+            let mut text = pattern.coerce_string()?;
+            if let Some(rest) = text.strip_prefix("~/") {
+                if let Some(home) = engine.get_env_var("HOME")? {
+                    text = format!("{}/{rest}", home.coerce_str()?);
+                }
+            }
+            let paths = expand_glob(&cwd, &text, span)?;
+            if paths.is_empty() {
+                return Err(plist_error(
+                    ErrorKind::NotFound,
+                    "No files matched the pattern".into(),
+                    span,
+                ));
+            }
+            for path in paths {
+                let (format, value) = if cached {
+                    let (format, plist) = document::open_cached(&path, span)?;
+                    let document = PlistDocument::shared(plist);
+                    (format, NuValue::custom(Box::new(document), call.head))
+                } else {
+                    let (format, plist) = open_file(&path, span)?;
+                    (format, into_nu_value(plist, call.head)?)
+                };
+                let name = path.strip_prefix(&cwd).unwrap_or(&path);
+                let mut record = Record::new();
+                record.push("name", NuValue::string(name.display().to_string(), span));
+                record.push("format", NuValue::string(format.as_str(), span));
+                record.push("value", value);
+                rows.push(NuValue::record(record, call.head));
+            }
+        }
+        Ok(NuValue::list(rows, call.head))
+    }
+}
+
+/// Parses a plist file, naming the file in any parse error.
+pub fn open_file(path: &Path, span: Span) -> Result<(PlistFormat, PlistValue), LabeledError> {
+    let map = map_file(path, span)?;
+    let (format, result) = try_parse(&map);
+    let plist = result.map_err(|e| {
+        plist_error(
+            ErrorKind::Parse,
+            format!("Failed to parse {}: {e}", path.display()),
+            span,
+        )
+    })?;
+    Ok((format, plist))
+}
+
+/// Expands a glob pattern relative to `cwd`, returning the matching files in
+/// sorted order.
+fn expand_glob(cwd: &Path, pattern: &str, span: Span) -> Result<Vec<PathBuf>, LabeledError> {
+    let pattern = if Path::new(pattern).is_absolute() {
+        pattern.to_string()
+    } else {
+        format!(
+            "{}/{pattern}",
+            glob::Pattern::escape(&cwd.to_string_lossy())
+        )
+    };
+    let entries = glob::glob(&pattern).map_err(|e| {
+        plist_error(
+            ErrorKind::General,
+            format!("Invalid glob pattern: {e}"),
+            span,
+        )
+    })?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| plist_error(ErrorKind::Io, format!("{e}"), span))?;
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Reads a format name from a flag, with an error listing the valid names.
+pub fn format_flag(name: Option<Spanned<String>>, head: Span) -> Result<PlistFormat, LabeledError> {
+    let Some(name) = name else {
+        return Err(plist_error(
+            ErrorKind::General,
+            "A format is required".into(),
+            head,
+        ));
+    };
+    PlistFormat::from_name(&name.item).ok_or_else(|| {
+        plist_error(
+            ErrorKind::General,
+            format!(
+                "Unknown format {}, expected xml, binary, openstep or gnustep",
+                name.item
+            ),
+            name.span,
+        )
+    })
+}
+
+/// Resolves a path argument against the current directory of the shell, which
+/// may differ from the plugin's own working directory.
+pub fn resolve_path(
+    engine: &EngineInterface,
+    path: &Spanned<String>,
+) -> Result<PathBuf, LabeledError> {
+    let cwd = engine.get_current_dir()?;
+    Ok(Path::new(&cwd).join(&path.item))
+}
+
+/// Maps a file into memory so it can be parsed without copying it.
+pub fn map_file(path: &Path, span: Span) -> Result<Mmap, LabeledError> {
+    let file = fs::File::open(path).map_err(|e| {
+        plist_error(
+            ErrorKind::Io,
+            format!("Failed to open {}: {e}", path.display()),
+            span,
+        )
+    })?;
+    // SAFETY: the map is only read while parsing. Another process truncating
+    // the file at the same time is the usual mmap caveat, which we accept in
+    // exchange for not copying large documents.
+    unsafe { Mmap::map(&file) }.map_err(|e| {
+        plist_error(
+            ErrorKind::Io,
+            format!("Failed to map {}: {e}", path.display()),
+            span,
+        )
+    })
+}
+
+/// Writes a file by writing a temporary file next to it and renaming it over
+/// the destination, so an interrupted write never leaves a truncated file.
+/// The contents are written through a buffer as they are produced. Symlinks
+/// are followed, so the file they point to is replaced rather than the link,
+/// and the new file keeps the permissions of the one it replaces.
+pub fn write_atomic(
+    path: &Path,
+    backup: bool,
+    span: Span,
+    contents: impl FnOnce(&mut BufWriter<fs::File>) -> Result<(), LabeledError>,
+) -> Result<(), LabeledError> {
+    let error = |action: &str, path: &Path, e: std::io::Error| {
+        plist_error(
+            ErrorKind::Io,
+            format!("Failed to {action} {}: {e}", path.display()),
+            span,
+        )
+    };
+    let resolved = match fs::canonicalize(path) {
+        Ok(resolved) => resolved,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(error("resolve", path, e)),
+    };
+    let path = resolved.as_path();
+    let file_name = path.file_name().ok_or_else(|| {
+        plist_error(
+            ErrorKind::Io,
+            format!("{} is not a file", path.display()),
+            span,
+        )
+    })?;
+    let mut temp_name = file_name.to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(temp_name);
+
+    let written = fs::File::create(&temp)
+        .map_err(|e| error("write", &temp, e))
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            contents(&mut writer)?;
+            let file = writer
+                .into_inner()
+                .map_err(|e| error("write", &temp, e.into_error()))?;
+            file.sync_all().map_err(|e| error("write", &temp, e))
+        })
+        .and_then(|()| match fs::metadata(path) {
+            Ok(metadata) => fs::set_permissions(&temp, metadata.permissions())
+                .map_err(|e| error("set permissions on", &temp, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(error("read", path, e)),
+        });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    if backup && path.exists() {
+        let mut backup_name = file_name.to_os_string();
+        backup_name.push(".bak");
+        let backup_path = path.with_file_name(backup_name);
+        if let Err(e) = fs::copy(path, &backup_path) {
+            let _ = fs::remove_file(&temp);
+            return Err(error("back up", path, e));
+        }
+    }
+
+    fs::rename(&temp, path).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        error("replace", path, e)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("nu_plugin_plist_{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("failed to create directory");
+        let path = dir.join("prefs.plist");
+
+        let contents = |text: &'static str| {
+            move |writer: &mut BufWriter<fs::File>| {
+                serialize(
+                    &plist::Value::String(text.to_string()),
+                    PlistFormat::OpenStep,
+                    writer,
+                    Span::test_data(),
+                )
+            }
+        };
+        write_atomic(&path, false, Span::test_data(), contents("first")).expect("failed to write");
+        write_atomic(&path, true, Span::test_data(), contents("second")).expect("failed to write");
+        assert_eq!(fs::read(&path).expect("failed to read"), b"second\n");
+        assert_eq!(
+            fs::read(dir.join("prefs.plist.bak")).expect("failed to read backup"),
+            b"first\n"
+        );
+        assert_eq!(fs::read_dir(&dir).expect("failed to list").count(), 2);
+
+        fs::remove_dir_all(&dir).expect("failed to clean up");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_symlink_permissions() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = std::env::temp_dir().join(format!("nu_plugin_plist_link_{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("failed to create directory");
+        let target = dir.join("prefs.plist");
+        let link = dir.join("link.plist");
+        fs::write(&target, "first").expect("failed to write");
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600))
+            .expect("failed to set permissions");
+        symlink(&target, &link).expect("failed to link");
+
+        write_atomic(&link, false, Span::test_data(), |writer| {
+            serialize(
+                &plist::Value::String("second".to_string()),
+                PlistFormat::OpenStep,
+                writer,
+                Span::test_data(),
+            )
+        })
+        .expect("failed to write");
+        assert!(fs::symlink_metadata(&link)
+            .expect("failed to stat link")
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read(&target).expect("failed to read"), b"second\n");
+        let mode = fs::metadata(&target)
+            .expect("failed to stat")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::remove_dir_all(&dir).expect("failed to clean up");
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let dir = std::env::temp_dir().join(format!("nu_plugin_plist_glob_{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("failed to create directory");
+        for name in ["b.plist", "a.plist", "notes.txt"] {
+            fs::write(dir.join(name), "{}").expect("failed to write");
+        }
+        let paths = expand_glob(&dir, "*.plist", Span::test_data()).expect("failed to glob");
+        assert_eq!(paths, vec![dir.join("a.plist"), dir.join("b.plist")]);
+        fs::remove_dir_all(&dir).expect("failed to clean up");
+    }
+
+    #[test]
+    fn test_map_file() {
+        let path = std::env::temp_dir().join(format!("nu_plugin_plist_map_{}", std::process::id()));
+        fs::write(&path, "{ a = 1; }").expect("failed to write");
+        let map = map_file(&path, Span::test_data()).expect("failed to map");
+        let (value, format) = parse_plist(&map, Span::test_data()).expect("failed to parse");
+        assert_eq!(format, PlistFormat::OpenStep);
+        assert!(value.as_dictionary().is_some());
+        drop(map);
+        fs::remove_file(&path).expect("failed to clean up");
+    }
+}
@@ -0,0 +1,1479 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+
+use chrono::Datelike;
+use nu_protocol::{LabeledError, Span, Value as NuValue};
+use plist::stream::XmlWriteOptions;
+use plist::{Dictionary, Value as PlistValue};
+
+use crate::binary::{self, Limits, WriteOptions};
+use crate::encoding;
+use crate::nu_plist::{
+    convert_date, convert_nu_value, describe_path, plist_error, ErrorKind, UID_KEY,
+};
+use crate::openstep;
+
+/// The on-disk encodings a plist document can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlistFormat {
+    Xml,
+    Binary,
+    OpenStep,
+    /// OpenStep with the GNUstep typed-value extensions.
+    GnuStep,
+}
+
+/// The years text plists can write dates in. Binary plists store dates as
+/// floats, so can hold dates far outside them.
+pub const TEXT_YEARS: std::ops::RangeInclusive<i32> = -9999..=9999;
+
+/// The elements a bare XML value can start with.
+const VALUE_ELEMENTS: [&[u8]; 10] = [
+    b"array", b"dict", b"string", b"integer", b"real", b"true", b"false", b"date", b"data", b"key",
+];
+
+/// Whether a document is a bare XML value such as `<string>a</string>`, which
+/// `to plist --fragment` writes. OpenStep data like `<0fbd>` is hex digits, so
+/// can't be mistaken for one.
+fn is_fragment(head: &[u8]) -> bool {
+    let Some(rest) = head.strip_prefix(b"<") else {
+        return false;
+    };
+    VALUE_ELEMENTS.iter().any(|name| {
+        rest.strip_prefix(*name)
+            .is_some_and(|rest| rest.starts_with(b">") || rest.starts_with(b"/>"))
+    })
+}
+
+impl PlistFormat {
+    /// Detects the format of a document from its leading bytes.
+    pub fn detect(bytes: &[u8]) -> PlistFormat {
+        let bytes = encoding::strip_bom(bytes);
+        if bytes.starts_with(b"bplist") {
+            return PlistFormat::Binary;
+        }
+        if let Some(head) = encoding::utf16_head(bytes) {
+            return PlistFormat::detect(&head);
+        }
+        let start = bytes
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(bytes.len());
+        let head = &bytes[start..];
+        if head.starts_with(b"<?xml")
+            || head.starts_with(b"<!")
+            || head.starts_with(b"<plist")
+            || is_fragment(head)
+        {
+            PlistFormat::Xml
+        } else {
+            PlistFormat::OpenStep
+        }
+    }
+
+    /// The name of the format as used in command flags.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlistFormat::Xml => "xml",
+            PlistFormat::Binary => "binary",
+            PlistFormat::OpenStep => "openstep",
+            PlistFormat::GnuStep => "gnustep",
+        }
+    }
+
+    /// The MIME type attached to serialized output.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            PlistFormat::Xml => "application/x-plist",
+            PlistFormat::Binary => "application/x-apple-binary-plist",
+            PlistFormat::OpenStep | PlistFormat::GnuStep => "text/plain",
+        }
+    }
+
+    /// Looks up a format by the name used in command flags.
+    pub fn from_name(name: &str) -> Option<PlistFormat> {
+        match name {
+            "xml" => Some(PlistFormat::Xml),
+            "binary" => Some(PlistFormat::Binary),
+            "openstep" | "ascii" => Some(PlistFormat::OpenStep),
+            "gnustep" => Some(PlistFormat::GnuStep),
+            _ => None,
+        }
+    }
+}
+
+/// A parse failure and where in the document it happened, when known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// Byte offset reported by the XML and binary parsers.
+    pub offset: Option<u64>,
+    /// Line and column reported by the OpenStep parser, or found from the
+    /// offset of an XML error.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The XML tag the error happened in or after.
+    pub element: Option<String>,
+    /// The index of the binary plist object the error happened in.
+    pub object: Option<u64>,
+}
+
+impl ParseError {
+    /// An error without a position.
+    pub fn new(message: String) -> Self {
+        ParseError {
+            message,
+            offset: None,
+            line: None,
+            column: None,
+            element: None,
+            object: None,
+        }
+    }
+
+    pub fn from_plist(error: &plist::Error) -> Self {
+        // The plist crate only exposes the offset through its message
+        let text = error.to_string();
+        let (message, offset) = match text.rsplit_once(" (offset ") {
+            Some((message, rest)) => (
+                message.to_string(),
+                rest.trim_end_matches(')').parse::<u64>().ok(),
+            ),
+            None => (text.clone(), None),
+        };
+        ParseError {
+            message,
+            offset,
+            line: None,
+            column: None,
+            element: None,
+            object: None,
+        }
+    }
+
+    /// Like [`ParseError::from_plist`] for an error in a binary document,
+    /// adding the object it happened in and whether the trailer and offset
+    /// table are intact.
+    pub fn from_binary(error: &plist::Error, bytes: &[u8]) -> Self {
+        let mut error = ParseError::from_plist(error);
+        let diagnosis = binary::diagnose(bytes, error.offset);
+        error.object = diagnosis.object;
+        match diagnosis.problem {
+            Some(problem) => error.message = format!("{}, {problem}", error.message),
+            None => error
+                .message
+                .push_str(", although the trailer and offset table look intact"),
+        }
+        error
+    }
+
+    /// Like [`ParseError::from_plist`] for an error in a text document,
+    /// finding the line, column and element at its offset.
+    pub fn from_plist_in(error: &plist::Error, bytes: &[u8]) -> Self {
+        let mut error = ParseError::from_plist(error);
+        if let Some(offset) = error.offset {
+            let mut lines = LineIndex::default();
+            lines.push(&bytes[..bytes.len().min(offset as usize)]);
+            lines.locate(&mut error);
+        }
+        error
+    }
+}
+
+impl From<openstep::OpenStepError> for ParseError {
+    fn from(error: openstep::OpenStepError) -> Self {
+        ParseError {
+            message: error.message,
+            offset: None,
+            line: Some(error.line),
+            column: Some(error.column),
+            element: None,
+            object: None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column, self.offset) {
+            (Some(line), Some(column), _) => match &self.element {
+                Some(element) => write!(
+                    f,
+                    "{} (line {line}, column {column}, near {element})",
+                    self.message
+                ),
+                None => write!(f, "{} (line {line}, column {column})", self.message),
+            },
+            (_, _, Some(offset)) => match self.object {
+                Some(object) => write!(f, "{} (offset {offset}, in object {object})", self.message),
+                None => write!(f, "{} (offset {offset})", self.message),
+            },
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Parses a plist document, returning the detected format even when parsing
+/// fails.
+pub fn try_parse(bytes: &[u8]) -> (PlistFormat, Result<PlistValue, ParseError>) {
+    try_parse_with(bytes, &Limits::default())
+}
+
+/// Like [`try_parse`], checking binary plists against `limits` first.
+pub fn try_parse_with(
+    bytes: &[u8],
+    limits: &Limits,
+) -> (PlistFormat, Result<PlistValue, ParseError>) {
+    // A byte order mark means nothing to the parsers, not even binary ones
+    let bytes = encoding::strip_bom(bytes);
+    let mut format = PlistFormat::detect(bytes);
+    let result = match format {
+        PlistFormat::Binary => binary::check_limits(bytes, limits)
+            .map_err(ParseError::new)
+            .and_then(|()| {
+                // Read as events rather than through serde, which formats
+                // dates along the way and panics on ones beyond year 9999
+                PlistValue::from_reader(io::Cursor::new(bytes))
+                    .map_err(|e| ParseError::from_binary(&e, bytes))
+            })
+            .map(|mut value| {
+                binary::round_dates(&mut value);
+                value
+            }),
+        PlistFormat::Xml => encoding::to_utf8(bytes)
+            .map_err(ParseError::new)
+            .and_then(|bytes| {
+                check_xml_depth(&bytes, limits.max_depth).map_err(ParseError::new)?;
+                plist::from_bytes(&bytes).map_err(|e| ParseError::from_plist_in(&e, &bytes))
+            }),
+        PlistFormat::OpenStep | PlistFormat::GnuStep => match encoding::to_utf8(bytes) {
+            Ok(bytes) => {
+                if bytes.windows(2).any(|w| w == b"<*") {
+                    format = PlistFormat::GnuStep;
+                }
+                openstep::from_bytes(&bytes, limits.max_depth).map_err(ParseError::from)
+            }
+            Err(e) => Err(ParseError::new(e)),
+        },
+    };
+    (format, result)
+}
+
+/// Checks that the arrays and dictionaries of an XML plist nest no more than
+/// `max_depth` levels deep before it is parsed, as the parser builds values
+/// recursively and would overflow the stack. Comments and CDATA sections are
+/// skipped, and text can't hold a `<` that isn't one of them.
+fn check_xml_depth(bytes: &[u8], max_depth: usize) -> Result<(), String> {
+    const SKIPPED: [(&[u8], &[u8]); 2] = [(b"!--", b"-->"), (b"![CDATA[", b"]]>")];
+    let mut depth = 0usize;
+    let mut rest = bytes;
+    while let Some(start) = rest.iter().position(|b| *b == b'<') {
+        rest = &rest[start + 1..];
+        if let Some((open, close)) = SKIPPED.iter().find(|(open, _)| rest.starts_with(open)) {
+            rest = &rest[open.len()..];
+            match rest.windows(close.len()).position(|w| w == *close) {
+                Some(end) => rest = &rest[end + close.len()..],
+                None => break,
+            }
+            continue;
+        }
+        let (closing, tag) = match rest.strip_prefix(b"/") {
+            Some(tag) => (true, tag),
+            None => (false, rest),
+        };
+        let Some(name) = [&b"array"[..], b"dict"]
+            .into_iter()
+            .find(|name| tag.starts_with(name))
+        else {
+            continue;
+        };
+        let Some(end) = tag.iter().position(|b| *b == b'>') else {
+            break;
+        };
+        let attributes = &tag[name.len()..end];
+        if !attributes.is_empty() && !matches!(attributes[0], b'/' | b' ' | b'\t' | b'\r' | b'\n') {
+            // Another element whose name starts the same
+            continue;
+        }
+        if closing {
+            depth = depth.saturating_sub(1);
+        } else if !attributes.ends_with(b"/") {
+            depth += 1;
+            if depth > max_depth {
+                return Err(format!(
+                    "The plist is nested more than {max_depth} levels deep"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Tracks the lines of a text document as it is read, to turn the byte
+/// offsets of XML parse errors into lines and columns.
+#[derive(Debug, Default)]
+pub struct LineIndex {
+    len: u64,
+    chars: u64,
+    /// The byte offset and character count at the start of each line after
+    /// the first.
+    lines: Vec<(u64, u64)>,
+    /// The last bytes read, to count the characters before an error and to
+    /// find the element it is in.
+    recent: VecDeque<u8>,
+}
+
+impl LineIndex {
+    const RECENT_LEN: usize = 4096;
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.len += 1;
+            // Continuation bytes don't start a character
+            if byte & 0xc0 != 0x80 {
+                self.chars += 1;
+            }
+            if byte == b'\n' {
+                self.lines.push((self.len, self.chars));
+            }
+        }
+        let skip = bytes.len().saturating_sub(Self::RECENT_LEN);
+        self.recent.extend(&bytes[skip..]);
+        let excess = self.recent.len().saturating_sub(Self::RECENT_LEN);
+        self.recent.drain(..excess);
+    }
+
+    /// Fills in the line, column and element of an error from its offset, if
+    /// that is among the recently read bytes.
+    pub fn locate(&self, error: &mut ParseError) {
+        let Some(offset) = error.offset.filter(|offset| *offset <= self.len) else {
+            return;
+        };
+        let Some(start) = self.recent.len().checked_sub((self.len - offset) as usize) else {
+            return;
+        };
+        let recent: Vec<u8> = self.recent.iter().copied().collect();
+        let chars_after = recent[start..]
+            .iter()
+            .filter(|byte| *byte & 0xc0 != 0x80)
+            .count() as u64;
+        let line = self
+            .lines
+            .partition_point(|(line_start, _)| *line_start <= offset);
+        let line_chars = line.checked_sub(1).map_or(0, |line| self.lines[line].1);
+        error.line = Some(line + 1);
+        error.column = Some((self.chars - chars_after - line_chars + 1) as usize);
+        error.element = recent[..start]
+            .iter()
+            .rposition(|byte| *byte == b'<')
+            .map(|open| {
+                let tag = &recent[open..recent.len().min(open + 64)];
+                let end = tag
+                    .iter()
+                    .position(|byte| *byte == b'>')
+                    .map_or(tag.len(), |end| end + 1);
+                String::from_utf8_lossy(&tag[..end]).into_owned()
+            });
+    }
+}
+
+/// Parses a plist document in any of the supported formats.
+pub fn parse_plist(bytes: &[u8], span: Span) -> Result<(PlistValue, PlistFormat), LabeledError> {
+    parse_plist_with(bytes, &Limits::default(), span)
+}
+
+/// Like [`parse_plist`] with limits on the size of binary plists.
+pub fn parse_plist_with(
+    bytes: &[u8],
+    limits: &Limits,
+    span: Span,
+) -> Result<(PlistValue, PlistFormat), LabeledError> {
+    let (format, result) = try_parse_with(bytes, limits);
+    let value = result.map_err(|e| plist_error(ErrorKind::Parse, format!("{}", e), span))?;
+    Ok((value, format))
+}
+
+/// The bytes of a string or binary pipeline input.
+pub fn input_bytes(input: &NuValue, head: Span) -> Result<&[u8], LabeledError> {
+    match input {
+        NuValue::String { val, .. } => Ok(val.as_bytes()),
+        NuValue::Binary { val, .. } => Ok(val),
+        _ => Err(plist_error(
+            ErrorKind::UnsupportedType,
+            format!("Invalid input, must be string or binary not: {:?}", input),
+            head,
+        )),
+    }
+}
+
+/// Parses a string or binary pipeline input as a plist document.
+pub fn parse_input(input: &NuValue, head: Span) -> Result<(PlistValue, PlistFormat), LabeledError> {
+    parse_plist(input_bytes(input, head)?, input.span())
+}
+
+/// Reads a command argument that is either a plist document, as a string or
+/// binary, or an already decoded Nu value.
+pub fn parse_operand(value: &NuValue) -> Result<PlistValue, LabeledError> {
+    match value {
+        NuValue::String { .. } | NuValue::Binary { .. } => Ok(parse_input(value, value.span())?.0),
+        _ => convert_nu_value(value),
+    }
+}
+
+/// Serializes a plist value in the given format into a writer, without
+/// buffering the whole document first.
+///
+/// The output only depends on the value, so equal values always serialize to
+/// the same bytes. Binary plists list objects in the order they are reached,
+/// dictionary keys before values, with equal strings, numbers and data stored
+/// once.
+pub fn serialize(
+    plist_val: &PlistValue,
+    format: PlistFormat,
+    writer: impl Write,
+    span: Span,
+) -> Result<(), LabeledError> {
+    serialize_with(
+        plist_val,
+        format,
+        &SerializeOptions::default(),
+        writer,
+        span,
+    )
+}
+
+/// The document type declaration Apple's tools write.
+pub const APPLE_DOCTYPE: &str = r#"<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">"#;
+
+/// Options for writing XML plists.
+#[derive(Debug, Clone)]
+pub struct XmlOptions {
+    /// The character indenting nested elements, and how many of it make up
+    /// one level. Without it the document is written without any whitespace.
+    pub indent: Option<(u8, usize)>,
+    /// Whether to start with the `<?xml ...?>` declaration.
+    pub declaration: bool,
+    /// The `<!DOCTYPE ...>` line, if any.
+    pub doctype: Option<String>,
+    /// Write only the value element, without the prologue or `<plist>` root.
+    pub fragment: bool,
+}
+
+impl Default for XmlOptions {
+    fn default() -> Self {
+        XmlOptions {
+            indent: Some((b'\t', 1)),
+            declaration: true,
+            doctype: Some(APPLE_DOCTYPE.to_string()),
+            fragment: false,
+        }
+    }
+}
+
+/// The line ending of the text formats.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn from_name(name: &str) -> Option<LineEnding> {
+        match name.to_ascii_lowercase().as_str() {
+            "lf" => Some(LineEnding::Lf),
+            "crlf" => Some(LineEnding::CrLf),
+            _ => None,
+        }
+    }
+}
+
+/// Options for the formats that have any.
+#[derive(Debug, Clone, Default)]
+pub struct SerializeOptions {
+    pub binary: WriteOptions,
+    pub xml: XmlOptions,
+    /// Applies to XML, OpenStep and GNUstep plists.
+    pub line_ending: LineEnding,
+}
+
+/// Turns the line feeds of a text plist into CRLF pairs. Those inside XML
+/// strings and keys belong to the value and are written as character
+/// references instead, as the plist crate doesn't normalize line endings when
+/// reading.
+fn to_crlf(text: &[u8], xml: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len() + text.len() / 16);
+    let mut in_text = false;
+    let mut rest = text;
+    while let Some((&b, tail)) = rest.split_first() {
+        match b {
+            b'<' if xml => {
+                let end = tail
+                    .iter()
+                    .position(|&b| b == b'>')
+                    .map_or(tail.len(), |i| i + 1);
+                let tag = &tail[..end];
+                in_text = tag == b"string>" || tag == b"key>";
+                out.push(b);
+                out.extend_from_slice(tag);
+                rest = &tail[end..];
+                continue;
+            }
+            b'\n' if in_text => out.extend_from_slice(b"&#10;"),
+            b'\n' => out.extend_from_slice(b"\r\n"),
+            b => out.push(b),
+        }
+        rest = tail;
+    }
+    out
+}
+
+/// Like [`serialize`], with options for the binary and XML writers.
+pub fn serialize_with(
+    plist_val: &PlistValue,
+    format: PlistFormat,
+    options: &SerializeOptions,
+    mut writer: impl Write,
+    span: Span,
+) -> Result<(), LabeledError> {
+    let error = |e: io::Error| {
+        // The writers report values they can't hold as invalid input
+        let kind = match e.kind() {
+            io::ErrorKind::InvalidInput => ErrorKind::UnsupportedType,
+            _ => ErrorKind::Io,
+        };
+        plist_error(kind, format!("{}", e), span)
+    };
+    if format != PlistFormat::Binary {
+        check_text_dates(plist_val, span)?;
+    }
+    if format != PlistFormat::Binary && options.line_ending == LineEnding::CrLf {
+        let options = SerializeOptions {
+            line_ending: LineEnding::Lf,
+            ..options.clone()
+        };
+        let mut text = Vec::new();
+        serialize_with(plist_val, format, &options, &mut text, span)?;
+        let text = to_crlf(&text, format == PlistFormat::Xml);
+        writer.write_all(&text).map_err(error)?;
+        return writer.flush().map_err(error);
+    }
+    match format {
+        PlistFormat::Binary => {
+            binary::write(plist_val, &options.binary, &mut writer).map_err(error)?
+        }
+        PlistFormat::Xml => write_xml(plist_val, &options.xml, &mut writer).map_err(error)?,
+        PlistFormat::OpenStep | PlistFormat::GnuStep => {
+            let text = openstep::to_string(plist_val, format == PlistFormat::GnuStep, span)?;
+            writer.write_all(text.as_bytes()).map_err(error)?
+        }
+    }
+    writer.flush().map_err(error)
+}
+
+/// Writes an XML plist. The plist crate only writes its fixed prologue, so the
+/// prologue and root element are written here.
+fn write_xml(
+    plist_val: &PlistValue,
+    options: &XmlOptions,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    let newline: &[u8] = if options.indent.is_some() { b"\n" } else { b"" };
+    // An indent of zero turns off line breaks as well
+    let (indent_char, indent_count) = options.indent.unwrap_or((b' ', 0));
+    let xml_options = XmlWriteOptions::default()
+        .indent(indent_char, indent_count)
+        .root_element(false);
+    if options.fragment {
+        return plist::to_writer_xml_with_options(&mut writer, &*xml_uids(plist_val), &xml_options)
+            .map_err(io::Error::other);
+    }
+    if options.declaration {
+        writer.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writer.write_all(newline)?;
+    }
+    if let Some(doctype) = &options.doctype {
+        writer.write_all(doctype.as_bytes())?;
+        writer.write_all(newline)?;
+    }
+    writer.write_all(b"<plist version=\"1.0\">")?;
+    writer.write_all(newline)?;
+    plist::to_writer_xml_with_options(&mut writer, &*xml_uids(plist_val), &xml_options)
+        .map_err(io::Error::other)?;
+    writer.write_all(newline)?;
+    writer.write_all(b"</plist>")
+}
+
+/// Splits XML plists written back to back, as some log exports do, at the
+/// end of each `</plist>` root.
+pub fn split_xml_documents(bytes: &[u8]) -> Vec<&[u8]> {
+    const END: &[u8] = b"</plist>";
+    let mut documents = Vec::new();
+    let mut rest = bytes;
+    loop {
+        let start = rest
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        rest = &rest[start..];
+        if rest.is_empty() {
+            break;
+        }
+        match rest.windows(END.len()).position(|w| w == END) {
+            Some(end) => {
+                let (document, tail) = rest.split_at(end + END.len());
+                documents.push(document);
+                rest = tail;
+            }
+            None => {
+                // Left for the parser to report as incomplete
+                documents.push(rest);
+                break;
+            }
+        }
+    }
+    documents
+}
+
+/// The format of a document and the version it declares: the two characters
+/// after the `bplist` magic of binary plists, or the `version` attribute of the
+/// `<plist>` root of XML ones. Text plists have no version.
+pub fn format_version(bytes: &[u8]) -> (PlistFormat, Option<String>) {
+    let bytes = encoding::strip_bom(bytes);
+    match PlistFormat::detect(bytes) {
+        PlistFormat::Binary => (
+            PlistFormat::Binary,
+            binary::version(bytes).map(str::to_string),
+        ),
+        PlistFormat::Xml => (
+            PlistFormat::Xml,
+            encoding::to_utf8(bytes)
+                .ok()
+                .and_then(|text| root_version(&text)),
+        ),
+        format => match encoding::to_utf8(bytes) {
+            Ok(text) if text.windows(2).any(|w| w == b"<*") => (PlistFormat::GnuStep, None),
+            _ => (format, None),
+        },
+    }
+}
+
+/// The value of the `version` attribute of the first `<plist>` element.
+fn root_version(text: &[u8]) -> Option<String> {
+    let start = text.windows(6).position(|w| w == b"<plist")? + 6;
+    let end = start + text[start..].iter().position(|b| *b == b'>')?;
+    let tag = std::str::from_utf8(&text[start..end]).ok()?;
+    let rest = tag[tag.find("version")? + "version".len()..]
+        .trim_start()
+        .strip_prefix('=')?
+        .trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &rest[1..];
+    Some(value[..value.find(quote)?].to_string())
+}
+
+/// Fails on dates outside [`TEXT_YEARS`], which the text writers can't
+/// format.
+fn check_text_dates(plist_val: &PlistValue, span: Span) -> Result<(), LabeledError> {
+    match plist_val {
+        PlistValue::Array(items) => items
+            .iter()
+            .try_for_each(|item| check_text_dates(item, span)),
+        PlistValue::Dictionary(dict) => dict
+            .values()
+            .try_for_each(|item| check_text_dates(item, span)),
+        PlistValue::Date(date) => match convert_date(date) {
+            Some(date) if TEXT_YEARS.contains(&date.year()) => Ok(()),
+            _ => Err(plist_error(
+                ErrorKind::Overflow,
+                "A date is outside the years -9999 to 9999 text plists can hold, write a binary plist instead".to_string(),
+                span,
+            )),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Sorts the keys of every dictionary in a value.
+pub fn sort_keys(plist_val: &mut PlistValue) {
+    match plist_val {
+        PlistValue::Array(items) => items.iter_mut().for_each(sort_keys),
+        PlistValue::Dictionary(dict) => {
+            dict.sort_keys();
+            dict.values_mut().for_each(sort_keys);
+        }
+        _ => {}
+    }
+}
+
+/// Rounds every real in a value to a number of decimal places, so results of
+/// arithmetic such as `0.1 + 0.2` are written as `0.3` rather than
+/// `0.30000000000000004`.
+pub fn round_reals(plist_val: &mut PlistValue, precision: usize) {
+    match plist_val {
+        PlistValue::Array(items) => items.iter_mut().for_each(|v| round_reals(v, precision)),
+        PlistValue::Dictionary(dict) => dict.values_mut().for_each(|v| round_reals(v, precision)),
+        PlistValue::Real(real) if real.is_finite() => {
+            // Going through the decimal text rounds exactly as it's printed
+            if let Ok(rounded) = format!("{real:.precision$}").parse() {
+                *real = rounded;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// What to do with NaN and infinite reals, which XML plists can't hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonFinite {
+    Error,
+    /// Leave out the entries holding them, as plists have no null.
+    Null,
+    /// Write them as the strings `NaN`, `inf` and `-inf`.
+    String,
+}
+
+impl NonFinite {
+    pub fn from_name(name: &str) -> Option<NonFinite> {
+        match name.to_ascii_lowercase().as_str() {
+            "error" => Some(NonFinite::Error),
+            "null" => Some(NonFinite::Null),
+            "string" => Some(NonFinite::String),
+            _ => None,
+        }
+    }
+}
+
+/// Applies a [`NonFinite`] policy to every NaN or infinite real in a value.
+pub fn non_finite_reals(
+    plist_val: &mut PlistValue,
+    policy: NonFinite,
+    span: Span,
+) -> Result<(), LabeledError> {
+    fn is_non_finite(value: &PlistValue) -> bool {
+        matches!(value, PlistValue::Real(real) if !real.is_finite())
+    }
+    match plist_val {
+        PlistValue::Array(items) => {
+            if policy == NonFinite::Null {
+                items.retain(|item| !is_non_finite(item));
+            }
+            items
+                .iter_mut()
+                .try_for_each(|item| non_finite_reals(item, policy, span))
+        }
+        PlistValue::Dictionary(dict) => {
+            if policy == NonFinite::Null {
+                dict.retain(|_, value| !is_non_finite(value));
+            }
+            dict.values_mut()
+                .try_for_each(|value| non_finite_reals(value, policy, span))
+        }
+        PlistValue::Real(real) if !real.is_finite() => match policy {
+            NonFinite::String => {
+                let text = real.to_string();
+                *plist_val = PlistValue::String(text);
+                Ok(())
+            }
+            // Only a root real is left for null, which can't be left out
+            NonFinite::Error | NonFinite::Null => Err(plist_error(
+                ErrorKind::UnsupportedType,
+                format!("Can't write {real}, plists have no NaN or infinity. Use --non-finite to replace it"),
+                span,
+            )),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// What to do with characters XML 1.0 doesn't allow, such as most control
+/// characters, in strings and keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidChars {
+    Error,
+    Strip,
+    /// Write them as `\u001b` style escapes. Reading the document back gives
+    /// the escape text, not the character.
+    Escape,
+}
+
+impl InvalidChars {
+    pub fn from_name(name: &str) -> Option<InvalidChars> {
+        match name.to_ascii_lowercase().as_str() {
+            "error" => Some(InvalidChars::Error),
+            "strip" => Some(InvalidChars::Strip),
+            "escape" => Some(InvalidChars::Escape),
+            _ => None,
+        }
+    }
+}
+
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{fffd}' | '\u{10000}'..='\u{10ffff}')
+}
+
+/// Applies an [`InvalidChars`] policy to every string and dictionary key in
+/// a value. Errors name the path of the offending string.
+pub fn invalid_xml_chars(
+    plist_val: &mut PlistValue,
+    policy: InvalidChars,
+    span: Span,
+) -> Result<(), LabeledError> {
+    fn replace(
+        text: &str,
+        kind: &str,
+        policy: InvalidChars,
+        path: &[String],
+        span: Span,
+    ) -> Result<Option<String>, LabeledError> {
+        let Some(invalid) = text.chars().find(|c| !is_xml_char(*c)) else {
+            return Ok(None);
+        };
+        let mut replaced = String::with_capacity(text.len());
+        for c in text.chars() {
+            match policy {
+                _ if is_xml_char(c) => replaced.push(c),
+                InvalidChars::Error => {
+                    return Err(plist_error(
+                        ErrorKind::UnsupportedType,
+                        format!(
+                            "The {kind} at {} contains U+{:04X}, which XML plists can't hold. Use --invalid-chars to strip or escape it",
+                            describe_path(path),
+                            u32::from(invalid)
+                        ),
+                        span,
+                    ));
+                }
+                InvalidChars::Strip => {}
+                InvalidChars::Escape => replaced.push_str(&format!("\\u{:04x}", u32::from(c))),
+            }
+        }
+        Ok(Some(replaced))
+    }
+
+    fn visit(
+        plist_val: &mut PlistValue,
+        policy: InvalidChars,
+        path: &mut Vec<String>,
+        span: Span,
+    ) -> Result<(), LabeledError> {
+        match plist_val {
+            PlistValue::String(text) => {
+                if let Some(replaced) = replace(text, "string", policy, path, span)? {
+                    *text = replaced;
+                }
+            }
+            PlistValue::Array(items) => {
+                for (index, item) in items.iter_mut().enumerate() {
+                    path.push(index.to_string());
+                    visit(item, policy, path, span)?;
+                    path.pop();
+                }
+            }
+            PlistValue::Dictionary(dict) => {
+                let mut entries = Vec::with_capacity(dict.len());
+                for (key, mut value) in std::mem::take(dict) {
+                    let key = replace(&key, "key", policy, path, span)?.unwrap_or(key);
+                    path.push(key.clone());
+                    visit(&mut value, policy, path, span)?;
+                    path.pop();
+                    entries.push((key, value));
+                }
+                // Replacing keys can make two of them equal, the last one wins
+                // like for any repeated key
+                *dict = entries.into_iter().collect();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    visit(plist_val, policy, &mut Vec::new(), span)
+}
+
+/// XML plists have no UID type, so UIDs are written as `CF$UID` dictionaries
+/// the way CoreFoundation writes them.
+fn xml_uids(plist_val: &PlistValue) -> Cow<PlistValue> {
+    fn has_uid(value: &PlistValue) -> bool {
+        match value {
+            PlistValue::Uid(_) => true,
+            PlistValue::Array(items) => items.iter().any(has_uid),
+            PlistValue::Dictionary(dict) => dict.values().any(has_uid),
+            _ => false,
+        }
+    }
+    fn replace(value: &mut PlistValue) {
+        match value {
+            PlistValue::Uid(uid) => {
+                let mut dict = Dictionary::new();
+                dict.insert(UID_KEY.to_string(), PlistValue::Integer(uid.get().into()));
+                *value = PlistValue::Dictionary(dict);
+            }
+            PlistValue::Array(items) => items.iter_mut().for_each(replace),
+            PlistValue::Dictionary(dict) => dict.values_mut().for_each(replace),
+            _ => {}
+        }
+    }
+
+    if !has_uid(plist_val) {
+        return Cow::Borrowed(plist_val);
+    }
+    let mut plist_val = plist_val.clone();
+    replace(&mut plist_val);
+    Cow::Owned(plist_val)
+}
+
+/// Serializes a plist value in the given format to raw bytes.
+pub fn plist_bytes(
+    plist_val: &PlistValue,
+    format: PlistFormat,
+    span: Span,
+) -> Result<Vec<u8>, LabeledError> {
+    let mut out = Vec::new();
+    serialize(plist_val, format, &mut out, span)?;
+    Ok(out)
+}
+
+/// Serializes a plist value in the given format. Binary plists are returned as
+/// Nu binary values, the text formats as strings.
+pub fn write_plist(
+    plist_val: &PlistValue,
+    format: PlistFormat,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    plist_output(plist_bytes(plist_val, format, span)?, format, span)
+}
+
+/// Wraps serialized plist bytes in a Nu value: binary for binary plists,
+/// strings for the text formats.
+pub fn plist_output(
+    out: Vec<u8>,
+    format: PlistFormat,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    match format {
+        PlistFormat::Binary => Ok(NuValue::binary(out, span)),
+        _ => Ok(NuValue::string(
+            String::from_utf8(out)
+                .map_err(|e| plist_error(ErrorKind::UnsupportedType, format!("{}", e), span))?,
+            span,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nu_protocol::Record;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(PlistFormat::detect(b"bplist00\x08"), PlistFormat::Binary);
+        assert_eq!(
+            PlistFormat::detect(b"\n<?xml version=\"1.0\"?><plist/>"),
+            PlistFormat::Xml
+        );
+        assert_eq!(PlistFormat::detect(b"<plist></plist>"), PlistFormat::Xml);
+        assert_eq!(PlistFormat::detect(b"{ a = b; }"), PlistFormat::OpenStep);
+        assert_eq!(PlistFormat::detect(b"<true/>"), PlistFormat::Xml);
+        assert_eq!(PlistFormat::detect(b"<dead>"), PlistFormat::OpenStep);
+    }
+
+    #[test]
+    fn test_parse_openstep() {
+        let (value, format) =
+            parse_plist(b"{ a = (1, 2); }", Span::test_data()).expect("failed to parse");
+        assert_eq!(format, PlistFormat::OpenStep);
+        assert!(value.as_dictionary().is_some());
+    }
+
+    #[test]
+    fn test_parse_gnustep() {
+        let (value, format) =
+            parse_plist(b"{ a = <*I1>; }", Span::test_data()).expect("failed to parse");
+        assert_eq!(format, PlistFormat::GnuStep);
+        assert_eq!(
+            value
+                .as_dictionary()
+                .and_then(|d| d.get("a"))
+                .and_then(|a| a.as_signed_integer()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let (format, result) = try_parse(b"{ a = (1, 2; }");
+        assert_eq!(format, PlistFormat::OpenStep);
+        let error = result.expect_err("expected a parse error");
+        assert!(error.line.is_some() && error.column.is_some());
+
+        let (format, result) = try_parse(b"<plist><dict><key>a</key></plist>");
+        assert_eq!(format, PlistFormat::Xml);
+        let error = result.expect_err("expected a parse error");
+        assert!(error.offset.is_some());
+        assert!(!error.message.contains("offset"));
+    }
+
+    #[test]
+    fn test_binary_error_location() {
+        let value = PlistValue::Array(vec![PlistValue::String("a".into())]);
+        let mut bytes =
+            plist_bytes(&value, PlistFormat::Binary, Span::test_data()).expect("failed to write");
+        // Replace the string's marker with an unknown object type
+        bytes[10] = 0x70;
+        let (_, result) = try_parse(&bytes);
+        let error = result.expect_err("expected a parse error");
+        assert_eq!(error.object, Some(1), "{error}");
+        assert!(error.message.contains("intact"), "{error}");
+    }
+
+    #[test]
+    fn test_xml_error_location() {
+        let xml = "<?xml version=\"1.0\"?>\n<plist>\n<dict>\n  <key>é</key>\n  <bogus/>\n</dict>\n</plist>";
+        let (_, result) = try_parse(xml.as_bytes());
+        let error = result.expect_err("expected a parse error");
+        assert_eq!(error.line, Some(5), "{error}");
+        assert_eq!(error.element.as_deref(), Some("<bogus/>"), "{error}");
+        assert!(error.to_string().contains("line 5"), "{error}");
+
+        let mut lines = LineIndex::default();
+        lines.push("ab\ncdé".as_bytes());
+        lines.push(b"f");
+        let mut error = ParseError::new("test".into());
+        error.offset = Some(7);
+        lines.locate(&mut error);
+        assert_eq!((error.line, error.column), (Some(2), Some(4)));
+    }
+
+    #[test]
+    fn test_write_openstep() {
+        let value = PlistValue::Array(vec![PlistValue::String("a b".to_string())]);
+        let out =
+            write_plist(&value, PlistFormat::OpenStep, Span::test_data()).expect("failed to write");
+        assert_eq!(
+            out,
+            NuValue::string("(\n    \"a b\"\n)\n", Span::test_data())
+        );
+    }
+
+    #[test]
+    fn test_binary_output_is_deterministic() {
+        let span = Span::test_data();
+        let mut record = Record::new();
+        record.push("a", NuValue::test_int(1));
+        record.push("b", NuValue::test_string("x"));
+        record.push("c", NuValue::test_list(vec![NuValue::test_string("x")]));
+        let value = convert_nu_value(&NuValue::test_record(record)).expect("failed to convert");
+
+        let expected = [
+            &b"bplist00\xd3\x01\x02\x03\x04\x05\x06QaQbQc\x10\x01Qx\xa1\x05"[..],
+            // offset table
+            b"\x08\x0f\x11\x13\x15\x17\x19",
+            // trailer: offset and reference sizes, object count, root, table offset
+            b"\0\0\0\0\0\0\x01\x01",
+            b"\0\0\0\0\0\0\0\x07",
+            b"\0\0\0\0\0\0\0\0",
+            b"\0\0\0\0\0\0\0\x1b",
+        ]
+        .concat();
+        for _ in 0..2 {
+            let out = plist_bytes(&value, PlistFormat::Binary, span).expect("failed to write");
+            assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn test_xml_uids() {
+        let value = PlistValue::Array(vec![PlistValue::Uid(plist::Uid::new(3))]);
+        let out =
+            plist_bytes(&value, PlistFormat::Xml, Span::test_data()).expect("failed to write");
+        let text = String::from_utf8(out).expect("invalid utf-8");
+        assert!(text.contains("<key>CF$UID</key>"));
+        assert!(text.contains("<integer>3</integer>"));
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let mut inner = Dictionary::new();
+        inner.insert("y".to_string(), PlistValue::Boolean(true));
+        inner.insert("x".to_string(), PlistValue::Boolean(false));
+        let mut dict = Dictionary::new();
+        dict.insert(
+            "b".to_string(),
+            PlistValue::Array(vec![PlistValue::Dictionary(inner)]),
+        );
+        dict.insert("a".to_string(), PlistValue::Boolean(true));
+        let mut value = PlistValue::Dictionary(dict);
+        sort_keys(&mut value);
+
+        let dict = value.as_dictionary().expect("expected a dictionary");
+        assert_eq!(dict.keys().collect::<Vec<_>>(), ["a", "b"]);
+        let inner = dict
+            .get("b")
+            .and_then(PlistValue::as_array)
+            .and_then(|items| items[0].as_dictionary())
+            .expect("expected a dictionary");
+        assert_eq!(inner.keys().collect::<Vec<_>>(), ["x", "y"]);
+    }
+
+    #[test]
+    fn test_xml_indent() {
+        let value = PlistValue::Array(vec![PlistValue::Boolean(true)]);
+        let options = SerializeOptions {
+            xml: XmlOptions {
+                indent: Some((b' ', 2)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        serialize_with(
+            &value,
+            PlistFormat::Xml,
+            &options,
+            &mut out,
+            Span::test_data(),
+        )
+        .expect("failed to write");
+        let text = String::from_utf8(out).expect("invalid utf-8");
+        assert!(text.contains("<array>\n  <true/>\n</array>"));
+    }
+
+    #[test]
+    fn test_xml_prologue() {
+        let mut dict = Dictionary::new();
+        dict.insert("a".to_string(), PlistValue::Array(vec![true.into()]));
+        let value = PlistValue::Dictionary(dict);
+        let span = Span::test_data();
+
+        let mut expected = Vec::new();
+        plist::to_writer_xml(&mut expected, &value).expect("failed to write");
+        assert_eq!(
+            plist_bytes(&value, PlistFormat::Xml, span).expect("failed to write"),
+            expected
+        );
+
+        let options = SerializeOptions {
+            xml: XmlOptions {
+                declaration: false,
+                doctype: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        serialize_with(&value, PlistFormat::Xml, &options, &mut out, span)
+            .expect("failed to write");
+        assert!(out.starts_with(b"<plist version=\"1.0\">\n<dict>"));
+        assert_eq!(
+            parse_plist(&out, span).ok(),
+            Some((value, PlistFormat::Xml))
+        );
+    }
+
+    #[test]
+    fn test_split_xml_documents() {
+        let doc = "<?xml version=\"1.0\"?>\n<plist version=\"1.0\"><string>a</string></plist>";
+        let joined = format!("{doc}\n{doc}{doc}\n");
+        let documents = split_xml_documents(joined.as_bytes());
+        assert_eq!(documents, vec![doc.as_bytes(); 3]);
+        assert_eq!(split_xml_documents(b" \n"), Vec::<&[u8]>::new());
+        assert_eq!(
+            split_xml_documents(b"<plist></plist><plist>"),
+            vec![&b"<plist></plist>"[..], b"<plist>"]
+        );
+    }
+
+    #[test]
+    fn test_check_xml_depth() {
+        let nested = |depth: usize| {
+            format!(
+                "<plist>{}<dict/><array />{}</plist>",
+                "<array>".repeat(depth),
+                "</array>".repeat(depth)
+            )
+        };
+        assert_eq!(check_xml_depth(nested(3).as_bytes(), 3), Ok(()));
+        assert!(check_xml_depth(nested(4).as_bytes(), 3).is_err());
+        let hidden = "<array><!-- <array><array> --><![CDATA[<dict>]]><dictionary/></array>";
+        assert_eq!(check_xml_depth(hidden.as_bytes(), 1), Ok(()));
+
+        let deep = nested(100_000);
+        let err = parse_plist(deep.as_bytes(), Span::test_data()).expect_err("should be too deep");
+        assert!(err.labels[0].text.contains("nested more than"));
+    }
+
+    #[test]
+    fn test_far_dates() {
+        let span = Span::test_data();
+        let far = std::time::SystemTime::UNIX_EPOCH
+            + std::time::Duration::from_secs(400_000 * 365 * 86_400);
+        let value = PlistValue::Array(vec![PlistValue::Date(far.into())]);
+        let bytes = plist_bytes(&value, PlistFormat::Binary, span).expect("failed to write");
+        let (read, _) = parse_plist(&bytes, span).expect("failed to parse");
+        assert_eq!(read, value);
+        let err = plist_bytes(&value, PlistFormat::Xml, span).expect_err("expected an error");
+        assert!(err.labels[0].text.contains("-9999 to 9999"));
+        assert!(plist_bytes(&value, PlistFormat::GnuStep, span).is_err());
+    }
+
+    #[test]
+    fn test_format_version() {
+        let span = Span::test_data();
+        let binary = plist_bytes(&PlistValue::Boolean(true), PlistFormat::Binary, span)
+            .expect("failed to write");
+        assert_eq!(
+            format_version(&binary),
+            (PlistFormat::Binary, Some("00".to_string()))
+        );
+        assert_eq!(
+            format_version(b"<?xml version=\"1.0\"?>\n<plist version='1.0'><true/></plist>"),
+            (PlistFormat::Xml, Some("1.0".to_string()))
+        );
+        assert_eq!(
+            format_version(b"<string>a</string>"),
+            (PlistFormat::Xml, None)
+        );
+        assert_eq!(
+            format_version(b"{ a = <*I1>; }"),
+            (PlistFormat::GnuStep, None)
+        );
+        assert_eq!(format_version(b"{ a = 1; }"), (PlistFormat::OpenStep, None));
+    }
+
+    #[test]
+    fn test_xml_fragment() {
+        let span = Span::test_data();
+        let mut dict = Dictionary::new();
+        dict.insert("a".to_string(), PlistValue::String("b".into()));
+        let value = PlistValue::Dictionary(dict);
+        let options = SerializeOptions {
+            xml: XmlOptions {
+                fragment: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        serialize_with(&value, PlistFormat::Xml, &options, &mut out, span)
+            .expect("failed to write");
+        assert_eq!(
+            out,
+            b"<dict>\n\t<key>a</key>\n\t<string>b</string>\n</dict>"
+        );
+        assert_eq!(
+            parse_plist(&out, span).ok(),
+            Some((value, PlistFormat::Xml))
+        );
+        assert_eq!(
+            parse_plist(b"<integer>4</integer>", span).ok(),
+            Some((PlistValue::Integer(4.into()), PlistFormat::Xml))
+        );
+    }
+
+    #[test]
+    fn test_xml_minified() {
+        let mut dict = Dictionary::new();
+        dict.insert("a".to_string(), PlistValue::Array(vec![true.into()]));
+        let value = PlistValue::Dictionary(dict);
+        let options = SerializeOptions {
+            xml: XmlOptions {
+                indent: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        serialize_with(
+            &value,
+            PlistFormat::Xml,
+            &options,
+            &mut out,
+            Span::test_data(),
+        )
+        .expect("failed to write");
+        assert!(!out.contains(&b'\n'));
+        assert!(out.ends_with(
+            b"<plist version=\"1.0\"><dict><key>a</key><array><true/></array></dict></plist>"
+        ));
+    }
+
+    #[test]
+    fn test_crlf() {
+        let mut dict = Dictionary::new();
+        dict.insert("a".to_string(), PlistValue::String("b\nc".into()));
+        let value = PlistValue::Dictionary(dict);
+        let options = SerializeOptions {
+            line_ending: LineEnding::CrLf,
+            ..Default::default()
+        };
+        for format in [PlistFormat::Xml, PlistFormat::OpenStep] {
+            let mut out = Vec::new();
+            serialize_with(&value, format, &options, &mut out, Span::test_data())
+                .expect("failed to write");
+            let text = String::from_utf8(out).expect("not utf-8");
+            assert!(text.contains("\r\n"));
+            assert!(!text.replace("\r\n", "").contains('\n'));
+            let (parsed, _) =
+                parse_plist(text.as_bytes(), Span::test_data()).expect("failed to parse");
+            assert_eq!(parsed, value);
+        }
+    }
+
+    #[test]
+    fn test_round_reals() {
+        let mut value = PlistValue::Array(vec![
+            PlistValue::Real(0.1 + 0.2),
+            PlistValue::Real(2.0 / 3.0),
+            PlistValue::Real(f64::NAN),
+        ]);
+        round_reals(&mut value, 2);
+        let PlistValue::Array(items) = &value else {
+            panic!("expected an array");
+        };
+        assert_eq!(items[0], PlistValue::Real(0.3));
+        assert_eq!(items[1], PlistValue::Real(0.67));
+        assert!(items[2].as_real().is_some_and(f64::is_nan));
+        let xml =
+            plist_bytes(&value, PlistFormat::Xml, Span::test_data()).expect("failed to write");
+        assert!(String::from_utf8_lossy(&xml).contains("<real>0.3</real>"));
+    }
+
+    #[test]
+    fn test_non_finite_reals() {
+        let span = Span::test_data();
+        let mut dict = Dictionary::new();
+        dict.insert("nan".to_string(), PlistValue::Real(f64::NAN));
+        dict.insert("one".to_string(), PlistValue::Real(1.0));
+        let value = PlistValue::Array(vec![
+            PlistValue::Real(f64::INFINITY),
+            PlistValue::Dictionary(dict),
+            PlistValue::Real(f64::NEG_INFINITY),
+        ]);
+
+        let mut strings = value.clone();
+        non_finite_reals(&mut strings, NonFinite::String, span).expect("failed to replace");
+        let PlistValue::Array(items) = &strings else {
+            panic!("expected an array");
+        };
+        assert_eq!(items[0], PlistValue::String("inf".into()));
+        assert_eq!(items[2], PlistValue::String("-inf".into()));
+
+        let mut nulls = value.clone();
+        non_finite_reals(&mut nulls, NonFinite::Null, span).expect("failed to drop");
+        let mut dict = Dictionary::new();
+        dict.insert("one".to_string(), PlistValue::Real(1.0));
+        assert_eq!(nulls, PlistValue::Array(vec![PlistValue::Dictionary(dict)]));
+
+        let mut errors = value;
+        assert!(non_finite_reals(&mut errors, NonFinite::Error, span).is_err());
+    }
+
+    #[test]
+    fn test_invalid_xml_chars() {
+        let span = Span::test_data();
+        let mut items = Dictionary::new();
+        items.insert("bell\u{7}".to_string(), PlistValue::String("ok".into()));
+        items.insert(
+            "callback".to_string(),
+            PlistValue::Array(vec![PlistValue::String("a\u{1b}[0m\tb".into())]),
+        );
+        let value = PlistValue::Dictionary(items);
+
+        let mut stripped = value.clone();
+        invalid_xml_chars(&mut stripped, InvalidChars::Strip, span).expect("failed to strip");
+        let mut dict = Dictionary::new();
+        dict.insert("bell".to_string(), PlistValue::String("ok".into()));
+        dict.insert(
+            "callback".to_string(),
+            PlistValue::Array(vec![PlistValue::String("a[0m\tb".into())]),
+        );
+        assert_eq!(stripped, PlistValue::Dictionary(dict));
+
+        let mut escaped = value.clone();
+        invalid_xml_chars(&mut escaped, InvalidChars::Escape, span).expect("failed to escape");
+        let xml = plist_bytes(&escaped, PlistFormat::Xml, span).expect("failed to write");
+        let xml = String::from_utf8(xml).expect("invalid UTF-8");
+        assert!(xml.contains("<key>bell\\u0007</key>"));
+        assert!(xml.contains("a\\u001b[0m\tb"));
+
+        let mut errors = PlistValue::Dictionary(Dictionary::new());
+        assert!(invalid_xml_chars(&mut errors, InvalidChars::Error, span).is_ok());
+        let mut errors = escaped;
+        if let PlistValue::Dictionary(dict) = &mut errors {
+            dict.insert("payload".to_string(), value);
+        }
+        let error = invalid_xml_chars(&mut errors, InvalidChars::Error, span)
+            .expect_err("expected an error");
+        let text = &error.labels[0].text;
+        assert!(
+            text.starts_with("The key at payload contains U+0007"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_read_non_finite_reals() {
+        let value = PlistValue::Array(vec![
+            PlistValue::Real(f64::NAN),
+            PlistValue::Real(f64::NEG_INFINITY),
+        ]);
+        let bytes =
+            plist_bytes(&value, PlistFormat::Binary, Span::test_data()).expect("failed to write");
+        let (parsed, _) = parse_plist(&bytes, Span::test_data()).expect("failed to parse");
+        let PlistValue::Array(items) = parsed else {
+            panic!("expected an array");
+        };
+        assert!(items[0].as_real().is_some_and(f64::is_nan));
+        assert_eq!(items[1], PlistValue::Real(f64::NEG_INFINITY));
+
+        // As CoreFoundation spells them in XML
+        let xml = b"<plist><array><real>nan</real><real>+infinity</real></array></plist>";
+        let (parsed, _) = parse_plist(xml, Span::test_data()).expect("failed to parse");
+        let PlistValue::Array(items) = parsed else {
+            panic!("expected an array");
+        };
+        assert!(items[0].as_real().is_some_and(f64::is_nan));
+        assert_eq!(items[1], PlistValue::Real(f64::INFINITY));
+    }
+
+    #[test]
+    fn test_parse_utf16() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\n<plist version=\"1.0\"><string>héllo</string></plist>";
+        let strings = "\u{feff}\"greeting\" = \"héllo\";";
+        let expected_strings = {
+            let mut dict = Dictionary::new();
+            dict.insert("greeting".to_string(), PlistValue::String("héllo".into()));
+            PlistValue::Dictionary(dict)
+        };
+        for (text, format, expected) in [
+            (
+                format!("\u{feff}{xml}"),
+                PlistFormat::Xml,
+                PlistValue::String("héllo".into()),
+            ),
+            (strings.to_string(), PlistFormat::OpenStep, expected_strings),
+        ] {
+            let bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            assert_eq!(PlistFormat::detect(&bytes), format);
+            let (value, _) = parse_plist(&bytes, Span::test_data()).expect("failed to parse");
+            assert_eq!(value, expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_bom() {
+        let mut dict = Dictionary::new();
+        dict.insert("a".to_string(), PlistValue::String("b".into()));
+        let expected = PlistValue::Dictionary(dict);
+        for format in [PlistFormat::Xml, PlistFormat::Binary, PlistFormat::OpenStep] {
+            let bytes = plist_bytes(&expected, format, Span::test_data()).expect("failed to write");
+            let bytes = [b"\xef\xbb\xbf", bytes.as_slice()].concat();
+            assert_eq!(PlistFormat::detect(&bytes), format);
+            let (value, _) = parse_plist(&bytes, Span::test_data()).expect("failed to parse");
+            assert_eq!(value, expected);
+        }
+    }
+}
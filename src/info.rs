@@ -0,0 +1,240 @@
+//! Document metadata without converting values to Nu.
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{Category, Example, LabeledError, Record, Signature, Span, Value as NuValue};
+use plist::Value as PlistValue;
+
+use crate::binary;
+use crate::format::{format_version, input_bytes, parse_plist, PlistFormat};
+use crate::nu_plist::{plist_error, ErrorKind, NuPlistPlugin};
+use crate::schema::value_type;
+
+pub struct PlistInfo;
+
+impl SimplePluginCommand for PlistInfo {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist info"
+    }
+
+    fn usage(&self) -> &str {
+        "Describe the structure of a plist document without converting it"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Returns the detected format, total size, object count and root type. For binary
+plists this is read from the trailer and offset table, and the version, offset
+and reference sizes and the number and size of string objects are included."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "open --raw ~/Library/Preferences/com.apple.finder.plist | plist info",
+            description: "Inspect a binary preferences file",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self)).category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let bytes = input_bytes(input, call.head)?;
+        match PlistFormat::detect(bytes) {
+            PlistFormat::Binary => binary_info(bytes, input.span(), call.head),
+            _ => text_info(bytes, input.span(), call.head),
+        }
+    }
+}
+
+fn binary_info(bytes: &[u8], input_span: Span, span: Span) -> Result<NuValue, LabeledError> {
+    let trailer = binary::read_trailer(bytes).ok_or_else(|| {
+        plist_error(
+            ErrorKind::Parse,
+            "Binary plist trailer is missing or corrupt".into(),
+            input_span,
+        )
+    })?;
+
+    let mut strings = 0;
+    let mut string_bytes: u64 = 0;
+    // Lengths come from the document, and no string is longer than it
+    let size = bytes.len() as u64;
+    for idx in 0..trailer.object_count {
+        let Some((marker, len, _)) = binary::object_offset(bytes, &trailer, idx)
+            .and_then(|o| binary::object_header(bytes, o))
+        else {
+            continue;
+        };
+        match marker >> 4 {
+            0x5 => {
+                strings += 1;
+                string_bytes = string_bytes.saturating_add(len.min(size));
+            }
+            0x6 => {
+                strings += 1;
+                string_bytes = string_bytes.saturating_add(len.saturating_mul(2).min(size));
+            }
+            _ => {}
+        }
+    }
+    let root_type = binary::object_offset(bytes, &trailer, trailer.root_object)
+        .and_then(|offset| bytes.get(offset))
+        .map(|marker| marker_type(*marker));
+
+    let mut record = Record::new();
+    record.push(
+        "format",
+        NuValue::string(PlistFormat::Binary.as_str(), span),
+    );
+    record.push(
+        "version",
+        binary::version(bytes)
+            .map(|v| NuValue::string(v, span))
+            .unwrap_or_else(|| NuValue::nothing(span)),
+    );
+    record.push("size", NuValue::filesize(bytes.len() as i64, span));
+    record.push("objects", NuValue::int(trailer.object_count as i64, span));
+    record.push(
+        "root_type",
+        root_type
+            .map(|t| NuValue::string(t, span))
+            .unwrap_or_else(|| NuValue::nothing(span)),
+    );
+    record.push(
+        "offset_size",
+        NuValue::int(trailer.offset_size.into(), span),
+    );
+    record.push("ref_size", NuValue::int(trailer.ref_size.into(), span));
+    record.push("strings", NuValue::int(strings, span));
+    record.push(
+        "string_bytes",
+        NuValue::filesize(i64::try_from(string_bytes).unwrap_or(i64::MAX), span),
+    );
+    Ok(NuValue::record(record, span))
+}
+
+fn text_info(bytes: &[u8], input_span: Span, span: Span) -> Result<NuValue, LabeledError> {
+    let (plist, _) = parse_plist(bytes, input_span)?;
+    // Read the same way as for `from plist --metadata`, so the two agree
+    let (format, version) = format_version(bytes);
+    let nothing = NuValue::nothing(span);
+
+    let mut record = Record::new();
+    record.push("format", NuValue::string(format.as_str(), span));
+    record.push(
+        "version",
+        version.map_or_else(|| nothing.clone(), |version| NuValue::string(version, span)),
+    );
+    record.push("size", NuValue::filesize(bytes.len() as i64, span));
+    record.push("objects", NuValue::int(count_objects(&plist), span));
+    record.push("root_type", NuValue::string(value_type(&plist), span));
+    record.push("offset_size", nothing.clone());
+    record.push("ref_size", nothing.clone());
+    record.push("strings", nothing.clone());
+    record.push("string_bytes", nothing);
+    Ok(NuValue::record(record, span))
+}
+
+/// Counts every value in a document, including dictionary keys as binary
+/// plists store them as objects too.
+fn count_objects(value: &PlistValue) -> i64 {
+    match value {
+        PlistValue::Array(arr) => 1 + arr.iter().map(count_objects).sum::<i64>(),
+        PlistValue::Dictionary(dict) => {
+            1 + dict.values().map(|v| 1 + count_objects(v)).sum::<i64>()
+        }
+        _ => 1,
+    }
+}
+
+/// The schema type name for a binary object marker.
+fn marker_type(marker: u8) -> &'static str {
+    match marker >> 4 {
+        0x0 if marker == 0x08 || marker == 0x09 => "bool",
+        0x1 => "int",
+        0x2 => "real",
+        0x3 => "date",
+        0x4 => "data",
+        0x5 | 0x6 => "string",
+        0x8 => "uid",
+        0xa => "array",
+        0xc => "array",
+        0xd => "dict",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_binary_info() {
+        let value = PlistValue::Array(vec![
+            PlistValue::String("abc".to_string()),
+            PlistValue::Integer(1.into()),
+        ]);
+        let mut bytes = Vec::new();
+        plist::to_writer_binary(&mut bytes, &value).expect("failed to write");
+
+        let info = binary_info(&bytes, Span::test_data(), Span::test_data()).expect("no info");
+        let info = info.as_record().expect("expected a record");
+        assert_eq!(info.get("objects"), Some(&NuValue::test_int(3)));
+        assert_eq!(info.get("strings"), Some(&NuValue::test_int(1)));
+        assert_eq!(info.get("root_type"), Some(&NuValue::test_string("array")));
+    }
+
+    #[test]
+    fn test_text_info() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict><key>a</key><integer>1</integer></dict></plist>"#;
+        let info = text_info(xml, Span::test_data(), Span::test_data()).expect("no info");
+        let info = info.as_record().expect("expected a record");
+        assert_eq!(info.get("format"), Some(&NuValue::test_string("xml")));
+        assert_eq!(info.get("version"), Some(&NuValue::test_string("1.0")));
+        assert_eq!(info.get("objects"), Some(&NuValue::test_int(3)));
+
+        let info = text_info(b"{ a = 1; }", Span::test_data(), Span::test_data()).expect("no info");
+        let info = info.as_record().expect("expected a record");
+        assert_eq!(info.get("format"), Some(&NuValue::test_string("openstep")));
+        assert_eq!(
+            info.get("version"),
+            Some(&NuValue::nothing(Span::test_data()))
+        );
+    }
+
+    #[test]
+    fn test_untrusted_string_length() {
+        // A UTF-16 string whose extended length claims u64::MAX characters
+        let mut bytes = b"bplist00\x6f\x13".to_vec();
+        bytes.extend([0xff; 8]);
+        bytes.push(8);
+        bytes.extend([0; 6]);
+        bytes.extend([1, 1]);
+        bytes.extend(1u64.to_be_bytes());
+        bytes.extend(0u64.to_be_bytes());
+        bytes.extend(18u64.to_be_bytes());
+
+        let info = binary_info(&bytes, Span::test_data(), Span::test_data()).expect("no info");
+        let info = info.as_record().expect("expected a record");
+        assert_eq!(info.get("strings"), Some(&NuValue::test_int(1)));
+        assert_eq!(
+            info.get("string_bytes"),
+            Some(&NuValue::filesize(bytes.len() as i64, Span::test_data()))
+        );
+    }
+
+    #[test]
+    fn test_corrupt_binary() {
+        assert!(binary_info(b"bplist00garbage", Span::test_data(), Span::test_data()).is_err());
+    }
+}
@@ -0,0 +1,1266 @@
+//! Encoding and decoding of NSKeyedArchiver archives.
+//!
+//! A keyed archive is a plist whose `$objects` array holds every archived
+//! object, with references between them stored as UIDs indexing into that
+//! array. The entry points are listed under `$top`.
+
+use std::collections::{HashMap, HashSet};
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{
+    engine::Closure, Category, Example, LabeledError, Record, Signature, Span, Spanned,
+    SyntaxShape, Value as NuValue,
+};
+use plist::{Dictionary, Integer, Uid, Value as PlistValue};
+use serde::{Deserialize, Serialize};
+
+use crate::binary::Limits;
+use crate::format::parse_input;
+use crate::nu_plist::{
+    apple_seconds, apple_time_to_date, convert_plist_value, plist_error, ErrorKind, NuPlistPlugin,
+    MAX_DEPTH,
+};
+
+pub struct FromNsKeyedArchive;
+pub struct ToNsKeyedArchive;
+
+impl SimplePluginCommand for FromNsKeyedArchive {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "from nskeyedarchive"
+    }
+
+    fn usage(&self) -> &str {
+        "Decode an NSKeyedArchiver archive into nested Nu values"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Objects referenced more than once are decoded at their first occurrence, tagged
+with an `$id` column, and replaced by `{$ref: <id>}` afterwards. Shared values
+that aren't records, such as arrays and data, are wrapped in a `{$id, value}`
+record instead. Reference cycles are broken the same way. Use --expand-shared to
+repeat shared objects instead, up to 20 million decoded objects.
+
+Decoding can be configured in `$env.config.plugins.plist.keyed_archive`:
+  allowed_classes: a list of class names; objects of any other class are not expanded
+  decoders: a record mapping class names to closures that receive the decoded object
+
+For example:
+  $env.config.plugins.plist = {
+    keyed_archive: {
+      decoders: { MyPoint: {|obj| $"($obj.x),($obj.y)" } }
+    }
+  }"#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "open --raw state.plist | from nskeyedarchive",
+                description: "Decode a keyed archive, resolving object references",
+                result: None,
+            },
+            Example {
+                example: "open --raw state.plist | from nskeyedarchive --allow-classes [NSDictionary NSArray]",
+                description: "Only expand objects of the given classes",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .named(
+                "allow-classes",
+                SyntaxShape::List(Box::new(SyntaxShape::String)),
+                "Only expand objects of these classes, overriding the plugin configuration",
+                Some('a'),
+            )
+            .switch(
+                "expand-shared",
+                "Repeat objects that are referenced more than once instead of emitting {$ref: n} markers",
+                Some('e'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let (plist, _) = parse_input(input, call.head)?;
+        let mut options = DecodeOptions::from_config(engine.get_plugin_config()?.as_ref())?;
+        if let Some(classes) = call.get_flag::<Vec<String>>("allow-classes")? {
+            options.allowed_classes = Some(classes.into_iter().collect());
+        }
+        options.expand_shared = call.has_flag("expand-shared")?;
+        decode_archive(&plist, &options, Some(engine), call.head)
+    }
+}
+
+/// Controls how archived objects are expanded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DecodeOptions {
+    /// When set, objects of classes not in the set are left unexpanded.
+    pub allowed_classes: Option<HashSet<String>>,
+    /// Closures used to decode objects of specific classes.
+    pub decoders: HashMap<String, Spanned<Closure>>,
+    /// Decode shared objects at every reference instead of only the first.
+    pub expand_shared: bool,
+    /// Bounds the number of objects decoded, which expanding shared objects
+    /// can multiply.
+    pub limits: Limits,
+}
+
+impl DecodeOptions {
+    /// Reads the `keyed_archive` section of the plugin configuration.
+    pub fn from_config(config: Option<&NuValue>) -> Result<Self, LabeledError> {
+        let mut options = DecodeOptions::default();
+        let Some(section) = config
+            .and_then(|c| c.as_record().ok())
+            .and_then(|c| c.get("keyed_archive"))
+        else {
+            return Ok(options);
+        };
+        let section = section.as_record()?;
+
+        if let Some(classes) = section.get("allowed_classes") {
+            options.allowed_classes = Some(
+                classes
+                    .as_list()?
+                    .iter()
+                    .map(|c| c.as_str().map(str::to_string))
+                    .collect::<Result<_, _>>()?,
+            );
+        }
+        if let Some(decoders) = section.get("decoders") {
+            for (class_name, decoder) in decoders.as_record()?.iter() {
+                let closure = decoder.as_closure().map_err(|_| {
+                    plist_error(
+                        ErrorKind::General,
+                        format!("Decoder for {class_name} must be a closure"),
+                        decoder.span(),
+                    )
+                })?;
+                options.decoders.insert(
+                    class_name.clone(),
+                    Spanned {
+                        item: closure.clone(),
+                        span: decoder.span(),
+                    },
+                );
+            }
+        }
+        Ok(options)
+    }
+
+    fn allows(&self, class_name: &str) -> bool {
+        match &self.allowed_classes {
+            Some(allowed) => allowed.contains(class_name),
+            None => true,
+        }
+    }
+}
+
+impl SimplePluginCommand for ToNsKeyedArchive {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "to nskeyedarchive"
+    }
+
+    fn usage(&self) -> &str {
+        "Encode Nu values as an NSKeyedArchiver binary plist"
+    }
+
+    fn extra_usage(&self) -> &str {
+        "Records with a `$class` column are archived as objects of that class, other records as NSDictionary and lists as NSArray."
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "{ name: \"demo\", items: [1 2 3] } | to nskeyedarchive",
+            description: "Archive a record as an NSDictionary",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self)).category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let archive = encode_archive(input)?;
+        let mut out = Vec::new();
+        plist::to_writer_binary(&mut out, &archive)
+            .map_err(|e| plist_error(ErrorKind::UnsupportedType, format!("{}", e), call.head))?;
+        Ok(NuValue::binary(out, call.head))
+    }
+}
+
+/// Returns true if the value looks like the root of a keyed archive.
+pub fn is_keyed_archive(plist_val: &PlistValue) -> bool {
+    plist_val.as_dictionary().is_some_and(|dict| {
+        dict.contains_key("$objects")
+            && dict.contains_key("$top")
+            && dict
+                .get("$archiver")
+                .and_then(PlistValue::as_string)
+                .is_some_and(|a| a == "NSKeyedArchiver")
+    })
+}
+
+/// Decodes a keyed archive. If `$top` only holds a `root` entry the decoded
+/// root object is returned directly, otherwise a record of every top entry.
+///
+/// The engine is needed to run custom decoders, which are skipped without it.
+pub fn decode_archive(
+    plist_val: &PlistValue,
+    options: &DecodeOptions,
+    engine: Option<&EngineInterface>,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    if !is_keyed_archive(plist_val) {
+        return Err(plist_error(
+            ErrorKind::Parse,
+            "Input is not an NSKeyedArchiver archive".to_string(),
+            span,
+        ));
+    }
+    let dict = plist_val.as_dictionary().ok_or_else(|| {
+        plist_error(
+            ErrorKind::Parse,
+            "Archive root must be a dictionary".into(),
+            span,
+        )
+    })?;
+    let objects = dict
+        .get("$objects")
+        .and_then(PlistValue::as_array)
+        .ok_or_else(|| {
+            plist_error(
+                ErrorKind::Parse,
+                "Archive $objects must be an array".into(),
+                span,
+            )
+        })?;
+    let top = dict
+        .get("$top")
+        .and_then(PlistValue::as_dictionary)
+        .ok_or_else(|| {
+            plist_error(
+                ErrorKind::Parse,
+                "Archive $top must be a dictionary".into(),
+                span,
+            )
+        })?;
+
+    let mut decoder = ArchiveDecoder {
+        objects,
+        options,
+        engine,
+        ref_counts: count_references(objects, top),
+        decoded: HashSet::new(),
+        in_progress: HashSet::new(),
+        inline: 0,
+        count: 0,
+        span,
+    };
+
+    if top.len() == 1 {
+        if let Some(root) = top.get("root") {
+            return decoder.decode_value(root);
+        }
+    }
+    let mut record = Record::new();
+    for (key, value) in top {
+        record.push(key.clone(), decoder.decode_value(value)?);
+    }
+    Ok(NuValue::record(record, span))
+}
+
+/// Builds a keyed archive with the given value as its root object.
+pub fn encode_archive(root: &NuValue) -> Result<PlistValue, LabeledError> {
+    let mut encoder = ArchiveEncoder {
+        objects: vec![PlistValue::String("$null".to_string())],
+        classes: HashMap::new(),
+        strings: HashMap::new(),
+    };
+    let root = encoder.encode_object(root)?;
+
+    let mut top = Dictionary::new();
+    top.insert("root".to_string(), PlistValue::Uid(root));
+    let mut dict = Dictionary::new();
+    dict.insert(
+        "$archiver".to_string(),
+        PlistValue::String("NSKeyedArchiver".to_string()),
+    );
+    dict.insert("$version".to_string(), PlistValue::Integer(100000.into()));
+    dict.insert("$top".to_string(), PlistValue::Dictionary(top));
+    dict.insert("$objects".to_string(), PlistValue::Array(encoder.objects));
+    Ok(PlistValue::Dictionary(dict))
+}
+
+struct ArchiveEncoder {
+    objects: Vec<PlistValue>,
+    classes: HashMap<String, Uid>,
+    /// Equal strings are archived once, as NSKeyedArchiver does.
+    strings: HashMap<String, Uid>,
+}
+
+impl ArchiveEncoder {
+    fn push(&mut self, object: PlistValue) -> Uid {
+        self.objects.push(object);
+        Uid::new(self.objects.len() as u64 - 1)
+    }
+
+    fn class_uid(&mut self, name: &str) -> Uid {
+        if let Some(uid) = self.classes.get(name) {
+            return *uid;
+        }
+        let mut class = Dictionary::new();
+        class.insert(
+            "$classname".to_string(),
+            PlistValue::String(name.to_string()),
+        );
+        class.insert(
+            "$classes".to_string(),
+            PlistValue::Array(vec![
+                PlistValue::String(name.to_string()),
+                PlistValue::String("NSObject".to_string()),
+            ]),
+        );
+        let uid = self.push(PlistValue::Dictionary(class));
+        self.classes.insert(name.to_string(), uid);
+        uid
+    }
+
+    /// Archives a value as an entry of `$objects` and returns its reference.
+    fn encode_object(&mut self, value: &NuValue) -> Result<Uid, LabeledError> {
+        let object = match value {
+            NuValue::Nothing { .. } => return Ok(Uid::new(0)),
+            NuValue::String { val, .. } => {
+                if let Some(uid) = self.strings.get(val) {
+                    return Ok(*uid);
+                }
+                let uid = self.push(PlistValue::String(val.clone()));
+                self.strings.insert(val.clone(), uid);
+                return Ok(uid);
+            }
+            NuValue::Bool { val, .. } => PlistValue::Boolean(*val),
+            NuValue::Int { val, .. } => PlistValue::Integer(Integer::from(*val)),
+            NuValue::Float { val, .. } => PlistValue::Real(*val),
+            NuValue::Binary { val, .. } => PlistValue::Data(val.clone()),
+            NuValue::Date { val, .. } => {
+                let seconds = apple_seconds(val);
+                let mut dict = Dictionary::new();
+                dict.insert("NS.time".to_string(), PlistValue::Real(seconds));
+                dict.insert(
+                    "$class".to_string(),
+                    PlistValue::Uid(self.class_uid("NSDate")),
+                );
+                PlistValue::Dictionary(dict)
+            }
+            NuValue::List { vals, .. } => {
+                // Reserve the slot first so containers precede their contents
+                let uid = self.push(PlistValue::Boolean(false));
+                let refs = vals
+                    .iter()
+                    .map(|v| self.encode_object(v).map(PlistValue::Uid))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut dict = Dictionary::new();
+                dict.insert("NS.objects".to_string(), PlistValue::Array(refs));
+                dict.insert(
+                    "$class".to_string(),
+                    PlistValue::Uid(self.class_uid("NSArray")),
+                );
+                self.objects[uid.get() as usize] = PlistValue::Dictionary(dict);
+                return Ok(uid);
+            }
+            NuValue::Record { val, .. } => {
+                let uid = self.push(PlistValue::Boolean(false));
+                let object = match val.get("$class") {
+                    Some(class) => self.encode_custom_object(class, val)?,
+                    None => self.encode_dictionary(val)?,
+                };
+                self.objects[uid.get() as usize] = object;
+                return Ok(uid);
+            }
+            _ => {
+                return Err(plist_error(
+                    ErrorKind::UnsupportedType,
+                    format!("{} values cannot be archived", value.get_type()),
+                    value.span(),
+                ))
+            }
+        };
+        Ok(self.push(object))
+    }
+
+    fn encode_dictionary(&mut self, record: &Record) -> Result<PlistValue, LabeledError> {
+        let mut keys = Vec::with_capacity(record.len());
+        let mut values = Vec::with_capacity(record.len());
+        for (key, value) in record.iter() {
+            keys.push(PlistValue::Uid(
+                self.encode_object(&NuValue::string(key.clone(), value.span()))?,
+            ));
+            values.push(PlistValue::Uid(self.encode_object(value)?));
+        }
+        let mut dict = Dictionary::new();
+        dict.insert("NS.keys".to_string(), PlistValue::Array(keys));
+        dict.insert("NS.objects".to_string(), PlistValue::Array(values));
+        dict.insert(
+            "$class".to_string(),
+            PlistValue::Uid(self.class_uid("NSDictionary")),
+        );
+        Ok(PlistValue::Dictionary(dict))
+    }
+
+    /// Archives a record with a `$class` column as an object of that class.
+    /// Scalars are stored inline as NSCoder does, everything else by reference.
+    fn encode_custom_object(
+        &mut self,
+        class: &NuValue,
+        record: &Record,
+    ) -> Result<PlistValue, LabeledError> {
+        let class_name = class.as_str().map_err(|_| {
+            plist_error(
+                ErrorKind::UnsupportedType,
+                "$class must be a string".to_string(),
+                class.span(),
+            )
+        })?;
+        let mut dict = Dictionary::new();
+        for (key, value) in record.iter() {
+            if key == "$class" {
+                continue;
+            }
+            let encoded = match value {
+                NuValue::Bool { val, .. } => PlistValue::Boolean(*val),
+                NuValue::Int { val, .. } => PlistValue::Integer(Integer::from(*val)),
+                NuValue::Float { val, .. } => PlistValue::Real(*val),
+                _ => PlistValue::Uid(self.encode_object(value)?),
+            };
+            dict.insert(key.clone(), encoded);
+        }
+        dict.insert(
+            "$class".to_string(),
+            PlistValue::Uid(self.class_uid(class_name)),
+        );
+        Ok(PlistValue::Dictionary(dict))
+    }
+}
+
+struct ArchiveDecoder<'a> {
+    objects: &'a [PlistValue],
+    options: &'a DecodeOptions,
+    engine: Option<&'a EngineInterface>,
+    /// How often each object is referenced from other objects.
+    ref_counts: Vec<usize>,
+    /// Shareable objects that have already been emitted once.
+    decoded: HashSet<usize>,
+    /// Objects currently being decoded, used to detect reference cycles. Its
+    /// size is how deeply objects are nested at the moment.
+    in_progress: HashSet<usize>,
+    /// Above zero while decoding values that must not be replaced by
+    /// references, such as dictionary keys.
+    inline: usize,
+    /// Objects decoded so far.
+    count: u64,
+    span: Span,
+}
+
+impl<'a> ArchiveDecoder<'a> {
+    fn decode_value(&mut self, value: &PlistValue) -> Result<NuValue, LabeledError> {
+        match value {
+            PlistValue::Uid(uid) => self.decode_object(uid.get() as usize),
+            PlistValue::Array(values) => Ok(NuValue::list(
+                values
+                    .iter()
+                    .map(|v| self.decode_value(v))
+                    .collect::<Result<_, _>>()?,
+                self.span,
+            )),
+            PlistValue::Dictionary(dict) => self.decode_dict(dict),
+            _ => convert_plist_value(value, self.span),
+        }
+    }
+
+    fn decode_object(&mut self, idx: usize) -> Result<NuValue, LabeledError> {
+        let object = self.objects.get(idx).ok_or_else(|| {
+            plist_error(
+                ErrorKind::Parse,
+                format!("Archive references object {idx} which does not exist"),
+                self.span,
+            )
+        })?;
+        if let PlistValue::String(s) = object {
+            if s == "$null" {
+                return Ok(NuValue::nothing(self.span));
+            }
+        }
+        // Plain strings and numbers are cheap to repeat, only collections
+        // and data are worth sharing.
+        let shareable = matches!(
+            object,
+            PlistValue::Dictionary(_) | PlistValue::Array(_) | PlistValue::Data(_)
+        );
+        let expand = self.options.expand_shared || self.inline > 0;
+        if self.in_progress.contains(&idx) || (shareable && !expand && self.decoded.contains(&idx))
+        {
+            return Ok(reference(idx, self.span));
+        }
+        self.count += 1;
+        if self.count > self.options.limits.max_objects {
+            return Err(plist_error(
+                ErrorKind::Overflow,
+                format!(
+                    "Archive decodes to more than {} objects",
+                    self.options.limits.max_objects
+                ),
+                self.span,
+            ));
+        }
+        // A chain of objects each referring to the next would otherwise
+        // overflow the stack
+        if self.in_progress.len() >= MAX_DEPTH {
+            return Err(plist_error(
+                ErrorKind::Overflow,
+                format!("Archive objects are nested more than {MAX_DEPTH} levels deep"),
+                self.span,
+            ));
+        }
+
+        self.in_progress.insert(idx);
+        let decoded = self.decode_value(object);
+        self.in_progress.remove(&idx);
+        if !shareable || expand {
+            return decoded;
+        }
+        self.decoded.insert(idx);
+
+        // Tag the first occurrence so later references can be matched to it
+        let decoded = decoded?;
+        let is_shared = self.ref_counts.get(idx).is_some_and(|count| *count > 1);
+        if !is_shared {
+            return Ok(decoded);
+        }
+        let mut record = Record::new();
+        record.push("$id", NuValue::int(idx as i64, self.span));
+        match decoded {
+            NuValue::Record { val, .. } => record.extend(val.into_owned()),
+            other => record.push("value", other),
+        }
+        Ok(NuValue::record(record, self.span))
+    }
+
+    /// Decodes an archived object dictionary, replacing the `$class`
+    /// reference with the class name.
+    fn decode_dict(&mut self, dict: &Dictionary) -> Result<NuValue, LabeledError> {
+        let class_name = self.class_name(dict);
+        let mut record = Record::new();
+        if let Some(class_name) = class_name {
+            record.push("$class", NuValue::string(class_name, self.span));
+            if !self.options.allows(class_name) {
+                record.push("$disallowed", NuValue::bool(true, self.span));
+                return Ok(NuValue::record(record, self.span));
+            }
+        }
+        let builtin = self.builtin_class(dict);
+        for (key, value) in dict {
+            if key == "$class" {
+                continue;
+            }
+            // Keys become column names, so they are decoded in full even if
+            // the same string is shared elsewhere
+            let is_keys =
+                key == "NS.keys" && matches!(builtin, Some("NSDictionary" | "NSMutableDictionary"));
+            self.inline += usize::from(is_keys);
+            let decoded = self.decode_value(value);
+            self.inline -= usize::from(is_keys);
+            record.push(key.clone(), decoded?);
+        }
+        let decoded = NuValue::record(record, self.span);
+
+        match (
+            class_name.and_then(|c| self.options.decoders.get(c)),
+            self.engine,
+        ) {
+            (Some(decoder), Some(engine)) => {
+                Ok(engine.eval_closure(decoder, vec![decoded.clone()], Some(decoded))?)
+            }
+            _ => match builtin {
+                Some(builtin) => self.decode_builtin(builtin, decoded),
+                None => Ok(decoded),
+            },
+        }
+    }
+
+    /// Finds the closest Foundation class in the object's class hierarchy
+    /// that has a built-in decoder.
+    fn builtin_class(&self, dict: &Dictionary) -> Option<&'a str> {
+        let uid = dict.get("$class")?.as_uid()?;
+        let classes = self
+            .objects
+            .get(uid.get() as usize)?
+            .as_dictionary()?
+            .get("$classes")?
+            .as_array()?;
+        classes
+            .iter()
+            .filter_map(PlistValue::as_string)
+            .find(|c| BUILTIN_CLASSES.contains(c))
+    }
+
+    /// Converts a generically decoded Foundation object into a plain Nu value.
+    fn decode_builtin(&self, class_name: &str, decoded: NuValue) -> Result<NuValue, LabeledError> {
+        let span = self.span;
+        let record = decoded.into_record()?;
+        let field = |name: &str| record.get(name).cloned().unwrap_or(NuValue::nothing(span));
+
+        let value = match class_name {
+            "NSDictionary" | "NSMutableDictionary" => {
+                let keys = field("NS.keys").into_list()?;
+                let values = field("NS.objects").into_list()?;
+                let mut out = Record::new();
+                for (key, value) in keys.into_iter().zip(values) {
+                    out.push(key.coerce_into_string()?, value);
+                }
+                NuValue::record(out, span)
+            }
+            "NSArray"
+            | "NSMutableArray"
+            | "NSSet"
+            | "NSMutableSet"
+            | "NSOrderedSet"
+            | "NSMutableOrderedSet" => NuValue::list(field("NS.objects").into_list()?, span),
+            "NSString" | "NSMutableString" => field("NS.string"),
+            "NSAttributedString" | "NSMutableAttributedString" => field("NSString"),
+            "NSData" | "NSMutableData" => field("NS.data"),
+            "NSDate" => {
+                let seconds = field("NS.time").as_float()?;
+                NuValue::date(apple_time_to_date(seconds, span)?, span)
+            }
+            "NSUUID" => {
+                let bytes = field("NS.uuidbytes");
+                NuValue::string(format_uuid(bytes.as_binary()?), span)
+            }
+            "NSURL" => {
+                let relative = field("NS.relative").coerce_into_string()?;
+                match field("NS.base") {
+                    NuValue::Nothing { .. } => NuValue::string(relative, span),
+                    base => NuValue::string(
+                        format!(
+                            "{}/{}",
+                            base.coerce_into_string()?.trim_end_matches('/'),
+                            relative.trim_start_matches('/')
+                        ),
+                        span,
+                    ),
+                }
+            }
+            "NSValue" => decode_ns_value(&record, span),
+            _ => NuValue::record(record, span),
+        };
+        Ok(value)
+    }
+
+    fn class_name(&self, dict: &Dictionary) -> Option<&'a str> {
+        let uid = dict.get("$class")?.as_uid()?;
+        self.objects
+            .get(uid.get() as usize)?
+            .as_dictionary()?
+            .get("$classname")?
+            .as_string()
+    }
+}
+
+/// Placeholder emitted in place of an already decoded or cyclic object.
+fn reference(idx: usize, span: Span) -> NuValue {
+    let mut record = Record::new();
+    record.push("$ref", NuValue::int(idx as i64, span));
+    NuValue::record(record, span)
+}
+
+/// Counts the references to each object from other objects and `$top`,
+/// excluding `$class` references.
+fn count_references(objects: &[PlistValue], top: &Dictionary) -> Vec<usize> {
+    fn visit(value: &PlistValue, counts: &mut [usize]) {
+        match value {
+            PlistValue::Uid(uid) => {
+                if let Some(count) = counts.get_mut(uid.get() as usize) {
+                    *count += 1;
+                }
+            }
+            PlistValue::Array(values) => values.iter().for_each(|v| visit(v, counts)),
+            PlistValue::Dictionary(dict) => dict
+                .iter()
+                .filter(|(key, _)| *key != "$class")
+                .for_each(|(_, v)| visit(v, counts)),
+            _ => {}
+        }
+    }
+
+    let mut counts = vec![0; objects.len()];
+    for object in objects.iter().chain(top.values()) {
+        visit(object, &mut counts);
+    }
+    counts
+}
+
+/// Foundation classes understood without any configuration.
+const BUILTIN_CLASSES: &[&str] = &[
+    "NSDictionary",
+    "NSMutableDictionary",
+    "NSArray",
+    "NSMutableArray",
+    "NSSet",
+    "NSMutableSet",
+    "NSOrderedSet",
+    "NSMutableOrderedSet",
+    "NSString",
+    "NSMutableString",
+    "NSAttributedString",
+    "NSMutableAttributedString",
+    "NSData",
+    "NSMutableData",
+    "NSDate",
+    "NSUUID",
+    "NSURL",
+    "NSValue",
+];
+
+fn format_uuid(bytes: &[u8]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{b:02X}")).collect();
+    if hex.len() != 32 {
+        return hex;
+    }
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Decodes the geometry types NSValue archives, keeping anything else as is.
+fn decode_ns_value(record: &Record, span: Span) -> NuValue {
+    let numbers = |name: &str| -> Vec<NuValue> {
+        record
+            .get(name)
+            .and_then(|v| v.as_str().ok())
+            .map(|s| {
+                s.split(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == 'e'))
+                    .filter_map(|n| n.parse::<f64>().ok())
+                    .map(|n| NuValue::float(n, span))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let named = |names: &[&str], values: Vec<NuValue>| -> NuValue {
+        NuValue::record(
+            names
+                .iter()
+                .map(|n| n.to_string())
+                .zip(values)
+                .collect::<Record>(),
+            span,
+        )
+    };
+    let field = |name: &str| record.get(name).cloned().unwrap_or(NuValue::nothing(span));
+
+    match record.get("NS.special").and_then(|v| v.as_int().ok()) {
+        Some(1) => named(&["x", "y"], numbers("NS.pointval")),
+        Some(2) => named(&["width", "height"], numbers("NS.sizeval")),
+        Some(3) => named(&["x", "y", "width", "height"], numbers("NS.rectval")),
+        Some(4) => named(
+            &["location", "length"],
+            vec![field("NS.rangeval.location"), field("NS.rangeval.length")],
+        ),
+        _ => NuValue::record(record.clone(), span),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nu_protocol::record;
+
+    fn archive(objects: Vec<PlistValue>) -> PlistValue {
+        let mut top = Dictionary::new();
+        top.insert("root".to_string(), PlistValue::Uid(Uid::new(1)));
+        let mut dict = Dictionary::new();
+        dict.insert(
+            "$archiver".to_string(),
+            PlistValue::String("NSKeyedArchiver".to_string()),
+        );
+        dict.insert("$version".to_string(), PlistValue::Integer(100000.into()));
+        dict.insert("$top".to_string(), PlistValue::Dictionary(top));
+        dict.insert("$objects".to_string(), PlistValue::Array(objects));
+        PlistValue::Dictionary(dict)
+    }
+
+    fn class(name: &str) -> PlistValue {
+        let mut dict = Dictionary::new();
+        dict.insert(
+            "$classname".to_string(),
+            PlistValue::String(name.to_string()),
+        );
+        dict.insert(
+            "$classes".to_string(),
+            PlistValue::Array(vec![PlistValue::String(name.to_string())]),
+        );
+        PlistValue::Dictionary(dict)
+    }
+
+    #[test]
+    fn test_decode_resolves_uids() {
+        let mut root = Dictionary::new();
+        root.insert("$class".to_string(), PlistValue::Uid(Uid::new(3)));
+        root.insert("title".to_string(), PlistValue::Uid(Uid::new(2)));
+        root.insert("missing".to_string(), PlistValue::Uid(Uid::new(0)));
+        let plist = archive(vec![
+            PlistValue::String("$null".to_string()),
+            PlistValue::Dictionary(root),
+            PlistValue::String("hello".to_string()),
+            class("MyObject"),
+        ]);
+
+        let decoded = decode_archive(&plist, &DecodeOptions::default(), None, Span::test_data())
+            .expect("failed to decode");
+        let record = decoded.as_record().expect("expected a record");
+        assert_eq!(
+            record.get("$class"),
+            Some(&NuValue::string("MyObject", Span::test_data()))
+        );
+        assert_eq!(
+            record.get("title"),
+            Some(&NuValue::string("hello", Span::test_data()))
+        );
+        assert_eq!(
+            record.get("missing"),
+            Some(&NuValue::nothing(Span::test_data()))
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_non_archive() {
+        let plist = PlistValue::Dictionary(Dictionary::new());
+        assert!(
+            decode_archive(&plist, &DecodeOptions::default(), None, Span::test_data()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_encode_dictionary() {
+        let value = NuValue::test_record(record! {
+            "name" => NuValue::test_string("demo"),
+            "alias" => NuValue::test_string("demo"),
+            "items" => NuValue::test_list(vec![NuValue::test_int(1)]),
+        });
+        let archive = encode_archive(&value).expect("failed to encode");
+        assert!(is_keyed_archive(&archive));
+
+        let objects = archive
+            .as_dictionary()
+            .and_then(|d| d.get("$objects"))
+            .and_then(PlistValue::as_array)
+            .expect("expected $objects");
+        // equal strings are only archived once
+        let demo_count = objects
+            .iter()
+            .filter(|o| o.as_string() == Some("demo"))
+            .count();
+        assert_eq!(demo_count, 1);
+
+        let decoded = decode_archive(&archive, &DecodeOptions::default(), None, Span::test_data())
+            .expect("failed to decode");
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn test_encode_custom_object() {
+        let value = NuValue::test_record(record! {
+            "$class" => NuValue::test_string("MyObject"),
+            "count" => NuValue::test_int(3),
+            "title" => NuValue::test_string("hello"),
+        });
+        let archive = encode_archive(&value).expect("failed to encode");
+        let decoded = decode_archive(&archive, &DecodeOptions::default(), None, Span::test_data())
+            .expect("failed to decode");
+        assert_eq!(decoded, value);
+
+        let mut out = Vec::new();
+        plist::to_writer_binary(&mut out, &archive).expect("failed to write binary plist");
+        assert!(out.starts_with(b"bplist00"));
+    }
+
+    #[test]
+    fn test_decode_allowlist() {
+        let mut root = Dictionary::new();
+        root.insert("$class".to_string(), PlistValue::Uid(Uid::new(2)));
+        root.insert("secret".to_string(), PlistValue::Integer(1.into()));
+        let plist = archive(vec![
+            PlistValue::String("$null".to_string()),
+            PlistValue::Dictionary(root),
+            class("Untrusted"),
+        ]);
+        let options = DecodeOptions {
+            allowed_classes: Some(HashSet::from(["NSDictionary".to_string()])),
+            ..Default::default()
+        };
+        let decoded =
+            decode_archive(&plist, &options, None, Span::test_data()).expect("failed to decode");
+        assert_eq!(
+            decoded,
+            NuValue::test_record(record! {
+                "$class" => NuValue::test_string("Untrusted"),
+                "$disallowed" => NuValue::bool(true, Span::test_data()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_options_from_config() {
+        let config = NuValue::test_record(record! {
+            "keyed_archive" => NuValue::test_record(record! {
+                "allowed_classes" => NuValue::test_list(vec![NuValue::test_string("NSArray")]),
+            }),
+        });
+        let options = DecodeOptions::from_config(Some(&config)).expect("valid config");
+        assert!(options.allows("NSArray"));
+        assert!(!options.allows("NSDictionary"));
+        assert!(DecodeOptions::from_config(None)
+            .expect("empty config")
+            .allows("NSDictionary"));
+    }
+
+    #[test]
+    fn test_decode_foundation_classes() {
+        let object = |class: u64, key: &str, value: PlistValue| {
+            let mut dict = Dictionary::new();
+            dict.insert("$class".to_string(), PlistValue::Uid(Uid::new(class)));
+            dict.insert(key.to_string(), value);
+            PlistValue::Dictionary(dict)
+        };
+        let mut root = Dictionary::new();
+        root.insert("$class".to_string(), PlistValue::Uid(Uid::new(5)));
+        root.insert("date".to_string(), PlistValue::Uid(Uid::new(2)));
+        root.insert("uuid".to_string(), PlistValue::Uid(Uid::new(3)));
+        root.insert("url".to_string(), PlistValue::Uid(Uid::new(4)));
+        let plist = archive(vec![
+            PlistValue::String("$null".to_string()),
+            PlistValue::Dictionary(root),
+            object(6, "NS.time", PlistValue::Real(1.5)),
+            object(7, "NS.uuidbytes", PlistValue::Data((0u8..16).collect())),
+            object(
+                8,
+                "NS.relative",
+                PlistValue::String("https://example.com".into()),
+            ),
+            class("MyObject"),
+            class("NSDate"),
+            class("NSUUID"),
+            class("NSURL"),
+        ]);
+
+        let decoded = decode_archive(&plist, &DecodeOptions::default(), None, Span::test_data())
+            .expect("failed to decode");
+        let record = decoded.as_record().expect("expected a record");
+        let date = record
+            .get("date")
+            .and_then(|d| match d {
+                NuValue::Date { val, .. } => Some(*val),
+                _ => None,
+            })
+            .expect("expected a date");
+        assert_eq!(date.to_rfc3339(), "2001-01-01T00:00:01.500+00:00");
+        assert_eq!(
+            record.get("uuid"),
+            Some(&NuValue::test_string(
+                "00010203-0405-0607-0809-0A0B0C0D0E0F"
+            ))
+        );
+        assert_eq!(
+            record.get("url"),
+            Some(&NuValue::test_string("https://example.com"))
+        );
+    }
+
+    #[test]
+    fn test_decode_ns_value_rect() {
+        let record = record! {
+            "NS.special" => NuValue::test_int(3),
+            "NS.rectval" => NuValue::test_string("{{1, 2}, {30.5, 40}}"),
+        };
+        assert_eq!(
+            decode_ns_value(&record, Span::test_data()),
+            NuValue::test_record(record! {
+                "x" => NuValue::float(1.0, Span::test_data()),
+                "y" => NuValue::float(2.0, Span::test_data()),
+                "width" => NuValue::float(30.5, Span::test_data()),
+                "height" => NuValue::float(40.0, Span::test_data()),
+            })
+        );
+    }
+
+    fn node(class: u64, next: u64) -> PlistValue {
+        let mut dict = Dictionary::new();
+        dict.insert("$class".to_string(), PlistValue::Uid(Uid::new(class)));
+        dict.insert("next".to_string(), PlistValue::Uid(Uid::new(next)));
+        PlistValue::Dictionary(dict)
+    }
+
+    #[test]
+    fn test_decode_breaks_cycles() {
+        // 1 -> 2 -> 1
+        let plist = archive(vec![
+            PlistValue::String("$null".to_string()),
+            node(3, 2),
+            node(3, 1),
+            class("Node"),
+        ]);
+        let decoded = decode_archive(&plist, &DecodeOptions::default(), None, Span::test_data())
+            .expect("failed to decode");
+        assert_eq!(
+            decoded,
+            NuValue::test_record(record! {
+                "$id" => NuValue::test_int(1),
+                "$class" => NuValue::test_string("Node"),
+                "next" => NuValue::test_record(record! {
+                    "$class" => NuValue::test_string("Node"),
+                    "next" => NuValue::test_record(record! { "$ref" => NuValue::test_int(1) }),
+                }),
+            })
+        );
+    }
+
+    fn items(decoded: &NuValue) -> &[NuValue] {
+        decoded
+            .as_record()
+            .ok()
+            .and_then(|r| r.get("items"))
+            .and_then(|i| i.as_list().ok())
+            .expect("expected items")
+    }
+
+    #[test]
+    fn test_decode_shared_array_and_data() {
+        let object = |class: u64, key: &str, value: PlistValue| {
+            let mut dict = Dictionary::new();
+            dict.insert("$class".to_string(), PlistValue::Uid(Uid::new(class)));
+            dict.insert(key.to_string(), value);
+            PlistValue::Dictionary(dict)
+        };
+        let mut root = Dictionary::new();
+        root.insert(
+            "items".to_string(),
+            PlistValue::Array(
+                [2, 2, 3, 3, 4, 4]
+                    .into_iter()
+                    .map(|i| PlistValue::Uid(Uid::new(i)))
+                    .collect(),
+            ),
+        );
+        let plist = archive(vec![
+            PlistValue::String("$null".to_string()),
+            PlistValue::Dictionary(root),
+            object(
+                5,
+                "NS.objects",
+                PlistValue::Array(vec![PlistValue::Uid(Uid::new(0))]),
+            ),
+            object(6, "NS.data", PlistValue::Data(vec![1, 2])),
+            PlistValue::Data(vec![3]),
+            class("NSArray"),
+            class("NSData"),
+        ]);
+
+        let decoded = decode_archive(&plist, &DecodeOptions::default(), None, Span::test_data())
+            .expect("failed to decode");
+        let reference = |id| NuValue::test_record(record! { "$ref" => NuValue::test_int(id) });
+        assert_eq!(
+            items(&decoded),
+            [
+                NuValue::test_record(record! {
+                    "$id" => NuValue::test_int(2),
+                    "value" => NuValue::test_list(vec![NuValue::test_nothing()]),
+                }),
+                reference(2),
+                NuValue::test_record(record! {
+                    "$id" => NuValue::test_int(3),
+                    "value" => NuValue::test_binary(vec![1, 2]),
+                }),
+                reference(3),
+                NuValue::test_record(record! {
+                    "$id" => NuValue::test_int(4),
+                    "value" => NuValue::test_binary(vec![3]),
+                }),
+                reference(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_shared_dictionary_key() {
+        let mut string = Dictionary::new();
+        string.insert("$class".to_string(), PlistValue::Uid(Uid::new(5)));
+        string.insert("NS.string".to_string(), PlistValue::String("name".into()));
+        let mut dict = Dictionary::new();
+        dict.insert("$class".to_string(), PlistValue::Uid(Uid::new(4)));
+        dict.insert(
+            "NS.keys".to_string(),
+            PlistValue::Array(vec![PlistValue::Uid(Uid::new(3))]),
+        );
+        dict.insert(
+            "NS.objects".to_string(),
+            PlistValue::Array(vec![PlistValue::Uid(Uid::new(3))]),
+        );
+        let mut root = Dictionary::new();
+        root.insert(
+            "items".to_string(),
+            PlistValue::Array(vec![
+                PlistValue::Uid(Uid::new(3)),
+                PlistValue::Uid(Uid::new(2)),
+            ]),
+        );
+        let plist = archive(vec![
+            PlistValue::String("$null".to_string()),
+            PlistValue::Dictionary(root),
+            PlistValue::Dictionary(dict),
+            PlistValue::Dictionary(string),
+            class("NSDictionary"),
+            class("NSMutableString"),
+        ]);
+
+        let decoded = decode_archive(&plist, &DecodeOptions::default(), None, Span::test_data())
+            .expect("failed to decode");
+        assert_eq!(
+            items(&decoded)[1],
+            NuValue::test_record(record! {
+                "name" => NuValue::test_record(record! { "$ref" => NuValue::test_int(3) }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_expansion_limit() {
+        // Each node refers to the next twice, doubling with every level
+        let pair = |next: u64| {
+            let mut dict = Dictionary::new();
+            dict.insert(
+                "items".to_string(),
+                PlistValue::Array(vec![
+                    PlistValue::Uid(Uid::new(next)),
+                    PlistValue::Uid(Uid::new(next)),
+                ]),
+            );
+            PlistValue::Dictionary(dict)
+        };
+        let mut objects = vec![PlistValue::String("$null".to_string())];
+        objects.extend((1..64).map(|i| pair(i + 1)));
+        objects.push(PlistValue::String("end".to_string()));
+        let plist = archive(objects);
+
+        let decoded = decode_archive(&plist, &DecodeOptions::default(), None, Span::test_data())
+            .expect("failed to decode");
+        assert_eq!(
+            items(&decoded)[1],
+            NuValue::test_record(record! { "$ref" => NuValue::test_int(2) })
+        );
+        let options = DecodeOptions {
+            expand_shared: true,
+            limits: Limits {
+                max_objects: 1000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let error = decode_archive(&plist, &options, None, Span::test_data())
+            .expect_err("expected an error");
+        assert!(error.labels[0].text.contains("more than 1000 objects"));
+    }
+
+    #[test]
+    fn test_decode_depth_limit() {
+        // Each node refers to the next, far deeper than any real archive
+        let mut objects = vec![PlistValue::String("$null".to_string())];
+        objects.extend((1..100_000).map(|i| node(100_001, i + 1)));
+        objects.push(PlistValue::String("end".to_string()));
+        objects.push(class("Node"));
+
+        let error = decode_archive(
+            &archive(objects),
+            &DecodeOptions::default(),
+            None,
+            Span::test_data(),
+        )
+        .expect_err("expected an error");
+        assert_eq!(error.code.as_deref(), Some("nu_plugin_plist::overflow"));
+    }
+
+    #[test]
+    fn test_decode_shared_references() {
+        let mut root = Dictionary::new();
+        root.insert(
+            "items".to_string(),
+            PlistValue::Array(vec![
+                PlistValue::Uid(Uid::new(2)),
+                PlistValue::Uid(Uid::new(2)),
+            ]),
+        );
+        let mut shared = Dictionary::new();
+        shared.insert("value".to_string(), PlistValue::Integer(7.into()));
+        let plist = archive(vec![
+            PlistValue::String("$null".to_string()),
+            PlistValue::Dictionary(root),
+            PlistValue::Dictionary(shared),
+        ]);
+
+        let decoded = decode_archive(&plist, &DecodeOptions::default(), None, Span::test_data())
+            .expect("failed to decode");
+        let items = decoded
+            .as_record()
+            .ok()
+            .and_then(|r| r.get("items"))
+            .and_then(|i| i.as_list().ok())
+            .expect("expected items");
+        assert_eq!(
+            items[0],
+            NuValue::test_record(record! {
+                "$id" => NuValue::test_int(2),
+                "value" => NuValue::test_int(7),
+            })
+        );
+        assert_eq!(
+            items[1],
+            NuValue::test_record(record! { "$ref" => NuValue::test_int(2) })
+        );
+
+        let options = DecodeOptions {
+            expand_shared: true,
+            ..Default::default()
+        };
+        let expanded =
+            decode_archive(&plist, &options, None, Span::test_data()).expect("failed to decode");
+        let items = expanded
+            .as_record()
+            .ok()
+            .and_then(|r| r.get("items"))
+            .and_then(|i| i.as_list().ok())
+            .expect("expected items");
+        assert_eq!(items[0], items[1]);
+    }
+}
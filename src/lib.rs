@@ -0,0 +1,23 @@
+mod binary;
+mod diff;
+mod document;
+mod edit;
+mod encoding;
+mod events;
+mod file;
+mod format;
+mod info;
+mod keyed_archive;
+mod merge;
+mod nu_plist;
+mod openstep;
+mod path;
+mod query;
+mod schema;
+mod search;
+mod split;
+mod validate;
+
+pub use format::{parse_plist, plist_bytes, PlistFormat};
+pub use keyed_archive::{decode_archive, encode_archive, DecodeOptions};
+pub use nu_plist::{convert_nu_value, convert_plist_value, into_nu_value, NuPlistPlugin};
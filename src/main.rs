@@ -1,8 +1,6 @@
 use nu_plugin::{serve_plugin, MsgPackSerializer};
 
-use nu_plist::NuPlistPlugin;
-
-mod nu_plist;
+use nu_plugin_plist::NuPlistPlugin;
 
 fn main() {
     serve_plugin(&NuPlistPlugin, MsgPackSerializer);
@@ -0,0 +1,322 @@
+//! Merging of plist documents.
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{
+    ast::{CellPath, PathMember},
+    Category, Example, LabeledError, Record, Signature, Span, SyntaxShape, Value as NuValue,
+};
+use plist::{Dictionary, Value as PlistValue};
+
+use crate::format::{parse_input, parse_operand, write_plist};
+use crate::nu_plist::{into_nu_value, NuPlistPlugin};
+
+pub struct PlistMerge;
+
+impl SimplePluginCommand for PlistMerge {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist merge"
+    }
+
+    fn usage(&self) -> &str {
+        "Deep merge another plist into a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Dictionaries are merged key by key, anything else in the other plist replaces
+the value in the input. The other plist may be a plist document, as a string or
+binary, or a Nu value. The result is written in the format of the input.
+
+With --base a three-way merge is done instead: changes made on either side
+relative to the common ancestor are combined, and values changed differently on
+both sides are reported as conflicts, keeping the input's value. The output is
+then a record with the merged document in `merged` and a `conflicts` table of
+path, base, ours and theirs."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "open --raw defaults.plist | plist merge (open --raw managed.plist)",
+                description: "Layer managed preferences over the defaults",
+                result: None,
+            },
+            Example {
+                example: "open --raw Info.plist | plist merge { LSEnvironment: { LANG: C } }",
+                description: "Merge a record into a document",
+                result: None,
+            },
+            Example {
+                example: "open --raw mine.plist | plist merge (open --raw theirs.plist) --base (open --raw original.plist)",
+                description: "Three-way merge two edited copies of a document",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required(
+                "other",
+                SyntaxShape::Any,
+                "The plist to merge in, its values win on conflicts",
+            )
+            .named(
+                "base",
+                SyntaxShape::Any,
+                "The common ancestor of both plists, for a three-way merge",
+                Some('b'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let other: NuValue = call.req(0)?;
+        let other = parse_operand(&other)?;
+        let (mut plist, format) = parse_input(input, call.head)?;
+
+        let Some(base) = call.get_flag::<NuValue>("base")? else {
+            deep_merge(&mut plist, other);
+            return write_plist(&plist, format, call.head);
+        };
+        let base = parse_operand(&base)?;
+        let mut conflicts = Vec::new();
+        let merged = merge3(
+            Some(&base),
+            Some(&plist),
+            Some(&other),
+            &mut Vec::new(),
+            &mut conflicts,
+        )
+        .unwrap_or(plist);
+
+        let span = call.head;
+        let conflicts = conflicts
+            .into_iter()
+            .map(|conflict| conflict.into_value(span))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut record = Record::new();
+        record.push("merged", write_plist(&merged, format, span)?);
+        record.push("conflicts", NuValue::list(conflicts, span));
+        Ok(NuValue::record(record, span))
+    }
+}
+
+/// A value changed differently on both sides of a three-way merge.
+pub struct Conflict {
+    pub path: Vec<PathMember>,
+    pub base: Option<PlistValue>,
+    pub ours: Option<PlistValue>,
+    pub theirs: Option<PlistValue>,
+}
+
+impl Conflict {
+    fn into_value(self, span: Span) -> Result<NuValue, LabeledError> {
+        let convert = |value: Option<PlistValue>| match value {
+            Some(value) => into_nu_value(value, span),
+            None => Ok(NuValue::nothing(span)),
+        };
+        let mut record = Record::new();
+        record.push(
+            "path",
+            NuValue::cell_path(CellPath { members: self.path }, span),
+        );
+        record.push("base", convert(self.base)?);
+        record.push("ours", convert(self.ours)?);
+        record.push("theirs", convert(self.theirs)?);
+        Ok(NuValue::record(record, span))
+    }
+}
+
+/// Three-way merges a value, where `None` means the value is absent on that
+/// side. Conflicting changes are collected and resolved in favour of `ours`.
+pub fn merge3(
+    base: Option<&PlistValue>,
+    ours: Option<&PlistValue>,
+    theirs: Option<&PlistValue>,
+    path: &mut Vec<PathMember>,
+    conflicts: &mut Vec<Conflict>,
+) -> Option<PlistValue> {
+    if ours == theirs || base == theirs {
+        return ours.cloned();
+    }
+    if base == ours {
+        return theirs.cloned();
+    }
+
+    if let (Some(PlistValue::Dictionary(ours)), Some(PlistValue::Dictionary(theirs))) =
+        (ours, theirs)
+    {
+        let base = base.and_then(PlistValue::as_dictionary);
+        let mut merged = Dictionary::new();
+        let keys = ours
+            .keys()
+            .chain(theirs.keys().filter(|key| !ours.contains_key(key)));
+        for key in keys {
+            path.push(PathMember::string(key.clone(), false, Span::unknown()));
+            let value = merge3(
+                base.and_then(|b| b.get(key)),
+                ours.get(key),
+                theirs.get(key),
+                path,
+                conflicts,
+            );
+            path.pop();
+            if let Some(value) = value {
+                merged.insert(key.clone(), value);
+            }
+        }
+        return Some(PlistValue::Dictionary(merged));
+    }
+
+    conflicts.push(Conflict {
+        path: path.clone(),
+        base: base.cloned(),
+        ours: ours.cloned(),
+        theirs: theirs.cloned(),
+    });
+    ours.cloned()
+}
+
+/// Merges `other` into `target`. Dictionaries are merged recursively, any
+/// other value in `other` replaces the one in `target`.
+pub fn deep_merge(target: &mut PlistValue, other: PlistValue) {
+    match (target, other) {
+        (PlistValue::Dictionary(target), PlistValue::Dictionary(other)) => {
+            for (key, value) in other {
+                match target.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, other) => *target = other,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn dict(entries: Vec<(&str, PlistValue)>) -> PlistValue {
+        PlistValue::Dictionary(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect::<Dictionary>(),
+        )
+    }
+
+    #[test]
+    fn test_deep_merge() {
+        let mut target = dict(vec![
+            ("a", PlistValue::Integer(1.into())),
+            (
+                "nested",
+                dict(vec![
+                    ("keep", PlistValue::Boolean(true)),
+                    ("replace", PlistValue::Integer(1.into())),
+                ]),
+            ),
+        ]);
+        let other = dict(vec![
+            (
+                "nested",
+                dict(vec![
+                    ("replace", PlistValue::Integer(2.into())),
+                    ("add", PlistValue::String("new".to_string())),
+                ]),
+            ),
+            ("b", PlistValue::Boolean(false)),
+        ]);
+        deep_merge(&mut target, other);
+        assert_eq!(
+            target,
+            dict(vec![
+                ("a", PlistValue::Integer(1.into())),
+                (
+                    "nested",
+                    dict(vec![
+                        ("keep", PlistValue::Boolean(true)),
+                        ("replace", PlistValue::Integer(2.into())),
+                        ("add", PlistValue::String("new".to_string())),
+                    ]),
+                ),
+                ("b", PlistValue::Boolean(false)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_merge3() {
+        let base = dict(vec![
+            ("same", PlistValue::Integer(1.into())),
+            ("ours", PlistValue::Integer(1.into())),
+            ("theirs", PlistValue::Integer(1.into())),
+            ("both", PlistValue::Integer(1.into())),
+            ("removed", PlistValue::Integer(1.into())),
+        ]);
+        let ours = dict(vec![
+            ("same", PlistValue::Integer(1.into())),
+            ("ours", PlistValue::Integer(2.into())),
+            ("theirs", PlistValue::Integer(1.into())),
+            ("both", PlistValue::Integer(2.into())),
+        ]);
+        let theirs = dict(vec![
+            ("same", PlistValue::Integer(1.into())),
+            ("ours", PlistValue::Integer(1.into())),
+            ("theirs", PlistValue::Integer(3.into())),
+            ("both", PlistValue::Integer(3.into())),
+            ("removed", PlistValue::Integer(1.into())),
+            ("added", PlistValue::Boolean(true)),
+        ]);
+
+        let mut conflicts = Vec::new();
+        let merged = merge3(
+            Some(&base),
+            Some(&ours),
+            Some(&theirs),
+            &mut Vec::new(),
+            &mut conflicts,
+        );
+        assert_eq!(
+            merged,
+            Some(dict(vec![
+                ("same", PlistValue::Integer(1.into())),
+                ("ours", PlistValue::Integer(2.into())),
+                ("theirs", PlistValue::Integer(3.into())),
+                ("both", PlistValue::Integer(2.into())),
+                ("added", PlistValue::Boolean(true)),
+            ]))
+        );
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].path,
+            vec![PathMember::string(
+                "both".to_string(),
+                false,
+                Span::unknown()
+            )]
+        );
+        assert_eq!(conflicts[0].theirs, Some(PlistValue::Integer(3.into())));
+    }
+
+    #[test]
+    fn test_merge_replaces_arrays() {
+        let mut target = dict(vec![(
+            "a",
+            PlistValue::Array(vec![PlistValue::Boolean(true)]),
+        )]);
+        deep_merge(&mut target, dict(vec![("a", PlistValue::Array(vec![]))]));
+        assert_eq!(target, dict(vec![("a", PlistValue::Array(vec![]))]));
+    }
+}
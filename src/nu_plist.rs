@@ -1,9 +1,58 @@
-use std::time::SystemTime;
+use std::borrow::Cow;
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use chrono::{DateTime, FixedOffset, Offset, Utc};
-use nu_plugin::{EngineInterface, EvaluatedCall, Plugin, PluginCommand, SimplePluginCommand};
-use nu_protocol::{Category, Example, LabeledError, Record, Signature, Span, Value as NuValue};
-use plist::{Date as PlistDate, Dictionary, Integer, Value as PlistValue};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, FixedOffset, Local, Offset, SecondsFormat, TimeZone, Utc};
+use nu_plugin::{EngineInterface, EvaluatedCall, Plugin, PluginCommand};
+use nu_protocol::{
+    ast::PathMember, record, Category, CustomValue, DataSource, Example, LabeledError, ListStream,
+    PipelineData, PipelineMetadata, Range, Record, ShellError, Signature, Span, Spanned,
+    SyntaxShape, Value as NuValue,
+};
+use plist::{Date as PlistDate, Dictionary, Integer, Uid, Value as PlistValue};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::binary::{Limits, WriteOptions};
+use crate::diff::{PlistDiff, PlistPatch};
+use crate::document::PlistDocument;
+use crate::edit::{PlistCopy, PlistDelete, PlistInsert, PlistRenameKey, PlistSet, PlistUpdate};
+use crate::encoding::to_utf8;
+use crate::events::{Document, Root};
+use crate::file::{map_file, resolve_path, write_atomic, PlistConvert, PlistOpen};
+use crate::format::{
+    format_version, input_bytes, invalid_xml_chars, non_finite_reals, parse_plist_with,
+    plist_output, round_reals, serialize_with, sort_keys, split_xml_documents, InvalidChars,
+    LineEnding, NonFinite, PlistFormat, SerializeOptions,
+};
+use crate::info::PlistInfo;
+use crate::keyed_archive::{
+    decode_archive, is_keyed_archive, DecodeOptions, FromNsKeyedArchive, ToNsKeyedArchive,
+};
+use crate::merge::PlistMerge;
+use crate::path::{PlistGet, PlistKeys, PlistPaths};
+use crate::query::PlistQuery;
+use crate::schema::PlistSchema;
+use crate::search::{PlistFind, PlistGrep};
+use crate::split::{PlistJoin, PlistSplit};
+use crate::validate::PlistValidate;
+
+/// Arrays at least this long are converted in parallel.
+const PARALLEL_THRESHOLD: usize = 1024;
+
+/// How deeply arrays and dictionaries may nest by default. Converting recurses,
+/// so hostile documents nested far deeper would overflow the stack.
+pub const MAX_DEPTH: usize = 256;
+
+fn too_deep(span: Span) -> LabeledError {
+    plist_error(
+        ErrorKind::Overflow,
+        "The plist is nested too deeply, --max-depth raises the limit".to_string(),
+        span,
+    )
+}
 
 pub struct NuPlistPlugin;
 struct FromPlist;
@@ -11,15 +60,53 @@ struct IntoPlist;
 
 impl Plugin for NuPlistPlugin {
     fn commands(&self) -> Vec<Box<dyn nu_plugin::PluginCommand<Plugin = Self>>> {
-        vec![Box::new(FromPlist), Box::new(IntoPlist)]
+        vec![
+            Box::new(FromPlist),
+            Box::new(IntoPlist),
+            Box::new(FromNsKeyedArchive),
+            Box::new(ToNsKeyedArchive),
+            Box::new(PlistGet),
+            Box::new(PlistPaths),
+            Box::new(PlistKeys),
+            Box::new(PlistFind),
+            Box::new(PlistGrep),
+            Box::new(PlistQuery),
+            Box::new(PlistSet),
+            Box::new(PlistDelete),
+            Box::new(PlistInsert),
+            Box::new(PlistUpdate),
+            Box::new(PlistCopy),
+            Box::new(PlistRenameKey),
+            Box::new(PlistMerge),
+            Box::new(PlistDiff),
+            Box::new(PlistPatch),
+            Box::new(PlistValidate),
+            Box::new(PlistSchema),
+            Box::new(PlistInfo),
+            Box::new(PlistSplit),
+            Box::new(PlistJoin),
+            Box::new(PlistConvert),
+            Box::new(PlistOpen),
+        ]
     }
 
     fn version(&self) -> String {
         env!("CARGO_PKG_VERSION").into()
     }
+
+    fn custom_value_dropped(
+        &self,
+        _engine: &EngineInterface,
+        custom_value: Box<dyn CustomValue>,
+    ) -> Result<(), LabeledError> {
+        if let Some(document) = custom_value.as_any().downcast_ref::<PlistDocument>() {
+            document.release();
+        }
+        Ok(())
+    }
 }
 
-impl SimplePluginCommand for IntoPlist {
+impl PluginCommand for IntoPlist {
     type Plugin = NuPlistPlugin;
 
     fn name(&self) -> &str {
@@ -30,46 +117,536 @@ impl SimplePluginCommand for IntoPlist {
         "Convert Nu values into plist"
     }
 
+    fn extra_usage(&self) -> &str {
+        r#"`save` runs this command for files with a .plist extension, so
+`$value | save settings.plist` writes an XML plist. The output is tagged with
+the content type of its format, which commands such as `http post` use for
+their headers. --output only replaces the file once the new one is complete.
+
+Output is reproducible: the same input always gives byte-identical plists.
+Keys are written in record order, binary plists store equal strings, numbers
+and data once, and reals are written in the shortest form that reads back as
+the same number.
+
+Binary plists store NaN and infinite floats as they are, while text plists
+can't hold them and XML plists can't hold most control characters. Those are
+errors unless --non-finite or --invalid-chars says otherwise. Escaped control
+characters read back as literal text, not as the characters.
+
+Records holding only one of these keys are written as the type they stand for,
+so values from `from plist` survive a trip through JSON:
+  CF$UID: a UID, as XML plists store them
+  __plist_data: data, as a base64 string
+  __plist_uint: an unsigned integer, as a string of digits
+  __plist_date: a date, as seconds since 2001-01-01
+
+Custom values from other plugins are written as their base value, lazy
+documents from `from plist --lazy` as the plist they were read from, and globs
+and cell paths as strings such as `*.plist` and `a.b.0`. `from plist` reads the
+XML written with --fragment back."#
+    }
+
     fn examples(&self) -> Vec<Example> {
-        vec![Example {
-            example: "{ a: 3 } | to plist",
-            description: "Convert a table into a plist file",
-            result: None,
-        }]
+        vec![
+            Example {
+                example: "{ a: 3 } | to plist",
+                description: "Convert a table into a plist file",
+                result: Some(NuValue::test_string(concat!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                    "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+                    "<plist version=\"1.0\">\n",
+                    "<dict>\n",
+                    "\t<key>a</key>\n",
+                    "\t<integer>3</integer>\n",
+                    "</dict>\n",
+                    "</plist>",
+                ))),
+            },
+            Example {
+                example: "{ a: 3 } | to plist --format openstep",
+                description: "Convert a table into an old-style OpenStep plist",
+                result: Some(NuValue::test_string("{\n    a = 3;\n}\n")),
+            },
+            Example {
+                example: "{ a: 3 } | to plist --format gnustep",
+                description: "Keep the type of numbers with GNUstep's extensions to OpenStep",
+                result: Some(NuValue::test_string("{\n    a = <*I3>;\n}\n")),
+            },
+            Example {
+                example: "{ a: 3 } | to plist --minify --no-declaration --no-doctype",
+                description: "Write an XML plist on one line, without the prologue",
+                result: Some(NuValue::test_string(
+                    "<plist version=\"1.0\"><dict><key>a</key><integer>3</integer></dict></plist>",
+                )),
+            },
+            Example {
+                example: "{ a: 3 } | to plist --indent 2 --no-declaration --no-doctype",
+                description: "Indent with two spaces instead of a tab",
+                result: Some(NuValue::test_string(concat!(
+                    "<plist version=\"1.0\">\n",
+                    "<dict>\n",
+                    "  <key>a</key>\n",
+                    "  <integer>3</integer>\n",
+                    "</dict>\n",
+                    "</plist>",
+                ))),
+            },
+            Example {
+                example: "{ a: 3 } | to plist --fragment",
+                description: "Write only the XML of the value, for splicing into a template",
+                result: Some(NuValue::test_string(
+                    "<dict>\n\t<key>a</key>\n\t<integer>3</integer>\n</dict>",
+                )),
+            },
+            Example {
+                example: "{ b: 1, a: 2 } | to plist --sort-keys --format openstep",
+                description: "Write keys in sorted order, for stable diffs",
+                result: Some(NuValue::test_string("{\n    a = 2;\n    b = 1;\n}\n")),
+            },
+            Example {
+                example: "{ pi: 3.14159 } | to plist --float-precision 2 --format openstep",
+                description: "Round reals to two decimal places",
+                result: Some(NuValue::test_string("{\n    pi = 3.14;\n}\n")),
+            },
+            Example {
+                example: "{ timeout: 90sec } | to plist --duration-unit ms --format openstep",
+                description: "Write durations as milliseconds",
+                result: Some(NuValue::test_string("{\n    timeout = 90000;\n}\n")),
+            },
+            Example {
+                example: "{ a: 1, b: null } | to plist --nulls skip --format openstep",
+                description: "Leave out nulls, which plists can't hold",
+                result: Some(NuValue::test_string("{\n    a = 1;\n}\n")),
+            },
+            Example {
+                example: "{ type: real, value: 1.5 } | to plist --from-tagged --fragment",
+                description: "Write a tagged value read with `from plist --tagged`",
+                result: Some(NuValue::test_string("<real>1.5</real>")),
+            },
+            Example {
+                example: "{ root: { CF$UID: 1 } } | to plist --binary",
+                description: "Write a UID, as used by keyed archives",
+                result: None,
+            },
+            Example {
+                example: "$library | to plist --binary --compact",
+                description: "Write a binary plist storing repeated records and lists once",
+                result: None,
+            },
+            Example {
+                example: "$value | to plist --binary --offset-size 4",
+                description: "Write a binary plist with 4 byte offsets for older readers",
+                result: None,
+            },
+            Example {
+                example: "[{ Label: a } { Label: b }] | to plist --per-item --format openstep",
+                description: "Write a plist document for each row of a table",
+                result: Some(NuValue::test_list(vec![
+                    NuValue::test_string("{\n    Label = a;\n}\n"),
+                    NuValue::test_string("{\n    Label = b;\n}\n"),
+                ])),
+            },
+            Example {
+                example: "$prefs | to plist --binary -o prefs.plist --backup",
+                description: "Save a preferences file, keeping the old one as prefs.plist.bak",
+                result: None,
+            },
+        ]
     }
 
     fn signature(&self) -> Signature {
         Signature::build(PluginCommand::name(self))
             .switch("binary", "Output plist in binary format", Some('b'))
+            .named(
+                "format",
+                SyntaxShape::String,
+                "Output format: xml (default), binary, openstep or gnustep",
+                Some('f'),
+            )
+            .named(
+                "output",
+                SyntaxShape::Filepath,
+                "Write the plist to this file instead of the output",
+                Some('o'),
+            )
+            .switch(
+                "backup",
+                "With --output, keep the previous file as <file>.bak",
+                None,
+            )
+            .switch(
+                "from-tagged",
+                "Read the input as the tagged values of `from plist --tagged`",
+                None,
+            )
+            .switch(
+                "per-item",
+                "Write each item of a list as its own document, returning a list",
+                None,
+            )
+            .switch(
+                "sort-keys",
+                "Write dictionary keys in sorted order",
+                Some('s'),
+            )
+            .named(
+                "float-precision",
+                SyntaxShape::Int,
+                "Round reals to this many decimal places",
+                None,
+            )
+            .named(
+                "non-finite",
+                SyntaxShape::String,
+                "What to do with NaN and infinite floats: error, null or string",
+                None,
+            )
+            .named(
+                "duration-unit",
+                SyntaxShape::String,
+                "Unit durations are written in: sec (default), ms, us or ns",
+                None,
+            )
+            .named(
+                "range-as",
+                SyntaxShape::String,
+                "How to write ranges: list (default) or record",
+                None,
+            )
+            .named(
+                "filesize-as",
+                SyntaxShape::String,
+                "How to write file sizes: bytes (default), string, or a unit such as kB or MiB",
+                None,
+            )
+            .named(
+                "nulls",
+                SyntaxShape::String,
+                "What to do with nulls: error (default), skip, empty-string or false",
+                None,
+            )
+            .named(
+                "invalid-chars",
+                SyntaxShape::String,
+                "What to do with control characters XML can't hold: error, strip or escape",
+                None,
+            )
+            .named(
+                "indent",
+                SyntaxShape::Int,
+                "Indent XML plists by this many spaces, or tabs with --tabs",
+                None,
+            )
+            .switch("tabs", "Indent XML plists with tabs, as Xcode does", None)
+            .named(
+                "line-ending",
+                SyntaxShape::String,
+                "Line ending of text plists: lf (default) or crlf",
+                None,
+            )
+            .switch(
+                "minify",
+                "Write XML plists without indentation or line breaks",
+                None,
+            )
+            .switch(
+                "fragment",
+                "Write only the XML value, without the declaration, DOCTYPE or <plist> root",
+                None,
+            )
+            .switch(
+                "no-declaration",
+                "Leave out the <?xml ...?> declaration of XML plists",
+                None,
+            )
+            .switch(
+                "no-doctype",
+                "Leave out the <!DOCTYPE ...> line of XML plists",
+                None,
+            )
+            .named(
+                "doctype",
+                SyntaxShape::String,
+                "Use this <!DOCTYPE ...> line in XML plists instead of Apple's",
+                None,
+            )
+            .switch(
+                "compact",
+                "Store repeated arrays and dictionaries once in binary plists",
+                None,
+            )
+            .named(
+                "offset-size",
+                SyntaxShape::Int,
+                "Width in bytes (1, 2, 4 or 8) of offsets and object references in binary plists",
+                None,
+            )
             .category(Category::Formats)
     }
 
     fn run(
         &self,
         _plugin: &NuPlistPlugin,
-        _engine: &EngineInterface,
+        engine: &EngineInterface,
         call: &EvaluatedCall,
-        input: &NuValue,
-    ) -> Result<NuValue, LabeledError> {
-        let plist_val = convert_nu_value(input)?;
-        let mut out = Vec::new();
-        if call.has_flag("binary")? {
-            plist::to_writer_binary(&mut out, &plist_val)
-                .map_err(|e| build_label_error(format!("{}", e), input.span()))?;
-            Ok(NuValue::binary(out, input.span()))
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let input = input.into_value(call.head)?;
+        let (span, input_type) = (input.span(), input.get_type());
+        // The input is moved into the plist, so large binaries aren't held
+        // twice
+        let mut plist_val = if call.has_flag("from-tagged")? {
+            let plist_val = from_tagged(&input)?;
+            drop(input);
+            plist_val
         } else {
-            plist::to_writer_xml(&mut out, &plist_val)
-                .map_err(|e| build_label_error(format!("{}", e), input.span()))?;
-            Ok(NuValue::string(
-                String::from_utf8(out)
-                    .map_err(|e| build_label_error(format!("{}", e), input.span()))?,
-                input.span(),
+            into_plist_value_with(input, ToPlistOptions::from_call(call)?)?
+        };
+        if call.has_flag("sort-keys")? {
+            sort_keys(&mut plist_val);
+        }
+        if let Some(precision) = call.get_flag::<Spanned<i64>>("float-precision")? {
+            if !(0..=17).contains(&precision.item) {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Invalid float precision {}, expected 0 to 17 decimal places",
+                        precision.item
+                    ),
+                    precision.span,
+                ));
+            }
+            round_reals(&mut plist_val, precision.item as usize);
+        }
+        let binary = call.has_flag("binary")?;
+        let format = match call.get_flag::<Spanned<String>>("format")? {
+            Some(name) => {
+                let format = PlistFormat::from_name(&name.item).ok_or_else(|| {
+                    plist_error(
+                        ErrorKind::General,
+                        format!(
+                            "Unknown format {}, expected xml, binary, openstep or gnustep",
+                            name.item
+                        ),
+                        name.span,
+                    )
+                })?;
+                if binary && format != PlistFormat::Binary {
+                    return Err(plist_error(
+                        ErrorKind::General,
+                        "--binary can't be combined with other --format values".to_string(),
+                        name.span,
+                    ));
+                }
+                format
+            }
+            None if binary => PlistFormat::Binary,
+            None => PlistFormat::Xml,
+        };
+        let non_finite = match call.get_flag::<Spanned<String>>("non-finite")? {
+            Some(name) => Some(NonFinite::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown policy {}, expected error, null or string",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?),
+            // Binary plists store NaN and infinity as they are
+            None => (format != PlistFormat::Binary).then_some(NonFinite::Error),
+        };
+        if let Some(policy) = non_finite {
+            non_finite_reals(&mut plist_val, policy, span)?;
+        }
+        let invalid_chars = match call.get_flag::<Spanned<String>>("invalid-chars")? {
+            Some(name) => Some(InvalidChars::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown policy {}, expected error, strip or escape",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?),
+            // Binary and OpenStep plists can hold any character
+            None => (format == PlistFormat::Xml).then_some(InvalidChars::Error),
+        };
+        if let Some(policy) = invalid_chars {
+            invalid_xml_chars(&mut plist_val, policy, span)?;
+        }
+        let options = write_options(call, format)?;
+        if call.has_flag("per-item")? {
+            if call.get_flag::<Spanned<String>>("output")?.is_some() {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    "--per-item can't be combined with --output".to_string(),
+                    call.head,
+                ));
+            }
+            let PlistValue::Array(items) = plist_val else {
+                return Err(plist_error(
+                    ErrorKind::UnsupportedType,
+                    format!("--per-item needs a list, not {}", input_type),
+                    span,
+                ));
+            };
+            let documents = items
+                .iter()
+                .map(|item| {
+                    let mut out = Vec::new();
+                    serialize_with(item, format, &options, &mut out, span)?;
+                    plist_output(out, format, span)
+                })
+                .collect::<Result<_, _>>()?;
+            return Ok(PipelineData::Value(NuValue::list(documents, span), None));
+        }
+        if let Some(output) = call.get_flag::<Spanned<String>>("output")? {
+            let path = resolve_path(engine, &output)?;
+            write_atomic(&path, call.has_flag("backup")?, output.span, |writer| {
+                serialize_with(&plist_val, format, &options, writer, span)
+            })?;
+            return Ok(PipelineData::empty());
+        }
+        plist_pipeline(&plist_val, format, &options, span)
+    }
+}
+
+/// Writes a document as the output of `to plist`, with the content type of its
+/// format so `save` and `http` can label it.
+fn plist_pipeline(
+    plist_val: &PlistValue,
+    format: PlistFormat,
+    options: &SerializeOptions,
+    span: Span,
+) -> Result<PipelineData, LabeledError> {
+    let mut out = Vec::new();
+    serialize_with(plist_val, format, options, &mut out, span)?;
+    let metadata = PipelineMetadata {
+        data_source: DataSource::None,
+        content_type: Some(format.content_type().into()),
+    };
+    Ok(PipelineData::Value(
+        plist_output(out, format, span)?,
+        Some(metadata),
+    ))
+}
+
+/// Reads the flags controlling the binary and XML writers.
+fn write_options(
+    call: &EvaluatedCall,
+    format: PlistFormat,
+) -> Result<SerializeOptions, LabeledError> {
+    let mut options = SerializeOptions::default();
+    let offset_size = call.get_flag::<Spanned<i64>>("offset-size")?;
+    let compact = call.has_flag("compact")?;
+    if format != PlistFormat::Binary && (offset_size.is_some() || compact) {
+        return Err(plist_error(
+            ErrorKind::General,
+            "--offset-size and --compact only apply to binary plists".to_string(),
+            call.head,
+        ));
+    }
+    let offset_size = match offset_size {
+        Some(size) if ![1, 2, 4, 8].contains(&size.item) => {
+            return Err(plist_error(
+                ErrorKind::General,
+                format!("Invalid offset size {}, expected 1, 2, 4 or 8", size.item),
+                size.span,
+            ))
+        }
+        size => size.map(|size| size.item as u8),
+    };
+    options.binary = WriteOptions {
+        offset_size,
+        compact,
+    };
+
+    let indent = call.get_flag::<Spanned<i64>>("indent")?;
+    let tabs = call.has_flag("tabs")?;
+    let minify = call.has_flag("minify")?;
+    if format != PlistFormat::Xml && (indent.is_some() || tabs || minify) {
+        return Err(plist_error(
+            ErrorKind::General,
+            "--indent, --tabs and --minify only apply to XML plists".to_string(),
+            call.head,
+        ));
+    }
+    if minify && (indent.is_some() || tabs) {
+        return Err(plist_error(
+            ErrorKind::General,
+            "--minify can't be combined with --indent or --tabs".to_string(),
+            call.head,
+        ));
+    }
+    let count = match &indent {
+        Some(count) if count.item < 1 => {
+            return Err(plist_error(
+                ErrorKind::General,
+                format!("Invalid indent {}, expected at least 1", count.item),
+                count.span,
             ))
         }
+        Some(count) => count.item as usize,
+        None => 1,
+    };
+    // Tabs stay the default unless a number of spaces is asked for
+    let indent_char = if tabs || indent.is_none() {
+        b'\t'
+    } else {
+        b' '
+    };
+    options.xml.indent = (!minify).then_some((indent_char, count));
+
+    if let Some(name) = call.get_flag::<Spanned<String>>("line-ending")? {
+        if format == PlistFormat::Binary {
+            return Err(plist_error(
+                ErrorKind::General,
+                "--line-ending only applies to text plists".to_string(),
+                name.span,
+            ));
+        }
+        options.line_ending = LineEnding::from_name(&name.item).ok_or_else(|| {
+            plist_error(
+                ErrorKind::General,
+                format!("Unknown line ending {}, expected lf or crlf", name.item),
+                name.span,
+            )
+        })?;
     }
+
+    let no_declaration = call.has_flag("no-declaration")?;
+    let no_doctype = call.has_flag("no-doctype")?;
+    let doctype = call.get_flag::<String>("doctype")?;
+    if format != PlistFormat::Xml && (no_declaration || no_doctype || doctype.is_some()) {
+        return Err(plist_error(
+            ErrorKind::General,
+            "--no-declaration, --no-doctype and --doctype only apply to XML plists".to_string(),
+            call.head,
+        ));
+    }
+    let fragment = call.has_flag("fragment")?;
+    if fragment && (format != PlistFormat::Xml || doctype.is_some()) {
+        return Err(plist_error(
+            ErrorKind::General,
+            "--fragment only applies to XML plists and can't be combined with --doctype"
+                .to_string(),
+            call.head,
+        ));
+    }
+    options.xml.fragment = fragment;
+    options.xml.declaration = !no_declaration;
+    if no_doctype {
+        options.xml.doctype = None;
+    } else if doctype.is_some() {
+        options.xml.doctype = doctype;
+    }
+    Ok(options)
 }
 
-impl SimplePluginCommand for FromPlist {
+impl PluginCommand for FromPlist {
     type Plugin = NuPlistPlugin;
 
     fn name(&self) -> &str {
@@ -77,78 +654,1373 @@ impl SimplePluginCommand for FromPlist {
     }
 
     fn usage(&self) -> &str {
-        "Convert Nu values into plist"
+        "Convert plist documents into Nu values"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"`open` runs this command for files with a .plist extension, whether they are
+XML, binary or OpenStep, and `open --raw file | from plist` decodes other
+extensions. UTF-16 documents, and XML declaring ISO-8859-1, Windows-1252 or
+Mac Roman, are transcoded before they are parsed. --path memory-maps the file,
+which is much faster for large binary plists.
+
+Records keep the keys in the order of the document, so writing them back with
+`to plist` doesn't reorder it. When the root is an array its rows are streamed,
+so commands like `first` don't wait for the whole document.
+
+A list of strings or binaries is decoded item by item. An item that can't be
+decoded becomes an error in its place while the other items are still decoded.
+
+Values without a Nu equivalent become records that `to plist` writes back as
+the same type: UIDs become `{CF$UID: n}`, as XML plists store them, and
+integers beyond the largest Nu int `{__plist_uint: "..."}`. With --tagged
+those integers have the type `uint` and their digits as the value.
+
+Binary and OpenStep plists always keep the last value of a repeated key, as
+CoreFoundation does. With --lenient the open arrays and dictionaries of a
+broken XML plist are closed, and `skipped` describes each problem passed over,
+including repeated keys and trailing content.
+
+Binary plists can refer to one object from many places, so a small file can
+decode to an enormous value. --max-objects and --max-bytes bound that before
+anything is converted."#
     }
 
     fn examples(&self) -> Vec<Example> {
-        vec![Example {
-            example: "{ a: 3 } | to plist",
-            description: "Convert a table into a plist file",
-            result: None,
-        }]
+        vec![
+            Example {
+                example: "'<plist><dict><key>a</key><integer>3</integer></dict></plist>' | from plist",
+                description: "Convert an XML plist into a record",
+                result: Some(NuValue::test_record(record! {
+                    "a" => NuValue::test_int(3),
+                })),
+            },
+            Example {
+                example: "'{ name = demo; tags = (a, b); }' | from plist",
+                description: "Convert an old-style OpenStep plist",
+                result: Some(NuValue::test_record(record! {
+                    "name" => NuValue::test_string("demo"),
+                    "tags" => NuValue::test_list(vec![
+                        NuValue::test_string("a"),
+                        NuValue::test_string("b"),
+                    ]),
+                })),
+            },
+            Example {
+                example: "'<plist><date>2024-01-01T00:00:00Z</date></plist>' | from plist --dates-as string --timezone +02:00",
+                description: "Return a date as a string in another timezone",
+                result: Some(NuValue::test_string("2024-01-01T02:00:00+02:00")),
+            },
+            Example {
+                example: "'<plist><date>2024-01-01T00:00:00Z</date></plist>' | from plist --dates-as raw",
+                description: "Return a date as the seconds since 2001-01-01",
+                result: Some(NuValue::test_float(725760000.0)),
+            },
+            Example {
+                example: "'<plist><data>AQI=</data></plist>' | from plist --data-as base64",
+                description: "Return data as base64 text",
+                result: Some(NuValue::test_string("AQI=")),
+            },
+            Example {
+                example: "'<plist><integer>18446744073709551615</integer></plist>' | from plist --uint-as string",
+                description: "Return an integer too large for a Nu int as a string",
+                result: Some(NuValue::test_string("18446744073709551615")),
+            },
+            Example {
+                example: "'<plist><dict><key>a</key><true/><key>a</key><false/></dict></plist>' | from plist --on-duplicate first",
+                description: "Keep the first of repeated keys",
+                result: Some(NuValue::test_record(record! {
+                    "a" => NuValue::test_bool(true),
+                })),
+            },
+            Example {
+                example: "'<plist><string>a</string></plist><plist><string>b</string></plist>' | from plist --multiple",
+                description: "Read XML plists written back to back",
+                result: Some(NuValue::test_list(vec![
+                    NuValue::test_string("a"),
+                    NuValue::test_string("b"),
+                ])),
+            },
+            Example {
+                example: "'<plist><real>1.5</real></plist>' | from plist --tagged",
+                description: "Return a value with its exact plist type",
+                result: Some(NuValue::test_record(record! {
+                    "type" => NuValue::test_string("real"),
+                    "value" => NuValue::test_float(1.5),
+                })),
+            },
+            Example {
+                example: r#"'<plist version="1.0"><true/></plist>' | from plist --metadata"#,
+                description: "Return the value with the format, version and size of the input",
+                result: Some(NuValue::test_record(record! {
+                    "value" => NuValue::test_bool(true),
+                    "format" => NuValue::test_string("xml"),
+                    "version" => NuValue::test_string("1.0"),
+                    "bytes" => NuValue::test_int(36),
+                })),
+            },
+            Example {
+                example: "from plist --path ~/Library/Safari/History.plist",
+                description: "Read a large plist file directly",
+                result: None,
+            },
+            Example {
+                example: "ls *.plist | each { open --raw $in.name } | from plist",
+                description: "Decode several plists at once",
+                result: None,
+            },
+        ]
     }
 
     fn signature(&self) -> Signature {
-        Signature::build(PluginCommand::name(self)).category(Category::Formats)
+        Signature::build(PluginCommand::name(self))
+            .named(
+                "path",
+                SyntaxShape::Filepath,
+                "Read the plist from this file instead of the input",
+                Some('p'),
+            )
+            .switch(
+                "multiple",
+                "Read XML plists written back to back, returning a list of them",
+                Some('m'),
+            )
+            .switch(
+                "metadata",
+                "Return a record of the value with the format, version and size of the input",
+                None,
+            )
+            .switch(
+                "tagged",
+                "Wrap every value in a record with its exact plist type",
+                None,
+            )
+            .switch(
+                "lazy",
+                "Keep the document in the plugin and only convert the values that are accessed",
+                Some('l'),
+            )
+            .named(
+                "uid-as",
+                SyntaxShape::String,
+                "How to represent UIDs: record (default), int or float",
+                None,
+            )
+            .named(
+                "uint-as",
+                SyntaxShape::String,
+                "How to represent integers above the int range: record (default), string or float",
+                None,
+            )
+            .named(
+                "dates-as",
+                SyntaxShape::String,
+                "How to represent dates: date (default), string, raw or tagged",
+                None,
+            )
+            .named(
+                "timezone",
+                SyntaxShape::String,
+                "Timezone of returned dates: utc (default), local or an offset like +02:00",
+                Some('z'),
+            )
+            .switch(
+                "raw-dates",
+                "Return dates as floats counting the seconds since 2001-01-01, like --dates-as raw",
+                None,
+            )
+            .named(
+                "data-as",
+                SyntaxShape::String,
+                "How to represent data: binary (default), base64, hex or tagged",
+                None,
+            )
+            .switch(
+                "decode-nested",
+                "Decode data holding a binary or XML plist",
+                None,
+            )
+            .switch(
+                "decode-archives",
+                "Decode data holding an NSKeyedArchiver archive",
+                None,
+            )
+            .switch(
+                "keep-raw",
+                "Return data decoded by --decode-nested or --decode-archives as { value, raw } records",
+                None,
+            )
+            .named(
+                "on-duplicate",
+                SyntaxShape::String,
+                "What to do with repeated dictionary keys in XML: last (default), first, error or collect",
+                None,
+            )
+            .switch(
+                "strict",
+                "Reject XML with repeated keys or anything after the root value",
+                None,
+            )
+            .switch(
+                "lenient",
+                "Keep what can be read of broken XML, returning { value, skipped }",
+                None,
+            )
+            .named(
+                "max-depth",
+                SyntaxShape::Int,
+                "How deeply arrays and dictionaries may nest (default 256)",
+                None,
+            )
+            .named(
+                "max-objects",
+                SyntaxShape::Int,
+                "How many values a binary plist may decode to (default 20 million)",
+                None,
+            )
+            .named(
+                "max-bytes",
+                SyntaxShape::Filesize,
+                "How much string and data content a binary plist may decode to (default 1GiB)",
+                None,
+            )
+            .category(Category::Formats)
     }
 
     fn run(
         &self,
         _plugin: &NuPlistPlugin,
-        _engine: &EngineInterface,
+        engine: &EngineInterface,
         call: &EvaluatedCall,
-        input: &NuValue,
-    ) -> Result<NuValue, LabeledError> {
-        match input {
-            NuValue::String { val, .. } => {
-                let plist = plist::from_bytes(val.as_bytes())
-                    .map_err(|e| build_label_error(format!("{}", e), input.span()))?;
-                let converted = convert_plist_value(&plist, call.head)?;
-                Ok(converted)
-            }
-            NuValue::Binary { val, .. } => {
-                let plist = plist::from_bytes(val)
-                    .map_err(|e| build_label_error(format!("{}", e), input.span()))?;
-                let converted = convert_plist_value(&plist, call.head)?;
-                Ok(converted)
-            }
-            _ => Err(build_label_error(
-                format!("Invalid input, must be string not: {:?}", input),
-                call.head,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let mut options = ConvertOptions::from_call(call)?;
+        let limits = Limits {
+            max_depth: options.max_depth,
+            ..read_limits(call)?
+        };
+        options.limits = limits;
+        if options.decode_archives {
+            let config = engine.get_plugin_config()?;
+            options.archives = Arc::new(DecodeOptions {
+                limits,
+                ..DecodeOptions::from_config(config.as_ref())?
+            });
+            options.engine = Some(engine.clone());
+        }
+        if call.has_flag("lazy")? {
+            if options.strict || options.lenient {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    "--strict and --lenient can't be used with --lazy".to_string(),
+                    call.head,
+                ));
+            }
+            for flag in ["multiple", "metadata", "tagged"] {
+                if call.has_flag(flag)? {
+                    return Err(plist_error(
+                        ErrorKind::General,
+                        format!("--{flag} can't be used with --lazy"),
+                        call.head,
+                    ));
+                }
+            }
+            return from_plist_lazy(engine, call, input, options, &limits);
+        }
+        if call.has_flag("tagged")? {
+            if options.strict || options.lenient {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    "--strict and --lenient can't be used with --tagged".to_string(),
+                    call.head,
+                ));
+            }
+            if call.has_flag("multiple")? {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    "--multiple can't be used with --tagged".to_string(),
+                    call.head,
+                ));
+            }
+            return from_plist_tagged(engine, call, input, options, &limits);
+        }
+        if call.has_flag("multiple")? {
+            if call.has_flag("metadata")? {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    "--metadata can't be used with --multiple".to_string(),
+                    call.head,
+                ));
+            }
+            return from_plist_multiple(engine, call, input, options, &limits);
+        }
+        if call.has_flag("metadata")? {
+            return from_plist_metadata(engine, call, input, options, &limits);
+        }
+        let document = if let Some(path) = call.get_flag::<Spanned<String>>("path")? {
+            let map = map_file(&resolve_path(engine, &path)?, path.span)?;
+            Document::from_bytes(map, &limits, path.span)?
+        } else {
+            match input {
+                // Streams are read as they arrive rather than being collected
+                // into a single value first
+                PipelineData::ByteStream(stream, ..) => {
+                    let span = stream.span();
+                    match stream.reader() {
+                        Some(reader) => Document::from_reader(reader, &limits, span)?,
+                        None => {
+                            return Err(plist_error(ErrorKind::Parse, "Empty input".into(), span))
+                        }
+                    }
+                }
+                PipelineData::Value(NuValue::String { val, internal_span }, ..) => {
+                    Document::from_bytes(val.into_bytes(), &limits, internal_span)?
+                }
+                PipelineData::Value(NuValue::Binary { val, internal_span }, ..) => {
+                    Document::from_bytes(val, &limits, internal_span)?
+                }
+                // Lists are decoded one document per item, and an item that
+                // fails becomes an error in its place
+                PipelineData::ListStream(stream, ..) => {
+                    let span = stream.span();
+                    let items = stream
+                        .into_iter()
+                        .map(move |item| from_plist_item_or_error(item, &options, &limits));
+                    return Ok(PipelineData::ListStream(
+                        ListStream::new(items, span, engine.signals().clone()),
+                        None,
+                    ));
+                }
+                PipelineData::Value(
+                    NuValue::List {
+                        vals,
+                        internal_span,
+                    },
+                    ..,
+                ) => {
+                    let items = vals
+                        .into_iter()
+                        .map(|item| from_plist_item_or_error(item, &options, &limits))
+                        .collect();
+                    return Ok(PipelineData::Value(
+                        NuValue::list(items, internal_span),
+                        None,
+                    ));
+                }
+                _ => {
+                    return Err(plist_error(
+                        ErrorKind::UnsupportedType,
+                        "Invalid input, must be string or binary".into(),
+                        call.head,
+                    ))
+                }
+            }
+        };
+
+        let head = call.head;
+        if options.lenient {
+            return Ok(PipelineData::Value(
+                lenient_value(document, &options, head)?,
+                None,
+            ));
+        }
+
+        // Rows of a root array are converted as they are consumed, so `first`
+        // or `where` can start before the whole list is built
+        let signals = engine.signals().clone();
+        match document {
+            Document::Events(events) => match events.options(options).into_rows(head)? {
+                Root::Rows(rows) => Ok(PipelineData::ListStream(
+                    ListStream::new(rows, head, signals),
+                    None,
+                )),
+                Root::Value(value) => Ok(PipelineData::Value(value, None)),
+            },
+            Document::Value(PlistValue::Array(rows)) => Ok(PipelineData::ListStream(
+                ListStream::new(array_rows(rows, options, head)?, head, signals),
+                None,
+            )),
+            Document::Value(plist) => Ok(PipelineData::Value(
+                into_nu_value_with(plist, &options, head)?,
+                None,
             )),
         }
     }
 }
 
-fn build_label_error(msg: String, span: Span) -> LabeledError {
-    LabeledError::new("ERROR from plugin").with_label(msg, span)
+/// Converts the rows of a root array as they are consumed. Rows are converted
+/// in parallel a chunk at a time, so the stream still starts before every row
+/// is converted. Like the rows read from events, the stream ends at the first
+/// error.
+fn array_rows(
+    rows: Vec<PlistValue>,
+    options: ConvertOptions,
+    head: Span,
+) -> Result<impl Iterator<Item = NuValue>, LabeledError> {
+    // The rows are inside the root array, as for a document converted whole
+    let depth = nested(options.max_depth, head)?;
+    let mut remaining = rows.into_iter();
+    let mut failed = false;
+    Ok(std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        let chunk: Vec<PlistValue> = remaining.by_ref().take(PARALLEL_THRESHOLD).collect();
+        if chunk.is_empty() {
+            return None;
+        }
+        let mut rows: Vec<Result<NuValue, LabeledError>> = chunk
+            .into_par_iter()
+            .map(|row| into_nu_value_at(row, &options, depth, head))
+            .collect();
+        if let Some(index) = rows.iter().position(Result::is_err) {
+            rows.truncate(index + 1);
+            failed = true;
+        }
+        Some(
+            rows.into_iter()
+                .map(move |row| row.unwrap_or_else(|e| NuValue::error(ShellError::from(e), head))),
+        )
+    })
+    .flatten())
+}
+
+/// Reads the `--max-objects` and `--max-bytes` flags.
+fn read_limits(call: &EvaluatedCall) -> Result<Limits, LabeledError> {
+    let mut limits = Limits::default();
+    let positive = |flag: Spanned<i64>| {
+        u64::try_from(flag.item)
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!("Invalid limit {}, expected a positive number", flag.item),
+                    flag.span,
+                )
+            })
+    };
+    if let Some(max) = call.get_flag::<Spanned<i64>>("max-objects")? {
+        limits.max_objects = positive(max)?;
+    }
+    if let Some(max) = call.get_flag::<Spanned<i64>>("max-bytes")? {
+        limits.max_bytes = positive(max)?;
+    }
+    Ok(limits)
+}
+
+/// Converts a whole document in lenient mode into a record of the value and
+/// what was passed over.
+fn lenient_value(
+    document: Document,
+    options: &ConvertOptions,
+    head: Span,
+) -> Result<NuValue, LabeledError> {
+    let (value, skipped) = match document {
+        Document::Events(events) => events.options(options.clone()).into_value_lenient(head)?,
+        Document::Value(plist) => (into_nu_value_with(plist, options, head)?, Vec::new()),
+    };
+    let mut record = Record::new();
+    record.push("value", value);
+    record.push(
+        "skipped",
+        NuValue::list(
+            skipped
+                .into_iter()
+                .map(|message| NuValue::string(message, head))
+                .collect(),
+            head,
+        ),
+    );
+    Ok(NuValue::record(record, head))
+}
+
+/// Decodes one item of a list piped into `from plist`, giving an error value
+/// if it can't be decoded.
+fn from_plist_item_or_error(item: NuValue, options: &ConvertOptions, limits: &Limits) -> NuValue {
+    let span = item.span();
+    from_plist_item(item, options, limits)
+        .unwrap_or_else(|e| NuValue::error(ShellError::from(e), span))
+}
+
+/// Decodes one item of a list piped into `from plist`.
+fn from_plist_item(
+    item: NuValue,
+    options: &ConvertOptions,
+    limits: &Limits,
+) -> Result<NuValue, LabeledError> {
+    let span = item.span();
+    let document = match item {
+        NuValue::String { val, .. } => Document::from_bytes(val.into_bytes(), limits, span)?,
+        NuValue::Binary { val, .. } => Document::from_bytes(val, limits, span)?,
+        other => {
+            return Err(plist_error(
+                ErrorKind::UnsupportedType,
+                format!(
+                    "Invalid list item, must be string or binary not {}",
+                    other.get_type()
+                ),
+                span,
+            ))
+        }
+    };
+    convert_document(document, options, span)
+}
+
+/// Converts a whole document, including the rows of a root array.
+fn convert_document(
+    document: Document,
+    options: &ConvertOptions,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    if options.lenient {
+        return lenient_value(document, options, span);
+    }
+    match document {
+        Document::Events(events) => match events.options(options.clone()).into_rows(span)? {
+            Root::Rows(rows) => {
+                let rows: Vec<NuValue> = rows.collect();
+                // Rows stop at the first error, which is the last row
+                if let Some(NuValue::Error { error, .. }) = rows.last() {
+                    return Err(LabeledError::from(*error.clone()));
+                }
+                Ok(NuValue::list(rows, span))
+            }
+            Root::Value(value) => Ok(value),
+        },
+        Document::Value(plist) => into_nu_value_with(plist, options, span),
+    }
+}
+
+/// Calls `f` with the whole document, read from the file given with --path or
+/// collected from the input.
+fn with_input_bytes<T>(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    input: PipelineData,
+    f: impl FnOnce(&[u8], Span) -> Result<T, LabeledError>,
+) -> Result<T, LabeledError> {
+    if let Some(path) = call.get_flag::<Spanned<String>>("path")? {
+        let map = map_file(&resolve_path(engine, &path)?, path.span)?;
+        f(&map, path.span)
+    } else {
+        let value = input.into_value(call.head)?;
+        f(input_bytes(&value, call.head)?, value.span())
+    }
+}
+
+/// Decodes XML plists written back to back into a list.
+fn from_plist_multiple(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    input: PipelineData,
+    options: ConvertOptions,
+    limits: &Limits,
+) -> Result<PipelineData, LabeledError> {
+    with_input_bytes(engine, call, input, |bytes, span| {
+        let bytes = to_utf8(bytes).map_err(|e| plist_error(ErrorKind::Parse, e, span))?;
+        let documents = split_xml_documents(&bytes)
+            .into_iter()
+            .map(|document| {
+                convert_document(
+                    Document::from_bytes(document.to_vec(), limits, span)?,
+                    &options,
+                    span,
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(PipelineData::Value(NuValue::list(documents, span), None))
+    })
 }
 
-fn convert_plist_value(plist_val: &PlistValue, span: Span) -> Result<NuValue, LabeledError> {
+fn from_plist_metadata(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    input: PipelineData,
+    options: ConvertOptions,
+    limits: &Limits,
+) -> Result<PipelineData, LabeledError> {
+    with_input_bytes(engine, call, input, |bytes, span| {
+        let value = convert_document(
+            Document::from_bytes(bytes.to_vec(), limits, span)?,
+            &options,
+            span,
+        )?;
+        Ok(PipelineData::Value(
+            metadata_record(value, bytes, call.head),
+            None,
+        ))
+    })
+}
+
+fn from_plist_tagged(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    input: PipelineData,
+    options: ConvertOptions,
+    limits: &Limits,
+) -> Result<PipelineData, LabeledError> {
+    let metadata = call.has_flag("metadata")?;
+    with_input_bytes(engine, call, input, |bytes, span| {
+        let (plist, _) = parse_plist_with(bytes, limits, span)?;
+        let value = tagged_value(&plist, &options, span)?;
+        Ok(PipelineData::Value(
+            match metadata {
+                true => metadata_record(value, bytes, call.head),
+                false => value,
+            },
+            None,
+        ))
+    })
+}
+
+/// Wraps a decoded value with the format, version and size of the document it
+/// was read from.
+fn metadata_record(value: NuValue, bytes: &[u8], span: Span) -> NuValue {
+    let (format, version) = format_version(bytes);
+    let mut record = Record::new();
+    record.push("value", value);
+    record.push("format", NuValue::string(format.as_str(), span));
+    record.push(
+        "version",
+        version.map_or_else(
+            || NuValue::nothing(span),
+            |version| NuValue::string(version, span),
+        ),
+    );
+    record.push("bytes", NuValue::int(bytes.len() as i64, span));
+    NuValue::record(record, span)
+}
+
+/// Parses the whole document and returns a custom value that converts it on
+/// demand.
+fn from_plist_lazy(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+    input: PipelineData,
+    options: ConvertOptions,
+    limits: &Limits,
+) -> Result<PipelineData, LabeledError> {
+    let (plist, _) = if let Some(path) = call.get_flag::<Spanned<String>>("path")? {
+        let map = map_file(&resolve_path(engine, &path)?, path.span)?;
+        parse_plist_with(&map, limits, path.span)?
+    } else {
+        let value = input.into_value(call.head)?;
+        parse_plist_with(input_bytes(&value, call.head)?, limits, value.span())?
+    };
+    let document = PlistDocument::new(plist).options(options);
+    Ok(PipelineData::Value(
+        NuValue::custom(Box::new(document), call.head),
+        None,
+    ))
+}
+
+/// The categories of errors the plugin reports. Each has its own message and
+/// code, so scripts can tell them apart in `try`/`catch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Invalid arguments and flags.
+    General,
+    /// The input isn't a valid plist document.
+    Parse,
+    /// A value has no equivalent in the format it is converted to.
+    UnsupportedType,
+    /// A number or document is too large to represent or exceeds a limit.
+    Overflow,
+    /// Reading or writing a file failed.
+    Io,
+    /// A key, index or path isn't in the document.
+    NotFound,
+}
+
+impl ErrorKind {
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorKind::General => "nu_plugin_plist::error",
+            ErrorKind::Parse => "nu_plugin_plist::parse_error",
+            ErrorKind::UnsupportedType => "nu_plugin_plist::unsupported_type",
+            ErrorKind::Overflow => "nu_plugin_plist::overflow",
+            ErrorKind::Io => "nu_plugin_plist::io_error",
+            ErrorKind::NotFound => "nu_plugin_plist::not_found",
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            ErrorKind::General => "Plist command failed",
+            ErrorKind::Parse => "Failed to parse plist",
+            ErrorKind::UnsupportedType => "Unsupported type",
+            ErrorKind::Overflow => "Value out of range",
+            ErrorKind::Io => "I/O error",
+            ErrorKind::NotFound => "Not found",
+        }
+    }
+
+    fn help(self) -> Option<&'static str> {
+        match self {
+            ErrorKind::General | ErrorKind::Io => None,
+            ErrorKind::NotFound => Some("`plist paths` lists every path in a document"),
+            ErrorKind::Parse => Some("`plist validate` lists every problem in a document"),
+            ErrorKind::UnsupportedType => {
+                Some("plists hold strings, numbers, booleans, dates, binary, lists and records")
+            }
+            ErrorKind::Overflow => {
+                Some("--max-depth, --max-objects and --max-bytes raise the limits of from plist")
+            }
+        }
+    }
+}
+
+/// Builds an error of the given kind, labelling `span` with `msg`.
+pub fn plist_error(kind: ErrorKind, msg: String, span: Span) -> LabeledError {
+    let error = LabeledError::new(kind.message())
+        .with_label(msg, span)
+        .with_code(kind.code());
+    match kind.help() {
+        Some(help) => error.with_help(help),
+        None => error,
+    }
+}
+
+/// How plist values without a direct Nu equivalent are represented.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvertOptions {
+    pub uid_as: UidAs,
+    pub uint_as: UintAs,
+    pub dates_as: DatesAs,
+    pub timezone: Timezone,
+    pub data_as: DataAs,
+    /// Decode data holding a binary or XML plist.
+    pub decode_nested: bool,
+    /// Decode data holding a keyed archive.
+    pub decode_archives: bool,
+    /// Keep the bytes of decoded data alongside the decoded value.
+    pub keep_raw: bool,
+    pub on_duplicate: OnDuplicate,
+    /// Reject XML documents with repeated keys or content after the root
+    /// value.
+    pub strict: bool,
+    /// Keep what could be read of broken XML documents, noting what was
+    /// skipped.
+    pub lenient: bool,
+    /// How many more levels of arrays and dictionaries may be entered.
+    pub max_depth: usize,
+    /// Limits on plists nested in data.
+    pub limits: Limits,
+    /// How keyed archives nested in data are decoded.
+    pub archives: Arc<DecodeOptions>,
+    /// Runs the decoders of `archives`. Lazy documents come back from Nu
+    /// without it, and skip the decoders.
+    #[serde(skip)]
+    pub engine: Option<EngineInterface>,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            uid_as: UidAs::default(),
+            uint_as: UintAs::default(),
+            dates_as: DatesAs::default(),
+            timezone: Timezone::default(),
+            data_as: DataAs::default(),
+            decode_nested: false,
+            decode_archives: false,
+            keep_raw: false,
+            on_duplicate: OnDuplicate::default(),
+            strict: false,
+            lenient: false,
+            max_depth: MAX_DEPTH,
+            limits: Limits::default(),
+            archives: Arc::default(),
+            engine: None,
+        }
+    }
+}
+
+impl ConvertOptions {
+    /// Reads the conversion flags of `from plist`.
+    pub fn from_call(call: &EvaluatedCall) -> Result<ConvertOptions, LabeledError> {
+        let mut options = ConvertOptions {
+            decode_nested: call.has_flag("decode-nested")?,
+            decode_archives: call.has_flag("decode-archives")?,
+            keep_raw: call.has_flag("keep-raw")?,
+            strict: call.has_flag("strict")?,
+            lenient: call.has_flag("lenient")?,
+            ..Default::default()
+        };
+        if options.strict && options.lenient {
+            return Err(plist_error(
+                ErrorKind::General,
+                "--strict and --lenient can't be combined".to_string(),
+                call.head,
+            ));
+        }
+        if let Some(name) = call.get_flag::<Spanned<String>>("uid-as")? {
+            options.uid_as = UidAs::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown UID representation {}, expected int, float or record",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?;
+        }
+        if let Some(name) = call.get_flag::<Spanned<String>>("uint-as")? {
+            options.uint_as = UintAs::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown unsigned integer representation {}, expected record, string or float",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?;
+        }
+        let raw_dates = call.has_flag("raw-dates")?;
+        if let Some(name) = call.get_flag::<Spanned<String>>("dates-as")? {
+            options.dates_as = DatesAs::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown date representation {}, expected date, string, raw or tagged",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?;
+            if raw_dates && options.dates_as != DatesAs::Raw {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    "--raw-dates can't be combined with other --dates-as values".to_string(),
+                    name.span,
+                ));
+            }
+        }
+        if raw_dates {
+            options.dates_as = DatesAs::Raw;
+        }
+        if let Some(name) = call.get_flag::<Spanned<String>>("timezone")? {
+            options.timezone = Timezone::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown timezone {}, expected utc, local or an offset like +02:00",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?;
+        }
+        if let Some(name) = call.get_flag::<Spanned<String>>("data-as")? {
+            options.data_as = DataAs::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown data representation {}, expected binary, base64, hex or tagged",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?;
+        }
+        if let Some(name) = call.get_flag::<Spanned<String>>("on-duplicate")? {
+            options.on_duplicate = OnDuplicate::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown duplicate key policy {}, expected first, last, error or collect",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?;
+            if options.strict && options.on_duplicate != OnDuplicate::Error {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    "--strict always rejects duplicate keys".to_string(),
+                    name.span,
+                ));
+            }
+        }
+        if options.strict {
+            options.on_duplicate = OnDuplicate::Error;
+        }
+        if let Some(depth) = call.get_flag::<Spanned<i64>>("max-depth")? {
+            options.max_depth = usize::try_from(depth.item)
+                .ok()
+                .filter(|&depth| depth > 0)
+                .ok_or_else(|| {
+                    plist_error(
+                        ErrorKind::General,
+                        format!("Invalid depth {}, expected at least 1", depth.item),
+                        depth.span,
+                    )
+                })?;
+        }
+        Ok(options)
+    }
+}
+
+/// The depth left for the values inside an array or dictionary that may nest
+/// `depth` more levels, failing when that is nested too deeply.
+fn nested(depth: usize, span: Span) -> Result<usize, LabeledError> {
+    depth.checked_sub(1).ok_or_else(|| too_deep(span))
+}
+
+/// What to do when a dictionary repeats a key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OnDuplicate {
+    First,
+    /// Keep the last value, at the position of the first. CoreFoundation reads
+    /// duplicates the same way.
+    #[default]
+    Last,
+    Error,
+    /// Keep every value in a list.
+    Collect,
+}
+
+impl OnDuplicate {
+    pub fn from_name(name: &str) -> Option<OnDuplicate> {
+        match name {
+            "first" => Some(OnDuplicate::First),
+            "last" => Some(OnDuplicate::Last),
+            "error" => Some(OnDuplicate::Error),
+            "collect" => Some(OnDuplicate::Collect),
+            _ => None,
+        }
+    }
+}
+
+/// How plist data is represented in Nu.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataAs {
+    #[default]
+    Binary,
+    Base64,
+    Hex,
+    /// A `{__plist_data: <base64>}` record, which `to plist` turns back into
+    /// data.
+    Tagged,
+}
+
+impl DataAs {
+    pub fn from_name(name: &str) -> Option<DataAs> {
+        match name {
+            "binary" => Some(DataAs::Binary),
+            "base64" => Some(DataAs::Base64),
+            "hex" => Some(DataAs::Hex),
+            "tagged" => Some(DataAs::Tagged),
+            _ => None,
+        }
+    }
+
+    pub fn convert(self, data: Cow<[u8]>, span: Span) -> NuValue {
+        match self {
+            DataAs::Binary => NuValue::binary(data.into_owned(), span),
+            DataAs::Base64 => NuValue::string(BASE64.encode(data), span),
+            DataAs::Hex => NuValue::string(
+                data.iter().map(|b| format!("{b:02x}")).collect::<String>(),
+                span,
+            ),
+            DataAs::Tagged => {
+                let mut record = Record::new();
+                record.push(DATA_KEY, NuValue::string(BASE64.encode(data), span));
+                NuValue::record(record, span)
+            }
+        }
+    }
+}
+
+/// How plist UIDs are represented in Nu.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UidAs {
+    Int,
+    Float,
+    /// A `{CF$UID: n}` record, the way XML plists store UIDs.
+    #[default]
+    Record,
+}
+
+impl UidAs {
+    pub fn from_name(name: &str) -> Option<UidAs> {
+        match name {
+            "int" => Some(UidAs::Int),
+            "float" => Some(UidAs::Float),
+            "record" => Some(UidAs::Record),
+            _ => None,
+        }
+    }
+
+    pub fn convert(self, uid: Uid, span: Span) -> Result<NuValue, LabeledError> {
+        let int = || {
+            i64::try_from(uid.get()).map_err(|_| {
+                plist_error(
+                    ErrorKind::Overflow,
+                    format!("Cannot convert UID {} to i64", uid.get()),
+                    span,
+                )
+            })
+        };
+        match self {
+            UidAs::Int => Ok(NuValue::int(int()?, span)),
+            UidAs::Float => Ok(NuValue::float(uid.get() as f64, span)),
+            UidAs::Record => {
+                let mut record = Record::new();
+                record.push(UID_KEY, NuValue::int(int()?, span));
+                Ok(NuValue::record(record, span))
+            }
+        }
+    }
+}
+
+/// How integers above `i64::MAX`, which binary plists can hold, are
+/// represented in Nu. Other integers are always Nu ints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UintAs {
+    /// A `{__plist_uint: "<decimal>"}` record, which `to plist` turns back
+    /// into the integer.
+    #[default]
+    Record,
+    String,
+    /// The nearest float, losing precision.
+    Float,
+}
+
+impl UintAs {
+    pub fn from_name(name: &str) -> Option<UintAs> {
+        match name {
+            "record" => Some(UintAs::Record),
+            "string" => Some(UintAs::String),
+            "float" => Some(UintAs::Float),
+            _ => None,
+        }
+    }
+
+    pub fn convert(self, int: Integer, span: Span) -> NuValue {
+        if let Some(signed) = int.as_signed() {
+            return NuValue::int(signed, span);
+        }
+        // Plist integers are either i64 or u64, so this is above i64::MAX
+        let uint = int.as_unsigned().unwrap_or(u64::MAX);
+        match self {
+            UintAs::Record => {
+                let mut record = Record::new();
+                record.push(UINT_KEY, NuValue::string(uint.to_string(), span));
+                NuValue::record(record, span)
+            }
+            UintAs::String => NuValue::string(uint.to_string(), span),
+            UintAs::Float => NuValue::float(uint as f64, span),
+        }
+    }
+}
+
+/// How plist dates are represented in Nu.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DatesAs {
+    #[default]
+    Date,
+    /// An RFC 3339 string in the timezone given with `--timezone`, UTC by
+    /// default.
+    String,
+    /// A float counting the seconds since 2001-01-01, the epoch of Apple's
+    /// own tools.
+    Raw,
+    /// A `{__plist_date: <seconds since 2001>}` record, which `to plist` turns
+    /// back into a date.
+    Tagged,
+}
+
+impl DatesAs {
+    pub fn from_name(name: &str) -> Option<DatesAs> {
+        match name {
+            "date" => Some(DatesAs::Date),
+            "string" => Some(DatesAs::String),
+            "raw" => Some(DatesAs::Raw),
+            "tagged" => Some(DatesAs::Tagged),
+            _ => None,
+        }
+    }
+
+    pub fn convert(
+        self,
+        date: &PlistDate,
+        timezone: Timezone,
+        span: Span,
+    ) -> Result<NuValue, LabeledError> {
+        let Some(date) = convert_date(date) else {
+            return Err(plist_error(
+                ErrorKind::Overflow,
+                "Date is out of the range of Nu dates".to_string(),
+                span,
+            ));
+        };
+        let date = timezone.apply(date);
+        Ok(match self {
+            DatesAs::Date => NuValue::date(date, span),
+            DatesAs::String => {
+                NuValue::string(date.to_rfc3339_opts(SecondsFormat::AutoSi, true), span)
+            }
+            DatesAs::Raw => NuValue::float(apple_seconds(&date), span),
+            DatesAs::Tagged => {
+                let mut record = Record::new();
+                record.push(DATE_KEY, NuValue::float(apple_seconds(&date), span));
+                NuValue::record(record, span)
+            }
+        })
+    }
+}
+
+/// The timezone dates are presented in. Plists store instants, so this only
+/// changes the offset they are shown with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Timezone {
+    #[default]
+    Utc,
+    /// The timezone of the machine running the plugin.
+    Local,
+    /// A fixed offset east of UTC, in seconds.
+    Fixed(i32),
+}
+
+impl Timezone {
+    pub fn from_name(name: &str) -> Option<Timezone> {
+        match name.to_ascii_lowercase().as_str() {
+            "utc" | "z" => Some(Timezone::Utc),
+            "local" => Some(Timezone::Local),
+            _ => name
+                .parse::<FixedOffset>()
+                .ok()
+                .map(|offset| Timezone::Fixed(offset.local_minus_utc())),
+        }
+    }
+
+    pub fn apply(self, date: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self {
+            Timezone::Utc => date.with_timezone(&Utc.fix()),
+            Timezone::Local => {
+                let offset = Local.offset_from_utc_datetime(&date.naive_utc()).fix();
+                date.with_timezone(&offset)
+            }
+            Timezone::Fixed(seconds) => match FixedOffset::east_opt(seconds) {
+                Some(offset) => date.with_timezone(&offset),
+                None => date,
+            },
+        }
+    }
+}
+
+/// Seconds between the Unix epoch and the Apple epoch of 2001-01-01.
+pub const APPLE_EPOCH_OFFSET: i64 = 978_307_200;
+
+/// The seconds between the Apple epoch and a date.
+pub fn apple_seconds(date: &DateTime<FixedOffset>) -> f64 {
+    (date.timestamp() - APPLE_EPOCH_OFFSET) as f64 + f64::from(date.timestamp_subsec_nanos()) / 1e9
+}
+
+/// The date a number of seconds after the Apple epoch. A float can't hold
+/// nanoseconds for dates years away from 2001, so the fraction is rounded to
+/// the finest decimal step it can hold rather than truncated, which would turn
+/// `.123456` into `.123455999`.
+pub fn apple_time_to_date(seconds: f64, span: Span) -> Result<DateTime<FixedOffset>, LabeledError> {
+    apple_timestamp(seconds).ok_or_else(|| {
+        plist_error(
+            ErrorKind::Overflow,
+            format!("Date {seconds} is out of range"),
+            span,
+        )
+    })
+}
+
+/// Like [`apple_time_to_date`], `None` when the date is out of range.
+pub fn apple_timestamp(seconds: f64) -> Option<DateTime<FixedOffset>> {
+    let mut secs = seconds.floor();
+    let ulp_nanos = seconds.abs().max(1.0) * f64::EPSILON * 1e9;
+    let step = 10f64.powi(ulp_nanos.log10().ceil().max(0.0) as i32);
+    let mut nanos = ((seconds - secs) * 1e9 / step).round() * step;
+    if nanos >= 1e9 {
+        secs += 1.0;
+        nanos = 0.0;
+    }
+    if !secs.is_finite() {
+        return None;
+    }
+    DateTime::from_timestamp((secs as i64).checked_add(APPLE_EPOCH_OFFSET)?, nanos as u32)
+        .map(|d| d.fixed_offset())
+}
+
+/// The key of the records holding dates as seconds since 2001-01-01.
+pub const DATE_KEY: &str = "__plist_date";
+
+/// The key of the dictionaries XML plists use in place of UIDs.
+pub const UID_KEY: &str = "CF$UID";
+
+/// The key of the records holding base64 data, for formats such as JSON that
+/// can't hold binary.
+pub const DATA_KEY: &str = "__plist_data";
+
+/// The key of the records holding integers too large for a Nu int.
+pub const UINT_KEY: &str = "__plist_uint";
+
+pub fn convert_plist_value(plist_val: &PlistValue, span: Span) -> Result<NuValue, LabeledError> {
+    convert_plist_value_with(plist_val, &ConvertOptions::default(), span)
+}
+
+/// Like [`convert_plist_value`], with UIDs represented as requested.
+pub fn convert_plist_value_with(
+    plist_val: &PlistValue,
+    options: &ConvertOptions,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    convert_plist_value_at(plist_val, options, options.max_depth, span)
+}
+
+/// Converts a plist value whose arrays and dictionaries may nest `depth` more
+/// levels.
+fn convert_plist_value_at(
+    plist_val: &PlistValue,
+    options: &ConvertOptions,
+    depth: usize,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
     match plist_val {
         PlistValue::String(s) => Ok(NuValue::string(s.to_owned(), span)),
         PlistValue::Boolean(b) => Ok(NuValue::bool(*b, span)),
         PlistValue::Real(r) => Ok(NuValue::float(*r, span)),
-        PlistValue::Date(d) => Ok(NuValue::date(convert_date(d), span)),
-        PlistValue::Integer(i) => {
-            let signed = i
-                .as_signed()
-                .ok_or_else(|| build_label_error(format!("Cannot convert {i} to i64"), span))?;
-            Ok(NuValue::int(signed, span))
-        }
-        PlistValue::Uid(uid) => Ok(NuValue::float(uid.get() as f64, span)),
-        PlistValue::Data(data) => Ok(NuValue::binary(data.to_owned(), span)),
-        PlistValue::Array(arr) => Ok(NuValue::list(convert_array(arr, span)?, span)),
-        PlistValue::Dictionary(dict) => Ok(convert_dict(dict, span)?),
+        PlistValue::Date(d) => options.dates_as.convert(d, options.timezone, span),
+        PlistValue::Integer(i) => Ok(options.uint_as.convert(*i, span)),
+        PlistValue::Uid(uid) => options.uid_as.convert(*uid, span),
+        PlistValue::Data(data) => convert_data(Cow::Borrowed(data), options, depth, span),
+        PlistValue::Array(arr) => Ok(NuValue::list(
+            convert_array(arr, options, depth, span)?,
+            span,
+        )),
+        PlistValue::Dictionary(dict) => Ok(convert_dict(dict, options, depth, span)?),
         _ => Ok(NuValue::nothing(span)),
     }
 }
 
-fn convert_dict(dict: &Dictionary, span: Span) -> Result<NuValue, LabeledError> {
+/// Converts a plist value that is no longer needed, moving its strings and
+/// data into the Nu value rather than copying them.
+pub fn into_nu_value(plist_val: PlistValue, span: Span) -> Result<NuValue, LabeledError> {
+    into_nu_value_with(plist_val, &ConvertOptions::default(), span)
+}
+
+/// Like [`into_nu_value`], with UIDs represented as requested.
+pub fn into_nu_value_with(
+    plist_val: PlistValue,
+    options: &ConvertOptions,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    into_nu_value_at(plist_val, options, options.max_depth, span)
+}
+
+/// Like [`convert_plist_value_at`] for a value that is no longer needed.
+fn into_nu_value_at(
+    plist_val: PlistValue,
+    options: &ConvertOptions,
+    depth: usize,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    match plist_val {
+        PlistValue::String(s) => Ok(NuValue::string(s, span)),
+        PlistValue::Data(data) => convert_data(Cow::Owned(data), options, depth, span),
+        PlistValue::Array(arr) => Ok(NuValue::list(
+            array_into_nu(arr, options, depth, span)?,
+            span,
+        )),
+        PlistValue::Dictionary(dict) => dict_into_nu(dict, options, depth, span),
+        other => convert_plist_value_at(&other, options, depth, span),
+    }
+}
+
+/// Converts data, decoding it first if it holds a plist or keyed archive and
+/// the options ask for that. Data that fails to parse is kept as it is, and a
+/// decoded plist may nest `depth` more levels.
+pub fn convert_data(
+    data: Cow<[u8]>,
+    options: &ConvertOptions,
+    depth: usize,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    let nested = data.starts_with(b"bplist00") || PlistFormat::detect(&data) == PlistFormat::Xml;
+    if !nested || !(options.decode_nested || options.decode_archives) {
+        return Ok(options.data_as.convert(data, span));
+    }
+    let limits = Limits {
+        max_depth: depth,
+        ..options.limits
+    };
+    let value = match parse_plist_with(&data, &limits, span) {
+        Ok((plist, _)) if options.decode_archives && is_keyed_archive(&plist) => {
+            decode_archive(&plist, &options.archives, options.engine.as_ref(), span)?
+        }
+        Ok((plist, _)) if options.decode_nested => into_nu_value_at(plist, options, depth, span)?,
+        _ => return Ok(options.data_as.convert(data, span)),
+    };
+    if !options.keep_raw {
+        return Ok(value);
+    }
+    let mut record = Record::new();
+    record.push("value", value);
+    record.push("raw", options.data_as.convert(data, span));
+    Ok(NuValue::record(record, span))
+}
+
+fn array_into_nu(
+    plist_array: Vec<PlistValue>,
+    options: &ConvertOptions,
+    depth: usize,
+    span: Span,
+) -> Result<Vec<NuValue>, LabeledError> {
+    let depth = nested(depth, span)?;
+    if plist_array.len() >= PARALLEL_THRESHOLD {
+        return plist_array
+            .into_par_iter()
+            .map(|v| into_nu_value_at(v, options, depth, span))
+            .collect();
+    }
+    plist_array
+        .into_iter()
+        .map(|v| into_nu_value_at(v, options, depth, span))
+        .collect()
+}
+
+// Keys are moved into the record as they are rather than interned. Nu records
+// store every column name as its own `String`, and values are serialized to
+// the engine anyway, so shared key storage in the plugin would not reach Nu.
+fn dict_into_nu(
+    dict: Dictionary,
+    options: &ConvertOptions,
+    depth: usize,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    let depth = nested(depth, span)?;
+    let (cols, vals): (Vec<String>, Vec<PlistValue>) = dict.into_iter().unzip();
+    let vals = vals
+        .into_iter()
+        .map(|v| into_nu_value_at(v, options, depth, span))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(NuValue::record(
+        Record::from_raw_cols_vals(cols, vals, span, span)?,
+        span,
+    ))
+}
+
+fn convert_dict(
+    dict: &Dictionary,
+    options: &ConvertOptions,
+    depth: usize,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    let depth = nested(depth, span)?;
     let cols: Vec<String> = dict.keys().cloned().collect();
     let vals: Result<Vec<NuValue>, LabeledError> = dict
         .values()
-        .map(|v| convert_plist_value(v, span))
+        .map(|v| convert_plist_value_at(v, options, depth, span))
         .collect();
     Ok(NuValue::record(
         Record::from_raw_cols_vals(cols, vals?, span, span)?,
@@ -156,60 +2028,832 @@ fn convert_dict(dict: &Dictionary, span: Span) -> Result<NuValue, LabeledError>
     ))
 }
 
-fn convert_array(plist_array: &[PlistValue], span: Span) -> Result<Vec<NuValue>, LabeledError> {
+/// Converts a plist value to the tagged representation of `from plist --tagged`,
+/// where every value is a `{type, value}` record naming its exact plist type.
+/// Integers beyond the largest Nu int have the type `uint` and a string value.
+pub fn tagged_value(
+    plist_val: &PlistValue,
+    options: &ConvertOptions,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    tagged_value_at(plist_val, options, options.max_depth, span)
+}
+
+fn tagged_value_at(
+    plist_val: &PlistValue,
+    options: &ConvertOptions,
+    depth: usize,
+    span: Span,
+) -> Result<NuValue, LabeledError> {
+    let (type_name, value) = match plist_val {
+        PlistValue::Array(arr) => {
+            let depth = nested(depth, span)?;
+            let items = arr
+                .iter()
+                .map(|item| tagged_value_at(item, options, depth, span))
+                .collect::<Result<_, _>>()?;
+            ("array", NuValue::list(items, span))
+        }
+        PlistValue::Dictionary(dict) => {
+            let depth = nested(depth, span)?;
+            let mut record = Record::new();
+            for (key, item) in dict {
+                record.push(key.clone(), tagged_value_at(item, options, depth, span)?);
+            }
+            ("dict", NuValue::record(record, span))
+        }
+        PlistValue::Integer(i) => match i.as_signed() {
+            Some(signed) => ("int", NuValue::int(signed, span)),
+            None => ("uint", NuValue::string(i.to_string(), span)),
+        },
+        PlistValue::Uid(uid) => match i64::try_from(uid.get()) {
+            Ok(int) => ("uid", NuValue::int(int, span)),
+            Err(_) => ("uid", NuValue::string(uid.get().to_string(), span)),
+        },
+        PlistValue::Date(date) => ("date", DatesAs::Date.convert(date, options.timezone, span)?),
+        PlistValue::Real(r) => ("real", NuValue::float(*r, span)),
+        PlistValue::Boolean(b) => ("bool", NuValue::bool(*b, span)),
+        PlistValue::Data(data) => ("data", NuValue::binary(data.clone(), span)),
+        PlistValue::String(s) => ("string", NuValue::string(s.clone(), span)),
+        _ => return Ok(NuValue::nothing(span)),
+    };
+    let mut record = Record::new();
+    record.push("type", NuValue::string(type_name, span));
+    record.push("value", value);
+    Ok(NuValue::record(record, span))
+}
+
+fn convert_array(
+    plist_array: &[PlistValue],
+    options: &ConvertOptions,
+    depth: usize,
+    span: Span,
+) -> Result<Vec<NuValue>, LabeledError> {
+    let depth = nested(depth, span)?;
+    if plist_array.len() >= PARALLEL_THRESHOLD {
+        return plist_array
+            .par_iter()
+            .map(|v| convert_plist_value_at(v, options, depth, span))
+            .collect();
+    }
     plist_array
         .iter()
-        .map(|v| convert_plist_value(v, span))
+        .map(|v| convert_plist_value_at(v, options, depth, span))
         .collect()
 }
 
-pub fn convert_date(plist_date: &PlistDate) -> DateTime<FixedOffset> {
-    // In the docs the plist date object is listed as a utc timestamp, so this
-    // conversion shoould be fine
-    let plist_sys_time: SystemTime = plist_date.to_owned().into();
-    let utc_date: DateTime<Utc> = plist_sys_time.into();
-    let utc_offset = utc_date.offset().fix();
-    utc_date.with_timezone(&utc_offset)
+/// The Apple epoch of 2001-01-01 as a system time.
+pub fn apple_epoch() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(APPLE_EPOCH_OFFSET as u64)
+}
+
+/// Converts a plist date to a UTC date by its distance from the Apple epoch,
+/// `None` when it is beyond the years chrono can represent.
+pub fn convert_date(plist_date: &PlistDate) -> Option<DateTime<FixedOffset>> {
+    let (secs, nanos) = match SystemTime::from(*plist_date).duration_since(apple_epoch()) {
+        Ok(after) => (i64::try_from(after.as_secs()).ok()?, after.subsec_nanos()),
+        Err(e) => {
+            let before = e.duration();
+            let secs = i64::try_from(before.as_secs()).ok()?;
+            match before.subsec_nanos() {
+                0 => (-secs, 0),
+                nanos => (-secs - 1, 1_000_000_000 - nanos),
+            }
+        }
+    };
+    DateTime::from_timestamp(secs.checked_add(APPLE_EPOCH_OFFSET)?, nanos).map(|d| d.fixed_offset())
 }
 
-fn convert_nu_value(nu_val: &NuValue) -> Result<PlistValue, LabeledError> {
-    let span = Span::test_data();
+/// Converts a date to a plist date by its distance from the Apple epoch,
+/// `None` when the system clock can't represent it.
+pub fn plist_date<Tz: TimeZone>(date: &DateTime<Tz>) -> Option<PlistDate> {
+    let secs = date.timestamp() - APPLE_EPOCH_OFFSET;
+    let whole = Duration::from_secs(secs.unsigned_abs());
+    let time = match secs {
+        0.. => apple_epoch().checked_add(whole)?,
+        _ => apple_epoch().checked_sub(whole)?,
+    };
+    time.checked_add(Duration::from_nanos(date.timestamp_subsec_nanos().into()))
+        .map(PlistDate::from)
+}
+
+/// How Nu values without a direct plist equivalent are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToPlistOptions {
+    pub duration_unit: DurationUnit,
+    pub ranges_as: RangesAs,
+    pub filesize_as: FilesizeAs,
+    pub nulls: Nulls,
+}
+
+impl ToPlistOptions {
+    /// Reads the `--duration-unit`, `--range-as`, `--filesize-as` and `--nulls`
+    /// flags of `to plist`.
+    pub fn from_call(call: &EvaluatedCall) -> Result<ToPlistOptions, LabeledError> {
+        let mut options = ToPlistOptions::default();
+        if let Some(name) = call.get_flag::<Spanned<String>>("duration-unit")? {
+            options.duration_unit = DurationUnit::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown duration unit {}, expected sec, ms, us or ns",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?;
+        }
+        if let Some(name) = call.get_flag::<Spanned<String>>("range-as")? {
+            options.ranges_as = RangesAs::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown range representation {}, expected list or record",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?;
+        }
+        if let Some(name) = call.get_flag::<Spanned<String>>("filesize-as")? {
+            options.filesize_as = FilesizeAs::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown file size representation {}, expected bytes, string or a unit such as kB or MiB",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?;
+        }
+        if let Some(name) = call.get_flag::<Spanned<String>>("nulls")? {
+            options.nulls = Nulls::from_name(&name.item).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Unknown null policy {}, expected error, skip, empty-string or false",
+                        name.item
+                    ),
+                    name.span,
+                )
+            })?;
+        }
+        Ok(options)
+    }
+}
+
+/// What nulls are written as, since plists have no null.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Nulls {
+    #[default]
+    Error,
+    /// Leave out the keys and list items holding them.
+    Skip,
+    EmptyString,
+    False,
+}
+
+impl Nulls {
+    pub fn from_name(name: &str) -> Option<Nulls> {
+        match name {
+            "error" => Some(Nulls::Error),
+            "skip" => Some(Nulls::Skip),
+            "empty-string" => Some(Nulls::EmptyString),
+            "false" => Some(Nulls::False),
+            _ => None,
+        }
+    }
+}
+
+/// The units file sizes can be written in, with their size in bytes.
+const FILESIZE_UNITS: [(&str, u64); 13] = [
+    ("B", 1),
+    ("kB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("TB", 1_000_000_000_000),
+    ("PB", 1_000_000_000_000_000),
+    ("EB", 1_000_000_000_000_000_000),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("TiB", 1 << 40),
+    ("PiB", 1 << 50),
+    ("EiB", 1 << 60),
+];
+
+/// How file sizes are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilesizeAs {
+    /// An integer number of bytes.
+    #[default]
+    Bytes,
+    /// A real counting units of this many bytes.
+    Real(u64),
+    /// A string in the largest binary unit the size reaches, like `1.5 MiB`.
+    String,
+}
+
+impl FilesizeAs {
+    pub fn from_name(name: &str) -> Option<FilesizeAs> {
+        match name.to_ascii_lowercase().as_str() {
+            "bytes" | "int" => Some(FilesizeAs::Bytes),
+            "string" => Some(FilesizeAs::String),
+            unit => FILESIZE_UNITS
+                .iter()
+                .find(|(name, _)| name.to_ascii_lowercase() == unit)
+                .map(|(_, bytes)| FilesizeAs::Real(*bytes)),
+        }
+    }
+
+    pub fn convert(self, bytes: i64) -> PlistValue {
+        match self {
+            FilesizeAs::Bytes => PlistValue::Integer(bytes.into()),
+            FilesizeAs::Real(unit) => PlistValue::Real(bytes as f64 / unit as f64),
+            FilesizeAs::String => {
+                let binary = &FILESIZE_UNITS[7..];
+                match binary
+                    .iter()
+                    .rev()
+                    .find(|(_, size)| bytes.unsigned_abs() >= *size)
+                {
+                    Some((unit, size)) => {
+                        PlistValue::String(format!("{:.1} {unit}", bytes as f64 / *size as f64))
+                    }
+                    None => PlistValue::String(format!("{bytes} B")),
+                }
+            }
+        }
+    }
+}
+
+/// How ranges are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RangesAs {
+    /// An array of every value in the range.
+    #[default]
+    List,
+    /// A `{from, to, step, inclusive}` dictionary.
+    Record,
+}
+
+impl RangesAs {
+    pub fn from_name(name: &str) -> Option<RangesAs> {
+        match name {
+            "list" => Some(RangesAs::List),
+            "record" => Some(RangesAs::Record),
+            _ => None,
+        }
+    }
+
+    /// Converts a range, `None` when it has no end to list the values up to.
+    pub fn convert(self, range: &Range) -> Option<PlistValue> {
+        match self {
+            RangesAs::List => range_values(range).map(PlistValue::Array),
+            RangesAs::Record => Some(range_record(range)),
+        }
+    }
+}
+
+/// How many values a range has, `None` when it has no end.
+fn range_len(range: &Range) -> Option<u64> {
+    match range {
+        Range::IntRange(range) => {
+            let (start, step) = (i128::from(range.start()), i128::from(range.step()));
+            let last = match (range.end(), step > 0) {
+                (Bound::Included(end), _) => i128::from(end),
+                (Bound::Excluded(end), true) => i128::from(end) - 1,
+                (Bound::Excluded(end), false) => i128::from(end) + 1,
+                (Bound::Unbounded, _) => return None,
+            };
+            let len = match step {
+                0 => 0,
+                1.. if last >= start => (last - start) / step + 1,
+                ..=-1 if last <= start => (start - last) / -step + 1,
+                _ => 0,
+            };
+            Some(len as u64)
+        }
+        Range::FloatRange(range) => {
+            let end = match range.end() {
+                Bound::Included(end) | Bound::Excluded(end) => end,
+                Bound::Unbounded => return None,
+            };
+            // An upper bound, whether the last step lands on the end is left
+            // to range_values
+            let steps = (end - range.start()) / range.step();
+            Some(match steps {
+                _ if range.step() == 0.0 || steps.is_nan() || steps < 0.0 => 0,
+                _ => (steps.floor() + 1.0).min(u64::MAX as f64) as u64,
+            })
+        }
+    }
+}
+
+/// Every value of a range with an end.
+fn range_values(range: &Range) -> Option<Vec<PlistValue>> {
+    let len = range_len(range)?;
+    let values = match range {
+        Range::IntRange(range) => (0..len)
+            .map(|i| {
+                let value = i128::from(range.start()) + i128::from(range.step()) * i128::from(i);
+                PlistValue::Integer((value as i64).into())
+            })
+            .collect(),
+        Range::FloatRange(range) => {
+            let (end, inclusive) = match range.end() {
+                Bound::Included(end) => (end, true),
+                Bound::Excluded(end) => (end, false),
+                Bound::Unbounded => return None,
+            };
+            let step = range.step();
+            // Multiplying rather than adding up steps keeps rounding errors
+            // from accumulating
+            (0..len)
+                .map(|i| range.start() + step * i as f64)
+                .take_while(|value| match (step > 0.0, inclusive) {
+                    (true, true) => *value <= end,
+                    (true, false) => *value < end,
+                    (false, true) => *value >= end,
+                    (false, false) => *value > end,
+                })
+                .map(PlistValue::Real)
+                .collect()
+        }
+    };
+    Some(values)
+}
+
+/// A `{from, to, step, inclusive}` dictionary describing a range.
+fn range_record(range: &Range) -> PlistValue {
+    let mut dict = Dictionary::new();
+    let end = match range {
+        Range::IntRange(range) => {
+            dict.insert(
+                "from".to_string(),
+                PlistValue::Integer(range.start().into()),
+            );
+            dict.insert("step".to_string(), PlistValue::Integer(range.step().into()));
+            range.end().map(|end| PlistValue::Integer(end.into()))
+        }
+        Range::FloatRange(range) => {
+            dict.insert("from".to_string(), PlistValue::Real(range.start()));
+            dict.insert("step".to_string(), PlistValue::Real(range.step()));
+            range.end().map(PlistValue::Real)
+        }
+    };
+    let inclusive = match end {
+        Bound::Included(end) => {
+            dict.insert("to".to_string(), end);
+            true
+        }
+        Bound::Excluded(end) => {
+            dict.insert("to".to_string(), end);
+            false
+        }
+        Bound::Unbounded => true,
+    };
+    dict.insert("inclusive".to_string(), PlistValue::Boolean(inclusive));
+    PlistValue::Dictionary(dict)
+}
+
+/// The unit durations are written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurationUnit {
+    #[default]
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    /// Integer nanoseconds, the precision Nu keeps durations in.
+    Nanoseconds,
+}
+
+impl DurationUnit {
+    pub fn from_name(name: &str) -> Option<DurationUnit> {
+        match name {
+            "sec" | "s" => Some(DurationUnit::Seconds),
+            "ms" => Some(DurationUnit::Milliseconds),
+            "us" | "µs" => Some(DurationUnit::Microseconds),
+            "ns" => Some(DurationUnit::Nanoseconds),
+            _ => None,
+        }
+    }
+
+    /// Converts a duration in nanoseconds.
+    pub fn convert(self, nanos: i64) -> PlistValue {
+        match self {
+            DurationUnit::Seconds => PlistValue::Real(nanos as f64 / 1e9),
+            DurationUnit::Milliseconds => PlistValue::Real(nanos as f64 / 1e6),
+            DurationUnit::Microseconds => PlistValue::Real(nanos as f64 / 1e3),
+            DurationUnit::Nanoseconds => PlistValue::Integer(nanos.into()),
+        }
+    }
+}
+
+pub fn convert_nu_value(nu_val: &NuValue) -> Result<PlistValue, LabeledError> {
+    convert_nu_value_with(nu_val, ToPlistOptions::default())
+}
+
+pub fn convert_nu_value_with(
+    nu_val: &NuValue,
+    options: ToPlistOptions,
+) -> Result<PlistValue, LabeledError> {
+    convert_nu_value_at(nu_val, options, MAX_DEPTH, &mut Vec::new())
+}
+
+/// Joins the keys and indices leading to a value into a cell path for error
+/// messages.
+pub fn describe_path(path: &[String]) -> String {
+    if path.is_empty() {
+        "the root".to_string()
+    } else {
+        path.join(".")
+    }
+}
+
+/// Converts a Nu value whose lists and records may nest `depth` more levels.
+/// `path` leads to the value, for errors.
+fn convert_nu_value_at(
+    nu_val: &NuValue,
+    options: ToPlistOptions,
+    depth: usize,
+    path: &mut Vec<String>,
+) -> Result<PlistValue, LabeledError> {
+    let nested = || {
+        depth.checked_sub(1).ok_or_else(|| {
+            plist_error(
+                ErrorKind::Overflow,
+                format!("Value is nested more than {MAX_DEPTH} levels deep"),
+                nu_val.span(),
+            )
+        })
+    };
     match nu_val {
         NuValue::String { val, .. } => Ok(PlistValue::String(val.to_owned())),
         NuValue::Bool { val, .. } => Ok(PlistValue::Boolean(*val)),
         NuValue::Float { val, .. } => Ok(PlistValue::Real(*val)),
         NuValue::Int { val, .. } => Ok(PlistValue::Integer(Into::<Integer>::into(*val))),
         NuValue::Binary { val, .. } => Ok(PlistValue::Data(val.to_owned())),
-        NuValue::Record { val, .. } => convert_nu_dict(val),
-        NuValue::List { vals, .. } => Ok(PlistValue::Array(
-            vals.iter()
-                .map(convert_nu_value)
-                .collect::<Result<_, _>>()?,
+        NuValue::Record { val, .. } => convert_nu_dict(val, options, nested()?, path),
+        NuValue::List { vals, .. } => {
+            let depth = nested()?;
+            let mut items = Vec::with_capacity(vals.len());
+            for (index, v) in vals.iter().enumerate() {
+                if v.is_nothing() && options.nulls == Nulls::Skip {
+                    continue;
+                }
+                path.push(index.to_string());
+                items.push(convert_nu_value_at(v, options, depth, path)?);
+                path.pop();
+            }
+            Ok(PlistValue::Array(items))
+        }
+        NuValue::Date { val, .. } => plist_date(val).map(PlistValue::Date).ok_or_else(|| {
+            plist_error(
+                ErrorKind::Overflow,
+                format!("The date at {} is out of range", describe_path(path)),
+                nu_val.span(),
+            )
+        }),
+        NuValue::Filesize { val, .. } => Ok(options.filesize_as.convert(*val)),
+        NuValue::Custom { val, .. } => {
+            // Lazy documents hold the plist itself, which keeps values such as
+            // UIDs exactly as they were read
+            if let Some(document) = val.as_any().downcast_ref::<PlistDocument>() {
+                if let Some(value) = document.plist_value(nu_val.span())? {
+                    return Ok(value);
+                }
+            }
+            let base = val.to_base_value(nu_val.span())?;
+            convert_nu_value_at(&base, options, nested()?, path)
+        }
+        NuValue::Glob { val, .. } => Ok(PlistValue::String(val.to_owned())),
+        NuValue::CellPath { val, .. } => Ok(PlistValue::String(
+            val.members
+                .iter()
+                .map(|member| match member {
+                    PathMember::String { val, optional, .. } => {
+                        format!("{val}{}", if *optional { "?" } else { "" })
+                    }
+                    PathMember::Int { val, optional, .. } => {
+                        format!("{val}{}", if *optional { "?" } else { "" })
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("."),
+        )),
+        NuValue::Closure { .. } => Err(plist_error(
+            ErrorKind::UnsupportedType,
+            format!(
+                "The closure at {} can't be written to a plist, call it with `do` first or leave it out",
+                describe_path(path)
+            ),
+            nu_val.span(),
+        )),
+        NuValue::Nothing { .. } if options.nulls == Nulls::EmptyString => {
+            Ok(PlistValue::String(String::new()))
+        }
+        NuValue::Nothing { .. } if options.nulls == Nulls::False => Ok(PlistValue::Boolean(false)),
+        NuValue::Duration { val, .. } => Ok(options.duration_unit.convert(*val)),
+        NuValue::Range { val, .. } => {
+            // Listing a range like 0..1_000_000_000_000 would exhaust memory
+            let max = Limits::default().max_objects;
+            let len = range_len(val).unwrap_or(0);
+            if options.ranges_as == RangesAs::List && len > max {
+                return Err(plist_error(
+                    ErrorKind::Overflow,
+                    format!(
+                        "The range at {} has {len} values, more than {max}, so it can only be written with --range-as record",
+                        describe_path(path)
+                    ),
+                    nu_val.span(),
+                ));
+            }
+            options.ranges_as.convert(val).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::UnsupportedType,
+                    format!(
+                        "The range at {} has no end, so it can only be written with --range-as record",
+                        describe_path(path)
+                    ),
+                    nu_val.span(),
+                )
+            })
+        }
+        _ => Err(plist_error(
+            ErrorKind::UnsupportedType,
+            format!(
+                "Can't convert the {} at {} to a plist value",
+                nu_val.get_type(),
+                describe_path(path)
+            ),
+            nu_val.span(),
+        )),
+    }
+}
+
+/// Like [`convert_nu_value_with`] for a value that is no longer needed, moving
+/// its strings and binary data into the plist rather than copying them.
+pub fn into_plist_value_with(
+    nu_val: NuValue,
+    options: ToPlistOptions,
+) -> Result<PlistValue, LabeledError> {
+    into_plist_value_at(nu_val, options, MAX_DEPTH, &mut Vec::new())
+}
+
+fn into_plist_value_at(
+    nu_val: NuValue,
+    options: ToPlistOptions,
+    depth: usize,
+    path: &mut Vec<String>,
+) -> Result<PlistValue, LabeledError> {
+    let span = nu_val.span();
+    let nested = || {
+        depth.checked_sub(1).ok_or_else(|| {
+            plist_error(
+                ErrorKind::Overflow,
+                format!("Value is nested more than {MAX_DEPTH} levels deep"),
+                span,
+            )
+        })
+    };
+    match nu_val {
+        NuValue::String { val, .. } => Ok(PlistValue::String(val)),
+        NuValue::Binary { val, .. } => Ok(PlistValue::Data(val)),
+        NuValue::List { vals, .. } => {
+            let depth = nested()?;
+            let mut items = Vec::with_capacity(vals.len());
+            for (index, v) in vals.into_iter().enumerate() {
+                if v.is_nothing() && options.nulls == Nulls::Skip {
+                    continue;
+                }
+                path.push(index.to_string());
+                items.push(into_plist_value_at(v, options, depth, path)?);
+                path.pop();
+            }
+            Ok(PlistValue::Array(items))
+        }
+        NuValue::Record { val, .. } => {
+            if let Some(value) = convert_marker_record(&val) {
+                return value;
+            }
+            let depth = nested()?;
+            let mut dict = Dictionary::new();
+            for (k, v) in val.into_owned() {
+                if v.is_nothing() && options.nulls == Nulls::Skip {
+                    continue;
+                }
+                path.push(k.clone());
+                dict.insert(k, into_plist_value_at(v, options, depth, path)?);
+                path.pop();
+            }
+            Ok(PlistValue::Dictionary(dict))
+        }
+        other => convert_nu_value_at(&other, options, depth, path),
+    }
+}
+
+/// Converts the tagged representation written by `from plist --tagged` back
+/// to the plist value it was read from.
+pub fn from_tagged(nu_val: &NuValue) -> Result<PlistValue, LabeledError> {
+    from_tagged_at(nu_val, MAX_DEPTH, &mut Vec::new())
+}
+
+fn invalid_tagged(path: &[String], message: String, span: Span) -> LabeledError {
+    plist_error(
+        ErrorKind::UnsupportedType,
+        format!("Invalid tagged value at {}: {message}", describe_path(path)),
+        span,
+    )
+}
+
+fn from_tagged_at(
+    nu_val: &NuValue,
+    depth: usize,
+    path: &mut Vec<String>,
+) -> Result<PlistValue, LabeledError> {
+    let span = nu_val.span();
+    let fields = match nu_val {
+        NuValue::Record { val, .. } if val.len() == 2 => val.get("type").zip(val.get("value")),
+        _ => None,
+    };
+    let Some((NuValue::String { val: type_name, .. }, value)) = fields else {
+        return Err(invalid_tagged(
+            path,
+            format!(
+                "expected a {{type, value}} record, not {}",
+                nu_val.get_type()
+            ),
+            span,
+        ));
+    };
+    let nested = || {
+        depth.checked_sub(1).ok_or_else(|| {
+            plist_error(
+                ErrorKind::Overflow,
+                format!("Value is nested more than {MAX_DEPTH} levels deep"),
+                span,
+            )
+        })
+    };
+    let parse_u64 = |digits: &str| {
+        digits.parse::<u64>().map_err(|e| {
+            invalid_tagged(
+                path,
+                format!("invalid {type_name} {digits}: {e}"),
+                value.span(),
+            )
+        })
+    };
+    match (type_name.as_str(), value) {
+        ("string", NuValue::String { val, .. }) => Ok(PlistValue::String(val.clone())),
+        ("bool", NuValue::Bool { val, .. }) => Ok(PlistValue::Boolean(*val)),
+        ("real", NuValue::Float { val, .. }) => Ok(PlistValue::Real(*val)),
+        ("int", NuValue::Int { val, .. }) => Ok(PlistValue::Integer((*val).into())),
+        ("uint", NuValue::String { val, .. }) => Ok(PlistValue::Integer(parse_u64(val)?.into())),
+        ("uid", NuValue::Int { val, .. }) => u64::try_from(*val)
+            .map(|uid| PlistValue::Uid(Uid::new(uid)))
+            .map_err(|_| invalid_tagged(path, format!("invalid uid {val}"), value.span())),
+        ("uid", NuValue::String { val, .. }) => Ok(PlistValue::Uid(Uid::new(parse_u64(val)?))),
+        ("data", NuValue::Binary { val, .. }) => Ok(PlistValue::Data(val.clone())),
+        ("date", NuValue::Date { val, .. }) => {
+            plist_date(val).map(PlistValue::Date).ok_or_else(|| {
+                plist_error(
+                    ErrorKind::Overflow,
+                    format!("The date at {} is out of range", describe_path(path)),
+                    value.span(),
+                )
+            })
+        }
+        ("array", NuValue::List { vals, .. }) => {
+            let depth = nested()?;
+            let mut items = Vec::with_capacity(vals.len());
+            for (index, item) in vals.iter().enumerate() {
+                path.push(index.to_string());
+                items.push(from_tagged_at(item, depth, path)?);
+                path.pop();
+            }
+            Ok(PlistValue::Array(items))
+        }
+        ("dict", NuValue::Record { val, .. }) => {
+            let depth = nested()?;
+            let mut dict = Dictionary::new();
+            for (key, item) in val.iter() {
+                path.push(key.clone());
+                dict.insert(key.clone(), from_tagged_at(item, depth, path)?);
+                path.pop();
+            }
+            Ok(PlistValue::Dictionary(dict))
+        }
+        (
+            "string" | "bool" | "real" | "int" | "uint" | "uid" | "data" | "date" | "array"
+            | "dict",
+            _,
+        ) => Err(invalid_tagged(
+            path,
+            format!(
+                "{type_name} can't hold a value of type {}",
+                value.get_type()
+            ),
+            value.span(),
         )),
-        NuValue::Date { val, .. } => Ok(PlistValue::Date(SystemTime::from(val.to_owned()).into())),
-        NuValue::Filesize { val, .. } => Ok(PlistValue::Integer(Into::<Integer>::into(*val))),
-        _ => Err(build_label_error(
-            format!("{:?} is not convertible", nu_val),
+        _ => Err(invalid_tagged(
+            path,
+            format!("unknown type {type_name}"),
             span,
         )),
     }
 }
 
-fn convert_nu_dict(record: &Record) -> Result<PlistValue, LabeledError> {
-    Ok(PlistValue::Dictionary(
-        record
-            .iter()
-            .map(|(k, v)| convert_nu_value(v).map(|v| (k.to_owned(), v)))
-            .collect::<Result<_, _>>()?,
-    ))
+/// Reads the single-key records, such as `{__plist_uid: 5}`, that stand for
+/// plist values Nu has no type for.
+fn convert_marker_record(record: &Record) -> Option<Result<PlistValue, LabeledError>> {
+    if let (1, Some(NuValue::Int { val, .. })) = (record.len(), record.get(UID_KEY)) {
+        if let Ok(uid) = u64::try_from(*val) {
+            return Some(Ok(PlistValue::Uid(Uid::new(uid))));
+        }
+    }
+    if let (1, Some(NuValue::String { val, internal_span })) = (record.len(), record.get(UINT_KEY))
+    {
+        return Some(
+            val.parse::<u64>()
+                .map(|uint| PlistValue::Integer(uint.into()))
+                .map_err(|e| {
+                    plist_error(
+                        ErrorKind::UnsupportedType,
+                        format!("Invalid integer in {UINT_KEY}: {e}"),
+                        *internal_span,
+                    )
+                }),
+        );
+    }
+    if let (1, Some(value)) = (record.len(), record.get(DATE_KEY)) {
+        let seconds = match value {
+            NuValue::Float { val, .. } => Some(*val),
+            NuValue::Int { val, .. } => Some(*val as f64),
+            _ => None,
+        };
+        if let Some(seconds) = seconds {
+            return Some(
+                apple_timestamp(seconds)
+                    .and_then(|date| plist_date(&date))
+                    .map(PlistValue::Date)
+                    .ok_or_else(|| {
+                        plist_error(
+                            ErrorKind::Overflow,
+                            format!("Date {seconds} is out of range"),
+                            value.span(),
+                        )
+                    }),
+            );
+        }
+    }
+    if let (1, Some(NuValue::String { val, internal_span })) = (record.len(), record.get(DATA_KEY))
+    {
+        return Some(BASE64.decode(val).map(PlistValue::Data).map_err(|e| {
+            plist_error(
+                ErrorKind::Parse,
+                format!("Invalid base64 in {DATA_KEY}: {e}"),
+                *internal_span,
+            )
+        }));
+    }
+    None
+}
+
+fn convert_nu_dict(
+    record: &Record,
+    options: ToPlistOptions,
+    depth: usize,
+    path: &mut Vec<String>,
+) -> Result<PlistValue, LabeledError> {
+    if let Some(value) = convert_marker_record(record) {
+        return value;
+    }
+    let mut dict = Dictionary::new();
+    for (k, v) in record.iter() {
+        if v.is_nothing() && options.nulls == Nulls::Skip {
+            continue;
+        }
+        path.push(k.to_owned());
+        dict.insert(k.to_owned(), convert_nu_value_at(v, options, depth, path)?);
+        path.pop();
+    }
+    Ok(PlistValue::Dictionary(dict))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use chrono::Datelike;
-    use plist::Uid;
-    use std::time::SystemTime;
+    use crate::format::{parse_plist, plist_bytes};
+    use chrono::{Datelike, NaiveDate};
+    use nu_protocol::{
+        ast::{CellPath, RangeInclusion},
+        engine::Closure,
+        FloatRange, IntRange,
+    };
+    use std::time::{Duration, SystemTime};
 
     #[test]
     fn test_convert_string() {
@@ -247,8 +2891,607 @@ mod test {
         let v = 12345678_u64;
         let uid = Uid::new(v);
         let plist_val = PlistValue::Uid(uid);
-        let result = convert_plist_value(&plist_val, Span::test_data());
-        assert_eq!(result, Ok(NuValue::float(v as f64, Span::test_data())));
+        let span = Span::test_data();
+        let result = convert_plist_value(&plist_val, span);
+        let mut record = Record::new();
+        record.push(UID_KEY, NuValue::int(v as i64, span));
+        assert_eq!(result, Ok(NuValue::record(record, span)));
+        assert_eq!(
+            convert_plist_value_with(&plist_val, &uid_as(UidAs::Int), span),
+            Ok(NuValue::int(v as i64, span))
+        );
+        assert_eq!(
+            convert_plist_value_with(&plist_val, &uid_as(UidAs::Float), span),
+            Ok(NuValue::float(v as f64, span))
+        );
+        let value = result.expect("failed to convert");
+        assert_eq!(convert_nu_value(&value).ok(), Some(plist_val));
+    }
+
+    #[test]
+    fn test_convert_error_path() {
+        let span = Span::test_data();
+        let mut item = Record::new();
+        item.push("callback", NuValue::test_nothing());
+        let mut payload = Record::new();
+        payload.push(
+            "items",
+            NuValue::test_list(vec![NuValue::test_int(1), NuValue::test_record(item)]),
+        );
+        let mut root = Record::new();
+        root.push("payload", NuValue::record(payload, span));
+        let error = convert_nu_value(&NuValue::test_record(root)).expect_err("expected an error");
+        let text = &error.labels[0].text;
+        assert!(
+            text.contains("nothing at payload.items.1.callback"),
+            "{text}"
+        );
+
+        let error = convert_nu_value(&NuValue::test_nothing()).expect_err("expected an error");
+        assert!(error.labels[0].text.contains("the root"));
+    }
+
+    #[test]
+    fn test_convert_duration() {
+        let span = Span::test_data();
+        let mut record = Record::new();
+        record.push("timeout", NuValue::duration(30_500_000_000, span));
+        let value = NuValue::record(record, span);
+        let plist_val = convert_nu_value(&value).expect("failed to convert");
+        assert_eq!(
+            plist_val.as_dictionary().and_then(|d| d.get("timeout")),
+            Some(&PlistValue::Real(30.5))
+        );
+
+        let convert = |unit| {
+            let options = ToPlistOptions {
+                duration_unit: unit,
+                ..Default::default()
+            };
+            convert_nu_value_with(&NuValue::duration(1_500_000, span), options)
+        };
+        assert_eq!(
+            convert(DurationUnit::Milliseconds),
+            Ok(PlistValue::Real(1.5))
+        );
+        assert_eq!(
+            convert(DurationUnit::Nanoseconds),
+            Ok(PlistValue::Integer(1_500_000.into()))
+        );
+        assert_eq!(
+            DurationUnit::from_name("us"),
+            Some(DurationUnit::Microseconds)
+        );
+        assert_eq!(DurationUnit::from_name("fortnight"), None);
+    }
+
+    #[test]
+    fn test_convert_filesize() {
+        let span = Span::test_data();
+        let size = NuValue::filesize(1_572_864, span);
+        assert_eq!(
+            convert_nu_value(&size),
+            Ok(PlistValue::Integer(1_572_864.into()))
+        );
+        let convert = |name| {
+            let options = ToPlistOptions {
+                filesize_as: FilesizeAs::from_name(name).expect("unknown representation"),
+                ..Default::default()
+            };
+            convert_nu_value_with(&size, options)
+        };
+        assert_eq!(convert("MiB"), Ok(PlistValue::Real(1.5)));
+        assert_eq!(convert("kb"), Ok(PlistValue::Real(1572.864)));
+        assert_eq!(convert("string"), Ok(PlistValue::String("1.5 MiB".into())));
+        assert_eq!(FilesizeAs::from_name("furlongs"), None);
+    }
+
+    #[test]
+    fn test_convert_nulls() {
+        let span = Span::test_data();
+        let mut record = Record::new();
+        record.push("name", NuValue::string("a", span));
+        record.push("missing", NuValue::nothing(span));
+        record.push(
+            "items",
+            NuValue::list(vec![NuValue::nothing(span), NuValue::int(1, span)], span),
+        );
+        let value = NuValue::record(record, span);
+        assert!(convert_nu_value(&value).is_err());
+
+        let convert = |nulls| {
+            let options = ToPlistOptions {
+                nulls,
+                ..Default::default()
+            };
+            convert_nu_value_with(&value, options).expect("failed to convert")
+        };
+        let skipped = convert(Nulls::Skip);
+        let dict = skipped.as_dictionary().expect("expected a dictionary");
+        assert_eq!(dict.get("missing"), None);
+        assert_eq!(dict.get("items"), Some(&PlistValue::Array(vec![1.into()])));
+        let dict = convert(Nulls::EmptyString);
+        assert_eq!(
+            dict.as_dictionary().and_then(|d| d.get("missing")),
+            Some(&PlistValue::String(String::new()))
+        );
+        let dict = convert(Nulls::False);
+        assert_eq!(
+            dict.as_dictionary().and_then(|d| d.get("missing")),
+            Some(&PlistValue::Boolean(false))
+        );
+    }
+
+    #[test]
+    fn test_convert_custom() {
+        let span = Span::test_data();
+        let mut dict = Dictionary::new();
+        dict.insert("root".to_string(), PlistValue::Uid(Uid::new(1)));
+        dict.insert("count".to_string(), 3.into());
+        let plist_val = PlistValue::Dictionary(dict);
+        let document = PlistDocument::new(plist_val.clone());
+        let value = NuValue::custom(Box::new(document.clone()), span);
+        assert_eq!(convert_nu_value(&value), Ok(plist_val.clone()));
+
+        let mut record = Record::new();
+        record.push("lazy", value);
+        let mut expected = Dictionary::new();
+        expected.insert("lazy".to_string(), plist_val);
+        assert_eq!(
+            convert_nu_value(&NuValue::record(record, span)),
+            Ok(PlistValue::Dictionary(expected))
+        );
+        document.release();
+    }
+
+    #[test]
+    fn test_convert_glob_cell_path_closure() {
+        let span = Span::test_data();
+        assert_eq!(
+            convert_nu_value(&NuValue::glob("*.plist", false, span)),
+            Ok(PlistValue::String("*.plist".into()))
+        );
+        let cell_path = CellPath {
+            members: vec![
+                PathMember::string("a".into(), false, span),
+                PathMember::int(0, true, span),
+            ],
+        };
+        assert_eq!(
+            convert_nu_value(&NuValue::cell_path(cell_path, span)),
+            Ok(PlistValue::String("a.0?".into()))
+        );
+
+        let closure = Closure {
+            block_id: 0,
+            captures: Vec::new(),
+        };
+        let mut record = Record::new();
+        record.push("on_change", NuValue::closure(closure, span));
+        let error =
+            convert_nu_value(&NuValue::record(record, span)).expect_err("expected an error");
+        assert!(error.labels[0].text.contains("closure at on_change"));
+    }
+
+    #[test]
+    fn test_array_rows_stop_at_error() {
+        let span = Span::test_data();
+        let far = std::time::UNIX_EPOCH + std::time::Duration::from_secs(9_000_000_000_000);
+        let rows = vec![
+            PlistValue::Integer(1.into()),
+            PlistValue::Date(far.into()),
+            PlistValue::Integer(2.into()),
+        ];
+        let rows: Vec<NuValue> = array_rows(rows, ConvertOptions::default(), span)
+            .expect("failed to convert")
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], NuValue::test_int(1));
+        assert!(matches!(rows[1], NuValue::Error { .. }));
+    }
+
+    #[test]
+    fn test_array_rows_max_depth() {
+        let span = Span::test_data();
+        let rows = || vec![PlistValue::Array(vec![PlistValue::Integer(1.into())])];
+        let options = ConvertOptions {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let streamed: Vec<NuValue> = array_rows(rows(), options.clone(), span)
+            .expect("failed to convert")
+            .collect();
+        let whole = into_nu_value_with(PlistValue::Array(rows()), &options, span)
+            .expect("failed to convert");
+        assert_eq!(NuValue::list(streamed, span), whole);
+
+        // One level less is too deep for both
+        let options = ConvertOptions {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let streamed: Vec<NuValue> = array_rows(rows(), options.clone(), span)
+            .expect("failed to convert")
+            .collect();
+        assert!(matches!(streamed[0], NuValue::Error { .. }));
+        assert!(into_nu_value_with(PlistValue::Array(rows()), &options, span).is_err());
+    }
+
+    #[test]
+    fn test_from_plist_item() {
+        let span = Span::test_data();
+        let limits = Limits::default();
+        let options = ConvertOptions::default();
+        let xml = NuValue::string(
+            r#"<plist version="1.0"><array><integer>1</integer><integer>2</integer></array></plist>"#,
+            span,
+        );
+        assert_eq!(
+            from_plist_item(xml, &options, &limits),
+            Ok(NuValue::list(
+                vec![NuValue::int(1, span), NuValue::int(2, span)],
+                span
+            ))
+        );
+        let bytes =
+            crate::format::plist_bytes(&PlistValue::Boolean(true), PlistFormat::Binary, span)
+                .expect("failed to write");
+        assert_eq!(
+            from_plist_item(NuValue::binary(bytes, span), &options, &limits),
+            Ok(NuValue::bool(true, span))
+        );
+        let fragment = NuValue::string("<string>a</string>", span);
+        assert_eq!(
+            from_plist_item(fragment, &options, &limits),
+            Ok(NuValue::string("a", span))
+        );
+        let broken = NuValue::string("<plist><array><integer>1</integer>", span);
+        assert!(from_plist_item(broken, &options, &limits).is_err());
+        assert!(from_plist_item(NuValue::int(1, span), &options, &limits).is_err());
+    }
+
+    #[test]
+    fn test_tagged_value() {
+        let span = Span::test_data();
+        let tagged = |type_name: &str, value: NuValue| {
+            let mut record = Record::new();
+            record.push("type", NuValue::string(type_name, span));
+            record.push("value", value);
+            NuValue::record(record, span)
+        };
+        let mut dict = Dictionary::new();
+        dict.insert("real".to_string(), PlistValue::Real(1.0));
+        dict.insert("uid".to_string(), PlistValue::Uid(Uid::new(7)));
+        dict.insert("data".to_string(), PlistValue::Data(vec![1, 2]));
+        dict.insert(
+            "items".to_string(),
+            PlistValue::Array(vec![1.into(), PlistValue::Integer(u64::MAX.into())]),
+        );
+        let mut expected = Record::new();
+        expected.push("real", tagged("real", NuValue::float(1.0, span)));
+        expected.push("uid", tagged("uid", NuValue::int(7, span)));
+        expected.push("data", tagged("data", NuValue::binary(vec![1, 2], span)));
+        expected.push(
+            "items",
+            tagged(
+                "array",
+                NuValue::list(
+                    vec![
+                        tagged("int", NuValue::int(1, span)),
+                        tagged("uint", NuValue::string(u64::MAX.to_string(), span)),
+                    ],
+                    span,
+                ),
+            ),
+        );
+        assert_eq!(
+            tagged_value(
+                &PlistValue::Dictionary(dict),
+                &ConvertOptions::default(),
+                span
+            ),
+            Ok(tagged("dict", NuValue::record(expected, span)))
+        );
+    }
+
+    #[test]
+    fn test_from_tagged() {
+        let span = Span::test_data();
+        let mut dict = Dictionary::new();
+        dict.insert("real".to_string(), PlistValue::Real(2.0));
+        dict.insert("int".to_string(), PlistValue::Integer((-2).into()));
+        dict.insert("uint".to_string(), PlistValue::Integer(u64::MAX.into()));
+        dict.insert("uid".to_string(), PlistValue::Uid(Uid::new(7)));
+        dict.insert("data".to_string(), PlistValue::Data(b"text".to_vec()));
+        dict.insert(
+            "date".to_string(),
+            PlistValue::Date(
+                PlistDate::from_xml_format("2024-01-01T00:00:00Z").expect("valid date"),
+            ),
+        );
+        let plist = PlistValue::Array(vec![PlistValue::Dictionary(dict), "a".into()]);
+        let tagged =
+            tagged_value(&plist, &ConvertOptions::default(), span).expect("failed to convert");
+        assert_eq!(from_tagged(&tagged), Ok(plist));
+
+        let mut record = Record::new();
+        record.push("type", NuValue::string("real", span));
+        record.push("value", NuValue::int(1, span));
+        let mut array = Record::new();
+        array.push("type", NuValue::string("array", span));
+        array.push(
+            "value",
+            NuValue::list(vec![NuValue::record(record, span)], span),
+        );
+        let error = from_tagged(&NuValue::record(array, span)).expect_err("expected an error");
+        assert!(error.labels[0]
+            .text
+            .contains("at 0: real can't hold a value of type int"));
+        let error = from_tagged(&NuValue::int(1, span)).expect_err("expected an error");
+        assert!(error.labels[0]
+            .text
+            .contains("at the root: expected a {type, value} record"));
+    }
+
+    #[test]
+    fn test_metadata_record() {
+        let span = Span::test_data();
+        let bytes =
+            crate::format::plist_bytes(&PlistValue::Boolean(true), PlistFormat::Binary, span)
+                .expect("failed to write");
+        let mut expected = Record::new();
+        expected.push("value", NuValue::bool(true, span));
+        expected.push("format", NuValue::string("binary", span));
+        expected.push("version", NuValue::string("00", span));
+        expected.push("bytes", NuValue::int(bytes.len() as i64, span));
+        assert_eq!(
+            metadata_record(NuValue::bool(true, span), &bytes, span),
+            NuValue::record(expected, span)
+        );
+        let record = metadata_record(NuValue::nothing(span), b"{ a = 1; }", span);
+        let record = record.as_record().expect("expected a record");
+        assert_eq!(
+            record.get("format"),
+            Some(&NuValue::string("openstep", span))
+        );
+        assert_eq!(record.get("version"), Some(&NuValue::nothing(span)));
+    }
+
+    #[test]
+    fn test_convert_range() {
+        let span = Span::test_data();
+        let range = |start: i64, next: Option<i64>, end: Option<i64>, inclusion| {
+            let value =
+                |v: Option<i64>| v.map_or(NuValue::nothing(span), |v| NuValue::int(v, span));
+            let range = IntRange::new(
+                NuValue::int(start, span),
+                value(next),
+                value(end),
+                inclusion,
+                span,
+            )
+            .expect("invalid range");
+            NuValue::range(Range::IntRange(range), span)
+        };
+        let ints = |values: &[i64]| {
+            PlistValue::Array(
+                values
+                    .iter()
+                    .map(|v| PlistValue::Integer((*v).into()))
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            convert_nu_value(&range(1, None, Some(3), RangeInclusion::Inclusive)),
+            Ok(ints(&[1, 2, 3]))
+        );
+        assert_eq!(
+            convert_nu_value(&range(10, Some(7), Some(1), RangeInclusion::RightExclusive)),
+            Ok(ints(&[10, 7, 4]))
+        );
+        let unbounded = range(1, None, None, RangeInclusion::Inclusive);
+        assert!(convert_nu_value(&unbounded).is_err());
+
+        let options = ToPlistOptions {
+            ranges_as: RangesAs::Record,
+            ..Default::default()
+        };
+        let mut dict = Dictionary::new();
+        dict.insert("from".to_string(), 1.into());
+        dict.insert("step".to_string(), 1.into());
+        dict.insert("inclusive".to_string(), true.into());
+        assert_eq!(
+            convert_nu_value_with(&unbounded, options),
+            Ok(PlistValue::Dictionary(dict.clone()))
+        );
+        dict.insert("to".to_string(), 5.into());
+        dict.insert("inclusive".to_string(), false.into());
+        assert_eq!(
+            convert_nu_value_with(
+                &range(1, None, Some(5), RangeInclusion::RightExclusive),
+                options
+            ),
+            Ok(PlistValue::Dictionary(dict))
+        );
+
+        let floats = FloatRange::new(
+            NuValue::float(0.0, span),
+            NuValue::float(0.5, span),
+            NuValue::float(2.0, span),
+            RangeInclusion::Inclusive,
+            span,
+        )
+        .expect("invalid range");
+        let values = convert_nu_value(&NuValue::range(Range::FloatRange(floats), span));
+        assert_eq!(values.map(|v| v.as_array().map(Vec::len)), Ok(Some(5)));
+
+        // Too many values to list, including a float range with a tiny step
+        let error = convert_nu_value(&range(0, None, Some(i64::MAX), RangeInclusion::Inclusive))
+            .expect_err("expected an error");
+        assert_eq!(error.code.as_deref(), Some(ErrorKind::Overflow.code()));
+        let floats = FloatRange::new(
+            NuValue::float(0.0, span),
+            NuValue::float(1e-300, span),
+            NuValue::float(1.0, span),
+            RangeInclusion::Inclusive,
+            span,
+        )
+        .expect("invalid range");
+        assert!(convert_nu_value(&NuValue::range(Range::FloatRange(floats), span)).is_err());
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let error = convert_nu_value(&NuValue::test_nothing()).expect_err("expected an error");
+        assert_eq!(
+            error.code.as_deref(),
+            Some("nu_plugin_plist::unsupported_type")
+        );
+        assert!(error.help.is_some());
+
+        let error = parse_plist(b"<plist><dict><key>a</key></plist>", Span::test_data())
+            .expect_err("expected an error");
+        assert_eq!(error.code.as_deref(), Some(ErrorKind::Parse.code()));
+        assert_eq!(error.msg, "Failed to parse plist");
+
+        let error = plist_error(ErrorKind::General, "test".into(), Span::test_data());
+        assert_eq!(error.code.as_deref(), Some("nu_plugin_plist::error"));
+
+        let plist_val = PlistValue::Dictionary(Dictionary::new());
+        let missing = PathMember::string("missing".into(), false, Span::test_data());
+        let error = crate::path::follow(&plist_val, &[missing]).expect_err("expected an error");
+        assert_eq!(error.code.as_deref(), Some("nu_plugin_plist::not_found"));
+    }
+
+    #[test]
+    fn test_raw_dates() {
+        let span = Span::test_data();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(978_307_201_500);
+        let plist_val = PlistValue::Date(time.into());
+        let options = ConvertOptions {
+            dates_as: DatesAs::Raw,
+            ..Default::default()
+        };
+        let raw = convert_plist_value_with(&plist_val, &options, span).expect("failed to convert");
+        assert_eq!(raw, NuValue::float(1.5, span));
+
+        let options = ConvertOptions {
+            dates_as: DatesAs::String,
+            ..Default::default()
+        };
+        assert_eq!(
+            convert_plist_value_with(&plist_val, &options, span),
+            Ok(NuValue::string("2001-01-01T00:00:01.500Z", span))
+        );
+
+        let mut record = Record::new();
+        record.push(DATE_KEY, raw);
+        assert_eq!(
+            convert_nu_value(&NuValue::record(record, span)).expect("failed to convert"),
+            plist_val
+        );
+    }
+
+    #[test]
+    fn test_date_precision() {
+        let span = Span::test_data();
+        let nanos = |seconds| {
+            apple_time_to_date(seconds, span)
+                .expect("failed to convert")
+                .timestamp_subsec_nanos()
+        };
+        assert_eq!(nanos(0.001), 1_000_000);
+        assert_eq!(nanos(0.000_000_001), 1);
+        assert_eq!(nanos(700_000_000.123_456), 123_456_000);
+        assert_eq!(nanos(-0.25), 750_000_000);
+        assert!(apple_time_to_date(f64::NAN, span).is_err());
+
+        let time = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        for (format, time) in [
+            (PlistFormat::Xml, time),
+            (
+                PlistFormat::Xml,
+                SystemTime::UNIX_EPOCH - Duration::new(1, 1),
+            ),
+            (PlistFormat::Binary, time - Duration::from_nanos(789)),
+        ] {
+            let plist_val = PlistValue::Date(time.into());
+            let bytes = plist_bytes(&plist_val, format, span).expect("failed to write");
+            let (parsed, _) = parse_plist(&bytes, span).expect("failed to parse");
+            let nu_val = convert_plist_value(&parsed, span).expect("failed to convert");
+            let NuValue::Date { val, .. } = &nu_val else {
+                panic!("expected a date");
+            };
+            let expected = DateTime::<Utc>::from(time);
+            assert_eq!(val.timestamp_nanos_opt(), expected.timestamp_nanos_opt());
+            assert_eq!(convert_nu_value(&nu_val), Ok(plist_val));
+        }
+
+        // Raw seconds come back as the same date
+        let date = DateTime::<Utc>::from(time - Duration::from_nanos(789)).fixed_offset();
+        let raw = apple_seconds(&date);
+        assert_eq!(apple_time_to_date(raw, span), Ok(date));
+    }
+
+    #[test]
+    fn test_date_range() {
+        let span = Span::test_data();
+        for year in [1, 1900, 4000] {
+            let date = NaiveDate::from_ymd_opt(year, 6, 15)
+                .and_then(|d| d.and_hms_milli_opt(12, 30, 15, 250))
+                .expect("invalid date")
+                .and_utc()
+                .fixed_offset();
+            let plist_val = PlistValue::Date(plist_date(&date).expect("failed to convert"));
+            for format in [PlistFormat::Xml, PlistFormat::Binary] {
+                let bytes = plist_bytes(&plist_val, format, span).expect("failed to write");
+                let (parsed, _) = parse_plist(&bytes, span).expect("failed to parse");
+                assert_eq!(
+                    convert_plist_value(&parsed, span),
+                    Ok(NuValue::date(date, span)),
+                    "{year} in {format:?}"
+                );
+            }
+            assert_eq!(convert_nu_value(&NuValue::date(date, span)), Ok(plist_val));
+        }
+
+        // Beyond chrono's range, but not the system clock's
+        let far = SystemTime::UNIX_EPOCH + Duration::from_secs(1 << 60);
+        assert_eq!(convert_date(&far.into()), None);
+        assert!(convert_plist_value(&PlistValue::Date(far.into()), span).is_err());
+        assert_eq!(apple_timestamp(1e30), None);
+    }
+
+    #[test]
+    fn test_timezone() {
+        let span = Span::test_data();
+        let plist_val = PlistValue::Date(SystemTime::UNIX_EPOCH.into());
+        assert_eq!(
+            Timezone::from_name("+02:00"),
+            Some(Timezone::Fixed(2 * 3600))
+        );
+        assert_eq!(Timezone::from_name("UTC"), Some(Timezone::Utc));
+        assert_eq!(Timezone::from_name("Mars/Olympus"), None);
+
+        let options = ConvertOptions {
+            dates_as: DatesAs::String,
+            timezone: Timezone::Fixed(-5 * 3600),
+            ..Default::default()
+        };
+        assert_eq!(
+            convert_plist_value_with(&plist_val, &options, span),
+            Ok(NuValue::string("1969-12-31T19:00:00-05:00", span))
+        );
+        let options = ConvertOptions {
+            timezone: Timezone::Local,
+            ..Default::default()
+        };
+        let NuValue::Date { val, .. } =
+            convert_plist_value_with(&plist_val, &options, span).expect("failed to convert")
+        else {
+            panic!("expected a date");
+        };
+        assert_eq!(val.timestamp(), 0);
     }
 
     #[test]
@@ -264,7 +3507,7 @@ mod test {
         let epoch = SystemTime::UNIX_EPOCH;
         let plist_date = epoch.into();
 
-        let datetime = convert_date(&plist_date);
+        let datetime = convert_date(&plist_date).expect("failed to convert");
         assert_eq!(1970, datetime.year());
         assert_eq!(1, datetime.month());
         assert_eq!(1, datetime.day());
@@ -275,7 +3518,13 @@ mod test {
         let mut dict = Dictionary::new();
         dict.insert("a".to_string(), PlistValue::String("c".to_string()));
         dict.insert("b".to_string(), PlistValue::String("d".to_string()));
-        let nu_dict = convert_dict(&dict, Span::test_data()).unwrap();
+        let nu_dict = convert_dict(
+            &dict,
+            &ConvertOptions::default(),
+            MAX_DEPTH,
+            Span::test_data(),
+        )
+        .unwrap();
         assert_eq!(
             nu_dict,
             NuValue::record(
@@ -299,7 +3548,13 @@ mod test {
         let mut arr = Vec::new();
         arr.push(PlistValue::String("a".to_string()));
         arr.push(PlistValue::String("b".to_string()));
-        let nu_arr = convert_array(&arr, Span::test_data()).unwrap();
+        let nu_arr = convert_array(
+            &arr,
+            &ConvertOptions::default(),
+            MAX_DEPTH,
+            Span::test_data(),
+        )
+        .unwrap();
         assert_eq!(
             nu_arr,
             vec![
@@ -308,4 +3563,360 @@ mod test {
             ]
         );
     }
+
+    fn uid_as(uid_as: UidAs) -> ConvertOptions {
+        ConvertOptions {
+            uid_as,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_convert_data_as() {
+        let span = Span::test_data();
+        let plist_val = PlistValue::Data(b"hello".to_vec());
+        let options = |data_as| ConvertOptions {
+            data_as,
+            ..Default::default()
+        };
+        assert_eq!(
+            convert_plist_value_with(&plist_val, &options(DataAs::Base64), span),
+            Ok(NuValue::string("aGVsbG8=", span))
+        );
+        assert_eq!(
+            into_nu_value_with(plist_val, &options(DataAs::Hex), span),
+            Ok(NuValue::string("68656c6c6f", span))
+        );
+    }
+
+    #[test]
+    fn test_tagged_data_round_trip() {
+        let span = Span::test_data();
+        let plist_val = PlistValue::Data(b"hello".to_vec());
+        let options = ConvertOptions {
+            data_as: DataAs::Tagged,
+            ..Default::default()
+        };
+        let value =
+            convert_plist_value_with(&plist_val, &options, span).expect("failed to convert");
+        assert_eq!(
+            value
+                .as_record()
+                .ok()
+                .and_then(|record| record.get(DATA_KEY)),
+            Some(&NuValue::string("aGVsbG8=", span))
+        );
+        assert_eq!(convert_nu_value(&value).ok(), Some(plist_val));
+
+        let mut invalid = Record::new();
+        invalid.push(DATA_KEY, NuValue::string("not base64!", span));
+        assert!(convert_nu_value(&NuValue::record(invalid, span)).is_err());
+    }
+
+    #[test]
+    fn test_decode_nested() {
+        let span = Span::test_data();
+        let mut inner = Vec::new();
+        plist::to_writer_binary(&mut inner, &PlistValue::Integer(7.into()))
+            .expect("failed to write");
+        let plist_val = PlistValue::Array(vec![
+            PlistValue::Data(inner.clone()),
+            PlistValue::Data(b"bplist00 but not really".to_vec()),
+        ]);
+        let mut options = ConvertOptions {
+            decode_nested: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            convert_plist_value_with(&plist_val, &options, span),
+            Ok(NuValue::list(
+                vec![
+                    NuValue::int(7, span),
+                    NuValue::binary(b"bplist00 but not really".to_vec(), span)
+                ],
+                span
+            ))
+        );
+
+        options.keep_raw = true;
+        let mut record = Record::new();
+        record.push("value", NuValue::int(7, span));
+        record.push("raw", NuValue::binary(inner.clone(), span));
+        assert_eq!(
+            into_nu_value_with(PlistValue::Data(inner), &options, span),
+            Ok(NuValue::record(record, span))
+        );
+
+        // data holding more objects than --max-objects allows is left as it is
+        let mut inner = Vec::new();
+        plist::to_writer_binary(
+            &mut inner,
+            &PlistValue::Array((0..5).map(|i| PlistValue::Integer(i.into())).collect()),
+        )
+        .expect("failed to write");
+        let options = ConvertOptions {
+            decode_nested: true,
+            limits: Limits {
+                max_objects: 3,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            convert_data(Cow::Owned(inner.clone()), &options, MAX_DEPTH, span),
+            Ok(NuValue::binary(inner, span))
+        );
+    }
+
+    #[test]
+    fn test_decode_archives() {
+        let span = Span::test_data();
+        let root = NuValue::list(vec![NuValue::string("Finder", span)], span);
+        let archive = crate::keyed_archive::encode_archive(&root).expect("failed to encode");
+        let mut bytes = Vec::new();
+        plist::to_writer_binary(&mut bytes, &archive).expect("failed to write");
+        let options = ConvertOptions {
+            decode_archives: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            convert_data(Cow::Owned(bytes.clone()), &options, MAX_DEPTH, span),
+            Ok(root)
+        );
+        // without the flag the archive is left as data
+        assert_eq!(
+            convert_data(
+                Cow::Owned(bytes.clone()),
+                &ConvertOptions::default(),
+                MAX_DEPTH,
+                span
+            ),
+            Ok(NuValue::binary(bytes.clone(), span))
+        );
+        // the allowlist from the plugin config applies to nested archives
+        let options = ConvertOptions {
+            archives: Arc::new(DecodeOptions {
+                allowed_classes: Some(std::collections::HashSet::from(
+                    ["NSDictionary".to_string()],
+                )),
+                ..Default::default()
+            }),
+            ..options
+        };
+        let mut disallowed = Record::new();
+        disallowed.push("$class", NuValue::string("NSArray", span));
+        disallowed.push("$disallowed", NuValue::bool(true, span));
+        assert_eq!(
+            convert_data(Cow::Owned(bytes), &options, MAX_DEPTH, span),
+            Ok(NuValue::record(disallowed, span))
+        );
+    }
+
+    #[test]
+    fn test_write_uid_records() {
+        let span = Span::test_data();
+        let mut uid = Record::new();
+        uid.push(UID_KEY, NuValue::int(5, span));
+        let mut root = Record::new();
+        root.push("root", NuValue::record(uid, span));
+        let plist_val = convert_nu_value(&NuValue::record(root, span)).expect("failed to convert");
+
+        let mut out = Vec::new();
+        serialize_with(
+            &plist_val,
+            PlistFormat::Binary,
+            &SerializeOptions::default(),
+            &mut out,
+            span,
+        )
+        .expect("failed to write");
+        let (parsed, _) = parse_plist(&out, span).expect("failed to parse");
+        assert_eq!(
+            parsed.as_dictionary().and_then(|dict| dict.get("root")),
+            Some(&PlistValue::Uid(Uid::new(5)))
+        );
+    }
+
+    #[test]
+    fn test_into_nu_value() {
+        let mut dict = Dictionary::new();
+        dict.insert("name".to_string(), PlistValue::String("a".to_string()));
+        dict.insert("data".to_string(), PlistValue::Data(vec![1, 2]));
+        let value = PlistValue::Array(vec![PlistValue::Dictionary(dict)]);
+        assert_eq!(
+            into_nu_value(value.clone(), Span::test_data()).unwrap(),
+            convert_plist_value(&value, Span::test_data()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_plist_pipeline_content_type() {
+        let span = Span::test_data();
+        let value = PlistValue::String("a".to_string());
+        for (format, content_type) in [
+            (PlistFormat::Xml, "application/x-plist"),
+            (PlistFormat::Binary, "application/x-apple-binary-plist"),
+            (PlistFormat::OpenStep, "text/plain"),
+            (PlistFormat::GnuStep, "text/plain"),
+        ] {
+            let output = plist_pipeline(&value, format, &SerializeOptions::default(), span)
+                .expect("failed to write");
+            let PipelineData::Value(value, Some(metadata)) = output else {
+                panic!("expected a value with metadata for {}", format.as_str());
+            };
+            assert_eq!(metadata.content_type.as_deref(), Some(content_type));
+            assert_eq!(
+                matches!(value, NuValue::Binary { .. }),
+                format == PlistFormat::Binary
+            );
+        }
+    }
+
+    #[test]
+    fn test_into_plist_value() {
+        let span = Span::test_data();
+        let data = vec![7u8; 1024];
+        let ptr = data.as_ptr();
+        let mut record = Record::new();
+        record.push("data", NuValue::binary(data, span));
+        record.push("missing", NuValue::nothing(span));
+        let mut uint = Record::new();
+        uint.push(UINT_KEY, NuValue::string("18446744073709551615", span));
+        record.push("uint", NuValue::record(uint, span));
+        let value = NuValue::list(vec![NuValue::record(record, span)], span);
+        let options = ToPlistOptions {
+            nulls: Nulls::Skip,
+            ..Default::default()
+        };
+
+        let expected = convert_nu_value_with(&value, options).expect("failed to convert");
+        let plist_val = into_plist_value_with(value, options).expect("failed to convert");
+        assert_eq!(plist_val, expected);
+        // the binary was moved into the plist rather than copied
+        let moved = plist_val
+            .as_array()
+            .and_then(|items| items[0].as_dictionary());
+        let moved = moved
+            .and_then(|dict| dict.get("data"))
+            .and_then(PlistValue::as_data);
+        assert_eq!(moved.map(<[u8]>::as_ptr), Some(ptr));
+    }
+
+    #[test]
+    fn test_convert_large_array_in_order() {
+        let arr: Vec<PlistValue> = (0..PARALLEL_THRESHOLD as i64 * 3)
+            .map(|i| PlistValue::Integer(i.into()))
+            .collect();
+        let nu_arr = convert_array(
+            &arr,
+            &ConvertOptions::default(),
+            MAX_DEPTH,
+            Span::test_data(),
+        )
+        .unwrap();
+        assert!(nu_arr
+            .iter()
+            .enumerate()
+            .all(|(i, v)| *v == NuValue::int(i as i64, Span::test_data())));
+    }
+
+    #[test]
+    fn test_convert_large_unsigned() {
+        let span = Span::test_data();
+        let plist_val = PlistValue::Integer(u64::MAX.into());
+        let value = convert_plist_value(&plist_val, span).expect("failed to convert");
+        let uint = value
+            .as_record()
+            .ok()
+            .and_then(|record| record.get(UINT_KEY))
+            .cloned();
+        assert_eq!(uint, Some(NuValue::test_string(u64::MAX.to_string())));
+        assert_eq!(convert_nu_value(&value), Ok(plist_val.clone()));
+
+        let options = ConvertOptions {
+            uint_as: UintAs::String,
+            ..Default::default()
+        };
+        assert_eq!(
+            convert_plist_value_with(&plist_val, &options, span),
+            Ok(NuValue::test_string("18446744073709551615"))
+        );
+        assert_eq!(
+            convert_plist_value(&PlistValue::Integer((-1).into()), span),
+            Ok(NuValue::test_int(-1))
+        );
+    }
+
+    #[test]
+    fn test_key_order_round_trip() {
+        let span = Span::test_data();
+        let keys = ["zeta", "alpha", "Mid", "beta", "10", "2"];
+        let record = |inner: Option<NuValue>| {
+            let mut record = Record::new();
+            for (i, key) in keys.iter().enumerate() {
+                record.push(*key, NuValue::int(i as i64, span));
+            }
+            if let Some(inner) = inner {
+                record.push("nested", inner);
+            }
+            NuValue::record(record, span)
+        };
+        let value = record(Some(NuValue::list(vec![record(None)], span)));
+
+        let plist_val = convert_nu_value(&value).expect("failed to convert");
+        for format in [
+            PlistFormat::Xml,
+            PlistFormat::Binary,
+            PlistFormat::OpenStep,
+            PlistFormat::GnuStep,
+        ] {
+            let bytes =
+                crate::format::plist_bytes(&plist_val, format, span).expect("failed to write");
+            let read = match Document::from_bytes(bytes, &Limits::default(), span)
+                .expect("failed to read")
+            {
+                Document::Events(events) => match events.into_rows(span) {
+                    Ok(Root::Value(value)) => value,
+                    _ => panic!("expected a value"),
+                },
+                Document::Value(plist) => into_nu_value(plist, span).expect("failed to convert"),
+            };
+            let columns = |value: &NuValue| {
+                value
+                    .as_record()
+                    .expect("not a record")
+                    .columns()
+                    .cloned()
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(columns(&read), columns(&value), "{format:?}");
+            let nested = read
+                .as_record()
+                .ok()
+                .and_then(|record| record.get("nested"))
+                .and_then(|nested| nested.as_list().ok())
+                .and_then(|rows| rows.first())
+                .expect("missing nested record");
+            assert_eq!(columns(nested), keys, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let span = Span::test_data();
+        let mut plist_val = PlistValue::Boolean(true);
+        let mut nu_val = NuValue::test_bool(true);
+        for _ in 0..MAX_DEPTH {
+            plist_val = PlistValue::Array(vec![plist_val]);
+            nu_val = NuValue::test_list(vec![nu_val]);
+        }
+        assert!(convert_plist_value(&plist_val, span).is_ok());
+        assert!(convert_nu_value(&nu_val).is_ok());
+
+        let plist_val = PlistValue::Array(vec![plist_val]);
+        let nu_val = NuValue::test_list(vec![nu_val]);
+        assert!(convert_plist_value(&plist_val, span).is_err());
+        assert!(into_nu_value(plist_val, span).is_err());
+        assert!(convert_nu_value(&nu_val).is_err());
+    }
 }
@@ -0,0 +1,773 @@
+//! Support for the old-style OpenStep (ASCII) property list format, as found
+//! in legacy NeXT tooling, `.strings` files and the output of `defaults read`.
+//!
+//! The GNUstep typed-value extensions (`<*I42>`, `<*R1.5>`, `<*BY>` and
+//! `<*D2001-01-01 00:00:00 +0000>`) are understood when reading and can
+//! optionally be written.
+//!
+//! The reader is our own rather than the `AsciiReader` of the plist crate,
+//! which reads data such as `<0fbd 7741>`, GNUstep typed values and
+//! `.strings` files as strings, and reports no line or column for errors.
+
+use std::fmt::{self, Write};
+
+use chrono::{DateTime, Datelike};
+use plist::{Dictionary, Integer, Value as PlistValue};
+
+use nu_protocol::{LabeledError, Span};
+
+use crate::format::TEXT_YEARS;
+use crate::nu_plist::{convert_date, plist_date, plist_error, ErrorKind};
+
+/// Date layout used by GNUstep `<*D...>` typed values.
+const GNUSTEP_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenStepError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for OpenStepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (line {}, column {})",
+            self.message, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for OpenStepError {}
+
+/// Parse an OpenStep plist document whose arrays and dictionaries nest no more
+/// than `max_depth` levels deep.
+///
+/// A document whose root is a bare list of `key = value;` pairs (the `.strings`
+/// file convention) is parsed as a dictionary.
+pub fn from_bytes(bytes: &[u8], max_depth: usize) -> Result<PlistValue, OpenStepError> {
+    let text = std::str::from_utf8(bytes).map_err(|e| OpenStepError {
+        message: format!("OpenStep plist is not valid UTF-8: {e}"),
+        line: 1,
+        column: 1,
+    })?;
+    let mut parser = Parser::new(text, max_depth);
+
+    parser.skip_whitespace()?;
+    if parser.is_strings_file()? {
+        return Ok(PlistValue::Dictionary(parser.parse_dict_body(None)?));
+    }
+
+    let value = parser.parse_value()?;
+    parser.skip_whitespace()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected characters after the root value"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+    column: usize,
+    /// How many arrays and dictionaries enclose the current position.
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str, max_depth: usize) -> Self {
+        Parser {
+            // A leading byte order mark is not part of the document
+            text: text.strip_prefix('\u{feff}').unwrap_or(text),
+            pos: 0,
+            line: 1,
+            column: 1,
+            depth: 0,
+            max_depth,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> OpenStepError {
+        OpenStepError {
+            message: message.into(),
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.text[self.pos..].chars().nth(1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), OpenStepError> {
+        self.skip_whitespace()?;
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected '{expected}' but found '{c}'"))),
+            None => Err(self.error(format!("expected '{expected}' but found end of input"))),
+        }
+    }
+
+    /// Skips whitespace as well as `//` and `/* */` comments.
+    fn skip_whitespace(&mut self) -> Result<(), OpenStepError> {
+        loop {
+            match (self.peek(), self.peek_second()) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                }
+                (Some('/'), Some('/')) => {
+                    while let Some(c) = self.bump() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match self.bump() {
+                            Some('*') if self.peek() == Some('/') => {
+                                self.bump();
+                                break;
+                            }
+                            Some(_) => {}
+                            None => return Err(self.error("unterminated comment")),
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Checks whether the document starts with `key =`, without consuming input.
+    fn is_strings_file(&mut self) -> Result<bool, OpenStepError> {
+        if !matches!(self.peek(), Some(c) if c == '"' || c == '\'' || is_unquoted_char(c)) {
+            return Ok(false);
+        }
+        let (pos, line, column) = (self.pos, self.line, self.column);
+        self.parse_string()?;
+        self.skip_whitespace()?;
+        let is_strings = self.peek() == Some('=');
+        self.pos = pos;
+        self.line = line;
+        self.column = column;
+        Ok(is_strings)
+    }
+
+    fn parse_value(&mut self) -> Result<PlistValue, OpenStepError> {
+        self.skip_whitespace()?;
+        match self.peek() {
+            Some('{' | '(') if self.depth >= self.max_depth => {
+                Err(self.error(format!("nested more than {} levels deep", self.max_depth)))
+            }
+            Some('{') => {
+                self.bump();
+                self.depth += 1;
+                let dict = self.parse_dict_body(Some('}'))?;
+                self.depth -= 1;
+                Ok(PlistValue::Dictionary(dict))
+            }
+            Some('(') => {
+                self.bump();
+                self.depth += 1;
+                let array = self.parse_array()?;
+                self.depth -= 1;
+                Ok(array)
+            }
+            Some('<') if self.peek_second() == Some('*') => {
+                self.bump();
+                self.bump();
+                self.parse_typed_value()
+            }
+            Some('<') => {
+                self.bump();
+                self.parse_data()
+            }
+            Some(c) if c == '"' || c == '\'' || is_unquoted_char(c) => {
+                Ok(PlistValue::String(self.parse_string()?))
+            }
+            Some(c) => Err(self.error(format!("unexpected character '{c}'"))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_dict_body(&mut self, close: Option<char>) -> Result<Dictionary, OpenStepError> {
+        let mut dict = Dictionary::new();
+        loop {
+            self.skip_whitespace()?;
+            match (self.peek(), close) {
+                (Some(c), Some(close)) if c == close => {
+                    self.bump();
+                    return Ok(dict);
+                }
+                (None, None) => return Ok(dict),
+                (None, Some(close)) => {
+                    return Err(self.error(format!("expected '{close}' but found end of input")))
+                }
+                _ => {}
+            }
+
+            let key = self.parse_string()?;
+            self.skip_whitespace()?;
+            // `.strings` files allow a bare key as shorthand for `key = key;`
+            let value = if self.peek() == Some(';') {
+                PlistValue::String(key.clone())
+            } else {
+                self.expect('=')?;
+                self.parse_value()?
+            };
+            self.expect(';')?;
+            dict.insert(key, value);
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<PlistValue, OpenStepError> {
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace()?;
+            if self.peek() == Some(')') {
+                self.bump();
+                return Ok(PlistValue::Array(values));
+            }
+            values.push(self.parse_value()?);
+            self.skip_whitespace()?;
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some(')') => {}
+                Some(c) => return Err(self.error(format!("expected ',' or ')' but found '{c}'"))),
+                None => return Err(self.error("expected ')' but found end of input")),
+            }
+        }
+    }
+
+    fn parse_data(&mut self) -> Result<PlistValue, OpenStepError> {
+        let mut data = Vec::new();
+        let mut high: Option<u8> = None;
+        loop {
+            match self.bump() {
+                Some('>') => break,
+                Some(c) if c.is_whitespace() => {}
+                Some(c) => {
+                    let nibble = c
+                        .to_digit(16)
+                        .ok_or_else(|| self.error(format!("invalid hex digit '{c}' in data")))?
+                        as u8;
+                    match high.take() {
+                        Some(h) => data.push((h << 4) | nibble),
+                        None => high = Some(nibble),
+                    }
+                }
+                None => return Err(self.error("unterminated data")),
+            }
+        }
+        if high.is_some() {
+            return Err(self.error("data contains an odd number of hex digits"));
+        }
+        Ok(PlistValue::Data(data))
+    }
+
+    /// Parses the body of a GNUstep `<*X...>` typed value, after the `<*`.
+    fn parse_typed_value(&mut self) -> Result<PlistValue, OpenStepError> {
+        let (line, column) = (self.line, self.column);
+        let kind = self
+            .bump()
+            .ok_or_else(|| self.error("unterminated typed value"))?;
+        let start = self.pos;
+        loop {
+            match self.bump() {
+                Some('>') => break,
+                Some(_) => {}
+                None => return Err(self.error("unterminated typed value")),
+            }
+        }
+        let body = self.text[start..self.pos - 1].trim();
+        let invalid = |what: &str| OpenStepError {
+            message: format!("invalid GNUstep {what} value '{body}'"),
+            line,
+            column,
+        };
+
+        match kind {
+            'I' => {
+                let int = match body.parse::<i64>() {
+                    Ok(i) => Integer::from(i),
+                    Err(_) => Integer::from(body.parse::<u64>().map_err(|_| invalid("integer"))?),
+                };
+                Ok(PlistValue::Integer(int))
+            }
+            'R' => Ok(PlistValue::Real(
+                body.parse::<f64>().map_err(|_| invalid("real"))?,
+            )),
+            'B' => match body {
+                "Y" | "YES" => Ok(PlistValue::Boolean(true)),
+                "N" | "NO" => Ok(PlistValue::Boolean(false)),
+                _ => Err(invalid("boolean")),
+            },
+            'D' => {
+                let date = DateTime::parse_from_str(body, GNUSTEP_DATE_FORMAT)
+                    .map_err(|_| invalid("date"))?;
+                plist_date(&date)
+                    .map(PlistValue::Date)
+                    .ok_or_else(|| invalid("date"))
+            }
+            other => Err(OpenStepError {
+                message: format!("unknown GNUstep typed value '<*{other}'"),
+                line,
+                column,
+            }),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, OpenStepError> {
+        self.skip_whitespace()?;
+        match self.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                self.bump();
+                self.parse_quoted_string(quote)
+            }
+            Some(c) if is_unquoted_char(c) => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if is_unquoted_char(c)) {
+                    self.bump();
+                }
+                Ok(self.text[start..self.pos].to_string())
+            }
+            Some(c) => Err(self.error(format!("expected a string but found '{c}'"))),
+            None => Err(self.error("expected a string but found end of input")),
+        }
+    }
+
+    fn parse_quoted_string(&mut self, quote: char) -> Result<String, OpenStepError> {
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => return Ok(out),
+                Some('\\') => out.push(self.parse_escape()?),
+                Some(c) => out.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, OpenStepError> {
+        let c = self
+            .bump()
+            .ok_or_else(|| self.error("unterminated escape sequence"))?;
+        let escaped = match c {
+            'a' => '\u{7}',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => '\u{b}',
+            'U' | 'u' => {
+                let mut code = self.parse_unicode_digits();
+                // Characters outside the Basic Multilingual Plane are written
+                // as a UTF-16 surrogate pair, such as \UD83D\UDE00
+                if (0xd800..0xdc00).contains(&code)
+                    && self.peek() == Some('\\')
+                    && matches!(self.peek_second(), Some('U' | 'u'))
+                {
+                    let (pos, line, column) = (self.pos, self.line, self.column);
+                    self.bump();
+                    self.bump();
+                    let low = self.parse_unicode_digits();
+                    if (0xdc00..0xe000).contains(&low) {
+                        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                    } else {
+                        self.pos = pos;
+                        self.line = line;
+                        self.column = column;
+                    }
+                }
+                char::from_u32(code)
+                    .ok_or_else(|| self.error(format!("invalid unicode escape \\U{code:04x}")))?
+            }
+            '0'..='7' => {
+                let mut code = c.to_digit(8).unwrap_or_default();
+                for _ in 0..2 {
+                    match self.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            self.bump();
+                            code = code * 8 + digit;
+                        }
+                        None => break,
+                    }
+                }
+                char::from_u32(code)
+                    .ok_or_else(|| self.error(format!("invalid octal escape \\{code:o}")))?
+            }
+            other => other,
+        };
+        Ok(escaped)
+    }
+
+    /// Reads the up to four hex digits of a `\U` escape.
+    fn parse_unicode_digits(&mut self) -> u32 {
+        let mut code = 0u32;
+        for _ in 0..4 {
+            match self.peek().and_then(|c| c.to_digit(16)) {
+                Some(digit) => {
+                    self.bump();
+                    code = code * 16 + digit;
+                }
+                None => break,
+            }
+        }
+        code
+    }
+}
+
+/// Serialize a value as an OpenStep plist document.
+///
+/// OpenStep only knows strings, data, arrays and dictionaries, so other types
+/// are written as strings unless `typed` is set, in which case the GNUstep
+/// typed-value extensions are used to preserve them.
+pub fn to_string(value: &PlistValue, typed: bool, span: Span) -> Result<String, LabeledError> {
+    let mut out = String::new();
+    write_value(&mut out, value, 0, typed, span)?;
+    out.push('\n');
+    Ok(out)
+}
+
+fn write_value(
+    out: &mut String,
+    value: &PlistValue,
+    depth: usize,
+    typed: bool,
+    span: Span,
+) -> Result<(), LabeledError> {
+    match value {
+        PlistValue::Dictionary(dict) if dict.is_empty() => out.push_str("{}"),
+        PlistValue::Dictionary(dict) => {
+            out.push_str("{\n");
+            for (key, value) in dict {
+                write_indent(out, depth + 1);
+                write_string(out, key);
+                out.push_str(" = ");
+                write_value(out, value, depth + 1, typed, span)?;
+                out.push_str(";\n");
+            }
+            write_indent(out, depth);
+            out.push('}');
+        }
+        PlistValue::Array(values) if values.is_empty() => out.push_str("()"),
+        PlistValue::Array(values) => {
+            out.push_str("(\n");
+            for (idx, value) in values.iter().enumerate() {
+                write_indent(out, depth + 1);
+                write_value(out, value, depth + 1, typed, span)?;
+                if idx + 1 < values.len() {
+                    out.push(',');
+                }
+                out.push('\n');
+            }
+            write_indent(out, depth);
+            out.push(')');
+        }
+        PlistValue::Data(data) => {
+            out.push('<');
+            for (idx, byte) in data.iter().enumerate() {
+                if idx > 0 && idx % 4 == 0 {
+                    out.push(' ');
+                }
+                let _ = write!(out, "{byte:02x}");
+            }
+            out.push('>');
+        }
+        PlistValue::String(s) => write_string(out, s),
+        PlistValue::Boolean(b) if typed => out.push_str(if *b { "<*BY>" } else { "<*BN>" }),
+        PlistValue::Boolean(b) => out.push_str(if *b { "YES" } else { "NO" }),
+        PlistValue::Integer(i) if typed => {
+            let _ = write!(out, "<*I{i}>");
+        }
+        PlistValue::Integer(i) => write_string(out, &i.to_string()),
+        PlistValue::Real(r) if typed => {
+            let _ = write!(out, "<*R{r}>");
+        }
+        PlistValue::Real(r) => write_string(out, &r.to_string()),
+        PlistValue::Date(d) => match convert_date(d) {
+            Some(date) if TEXT_YEARS.contains(&date.year()) && typed => {
+                let _ = write!(out, "<*D{}>", date.format(GNUSTEP_DATE_FORMAT));
+            }
+            Some(date) if TEXT_YEARS.contains(&date.year()) => {
+                write_string(out, &d.to_xml_format())
+            }
+            _ => {
+                return Err(plist_error(
+                    ErrorKind::Overflow,
+                    "A date is outside the years -9999 to 9999 OpenStep plists can hold".into(),
+                    span,
+                ))
+            }
+        },
+        // OpenStep has no UID type, use the same dictionary form as plutil
+        PlistValue::Uid(uid) => {
+            let _ = write!(out, "{{ CF$UID = {}; }}", uid.get());
+        }
+        _ => {
+            return Err(plist_error(
+                ErrorKind::UnsupportedType,
+                "OpenStep plists can't hold this value".into(),
+                span,
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn write_indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str("    ");
+    }
+}
+
+/// Writes a string, quoting and escaping it unless it only contains
+/// characters that are allowed unquoted. Strings containing `//` or `/*` are
+/// quoted too, as unquoted they would read back as the start of a comment.
+fn write_string(out: &mut String, s: &str) {
+    let comment = s.contains("//") || s.contains("/*");
+    if !s.is_empty() && !comment && s.chars().all(is_unquoted_char) {
+        out.push_str(s);
+        return;
+    }
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\U{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Characters allowed in an unquoted OpenStep string.
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '+' | '/' | ':' | '.' | '-')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nu_plist::MAX_DEPTH;
+    use plist::Date as PlistDate;
+
+    fn write(value: &PlistValue, typed: bool) -> String {
+        to_string(value, typed, Span::test_data()).expect("failed to write")
+    }
+
+    #[test]
+    fn test_parse_dict() {
+        let input = br#"{
+            // a comment
+            name = "Hello World";
+            count = 3;
+            /* block
+               comment */
+            items = (a, "b c", );
+        }"#;
+        let value = from_bytes(input, MAX_DEPTH).expect("failed to parse");
+        let dict = value.as_dictionary().expect("expected a dictionary");
+        assert_eq!(
+            dict.get("name").and_then(|v| v.as_string()),
+            Some("Hello World")
+        );
+        assert_eq!(dict.get("count").and_then(|v| v.as_string()), Some("3"));
+        assert_eq!(
+            dict.get("items"),
+            Some(&PlistValue::Array(vec![
+                PlistValue::String("a".to_string()),
+                PlistValue::String("b c".to_string()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_data() {
+        let value = from_bytes(b"<0fbd 7741>", MAX_DEPTH).expect("failed to parse");
+        assert_eq!(value, PlistValue::Data(vec![0x0f, 0xbd, 0x77, 0x41]));
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        let value = from_bytes(br#""tab\there \"quoted\" \U00e9 \101""#, MAX_DEPTH)
+            .expect("failed to parse");
+        assert_eq!(
+            value,
+            PlistValue::String("tab\there \"quoted\" \u{e9} A".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_strings_file() {
+        let value = from_bytes(b"\"greeting\" = \"Hello\";\nfarewell = Bye;\n", MAX_DEPTH).unwrap();
+        let dict = value.as_dictionary().expect("expected a dictionary");
+        assert_eq!(dict.len(), 2);
+        assert_eq!(
+            dict.get("greeting").and_then(|v| v.as_string()),
+            Some("Hello")
+        );
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let err =
+            from_bytes(b"{\n  a = b\n}", MAX_DEPTH).expect_err("missing semicolon should fail");
+        assert_eq!(err.line, 3);
+        assert_eq!(err.column, 1);
+    }
+
+    #[test]
+    fn test_write_dict() {
+        let mut dict = Dictionary::new();
+        dict.insert("name".to_string(), PlistValue::String("Hello World".into()));
+        dict.insert("plain".to_string(), PlistValue::String("abc.def".into()));
+        dict.insert("enabled".to_string(), PlistValue::Boolean(true));
+        dict.insert("data".to_string(), PlistValue::Data(vec![0, 1, 2, 3, 4]));
+        dict.insert(
+            "items".to_string(),
+            PlistValue::Array(vec![PlistValue::Integer(1.into())]),
+        );
+        let out = write(&PlistValue::Dictionary(dict), false);
+        assert_eq!(
+            out,
+            "{\n    name = \"Hello World\";\n    plain = abc.def;\n    enabled = YES;\n    data = <00010203 04>;\n    items = (\n        1\n    );\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_write_escapes() {
+        let out = write(&PlistValue::String("say \"hi\"\n".into()), false);
+        assert_eq!(out, "\"say \\\"hi\\\"\\n\"\n");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut dict = Dictionary::new();
+        dict.insert("key with space".to_string(), PlistValue::String("".into()));
+        dict.insert("data".to_string(), PlistValue::Data(vec![0xde, 0xad]));
+        dict.insert(
+            "nested".to_string(),
+            PlistValue::Array(vec![PlistValue::Dictionary(Dictionary::new())]),
+        );
+        let value = PlistValue::Dictionary(dict);
+        assert_eq!(
+            from_bytes(write(&value, false).as_bytes(), MAX_DEPTH),
+            Ok(value)
+        );
+    }
+
+    #[test]
+    fn test_round_trip_comment_markers() {
+        for text in ["//cdn.example.com/x", "a/*b", "/usr/bin"] {
+            let value = PlistValue::Array(vec![PlistValue::String(text.to_string())]);
+            assert_eq!(
+                from_bytes(write(&value, false).as_bytes(), MAX_DEPTH),
+                Ok(value)
+            );
+        }
+        assert_eq!(
+            write(&PlistValue::String("/usr/bin".into()), false),
+            "/usr/bin\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_gnustep_typed_values() {
+        let input = b"{ i = <*I-42>; r = <*R1.5>; b = <*BY>; d = <*D2001-01-01 00:00:10 +0000>; }";
+        let value = from_bytes(input, MAX_DEPTH).expect("failed to parse");
+        let dict = value.as_dictionary().expect("expected a dictionary");
+        assert_eq!(dict.get("i"), Some(&PlistValue::Integer((-42).into())));
+        assert_eq!(dict.get("r"), Some(&PlistValue::Real(1.5)));
+        assert_eq!(dict.get("b"), Some(&PlistValue::Boolean(true)));
+        let date = dict
+            .get("d")
+            .and_then(|d| d.as_date())
+            .expect("expected a date");
+        assert_eq!(date.to_xml_format(), "2001-01-01T00:00:10Z");
+    }
+
+    #[test]
+    fn test_gnustep_round_trip() {
+        let mut dict = Dictionary::new();
+        dict.insert("int".to_string(), PlistValue::Integer(7.into()));
+        dict.insert("real".to_string(), PlistValue::Real(0.25));
+        dict.insert("flag".to_string(), PlistValue::Boolean(false));
+        dict.insert(
+            "date".to_string(),
+            PlistValue::Date(
+                PlistDate::from_xml_format("2024-02-29T12:30:00Z").expect("valid date"),
+            ),
+        );
+        let value = PlistValue::Dictionary(dict);
+        let out = write(&value, true);
+        assert!(out.contains("date = <*D2024-02-29 12:30:00 +0000>;"));
+        assert_eq!(from_bytes(out.as_bytes(), MAX_DEPTH), Ok(value));
+    }
+
+    #[test]
+    fn test_write_unrepresentable_date() {
+        let far = std::time::UNIX_EPOCH + std::time::Duration::from_secs(9_000_000_000_000);
+        let value = PlistValue::Date(PlistDate::from(far));
+        for typed in [false, true] {
+            let error = to_string(&value, typed, Span::test_data()).expect_err("expected an error");
+            assert_eq!(error.code.as_deref(), Some(ErrorKind::Overflow.code()));
+        }
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let doc = format!("{}a{}", "(".repeat(100_000), ")".repeat(100_000));
+        let err = from_bytes(doc.as_bytes(), MAX_DEPTH).expect_err("deep nesting should fail");
+        assert!(err.message.contains("levels deep"));
+
+        assert!(from_bytes(b"((a))", 2).is_ok());
+        let err = from_bytes(b"((a))", 1).expect_err("a limit of 1 should fail");
+        assert!(err.message.contains("nested more than 1 levels deep"));
+    }
+
+    #[test]
+    fn test_surrogate_pair() {
+        assert_eq!(
+            from_bytes(br#""\UD83D\UDE00 \u00e9""#, MAX_DEPTH),
+            Ok(PlistValue::String("\u{1f600} \u{e9}".to_string()))
+        );
+        // A high surrogate on its own isn't a character
+        assert!(from_bytes(br#""\UD83D a""#, MAX_DEPTH).is_err());
+        assert!(from_bytes(br#""\UD83D\U0041""#, MAX_DEPTH).is_err());
+    }
+}
@@ -0,0 +1,934 @@
+//! Cell-path navigation over plist documents.
+//!
+//! These commands work on the parsed plist tree directly so that only the
+//! addressed value is converted to Nu.
+
+use std::borrow::Cow;
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{
+    ast::{CellPath, PathMember},
+    Category, Example, LabeledError, Record, Signature, Span, Spanned, SyntaxShape,
+    Value as NuValue,
+};
+use plist::{Dictionary, Value as PlistValue};
+
+use crate::binary;
+use crate::events::Events;
+use crate::format::{input_bytes, parse_input, parse_plist, PlistFormat};
+use crate::nu_plist::{convert_plist_value, into_nu_value, plist_error, ErrorKind, NuPlistPlugin};
+use crate::schema::value_type;
+
+pub struct PlistGet;
+pub struct PlistPaths;
+pub struct PlistKeys;
+
+impl SimplePluginCommand for PlistGet {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist get"
+    }
+
+    fn usage(&self) -> &str {
+        "Extract the value at a cell path from a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"A `*` member matches every entry of a dictionary or array and `**` matches any
+number of levels. When the path has wildcards a list of every matching value is
+returned."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "open --raw Info.plist | plist get LSEnvironment.PATH",
+                description: "Read a single key without converting the whole document",
+                result: None,
+            },
+            Example {
+                example: "open --raw profile.mobileconfig | plist get PayloadContent.*.PayloadType",
+                description: "Read a key from every element of an array",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required("path", SyntaxShape::CellPath, "The cell path to extract")
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let path: CellPath = call.req(0)?;
+        let bytes = input_bytes(input, call.head)?;
+        get(bytes, &path.members, input.span(), call.head)
+    }
+}
+
+/// The value at `members` in a document, or a list of the values a path with
+/// wildcards matches.
+fn get(
+    bytes: &[u8],
+    members: &[PathMember],
+    span: Span,
+    head: Span,
+) -> Result<NuValue, LabeledError> {
+    if !has_wildcards(members) && PlistFormat::detect(bytes) == PlistFormat::Xml {
+        // Only the value at the path is converted, the rest is skipped
+        return Ok(Events::from_xml(bytes, span)?
+            .follow(members, head)?
+            .unwrap_or_else(|| NuValue::nothing(head)));
+    }
+
+    let (mut plist, _) = parse_plist(bytes, span)?;
+    if has_wildcards(members) {
+        let values = expand(&plist, members)
+            .iter()
+            .filter_map(|concrete| follow(&plist, concrete).transpose())
+            .map(|value| value.and_then(|v| convert_plist_value(v, head)))
+            .collect::<Result<_, _>>()?;
+        return Ok(NuValue::list(values, head));
+    }
+    // The document is discarded, so the value is moved out rather than
+    // copying large strings and data
+    match take(&mut plist, members)? {
+        Some(value) => into_nu_value(value, head),
+        None => Ok(NuValue::nothing(head)),
+    }
+}
+
+impl SimplePluginCommand for PlistPaths {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist paths"
+    }
+
+    fn usage(&self) -> &str {
+        "List the cell path and type of every leaf value in a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        "Empty dictionaries and arrays are listed as leaves."
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "open --raw profile.mobileconfig | plist paths | where type == string",
+            description: "Find where the string values of a configuration profile live",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self)).category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let (plist, _) = parse_input(input, call.head)?;
+        let mut rows = Vec::new();
+        walk_leaves(&plist, &mut Vec::new(), &mut |path, value| {
+            let mut record = Record::new();
+            record.push(
+                "path",
+                NuValue::cell_path(
+                    CellPath {
+                        members: path.to_vec(),
+                    },
+                    call.head,
+                ),
+            );
+            record.push("type", NuValue::string(value_type(value), call.head));
+            rows.push(NuValue::record(record, call.head));
+        });
+        Ok(NuValue::list(rows, call.head))
+    }
+}
+
+impl SimplePluginCommand for PlistKeys {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist keys"
+    }
+
+    fn usage(&self) -> &str {
+        "List the keys of the root dictionary of a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        "For binary plists only the root dictionary and its keys are read, the values are skipped."
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "open --raw ~/Library/Preferences/com.apple.finder.plist | plist keys",
+            description: "List the settings stored in a preferences file",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self)).category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let bytes = input_bytes(input, call.head)?;
+        let keys = root_keys(bytes, input.span())?;
+        Ok(NuValue::list(
+            keys.into_iter()
+                .map(|key| NuValue::string(key, call.head))
+                .collect(),
+            call.head,
+        ))
+    }
+}
+
+/// The keys of a document's root dictionary, read without decoding the values
+/// of binary and XML plists.
+fn root_keys(bytes: &[u8], span: Span) -> Result<Vec<String>, LabeledError> {
+    match PlistFormat::detect(bytes) {
+        PlistFormat::Binary => binary::read_trailer(bytes)
+            .and_then(|trailer| binary::dict_keys(bytes, &trailer, trailer.root_object))
+            .ok_or_else(|| {
+                plist_error(
+                    ErrorKind::UnsupportedType,
+                    "Root of the binary plist is not a readable dictionary".into(),
+                    span,
+                )
+            }),
+        PlistFormat::Xml => Events::from_xml(bytes, span)?.root_keys(),
+        _ => match parse_plist(bytes, span)?.0 {
+            PlistValue::Dictionary(dict) => Ok(dict.keys().cloned().collect()),
+            other => Err(plist_error(
+                ErrorKind::UnsupportedType,
+                format!("Root of the plist is a {}, not a dict", value_type(&other)),
+                span,
+            )),
+        },
+    }
+}
+
+/// Calls `visit` with the path of every leaf value, in document order.
+pub fn walk_leaves<'a>(
+    value: &'a PlistValue,
+    path: &mut Vec<PathMember>,
+    visit: &mut impl FnMut(&[PathMember], &'a PlistValue),
+) {
+    match value {
+        PlistValue::Dictionary(dict) if !dict.is_empty() => {
+            for (key, entry) in dict {
+                path.push(PathMember::string(key.clone(), false, Span::unknown()));
+                walk_leaves(entry, path, visit);
+                path.pop();
+            }
+        }
+        PlistValue::Array(arr) if !arr.is_empty() => {
+            for (idx, element) in arr.iter().enumerate() {
+                path.push(PathMember::int(idx, false, Span::unknown()));
+                walk_leaves(element, path, visit);
+                path.pop();
+            }
+        }
+        _ => visit(path, value),
+    }
+}
+
+/// Whether a path contains `*` or `**` members.
+pub fn has_wildcards(members: &[PathMember]) -> bool {
+    members.iter().any(|m| wildcard(m).is_some())
+}
+
+/// Expands the `*` and `**` members of a path into every concrete path they
+/// match in the document, in document order. Literal members that don't
+/// exist under a wildcard are skipped rather than reported.
+pub fn expand(value: &PlistValue, members: &[PathMember]) -> Vec<Vec<PathMember>> {
+    let mut paths = Vec::new();
+    expand_into(value, members, &mut Vec::new(), &mut paths);
+    paths
+}
+
+fn expand_into(
+    value: &PlistValue,
+    members: &[PathMember],
+    prefix: &mut Vec<PathMember>,
+    paths: &mut Vec<Vec<PathMember>>,
+) {
+    let Some((first, rest)) = members.split_first() else {
+        paths.push(prefix.clone());
+        return;
+    };
+    match wildcard(first) {
+        Some(recursive) => {
+            if recursive {
+                expand_into(value, rest, prefix, paths);
+            }
+            let remaining = if recursive { members } else { rest };
+            for (member, child) in children(value) {
+                prefix.push(member);
+                expand_into(child, remaining, prefix, paths);
+                prefix.pop();
+            }
+        }
+        None => {
+            if let Ok(Some(child)) = step(value, first) {
+                prefix.push(first.clone());
+                expand_into(child, rest, prefix, paths);
+                prefix.pop();
+            }
+        }
+    }
+}
+
+/// `Some(false)` for a `*` member, `Some(true)` for `**`.
+fn wildcard(member: &PathMember) -> Option<bool> {
+    match member {
+        PathMember::String { val, .. } if val == "*" => Some(false),
+        PathMember::String { val, .. } if val == "**" => Some(true),
+        _ => None,
+    }
+}
+
+fn children(value: &PlistValue) -> Vec<(PathMember, &PlistValue)> {
+    match value {
+        PlistValue::Dictionary(dict) => dict
+            .iter()
+            .map(|(k, v)| (PathMember::string(k.clone(), false, Span::unknown()), v))
+            .collect(),
+        PlistValue::Array(arr) => arr
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (PathMember::int(i, false, Span::unknown()), v))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Reads a path given as a cell path, as a dotted string such as
+/// `Profiles.0.Name`, in which all-digit members are array indices, or as a
+/// list of keys and indices. `to json` writes cell paths as such lists, whose
+/// keys are kept whole even when they contain dots.
+pub fn path_members(value: &NuValue) -> Result<Vec<PathMember>, LabeledError> {
+    let span = value.span();
+    match value {
+        NuValue::CellPath { val, .. } => Ok(val.members.clone()),
+        NuValue::List { vals, .. } => vals
+            .iter()
+            .map(|member| match member {
+                NuValue::String { val, .. } => {
+                    Ok(PathMember::string(val.clone(), false, member.span()))
+                }
+                NuValue::Int { val, .. } if *val >= 0 => {
+                    Ok(PathMember::int(*val as usize, false, member.span()))
+                }
+                _ => Err(plist_error(
+                    ErrorKind::General,
+                    format!(
+                        "Expected a key or index in the path, found {}",
+                        member.get_type()
+                    ),
+                    member.span(),
+                )),
+            })
+            .collect(),
+        NuValue::String { val, .. } => Ok(val
+            .split('.')
+            .filter(|member| !member.is_empty())
+            .map(|member| match member.parse::<usize>() {
+                Ok(idx) => PathMember::int(idx, false, span),
+                Err(_) => PathMember::string(member.to_string(), false, span),
+            })
+            .collect()),
+        _ => Err(plist_error(
+            ErrorKind::General,
+            format!(
+                "Expected a cell path, string or list, found {}",
+                value.get_type()
+            ),
+            span,
+        )),
+    }
+}
+
+/// Follows a cell path into a plist value. Returns `None` when an optional
+/// member is missing.
+pub fn follow<'a>(
+    value: &'a PlistValue,
+    members: &[PathMember],
+) -> Result<Option<&'a PlistValue>, LabeledError> {
+    let mut current = value;
+    for member in members {
+        match step(current, member)? {
+            Some(next) => current = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+/// Moves the value at a path out of a document that is about to be discarded,
+/// leaving a placeholder behind. Missing optional members give `None`.
+pub fn take(
+    value: &mut PlistValue,
+    members: &[PathMember],
+) -> Result<Option<PlistValue>, LabeledError> {
+    if follow(value, members)?.is_none() {
+        return Ok(None);
+    }
+    let target = follow_mut(value, members)?;
+    Ok(Some(std::mem::replace(target, PlistValue::Boolean(false))))
+}
+
+/// Mutable version of [`follow`] that errors on any missing member.
+pub fn follow_mut<'a>(
+    value: &'a mut PlistValue,
+    members: &[PathMember],
+) -> Result<&'a mut PlistValue, LabeledError> {
+    let mut current = value;
+    for member in members {
+        current = step_mut(current, member)?;
+    }
+    Ok(current)
+}
+
+/// Sets the value at a path. The final member may name a new dictionary key,
+/// or the index just past the end of an array to append.
+pub fn set(
+    root: &mut PlistValue,
+    members: &[PathMember],
+    new_value: PlistValue,
+) -> Result<(), LabeledError> {
+    let Some((last, parents)) = members.split_last() else {
+        *root = new_value;
+        return Ok(());
+    };
+    let parent = follow_mut(root, parents)?;
+    match (&*member_in(last, parent), parent) {
+        (PathMember::String { val, .. }, PlistValue::Dictionary(dict)) => {
+            dict.insert(val.clone(), new_value);
+        }
+        (PathMember::Int { val, .. }, PlistValue::Array(arr)) if *val == arr.len() => {
+            arr.push(new_value);
+        }
+        (PathMember::Int { val, .. }, PlistValue::Array(arr)) if *val < arr.len() => {
+            arr[*val] = new_value;
+        }
+        (member, parent) => return Err(missing_member(parent, member)),
+    }
+    Ok(())
+}
+
+/// Inserts a new entry at a path. Unlike [`set`] existing dictionary keys are
+/// an error, and array elements from the index onwards are shifted along.
+pub fn insert(
+    root: &mut PlistValue,
+    members: &[PathMember],
+    new_value: PlistValue,
+    span: Span,
+) -> Result<(), LabeledError> {
+    let Some((last, parents)) = members.split_last() else {
+        return Err(plist_error(
+            ErrorKind::General,
+            "Cannot insert at the root of a document".into(),
+            span,
+        ));
+    };
+    let parent = follow_mut(root, parents)?;
+    match (&*member_in(last, parent), parent) {
+        (PathMember::String { val, span, .. }, PlistValue::Dictionary(dict)) => {
+            if dict.contains_key(val) {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    format!("Key {val} already exists"),
+                    *span,
+                ));
+            }
+            dict.insert(val.clone(), new_value);
+        }
+        (PathMember::Int { val, .. }, PlistValue::Array(arr)) if *val <= arr.len() => {
+            arr.insert(*val, new_value);
+        }
+        (member, parent) => return Err(missing_member(parent, member)),
+    }
+    Ok(())
+}
+
+/// Renames the dictionary key at a path, keeping its position in the
+/// dictionary.
+pub fn rename_key(
+    root: &mut PlistValue,
+    members: &[PathMember],
+    new_name: &Spanned<String>,
+) -> Result<(), LabeledError> {
+    let Some((last, parents)) = members.split_last() else {
+        return Err(plist_error(
+            ErrorKind::General,
+            "Cannot rename the root of a document".into(),
+            new_name.span,
+        ));
+    };
+    let parent = follow_mut(root, parents)?;
+    match (&*member_in(last, parent), parent) {
+        (PathMember::String { val, .. }, PlistValue::Dictionary(dict))
+            if dict.contains_key(val) =>
+        {
+            if *val == new_name.item {
+                return Ok(());
+            }
+            if dict.contains_key(&new_name.item) {
+                return Err(plist_error(
+                    ErrorKind::General,
+                    format!("Key {} already exists", new_name.item),
+                    new_name.span,
+                ));
+            }
+            let entries = std::mem::replace(dict, Dictionary::new());
+            for (key, value) in entries {
+                let key = if key == *val {
+                    new_name.item.clone()
+                } else {
+                    key
+                };
+                dict.insert(key, value);
+            }
+            Ok(())
+        }
+        (member, parent) => Err(missing_member(parent, member)),
+    }
+}
+
+/// Removes and returns the value at a path.
+pub fn remove(
+    root: &mut PlistValue,
+    members: &[PathMember],
+    span: Span,
+) -> Result<PlistValue, LabeledError> {
+    let Some((last, parents)) = members.split_last() else {
+        return Err(plist_error(
+            ErrorKind::General,
+            "Cannot remove the root of a document".into(),
+            span,
+        ));
+    };
+    let parent = follow_mut(root, parents)?;
+    match (&*member_in(last, parent), parent) {
+        (PathMember::String { val, .. }, PlistValue::Dictionary(dict))
+            if dict.contains_key(val) =>
+        {
+            // Dictionary::remove swaps the last entry into place, retain keeps
+            // the remaining keys in document order.
+            let mut removed = None;
+            dict.retain(|key, value| {
+                if key == val {
+                    removed = Some(std::mem::replace(value, PlistValue::Boolean(false)));
+                    false
+                } else {
+                    true
+                }
+            });
+            removed.ok_or_else(|| {
+                plist_error(
+                    ErrorKind::NotFound,
+                    format!("Key {val} not found"),
+                    member_span(last),
+                )
+            })
+        }
+        (PathMember::Int { val, .. }, PlistValue::Array(arr)) if *val < arr.len() => {
+            Ok(arr.remove(*val))
+        }
+        (member, parent) => Err(missing_member(parent, member)),
+    }
+}
+
+fn step_mut<'a>(
+    value: &'a mut PlistValue,
+    member: &PathMember,
+) -> Result<&'a mut PlistValue, LabeledError> {
+    let member = member_in(member, value);
+    let member = &*member;
+    let exists = match (member, &*value) {
+        (PathMember::String { val, .. }, PlistValue::Dictionary(dict)) => dict.contains_key(val),
+        (PathMember::Int { val, .. }, PlistValue::Array(arr)) => *val < arr.len(),
+        _ => false,
+    };
+    if !exists {
+        return Err(missing_member(value, member));
+    }
+    match (member, value) {
+        (PathMember::String { val, .. }, PlistValue::Dictionary(dict)) => dict.get_mut(val),
+        (PathMember::Int { val, .. }, PlistValue::Array(arr)) => arr.get_mut(*val),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        plist_error(
+            ErrorKind::NotFound,
+            format!("Cannot access {}", describe(member)),
+            member_span(member),
+        )
+    })
+}
+
+fn step<'a>(
+    value: &'a PlistValue,
+    member: &PathMember,
+) -> Result<Option<&'a PlistValue>, LabeledError> {
+    let member = member_in(member, value);
+    let member = &*member;
+    let found = match (member, value) {
+        (PathMember::String { val, .. }, PlistValue::Dictionary(dict)) => dict.get(val),
+        (PathMember::Int { val, .. }, PlistValue::Array(arr)) => arr.get(*val),
+        _ => None,
+    };
+    match found {
+        Some(found) => Ok(Some(found)),
+        None if member_optional(member) => Ok(None),
+        None => Err(missing_member(value, member)),
+    }
+}
+
+/// The error reported when a path member does not exist in a value.
+pub fn missing_member(value: &PlistValue, member: &PathMember) -> LabeledError {
+    let span = member_span(member);
+    match (&*member_in(member, value), value) {
+        (PathMember::String { val, .. }, PlistValue::Dictionary(_)) => {
+            plist_error(ErrorKind::NotFound, format!("Key {val} not found"), span)
+        }
+        (PathMember::Int { val, .. }, PlistValue::Array(arr)) => plist_error(
+            ErrorKind::NotFound,
+            format!("Index {val} out of range, array has {} elements", arr.len()),
+            span,
+        ),
+        _ => plist_error(
+            ErrorKind::NotFound,
+            format!(
+                "Cannot access {} on a {}",
+                describe(member),
+                value_type(value)
+            ),
+            span,
+        ),
+    }
+}
+
+/// Nu parses numeric members as indices, which for a dictionary mean a key,
+/// as in `Tracks.1234.Name`.
+pub fn key_member(member: &PathMember) -> Cow<'_, PathMember> {
+    match member {
+        PathMember::Int {
+            val,
+            span,
+            optional,
+        } => Cow::Owned(PathMember::string(val.to_string(), *optional, *span)),
+        member => Cow::Borrowed(member),
+    }
+}
+
+/// The member as it applies to `parent`, a key when that is a dictionary.
+fn member_in<'a>(member: &'a PathMember, parent: &PlistValue) -> Cow<'a, PathMember> {
+    match parent {
+        PlistValue::Dictionary(_) => key_member(member),
+        _ => Cow::Borrowed(member),
+    }
+}
+
+pub fn member_span(member: &PathMember) -> Span {
+    match member {
+        PathMember::String { span, .. } | PathMember::Int { span, .. } => *span,
+    }
+}
+
+pub fn member_optional(member: &PathMember) -> bool {
+    match member {
+        PathMember::String { optional, .. } | PathMember::Int { optional, .. } => *optional,
+    }
+}
+
+pub fn describe(member: &PathMember) -> String {
+    match member {
+        PathMember::String { val, .. } => format!("key {val}"),
+        PathMember::Int { val, .. } => format!("index {val}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(name: &str) -> PathMember {
+        PathMember::string(name.to_string(), false, Span::test_data())
+    }
+
+    fn document() -> PlistValue {
+        let mut env = Dictionary::new();
+        env.insert("PATH".to_string(), PlistValue::String("/bin".to_string()));
+        let mut root = Dictionary::new();
+        root.insert("LSEnvironment".to_string(), PlistValue::Dictionary(env));
+        root.insert(
+            "items".to_string(),
+            PlistValue::Array(vec![PlistValue::Integer(1.into())]),
+        );
+        PlistValue::Dictionary(root)
+    }
+
+    #[test]
+    fn test_follow_path() {
+        let doc = document();
+        let found = follow(&doc, &[key("LSEnvironment"), key("PATH")]).expect("failed to follow");
+        assert_eq!(found, Some(&PlistValue::String("/bin".to_string())));
+
+        let found = follow(
+            &doc,
+            &[key("items"), PathMember::int(0, false, Span::test_data())],
+        )
+        .expect("failed to follow");
+        assert_eq!(found, Some(&PlistValue::Integer(1.into())));
+    }
+
+    #[test]
+    fn test_path_members() {
+        let members =
+            path_members(&NuValue::test_string("Profiles.0.Name")).expect("failed to parse");
+        assert_eq!(
+            members,
+            vec![
+                key("Profiles"),
+                PathMember::int(0, false, Span::test_data()),
+                key("Name")
+            ]
+        );
+
+        let members = path_members(&NuValue::test_list(vec![
+            NuValue::test_string("com.apple.Safari"),
+            NuValue::test_int(0),
+        ]))
+        .expect("failed to parse");
+        assert_eq!(
+            members,
+            vec![
+                key("com.apple.Safari"),
+                PathMember::int(0, false, Span::test_data())
+            ]
+        );
+        assert!(path_members(&NuValue::test_list(vec![NuValue::test_int(-1)])).is_err());
+    }
+
+    #[test]
+    fn test_other_encodings() {
+        let span = Span::test_data();
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\
+            <plist><dict><key>Grüße</key><string>café</string></dict></plist>";
+        let utf16: Vec<u8> = [0xfeff]
+            .into_iter()
+            .chain(xml.encode_utf16())
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let bom = [b"\xef\xbb\xbf", xml.replace("UTF-16", "UTF-8").as_bytes()].concat();
+        for bytes in [utf16, bom] {
+            assert_eq!(
+                root_keys(&bytes, span).expect("failed to read keys"),
+                vec!["Grüße".to_string()]
+            );
+            assert_eq!(
+                get(&bytes, &[key("Grüße")], span, span).expect("failed to get"),
+                NuValue::test_string("café")
+            );
+        }
+    }
+
+    #[test]
+    fn test_numeric_keys() {
+        let span = Span::test_data();
+        let mut track = Dictionary::new();
+        track.insert("Name".to_string(), PlistValue::String("Intro".to_string()));
+        let mut tracks = Dictionary::new();
+        tracks.insert("1234".to_string(), PlistValue::Dictionary(track));
+        let mut root = Dictionary::new();
+        root.insert("Tracks".to_string(), PlistValue::Dictionary(tracks));
+        let mut doc = PlistValue::Dictionary(root);
+
+        let members = path_members(&NuValue::test_string("Tracks.1234.Name")).expect("valid path");
+        let name = PlistValue::String("Intro".to_string());
+        assert_eq!(
+            follow(&doc, &members).expect("failed to follow"),
+            Some(&name)
+        );
+        assert_eq!(
+            follow_mut(&mut doc, &members).expect("failed to follow"),
+            &name
+        );
+
+        let mut bytes = Vec::new();
+        plist::to_writer_xml(&mut bytes, &doc).expect("failed to write");
+        assert_eq!(
+            get(&bytes, &members, span, span).expect("failed to get"),
+            NuValue::test_string("Intro")
+        );
+
+        set(&mut doc, &members[..2], PlistValue::Integer(1.into())).expect("failed to set");
+        assert_eq!(
+            remove(&mut doc, &members[..2], span).expect("failed to remove"),
+            PlistValue::Integer(1.into())
+        );
+    }
+
+    #[test]
+    fn test_follow_missing() {
+        let doc = document();
+        assert!(follow(&doc, &[key("missing")]).is_err());
+        let optional = PathMember::string("missing".to_string(), true, Span::test_data());
+        assert_eq!(follow(&doc, &[optional]).expect("failed to follow"), None);
+    }
+
+    #[test]
+    fn test_walk_leaves() {
+        let mut leaves = Vec::new();
+        walk_leaves(&document(), &mut Vec::new(), &mut |path, value| {
+            leaves.push((path.len(), value_type(value)))
+        });
+        assert_eq!(leaves, vec![(2, "string"), (2, "int")]);
+    }
+
+    #[test]
+    fn test_expand_wildcards() {
+        let doc = document();
+        let described = |paths: Vec<Vec<PathMember>>| -> Vec<Vec<String>> {
+            paths
+                .iter()
+                .map(|path| path.iter().map(describe).collect())
+                .collect()
+        };
+        let env_path = vec![vec![
+            "key LSEnvironment".to_string(),
+            "key PATH".to_string(),
+        ]];
+
+        let paths = expand(&doc, &[key("*"), key("PATH")]);
+        assert_eq!(described(paths), env_path);
+
+        let all = expand(&doc, &[key("*"), key("*")]);
+        assert_eq!(all.len(), 2);
+
+        let deep = expand(&doc, &[key("**"), key("PATH")]);
+        assert_eq!(described(deep), env_path);
+    }
+
+    #[test]
+    fn test_take() {
+        let mut doc = document();
+        let taken = take(&mut doc, &[key("LSEnvironment"), key("PATH")]).expect("failed to take");
+        assert_eq!(taken, Some(PlistValue::String("/bin".to_string())));
+        let missing = PathMember::string("missing".to_string(), true, Span::test_data());
+        assert_eq!(take(&mut doc, &[missing]).expect("failed to take"), None);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut doc = document();
+        let path = [key("LSEnvironment"), key("PATH")];
+        set(&mut doc, &path, PlistValue::String("/usr/bin".to_string())).expect("failed to set");
+        assert_eq!(
+            follow(&doc, &path).expect("failed to follow"),
+            Some(&PlistValue::String("/usr/bin".to_string()))
+        );
+
+        let append = [key("items"), PathMember::int(1, false, Span::test_data())];
+        set(&mut doc, &append, PlistValue::Boolean(true)).expect("failed to append");
+        assert_eq!(
+            follow(&doc, &append).expect("failed to follow"),
+            Some(&PlistValue::Boolean(true))
+        );
+
+        let missing = [key("missing"), key("PATH")];
+        assert!(set(&mut doc, &missing, PlistValue::Boolean(true)).is_err());
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut doc = document();
+        let first = [key("items"), PathMember::int(0, false, Span::test_data())];
+        insert(
+            &mut doc,
+            &first,
+            PlistValue::Boolean(true),
+            Span::test_data(),
+        )
+        .expect("failed to insert");
+        assert_eq!(
+            follow(&doc, &[key("items")]).expect("failed to follow"),
+            Some(&PlistValue::Array(vec![
+                PlistValue::Boolean(true),
+                PlistValue::Integer(1.into())
+            ]))
+        );
+        assert!(insert(
+            &mut doc,
+            &[key("items")],
+            PlistValue::Boolean(true),
+            Span::test_data()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_rename_key() {
+        let mut doc = document();
+        let new_name = Spanned {
+            item: "Environment".to_string(),
+            span: Span::test_data(),
+        };
+        rename_key(&mut doc, &[key("LSEnvironment")], &new_name).expect("failed to rename");
+        assert_eq!(
+            doc.as_dictionary()
+                .map(|d| d.keys().cloned().collect::<Vec<_>>()),
+            Some(vec!["Environment".to_string(), "items".to_string()])
+        );
+
+        let taken = Spanned {
+            item: "items".to_string(),
+            span: Span::test_data(),
+        };
+        assert!(rename_key(&mut doc, &[key("Environment")], &taken).is_err());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut doc = document();
+        let removed =
+            remove(&mut doc, &[key("LSEnvironment")], Span::test_data()).expect("failed to remove");
+        assert!(removed.as_dictionary().is_some());
+        assert_eq!(
+            doc.as_dictionary()
+                .map(|d| d.keys().cloned().collect::<Vec<_>>()),
+            Some(vec!["items".to_string()])
+        );
+
+        let first = [key("items"), PathMember::int(0, false, Span::test_data())];
+        assert_eq!(
+            remove(&mut doc, &first, Span::test_data()).expect("failed to remove"),
+            PlistValue::Integer(1.into())
+        );
+        assert!(remove(&mut doc, &first, Span::test_data()).is_err());
+    }
+}
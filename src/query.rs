@@ -0,0 +1,687 @@
+//! A JSONPath-like query language evaluated directly against plist values.
+//!
+//! Supported syntax:
+//!
+//! - `$` the root, optional at the start of a query
+//! - `.key` or `['key']` a dictionary entry, `.*` or `[*]` every child
+//! - `[n]` an array element, negative indices count from the end
+//! - `[start:end]` a slice of an array
+//! - `..key`, `..*` or `..[n]` recursive descent
+//! - `[?(@.key)]` children that have a key, and `[?(@.key == 'value')]`
+//!   children where a comparison holds, using `==`, `!=`, `<`, `<=`, `>`,
+//!   `>=`, combined with `&&` and `||`
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{
+    ast::{CellPath, PathMember},
+    Category, Example, LabeledError, Record, Signature, Span, Spanned, SyntaxShape,
+    Value as NuValue,
+};
+use plist::Value as PlistValue;
+
+use crate::format::parse_input;
+use crate::nu_plist::{convert_plist_value, plist_error, ErrorKind, NuPlistPlugin};
+
+pub struct PlistQuery;
+
+impl SimplePluginCommand for PlistQuery {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist query"
+    }
+
+    fn usage(&self) -> &str {
+        "Select values from a plist document with a JSONPath-like query"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Queries start at the root `$` and are built from these segments:
+  .key or ['key']       a dictionary entry
+  .* or [*]             every child of a dictionary or array
+  [n]                   an array element, negative indices count from the end
+  [start:end]           a slice of an array
+  ..key                 every matching entry at any depth
+  [?(@.key)]            children that have the key
+  [?(@.key == 'x')]     children where the comparison holds; ==, !=, <, <=, >, >=
+                        can be combined with && and ||
+
+The matched values are returned as a list, or with --paths as a table of their
+paths and values."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "open --raw profile.mobileconfig | plist query '$.PayloadContent[*].PayloadType'",
+                description: "Get the type of every payload in a configuration profile",
+                result: None,
+            },
+            Example {
+                example: "open --raw profile.mobileconfig | plist query '$..PayloadIdentifier' --paths",
+                description: "Find every PayloadIdentifier at any depth, with its path",
+                result: None,
+            },
+            Example {
+                example: "open --raw profile.mobileconfig | plist query \"$.PayloadContent[?(@.PayloadType == 'com.apple.wifi.managed')]\"",
+                description: "Select payloads of one type",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required("query", SyntaxShape::String, "The query to evaluate")
+            .switch(
+                "paths",
+                "Return a table of paths and values instead of only the values",
+                Some('p'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let query: Spanned<String> = call.req(0)?;
+        let segments = parse_query(&query.item).map_err(|e| {
+            // Point at the offending character when the query is a literal,
+            // skipping over the opening quote
+            let span_len = query.span.end - query.span.start;
+            let span = match span_len.checked_sub(query.item.len()) {
+                Some(quotes) => {
+                    let start = query.span.start + quotes / 2 + e.position;
+                    Span::new(start, (start + 1).min(query.span.end))
+                }
+                None => query.span,
+            };
+            plist_error(ErrorKind::General, e.message, span)
+        })?;
+        let (plist, _) = parse_input(input, call.head)?;
+
+        let span = call.head;
+        let with_paths = call.has_flag("paths")?;
+        let rows = evaluate(&segments, &plist)
+            .into_iter()
+            .map(|(path, value)| {
+                let value = convert_plist_value(value, span)?;
+                if !with_paths {
+                    return Ok(value);
+                }
+                let mut record = Record::new();
+                record.push("path", NuValue::cell_path(CellPath { members: path }, span));
+                record.push("value", value);
+                Ok(NuValue::record(record, span))
+            })
+            .collect::<Result<_, LabeledError>>()?;
+        Ok(NuValue::list(rows, span))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Index(i64),
+    Wildcard,
+    Slice(Option<i64>, Option<i64>),
+    Filter(Filter),
+    /// Applies the inner segment to a node and all of its descendants.
+    Descendant(Box<Segment>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Exists(Vec<Segment>),
+    Compare(Vec<Segment>, Comparison, PlistValue),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    /// Byte position in the query where the error was found.
+    pub position: usize,
+}
+
+/// Parses a query into its segments.
+pub fn parse_query(query: &str) -> Result<Vec<Segment>, QueryError> {
+    let mut parser = QueryParser {
+        chars: query.char_indices().collect(),
+        pos: 0,
+        len: query.len(),
+    };
+    parser.skip_whitespace();
+    parser.eat('$');
+    let segments = parser.segments(false)?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(segments),
+        Some(c) => Err(parser.error(format!("Unexpected character '{c}'"))),
+    }
+}
+
+struct QueryParser {
+    chars: Vec<(usize, char)>,
+    pos: usize,
+    len: usize,
+}
+
+impl QueryParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|(_, c)| *c)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).map(|(_, c)| *c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        let matches = s
+            .chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_at(i) == Some(c));
+        if matches {
+            self.pos += s.chars().count();
+        }
+        matches
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, message: String) -> QueryError {
+        QueryError {
+            message,
+            position: self
+                .chars
+                .get(self.pos)
+                .map(|(i, _)| *i)
+                .unwrap_or(self.len),
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), QueryError> {
+        self.skip_whitespace();
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected '{c}'")))
+        }
+    }
+
+    /// Parses segments until the end of the query, or in a filter until a
+    /// character that can't start a segment.
+    fn segments(&mut self, relative: bool) -> Result<Vec<Segment>, QueryError> {
+        let mut segments = Vec::new();
+        loop {
+            if self.eat_str("..") {
+                let inner = match self.peek() {
+                    Some('[') => self.bracket(relative)?,
+                    _ => self.dotted()?,
+                };
+                segments.push(Segment::Descendant(Box::new(inner)));
+            } else if self.eat('.') {
+                segments.push(self.dotted()?);
+            } else if self.peek() == Some('[') {
+                segments.push(self.bracket(relative)?);
+            } else {
+                return Ok(segments);
+            }
+        }
+    }
+
+    /// A segment following a dot: a key name or `*`.
+    fn dotted(&mut self) -> Result<Segment, QueryError> {
+        if self.eat('*') {
+            return Ok(Segment::Wildcard);
+        }
+        let start = self.pos;
+        while self.peek().is_some_and(|c| {
+            !matches!(
+                c,
+                '.' | '[' | ']' | '(' | ')' | '=' | '!' | '<' | '>' | '&' | '|'
+            ) && !c.is_whitespace()
+        }) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.error("Expected a key name".into()));
+        }
+        Ok(Segment::Key(
+            self.chars[start..self.pos]
+                .iter()
+                .map(|(_, c)| *c)
+                .collect(),
+        ))
+    }
+
+    /// A bracketed segment: `['key']`, `[n]`, `[*]`, `[a:b]` or `[?(...)]`.
+    fn bracket(&mut self, relative: bool) -> Result<Segment, QueryError> {
+        self.expect('[')?;
+        self.skip_whitespace();
+        let segment = match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                Segment::Wildcard
+            }
+            Some('\'' | '"') => Segment::Key(self.quoted()?),
+            Some('?') if !relative => {
+                self.pos += 1;
+                self.expect('(')?;
+                let filter = self.filter()?;
+                self.expect(')')?;
+                Segment::Filter(filter)
+            }
+            _ => {
+                let start = self.integer()?;
+                self.skip_whitespace();
+                if self.eat(':') {
+                    let end = self.integer()?;
+                    Segment::Slice(start, end)
+                } else {
+                    match start {
+                        Some(idx) => Segment::Index(idx),
+                        None => return Err(self.error("Expected an index".into())),
+                    }
+                }
+            }
+        };
+        self.expect(']')?;
+        Ok(segment)
+    }
+
+    fn quoted(&mut self) -> Result<String, QueryError> {
+        let Some(quote) = self.peek() else {
+            return Err(self.error("Expected a quoted string".into()));
+        };
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("Unterminated string".into())),
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    if let Some(c) = self.peek() {
+                        text.push(c);
+                        self.pos += 1;
+                    }
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn integer(&mut self) -> Result<Option<i64>, QueryError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        self.eat('-');
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Ok(None);
+        }
+        let text: String = self.chars[start..self.pos]
+            .iter()
+            .map(|(_, c)| *c)
+            .collect();
+        text.parse()
+            .map(Some)
+            .map_err(|_| self.error(format!("Invalid index {text}")))
+    }
+
+    fn filter(&mut self) -> Result<Filter, QueryError> {
+        let mut filter = self.filter_and()?;
+        loop {
+            self.skip_whitespace();
+            if self.eat_str("||") {
+                filter = Filter::Or(Box::new(filter), Box::new(self.filter_and()?));
+            } else {
+                return Ok(filter);
+            }
+        }
+    }
+
+    fn filter_and(&mut self) -> Result<Filter, QueryError> {
+        let mut filter = self.condition()?;
+        loop {
+            self.skip_whitespace();
+            if self.eat_str("&&") {
+                filter = Filter::And(Box::new(filter), Box::new(self.condition()?));
+            } else {
+                return Ok(filter);
+            }
+        }
+    }
+
+    fn condition(&mut self) -> Result<Filter, QueryError> {
+        self.skip_whitespace();
+        if self.eat('(') {
+            let filter = self.filter()?;
+            self.expect(')')?;
+            return Ok(filter);
+        }
+        if !self.eat('@') {
+            return Err(self.error("Expected '@'".into()));
+        }
+        let path = self.segments(true)?;
+        self.skip_whitespace();
+        let comparison = if self.eat_str("==") {
+            Comparison::Eq
+        } else if self.eat_str("!=") {
+            Comparison::Ne
+        } else if self.eat_str("<=") {
+            Comparison::Le
+        } else if self.eat_str(">=") {
+            Comparison::Ge
+        } else if self.eat('<') {
+            Comparison::Lt
+        } else if self.eat('>') {
+            Comparison::Gt
+        } else {
+            return Ok(Filter::Exists(path));
+        };
+        let literal = self.literal()?;
+        Ok(Filter::Compare(path, comparison, literal))
+    }
+
+    fn literal(&mut self) -> Result<PlistValue, QueryError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('\'' | '"') => Ok(PlistValue::String(self.quoted()?)),
+            _ => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.'))
+                {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos]
+                    .iter()
+                    .map(|(_, c)| *c)
+                    .collect();
+                if text == "true" {
+                    Ok(PlistValue::Boolean(true))
+                } else if text == "false" {
+                    Ok(PlistValue::Boolean(false))
+                } else if let Ok(i) = text.parse::<i64>() {
+                    Ok(PlistValue::Integer(i.into()))
+                } else if let Ok(r) = text.parse::<f64>() {
+                    Ok(PlistValue::Real(r))
+                } else {
+                    self.pos = start;
+                    Err(self.error("Expected a string, number or boolean".into()))
+                }
+            }
+        }
+    }
+}
+
+/// Evaluates a query, returning every matched value with its path.
+pub fn evaluate<'a>(
+    segments: &[Segment],
+    root: &'a PlistValue,
+) -> Vec<(Vec<PathMember>, &'a PlistValue)> {
+    let mut nodes = vec![(Vec::new(), root)];
+    for segment in segments {
+        nodes = nodes
+            .into_iter()
+            .flat_map(|(path, value)| apply(segment, path, value))
+            .collect();
+    }
+    nodes
+}
+
+fn apply<'a>(
+    segment: &Segment,
+    path: Vec<PathMember>,
+    value: &'a PlistValue,
+) -> Vec<(Vec<PathMember>, &'a PlistValue)> {
+    let child = |member: PathMember, child: &'a PlistValue| {
+        let mut path = path.clone();
+        path.push(member);
+        (path, child)
+    };
+    match (segment, value) {
+        (Segment::Key(key), PlistValue::Dictionary(dict)) => dict
+            .get(key)
+            .map(|v| vec![child(key_member(key), v)])
+            .unwrap_or_default(),
+        (Segment::Index(idx), PlistValue::Array(arr)) => {
+            let idx = if *idx < 0 {
+                arr.len() as i64 + idx
+            } else {
+                *idx
+            };
+            usize::try_from(idx)
+                .ok()
+                .and_then(|idx| arr.get(idx).map(|v| vec![child(index_member(idx), v)]))
+                .unwrap_or_default()
+        }
+        (Segment::Slice(start, end), PlistValue::Array(arr)) => {
+            let len = arr.len() as i64;
+            let clamp = |i: i64| (if i < 0 { len + i } else { i }).clamp(0, len) as usize;
+            let start = start.map(clamp).unwrap_or(0);
+            let end = end.map(clamp).unwrap_or(arr.len());
+            (start..end.max(start))
+                .map(|idx| child(index_member(idx), &arr[idx]))
+                .collect()
+        }
+        (Segment::Wildcard, _) => children(value)
+            .into_iter()
+            .map(|(member, v)| child(member, v))
+            .collect(),
+        (Segment::Filter(filter), _) => children(value)
+            .into_iter()
+            .filter(|(_, v)| matches_filter(filter, v))
+            .map(|(member, v)| child(member, v))
+            .collect(),
+        (Segment::Descendant(inner), _) => {
+            let mut found = apply(inner, path.clone(), value);
+            for (member, v) in children(value) {
+                let mut path = path.clone();
+                path.push(member);
+                found.extend(apply(segment, path, v));
+            }
+            found
+        }
+        _ => vec![],
+    }
+}
+
+fn children(value: &PlistValue) -> Vec<(PathMember, &PlistValue)> {
+    match value {
+        PlistValue::Dictionary(dict) => dict.iter().map(|(k, v)| (key_member(k), v)).collect(),
+        PlistValue::Array(arr) => arr
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (index_member(i), v))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn key_member(key: &str) -> PathMember {
+    PathMember::string(key.to_string(), false, Span::unknown())
+}
+
+fn index_member(idx: usize) -> PathMember {
+    PathMember::int(idx, false, Span::unknown())
+}
+
+fn matches_filter(filter: &Filter, value: &PlistValue) -> bool {
+    match filter {
+        Filter::Exists(path) => !evaluate(path, value).is_empty(),
+        Filter::Compare(path, comparison, literal) => evaluate(path, value)
+            .iter()
+            .any(|(_, v)| compare(v, *comparison, literal)),
+        Filter::And(a, b) => matches_filter(a, value) && matches_filter(b, value),
+        Filter::Or(a, b) => matches_filter(a, value) || matches_filter(b, value),
+    }
+}
+
+fn compare(value: &PlistValue, comparison: Comparison, literal: &PlistValue) -> bool {
+    let ordering = match (value, literal) {
+        (PlistValue::String(a), PlistValue::String(b)) => a.partial_cmp(b),
+        (PlistValue::Boolean(a), PlistValue::Boolean(b)) => a.partial_cmp(b),
+        (a, b) => match (number(a), number(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => None,
+        },
+    };
+    match ordering {
+        Some(ordering) => match comparison {
+            Comparison::Eq => ordering.is_eq(),
+            Comparison::Ne => ordering.is_ne(),
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Le => ordering.is_le(),
+            Comparison::Gt => ordering.is_gt(),
+            Comparison::Ge => ordering.is_ge(),
+        },
+        // Values of different types are only ever unequal
+        None => comparison == Comparison::Ne,
+    }
+}
+
+fn number(value: &PlistValue) -> Option<f64> {
+    match value {
+        PlistValue::Integer(i) => i.as_signed().map(|i| i as f64),
+        PlistValue::Real(r) => Some(*r),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plist::Dictionary;
+
+    fn payload(kind: &str, id: &str) -> PlistValue {
+        let mut dict = Dictionary::new();
+        dict.insert(
+            "PayloadType".to_string(),
+            PlistValue::String(kind.to_string()),
+        );
+        dict.insert(
+            "PayloadIdentifier".to_string(),
+            PlistValue::String(id.to_string()),
+        );
+        PlistValue::Dictionary(dict)
+    }
+
+    fn profile() -> PlistValue {
+        let mut root = Dictionary::new();
+        root.insert(
+            "PayloadIdentifier".to_string(),
+            PlistValue::String("root".to_string()),
+        );
+        root.insert(
+            "PayloadContent".to_string(),
+            PlistValue::Array(vec![
+                payload("com.apple.wifi.managed", "wifi"),
+                payload("com.apple.vpn.managed", "vpn"),
+            ]),
+        );
+        PlistValue::Dictionary(root)
+    }
+
+    fn query(q: &str) -> Vec<PlistValue> {
+        let segments = parse_query(q).expect("failed to parse query");
+        let doc = profile();
+        evaluate(&segments, &doc)
+            .into_iter()
+            .map(|(_, v)| v.clone())
+            .collect()
+    }
+
+    fn strings(values: &[&str]) -> Vec<PlistValue> {
+        values
+            .iter()
+            .map(|s| PlistValue::String(s.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_wildcard() {
+        assert_eq!(
+            query("$.PayloadContent[*].PayloadType"),
+            strings(&["com.apple.wifi.managed", "com.apple.vpn.managed"])
+        );
+    }
+
+    #[test]
+    fn test_recursive_descent() {
+        assert_eq!(
+            query("$..PayloadIdentifier"),
+            strings(&["root", "wifi", "vpn"])
+        );
+    }
+
+    #[test]
+    fn test_index_and_slice() {
+        assert_eq!(
+            query("$.PayloadContent[-1]['PayloadIdentifier']"),
+            strings(&["vpn"])
+        );
+        assert_eq!(
+            query("$.PayloadContent[0:1].PayloadIdentifier"),
+            strings(&["wifi"])
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        assert_eq!(
+            query(
+                "$.PayloadContent[?(@.PayloadType == 'com.apple.vpn.managed')].PayloadIdentifier"
+            ),
+            strings(&["vpn"])
+        );
+        assert_eq!(
+            query("$.PayloadContent[?(@.PayloadIdentifier != 'vpn' && @.PayloadType)].PayloadIdentifier"),
+            strings(&["wifi"])
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = parse_query("$.a[?(@.b ==)]").expect_err("expected an error");
+        assert_eq!(error.position, 12);
+    }
+}
@@ -0,0 +1,593 @@
+//! Schemas describing the expected shape of plist documents.
+//!
+//! A schema is a Nu record with any of these columns:
+//!
+//! - `type`: a type name or list of type names (string, int, real, bool,
+//!   date, data, dict, array or uid)
+//! - `required`: whether a dictionary entry must be present
+//! - `keys`: a record of schemas for the entries of a dictionary
+//! - `additional`: whether a dictionary may have entries not in `keys`
+//! - `items`: the schema of every element of an array
+//! - `min` and `max`: the range of a number
+//! - `values`: the list of allowed values
+//!
+//! A plain type name can be used in place of a record.
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{
+    ast::{CellPath, PathMember},
+    Category, Example, LabeledError, Record, Signature, Span, Value as NuValue,
+};
+use plist::Value as PlistValue;
+
+use crate::format::parse_input;
+use crate::nu_plist::{
+    convert_nu_value, convert_plist_value, plist_error, ErrorKind, NuPlistPlugin,
+};
+
+pub struct PlistSchema;
+
+impl SimplePluginCommand for PlistSchema {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist schema"
+    }
+
+    fn usage(&self) -> &str {
+        "Infer a schema from one or more plist documents"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"The input is a plist document or a list of them. Keys present in every
+document are marked as required, and numbers get the range of the values seen.
+The result can be passed to `plist validate --schema`."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "ls ~/Library/LaunchAgents/*.plist | each { open --raw $in.name } | plist schema | save launchd-schema.nuon",
+            description: "Build a baseline schema from known-good launchd jobs",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self)).category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let documents = match input {
+            NuValue::List { vals, .. } => vals.as_slice(),
+            _ => std::slice::from_ref(input),
+        };
+        let mut schema: Option<Schema> = None;
+        for document in documents {
+            let (plist, _) = parse_input(document, call.head)?;
+            let inferred = Schema::infer(&plist);
+            schema = Some(match schema {
+                Some(schema) => schema.merge(inferred),
+                None => inferred,
+            });
+        }
+        match schema {
+            Some(schema) => schema.to_value(call.head),
+            None => Err(plist_error(
+                ErrorKind::Parse,
+                "No documents to infer a schema from".into(),
+                call.head,
+            )),
+        }
+    }
+}
+
+const TYPE_NAMES: &[&str] = &[
+    "string", "int", "real", "bool", "date", "data", "dict", "array", "uid",
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    /// Allowed types, any type when `None`.
+    pub types: Option<Vec<String>>,
+    pub required: bool,
+    pub keys: Vec<(String, Schema)>,
+    pub additional: bool,
+    pub items: Option<Box<Schema>>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub values: Option<Vec<PlistValue>>,
+}
+
+/// A place where a document does not match its schema.
+#[derive(Debug, PartialEq)]
+pub struct Violation {
+    pub path: Vec<PathMember>,
+    pub message: String,
+}
+
+impl Violation {
+    pub fn into_value(self, span: Span) -> NuValue {
+        let mut record = Record::new();
+        record.push(
+            "path",
+            NuValue::cell_path(CellPath { members: self.path }, span),
+        );
+        record.push("message", NuValue::string(self.message, span));
+        NuValue::record(record, span)
+    }
+}
+
+impl Schema {
+    /// Reads a schema from its Nu representation.
+    pub fn from_value(value: &NuValue) -> Result<Schema, LabeledError> {
+        let span = value.span();
+        if let NuValue::String { val, .. } = value {
+            return Ok(Schema {
+                types: Some(vec![type_name(val, span)?]),
+                additional: true,
+                ..Default::default()
+            });
+        }
+
+        let record = value.as_record()?;
+        let mut schema = Schema {
+            additional: true,
+            ..Default::default()
+        };
+        for (column, value) in record.iter() {
+            match column.as_str() {
+                "type" => {
+                    let names = match value {
+                        NuValue::List { vals, .. } => vals
+                            .iter()
+                            .map(|v| type_name(v.as_str()?, v.span()))
+                            .collect::<Result<_, LabeledError>>()?,
+                        _ => vec![type_name(value.as_str()?, value.span())?],
+                    };
+                    schema.types = Some(names);
+                }
+                "required" => schema.required = value.as_bool()?,
+                "additional" => schema.additional = value.as_bool()?,
+                "keys" => {
+                    schema.keys = value
+                        .as_record()?
+                        .iter()
+                        .map(|(key, v)| Ok((key.clone(), Schema::from_value(v)?)))
+                        .collect::<Result<_, LabeledError>>()?;
+                }
+                "items" => schema.items = Some(Box::new(Schema::from_value(value)?)),
+                "min" => schema.min = Some(number(value)?),
+                "max" => schema.max = Some(number(value)?),
+                "values" => {
+                    schema.values = Some(
+                        value
+                            .as_list()?
+                            .iter()
+                            .map(convert_nu_value)
+                            .collect::<Result<_, _>>()?,
+                    );
+                }
+                other => {
+                    return Err(plist_error(
+                        ErrorKind::General,
+                        format!("Unknown schema column {other}"),
+                        value.span(),
+                    ))
+                }
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Infers the schema of a single value. Every dictionary entry is
+    /// required and numbers are limited to the value seen.
+    pub fn infer(value: &PlistValue) -> Schema {
+        let number = as_number(value);
+        let mut schema = Schema {
+            types: Some(vec![value_type(value).to_string()]),
+            additional: true,
+            min: number,
+            max: number,
+            ..Default::default()
+        };
+        match value {
+            PlistValue::Dictionary(dict) => {
+                schema.keys = dict
+                    .iter()
+                    .map(|(key, entry)| {
+                        let mut entry = Schema::infer(entry);
+                        entry.required = true;
+                        (key.clone(), entry)
+                    })
+                    .collect();
+            }
+            PlistValue::Array(arr) => {
+                schema.items = arr
+                    .iter()
+                    .map(Schema::infer)
+                    .reduce(Schema::merge)
+                    .map(Box::new);
+            }
+            _ => {}
+        }
+        schema
+    }
+
+    /// Combines two schemas into one that accepts everything either accepts.
+    pub fn merge(mut self, other: Schema) -> Schema {
+        self.types = match (self.types, other.types) {
+            (Some(mut types), Some(others)) => {
+                for t in others {
+                    if !types.contains(&t) {
+                        types.push(t);
+                    }
+                }
+                Some(types)
+            }
+            _ => None,
+        };
+        self.required = self.required && other.required;
+        self.additional = self.additional || other.additional;
+
+        let mut other_keys = other.keys;
+        for (key, schema) in self.keys.iter_mut() {
+            match other_keys.iter().position(|(k, _)| k == key) {
+                Some(idx) => {
+                    let (_, theirs) = other_keys.remove(idx);
+                    *schema = std::mem::take(schema).merge(theirs);
+                }
+                None => schema.required = false,
+            }
+        }
+        for (key, mut schema) in other_keys {
+            schema.required = false;
+            self.keys.push((key, schema));
+        }
+
+        self.items = match (self.items, other.items) {
+            (Some(items), Some(others)) => Some(Box::new(items.merge(*others))),
+            (items, others) => items.or(others),
+        };
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        self.values = match (self.values, other.values) {
+            (Some(mut values), Some(others)) => {
+                for value in others {
+                    if !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+                Some(values)
+            }
+            _ => None,
+        };
+        self
+    }
+
+    /// The Nu representation of the schema, leaving out default columns.
+    pub fn to_value(&self, span: Span) -> Result<NuValue, LabeledError> {
+        let mut record = Record::new();
+        if let Some(types) = &self.types {
+            let value = match types.as_slice() {
+                [single] => NuValue::string(single.clone(), span),
+                _ => NuValue::list(
+                    types
+                        .iter()
+                        .map(|t| NuValue::string(t.clone(), span))
+                        .collect(),
+                    span,
+                ),
+            };
+            record.push("type", value);
+        }
+        if self.required {
+            record.push("required", NuValue::bool(true, span));
+        }
+        if !self.additional {
+            record.push("additional", NuValue::bool(false, span));
+        }
+        if !self.keys.is_empty() {
+            let mut keys = Record::new();
+            for (key, schema) in &self.keys {
+                keys.push(key.clone(), schema.to_value(span)?);
+            }
+            record.push("keys", NuValue::record(keys, span));
+        }
+        if let Some(items) = &self.items {
+            record.push("items", items.to_value(span)?);
+        }
+        let integral = self.types.as_deref() == Some(&["int".to_string()]);
+        let number = |n: f64| {
+            if integral {
+                NuValue::int(n as i64, span)
+            } else {
+                NuValue::float(n, span)
+            }
+        };
+        if let Some(min) = self.min {
+            record.push("min", number(min));
+        }
+        if let Some(max) = self.max {
+            record.push("max", number(max));
+        }
+        if let Some(values) = &self.values {
+            let values = values
+                .iter()
+                .map(|v| convert_plist_value(v, span))
+                .collect::<Result<_, _>>()?;
+            record.push("values", NuValue::list(values, span));
+        }
+        Ok(NuValue::record(record, span))
+    }
+
+    /// Checks a value against the schema, collecting every violation.
+    pub fn check(
+        &self,
+        value: &PlistValue,
+        path: &mut Vec<PathMember>,
+        violations: &mut Vec<Violation>,
+    ) {
+        let mut violation = |message: String| {
+            violations.push(Violation {
+                path: path.clone(),
+                message,
+            })
+        };
+
+        let actual = value_type(value);
+        if let Some(types) = &self.types {
+            if !types.iter().any(|t| t == actual) {
+                violation(format!("Expected {}, found {actual}", types.join(" or ")));
+                return;
+            }
+        }
+        if let Some(values) = &self.values {
+            if !values.contains(value) {
+                violation("Value is not one of the allowed values".to_string());
+            }
+        }
+        if let Some(number) = as_number(value) {
+            if let Some(min) = self.min.filter(|min| number < *min) {
+                violation(format!("Value {number} is below the minimum of {min}"));
+            }
+            if let Some(max) = self.max.filter(|max| number > *max) {
+                violation(format!("Value {number} is above the maximum of {max}"));
+            }
+        }
+
+        match value {
+            PlistValue::Dictionary(dict) => {
+                for (key, schema) in &self.keys {
+                    match dict.get(key) {
+                        Some(entry) => {
+                            path.push(PathMember::string(key.clone(), false, Span::unknown()));
+                            schema.check(entry, path, violations);
+                            path.pop();
+                        }
+                        None if schema.required => violations.push(Violation {
+                            path: path.clone(),
+                            message: format!("Missing required key {key}"),
+                        }),
+                        None => {}
+                    }
+                }
+                if !self.additional {
+                    for key in dict.keys() {
+                        if !self.keys.iter().any(|(k, _)| k == key) {
+                            violations.push(Violation {
+                                path: path.clone(),
+                                message: format!("Unexpected key {key}"),
+                            });
+                        }
+                    }
+                }
+            }
+            PlistValue::Array(arr) => {
+                if let Some(items) = &self.items {
+                    for (idx, element) in arr.iter().enumerate() {
+                        path.push(PathMember::int(idx, false, Span::unknown()));
+                        items.check(element, path, violations);
+                        path.pop();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The schema type name of a plist value.
+pub fn value_type(value: &PlistValue) -> &'static str {
+    match value {
+        PlistValue::Array(_) => "array",
+        PlistValue::Dictionary(_) => "dict",
+        PlistValue::Boolean(_) => "bool",
+        PlistValue::Data(_) => "data",
+        PlistValue::Date(_) => "date",
+        PlistValue::Real(_) => "real",
+        PlistValue::Integer(_) => "int",
+        PlistValue::String(_) => "string",
+        PlistValue::Uid(_) => "uid",
+        _ => "unknown",
+    }
+}
+
+fn as_number(value: &PlistValue) -> Option<f64> {
+    match value {
+        PlistValue::Integer(i) => i
+            .as_signed()
+            .map(|i| i as f64)
+            .or_else(|| i.as_unsigned().map(|u| u as f64)),
+        PlistValue::Real(r) => Some(*r),
+        _ => None,
+    }
+}
+
+fn type_name(name: &str, span: Span) -> Result<String, LabeledError> {
+    let name = match name {
+        "integer" => "int",
+        "float" => "real",
+        "boolean" => "bool",
+        "dictionary" | "record" => "dict",
+        "list" => "array",
+        other => other,
+    };
+    if TYPE_NAMES.contains(&name) {
+        Ok(name.to_string())
+    } else {
+        Err(plist_error(
+            ErrorKind::General,
+            format!(
+                "Unknown schema type {name}, expected one of {}",
+                TYPE_NAMES.join(", ")
+            ),
+            span,
+        ))
+    }
+}
+
+fn number(value: &NuValue) -> Result<f64, LabeledError> {
+    match value {
+        NuValue::Int { val, .. } => Ok(*val as f64),
+        NuValue::Float { val, .. } => Ok(*val),
+        _ => Err(plist_error(
+            ErrorKind::General,
+            format!("Expected a number, found {}", value.get_type()),
+            value.span(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nu_protocol::record;
+    use plist::Dictionary;
+
+    fn violations(schema: &Schema, value: &PlistValue) -> Vec<String> {
+        let mut violations = Vec::new();
+        schema.check(value, &mut Vec::new(), &mut violations);
+        violations.into_iter().map(|v| v.message).collect()
+    }
+
+    fn launchd_schema() -> Schema {
+        Schema::from_value(&NuValue::test_record(record! {
+            "type" => NuValue::test_string("dict"),
+            "additional" => NuValue::test_bool(false),
+            "keys" => NuValue::test_record(record! {
+                "Label" => NuValue::test_record(record! {
+                    "type" => NuValue::test_string("string"),
+                    "required" => NuValue::test_bool(true),
+                }),
+                "Nice" => NuValue::test_record(record! {
+                    "type" => NuValue::test_string("int"),
+                    "min" => NuValue::test_int(-20),
+                    "max" => NuValue::test_int(20),
+                }),
+                "ProgramArguments" => NuValue::test_record(record! {
+                    "type" => NuValue::test_string("array"),
+                    "items" => NuValue::test_string("string"),
+                }),
+            }),
+        }))
+        .expect("failed to read schema")
+    }
+
+    #[test]
+    fn test_valid_document() {
+        let mut dict = Dictionary::new();
+        dict.insert("Label".to_string(), PlistValue::String("demo".to_string()));
+        dict.insert(
+            "ProgramArguments".to_string(),
+            PlistValue::Array(vec![PlistValue::String("/bin/true".to_string())]),
+        );
+        assert!(violations(&launchd_schema(), &PlistValue::Dictionary(dict)).is_empty());
+    }
+
+    #[test]
+    fn test_violations() {
+        let mut dict = Dictionary::new();
+        dict.insert("Nice".to_string(), PlistValue::Integer(40.into()));
+        dict.insert(
+            "ProgramArguments".to_string(),
+            PlistValue::Array(vec![PlistValue::Integer(1.into())]),
+        );
+        dict.insert("Extra".to_string(), PlistValue::Boolean(true));
+        assert_eq!(
+            violations(&launchd_schema(), &PlistValue::Dictionary(dict)),
+            vec![
+                "Missing required key Label",
+                "Value 40 is above the maximum of 20",
+                "Expected string, found int",
+                "Unexpected key Extra",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        let mut first = Dictionary::new();
+        first.insert("Label".to_string(), PlistValue::String("a".to_string()));
+        first.insert("Nice".to_string(), PlistValue::Integer(5.into()));
+        let mut second = Dictionary::new();
+        second.insert("Label".to_string(), PlistValue::String("b".to_string()));
+        second.insert("Nice".to_string(), PlistValue::Integer((-5).into()));
+        second.insert("Disabled".to_string(), PlistValue::Boolean(true));
+
+        let schema = Schema::infer(&PlistValue::Dictionary(first))
+            .merge(Schema::infer(&PlistValue::Dictionary(second)));
+        assert_eq!(
+            schema
+                .to_value(Span::test_data())
+                .expect("failed to convert"),
+            NuValue::test_record(record! {
+                "type" => NuValue::test_string("dict"),
+                "keys" => NuValue::test_record(record! {
+                    "Label" => NuValue::test_record(record! {
+                        "type" => NuValue::test_string("string"),
+                        "required" => NuValue::test_bool(true),
+                    }),
+                    "Nice" => NuValue::test_record(record! {
+                        "type" => NuValue::test_string("int"),
+                        "required" => NuValue::test_bool(true),
+                        "min" => NuValue::test_int(-5),
+                        "max" => NuValue::test_int(5),
+                    }),
+                    "Disabled" => NuValue::test_record(record! {
+                        "type" => NuValue::test_string("bool"),
+                    }),
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_inferred_schema_round_trip() {
+        let value = PlistValue::Array(vec![PlistValue::Integer(1.into()), PlistValue::Real(2.5)]);
+        let schema = Schema::infer(&value);
+        let read_back = Schema::from_value(
+            &schema
+                .to_value(Span::test_data())
+                .expect("failed to convert"),
+        )
+        .expect("failed to read schema");
+        assert!(violations(&read_back, &value).is_empty());
+    }
+
+    #[test]
+    fn test_unknown_type() {
+        assert!(Schema::from_value(&NuValue::test_string("number")).is_err());
+    }
+}
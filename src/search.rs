@@ -0,0 +1,359 @@
+//! Searching plist documents for keys and values.
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{
+    ast::{CellPath, PathMember},
+    Category, Example, LabeledError, Record, Signature, Span, Spanned, SyntaxShape,
+    Value as NuValue,
+};
+use plist::Value as PlistValue;
+use regex::{Regex, RegexBuilder};
+
+use crate::format::parse_input;
+use crate::nu_plist::{convert_date, convert_plist_value, plist_error, ErrorKind, NuPlistPlugin};
+use crate::path::walk_leaves;
+use crate::schema::value_type;
+
+pub struct PlistFind;
+pub struct PlistGrep;
+
+/// The value types `plist grep` can match against.
+const GREP_TYPES: &[&str] = &["string", "data", "int", "real", "bool", "date"];
+
+impl SimplePluginCommand for PlistFind {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist find"
+    }
+
+    fn usage(&self) -> &str {
+        "Search the keys and string values of a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Every dictionary key and string value containing the term is listed with its
+path, whether the key or the value matched, the matched text and the type of the
+value at that path. Matching ignores case unless --case-sensitive is given."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "open --raw ~/Library/Preferences/com.apple.dock.plist | plist find autohide",
+            description: "Find where a setting lives",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required("term", SyntaxShape::String, "The text to search for")
+            .switch("case-sensitive", "Match case exactly", Some('s'))
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let term: String = call.req(0)?;
+        let matcher = Matcher::new(&term, call.has_flag("case-sensitive")?);
+        let (plist, _) = parse_input(input, call.head)?;
+
+        let mut rows = Vec::new();
+        find(&plist, &matcher, &mut Vec::new(), &mut |found| {
+            rows.push(found.into_value(call.head))
+        });
+        Ok(NuValue::list(rows, call.head))
+    }
+}
+
+impl SimplePluginCommand for PlistGrep {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist grep"
+    }
+
+    fn usage(&self) -> &str {
+        "Match a regular expression against the values of a plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Each matching value is listed with its path and type. Only string values are
+searched unless --types is given. Data values are matched as UTF-8 text, and
+numbers, booleans and dates in their text form."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "open --raw profile.mobileconfig | plist grep '^com\\.example\\.'",
+                description: "Find every value starting with a reverse-DNS prefix",
+                result: None,
+            },
+            Example {
+                example: "open --raw state.plist | plist grep -i token --types [string data]",
+                description: "Search strings and data blobs ignoring case",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .required("pattern", SyntaxShape::String, "The regular expression")
+            .switch("ignore-case", "Match case-insensitively", Some('i'))
+            .named(
+                "types",
+                SyntaxShape::List(Box::new(SyntaxShape::String)),
+                "Value types to search: string, data, int, real, bool or date (default string)",
+                Some('t'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let pattern: Spanned<String> = call.req(0)?;
+        let regex = RegexBuilder::new(&pattern.item)
+            .case_insensitive(call.has_flag("ignore-case")?)
+            .build()
+            .map_err(|e| {
+                plist_error(
+                    ErrorKind::General,
+                    format!("Invalid regex: {e}"),
+                    pattern.span,
+                )
+            })?;
+        let types = match call.get_flag::<Vec<Spanned<String>>>("types")? {
+            Some(types) => {
+                for t in &types {
+                    if !GREP_TYPES.contains(&t.item.as_str()) {
+                        return Err(plist_error(
+                            ErrorKind::General,
+                            format!(
+                                "Unknown type {}, expected one of {}",
+                                t.item,
+                                GREP_TYPES.join(", ")
+                            ),
+                            t.span,
+                        ));
+                    }
+                }
+                types.into_iter().map(|t| t.item).collect()
+            }
+            None => vec!["string".to_string()],
+        };
+        let (plist, _) = parse_input(input, call.head)?;
+
+        let mut matches = Vec::new();
+        walk_leaves(&plist, &mut Vec::new(), &mut |path, value| {
+            if grep_matches(&regex, &types, value) {
+                matches.push((path.to_vec(), value));
+            }
+        });
+
+        let span = call.head;
+        let rows = matches
+            .into_iter()
+            .map(|(path, value)| {
+                let mut record = Record::new();
+                record.push("path", NuValue::cell_path(CellPath { members: path }, span));
+                record.push("value", convert_plist_value(value, span)?);
+                record.push("type", NuValue::string(value_type(value), span));
+                Ok(NuValue::record(record, span))
+            })
+            .collect::<Result<_, LabeledError>>()?;
+        Ok(NuValue::list(rows, span))
+    }
+}
+
+/// Whether a leaf value of one of the given types matches the regex.
+fn grep_matches(regex: &Regex, types: &[String], value: &PlistValue) -> bool {
+    if !types.iter().any(|t| t == value_type(value)) {
+        return false;
+    }
+    match value {
+        PlistValue::String(s) => regex.is_match(s),
+        PlistValue::Data(data) => regex.is_match(&String::from_utf8_lossy(data)),
+        PlistValue::Integer(i) => regex.is_match(&i.to_string()),
+        PlistValue::Real(r) => regex.is_match(&r.to_string()),
+        PlistValue::Boolean(b) => regex.is_match(&b.to_string()),
+        PlistValue::Date(d) => convert_date(d).is_some_and(|d| regex.is_match(&d.to_rfc3339())),
+        _ => false,
+    }
+}
+
+struct Matcher {
+    term: String,
+    case_sensitive: bool,
+}
+
+impl Matcher {
+    fn new(term: &str, case_sensitive: bool) -> Self {
+        let term = if case_sensitive {
+            term.to_string()
+        } else {
+            term.to_lowercase()
+        };
+        Matcher {
+            term,
+            case_sensitive,
+        }
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        if self.case_sensitive {
+            text.contains(&self.term)
+        } else {
+            text.to_lowercase().contains(&self.term)
+        }
+    }
+}
+
+/// A key or string value matching a search.
+struct Found<'a> {
+    path: Vec<PathMember>,
+    in_key: bool,
+    text: &'a str,
+    value: &'a PlistValue,
+}
+
+impl Found<'_> {
+    fn into_value(self, span: Span) -> NuValue {
+        let mut record = Record::new();
+        record.push(
+            "path",
+            NuValue::cell_path(CellPath { members: self.path }, span),
+        );
+        record.push(
+            "match",
+            NuValue::string(if self.in_key { "key" } else { "value" }, span),
+        );
+        record.push("text", NuValue::string(self.text, span));
+        record.push("type", NuValue::string(value_type(self.value), span));
+        NuValue::record(record, span)
+    }
+}
+
+fn find<'a>(
+    value: &'a PlistValue,
+    matcher: &Matcher,
+    path: &mut Vec<PathMember>,
+    found: &mut impl FnMut(Found<'a>),
+) {
+    match value {
+        PlistValue::Dictionary(dict) => {
+            for (key, entry) in dict {
+                path.push(PathMember::string(key.clone(), false, Span::unknown()));
+                if matcher.matches(key) {
+                    found(Found {
+                        path: path.clone(),
+                        in_key: true,
+                        text: key,
+                        value: entry,
+                    });
+                }
+                find(entry, matcher, path, found);
+                path.pop();
+            }
+        }
+        PlistValue::Array(arr) => {
+            for (idx, element) in arr.iter().enumerate() {
+                path.push(PathMember::int(idx, false, Span::unknown()));
+                find(element, matcher, path, found);
+                path.pop();
+            }
+        }
+        PlistValue::String(text) if matcher.matches(text) => found(Found {
+            path: path.clone(),
+            in_key: false,
+            text,
+            value,
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use plist::Dictionary;
+
+    #[test]
+    fn test_find() {
+        let mut inner = Dictionary::new();
+        inner.insert("autohide".to_string(), PlistValue::Boolean(true));
+        inner.insert(
+            "label".to_string(),
+            PlistValue::String("AutoHide delay".to_string()),
+        );
+        let mut root = Dictionary::new();
+        root.insert(
+            "items".to_string(),
+            PlistValue::Array(vec![PlistValue::Dictionary(inner)]),
+        );
+        let plist = PlistValue::Dictionary(root);
+
+        let mut found = Vec::new();
+        find(
+            &plist,
+            &Matcher::new("AUTOHIDE", false),
+            &mut Vec::new(),
+            &mut |f| found.push((f.path.len(), f.in_key, f.text.to_string())),
+        );
+        assert_eq!(
+            found,
+            vec![
+                (3, true, "autohide".to_string()),
+                (3, false, "AutoHide delay".to_string())
+            ]
+        );
+
+        let mut count = 0;
+        find(
+            &plist,
+            &Matcher::new("AutoHide", true),
+            &mut Vec::new(),
+            &mut |_| count += 1,
+        );
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_grep_matches() {
+        let regex = Regex::new("^com\\.example").expect("invalid regex");
+        let strings = vec!["string".to_string()];
+        assert!(grep_matches(
+            &regex,
+            &strings,
+            &PlistValue::String("com.example.app".to_string())
+        ));
+        assert!(!grep_matches(
+            &regex,
+            &strings,
+            &PlistValue::Data(b"com.example.app".to_vec())
+        ));
+        assert!(grep_matches(
+            &regex,
+            &["data".to_string()],
+            &PlistValue::Data(b"com.example.app".to_vec())
+        ));
+
+        let digits = Regex::new("^4[0-9]$").expect("invalid regex");
+        assert!(grep_matches(
+            &digits,
+            &["int".to_string()],
+            &PlistValue::Integer(42.into())
+        ));
+    }
+}
@@ -0,0 +1,341 @@
+//! Splitting a document whose root is an array into one document per item,
+//! and joining documents back into one array.
+
+use std::path::{Path, PathBuf};
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, ListStream, PipelineData, ShellError, Signature, Span,
+    Spanned, SyntaxShape, Value as NuValue,
+};
+use plist::Value as PlistValue;
+
+use crate::file::{format_flag, resolve_path, write_atomic};
+use crate::format::{parse_input, serialize, write_plist, PlistFormat};
+use crate::nu_plist::{convert_nu_value, plist_error, ErrorKind, NuPlistPlugin};
+use crate::schema::value_type;
+
+pub struct PlistSplit;
+pub struct PlistJoin;
+
+impl PluginCommand for PlistSplit {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist split"
+    }
+
+    fn usage(&self) -> &str {
+        "Split a plist whose root is an array into one document per item"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"The input may be a plist document, as a string or binary, or a Nu list. Each
+item is written in the format of the input, or the one given with --format.
+
+Without --directory the documents are streamed, each written as it is
+reached. With it they are
+written to <prefix>-0001.plist, <prefix>-0002.plist and so on in that
+directory, and the paths of the files are returned. The prefix is `out` unless
+--prefix gives another."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "open --raw agents.plist | plist split",
+                description: "Split an array of launch agents into separate documents",
+                result: None,
+            },
+            Example {
+                example: "open --raw agents.plist | plist split --directory agents --prefix agent",
+                description: "Write each item to agents/agent-0001.plist and onwards",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .named(
+                "format",
+                SyntaxShape::String,
+                "Output format: xml, binary, openstep or gnustep",
+                Some('f'),
+            )
+            .named(
+                "directory",
+                SyntaxShape::Directory,
+                "Write each item to a numbered file in this directory",
+                Some('d'),
+            )
+            .named(
+                "prefix",
+                SyntaxShape::String,
+                "Start of the names of the written files, out by default",
+                Some('p'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let input = input.into_value(call.head)?;
+        let (plist, input_format) = match &input {
+            NuValue::String { .. } | NuValue::Binary { .. } => parse_input(&input, call.head)?,
+            _ => (convert_nu_value(&input)?, PlistFormat::Xml),
+        };
+        let format = match call.get_flag::<Spanned<String>>("format")? {
+            Some(name) => format_flag(Some(name), call.head)?,
+            None => input_format,
+        };
+        let items = split_root(plist, input.span())?;
+
+        let Some(directory) = call.get_flag::<Spanned<String>>("directory")? else {
+            let documents = split_documents(items, format, call.head);
+            return Ok(PipelineData::ListStream(
+                ListStream::new(documents, call.head, engine.signals().clone()),
+                None,
+            ));
+        };
+        let prefix = call
+            .get_flag::<String>("prefix")?
+            .unwrap_or_else(|| "out".to_string());
+        let directory = resolve_path(engine, &directory)?;
+        // Files are written before returning, so they exist even if the
+        // paths are never read
+        let paths = write_items(&directory, &prefix, &items, format, call.head)?;
+        Ok(PipelineData::Value(
+            NuValue::list(
+                paths
+                    .into_iter()
+                    .map(|path| NuValue::string(path.display().to_string(), call.head))
+                    .collect(),
+                call.head,
+            ),
+            None,
+        ))
+    }
+}
+
+impl SimplePluginCommand for PlistJoin {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist join"
+    }
+
+    fn usage(&self) -> &str {
+        "Combine a list of plists into one document whose root is an array of them"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Items may be plist documents, as strings or binaries, or Nu values, and keep
+their order in the array. The document is written in the format of the first
+document in the list, or XML, unless --format gives another. This is the
+inverse of `plist split`."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![
+            Example {
+                example: "ls agents/*.plist | each { open --raw $in.name } | plist join",
+                description: "Combine a directory of plists into one array",
+                result: None,
+            },
+            Example {
+                example: "[{ Label: a } { Label: b }] | plist join --format binary",
+                description: "Write records as a binary plist array",
+                result: None,
+            },
+        ]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .named(
+                "format",
+                SyntaxShape::String,
+                "Output format: xml, binary, openstep or gnustep",
+                Some('f'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let NuValue::List { vals, .. } = input else {
+            return Err(plist_error(
+                ErrorKind::UnsupportedType,
+                format!("Invalid input, must be a list not {}", input.get_type()),
+                input.span(),
+            ));
+        };
+        let (items, first_format) = join_items(vals)?;
+        let format = match call.get_flag::<Spanned<String>>("format")? {
+            Some(name) => format_flag(Some(name), call.head)?,
+            None => first_format.unwrap_or(PlistFormat::Xml),
+        };
+        write_plist(&PlistValue::Array(items), format, call.head)
+    }
+}
+
+/// Decodes the items of a list to join, with the format of the first plist
+/// document among them.
+fn join_items(vals: &[NuValue]) -> Result<(Vec<PlistValue>, Option<PlistFormat>), LabeledError> {
+    let mut first_format = None;
+    let mut items = Vec::with_capacity(vals.len());
+    for value in vals {
+        match value {
+            NuValue::String { .. } | NuValue::Binary { .. } => {
+                let (plist, format) = parse_input(value, value.span())?;
+                first_format.get_or_insert(format);
+                items.push(plist);
+            }
+            _ => items.push(convert_nu_value(value)?),
+        }
+    }
+    Ok((items, first_format))
+}
+
+/// The items of a document whose root is an array.
+fn split_root(plist: PlistValue, span: Span) -> Result<Vec<PlistValue>, LabeledError> {
+    match plist {
+        PlistValue::Array(items) => Ok(items),
+        other => Err(plist_error(
+            ErrorKind::UnsupportedType,
+            format!(
+                "Only a plist whose root is an array can be split, not a {}",
+                value_type(&other)
+            ),
+            span,
+        )),
+    }
+}
+
+/// Writes each item as its own document when it is reached, giving an error
+/// in place of an item that can't be written.
+fn split_documents(
+    items: Vec<PlistValue>,
+    format: PlistFormat,
+    span: Span,
+) -> impl Iterator<Item = NuValue> {
+    items.into_iter().map(move |item| {
+        write_plist(&item, format, span)
+            .unwrap_or_else(|e| NuValue::error(ShellError::from(e), span))
+    })
+}
+
+/// Writes each item to its own numbered file, padding the numbers to at least
+/// four digits so the files sort in order.
+fn write_items(
+    directory: &Path,
+    prefix: &str,
+    items: &[PlistValue],
+    format: PlistFormat,
+    span: Span,
+) -> Result<Vec<PathBuf>, LabeledError> {
+    let width = items.len().to_string().len().max(4);
+    items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let path = directory.join(format!("{prefix}-{:0width$}.plist", index + 1));
+            write_atomic(&path, false, span, |writer| {
+                serialize(item, format, writer, span)
+            })?;
+            Ok(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::{parse_plist, plist_bytes};
+    use nu_protocol::Record;
+    use std::fs;
+
+    #[test]
+    fn test_split_root() {
+        let span = Span::test_data();
+        let items =
+            split_root(PlistValue::Array(vec![1.into(), 2.into()]), span).expect("failed to split");
+        assert_eq!(items, vec![1.into(), 2.into()]);
+        let error =
+            split_root(PlistValue::String("a".into()), span).expect_err("expected an error");
+        assert!(error.labels[0].text.contains("not a string"));
+    }
+
+    #[test]
+    fn test_join_items() {
+        let span = Span::test_data();
+        let mut record = Record::new();
+        record.push("Label", NuValue::string("b", span));
+        let vals = vec![
+            NuValue::binary(
+                plist_bytes(&PlistValue::Boolean(true), PlistFormat::Binary, span)
+                    .expect("failed to write"),
+                span,
+            ),
+            NuValue::record(record, span),
+            NuValue::string("<string>c</string>", span),
+        ];
+        let (items, format) = join_items(&vals).expect("failed to join");
+        assert_eq!(format, Some(PlistFormat::Binary));
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0], PlistValue::Boolean(true));
+        assert_eq!(items[2], PlistValue::String("c".into()));
+        assert_eq!(
+            split_root(PlistValue::Array(items.clone()), span).ok(),
+            Some(items)
+        );
+    }
+
+    #[test]
+    fn test_split_documents() {
+        let span = Span::test_data();
+        let far = std::time::UNIX_EPOCH + std::time::Duration::from_secs(9_000_000_000_000);
+        let items = vec![
+            PlistValue::String("a".into()),
+            PlistValue::Date(far.into()),
+            PlistValue::Boolean(true),
+        ];
+        let documents: Vec<NuValue> = split_documents(items, PlistFormat::OpenStep, span).collect();
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents[0], NuValue::test_string("a\n"));
+        assert!(matches!(documents[1], NuValue::Error { .. }));
+        assert!(!matches!(documents[2], NuValue::Error { .. }));
+    }
+
+    #[test]
+    fn test_write_items() {
+        let span = Span::test_data();
+        let dir =
+            std::env::temp_dir().join(format!("nu_plugin_plist_split_{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("failed to create directory");
+        let items = vec![PlistValue::String("a".into()), PlistValue::Boolean(true)];
+        let paths =
+            write_items(&dir, "agent", &items, PlistFormat::Binary, span).expect("failed to write");
+        assert_eq!(
+            paths,
+            vec![dir.join("agent-0001.plist"), dir.join("agent-0002.plist")]
+        );
+        let bytes = fs::read(&paths[1]).expect("failed to read");
+        assert_eq!(
+            parse_plist(&bytes, span).ok(),
+            Some((PlistValue::Boolean(true), PlistFormat::Binary))
+        );
+        fs::remove_dir_all(&dir).expect("failed to clean up");
+    }
+}
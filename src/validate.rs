@@ -0,0 +1,186 @@
+//! Checking that documents are well-formed plists.
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand, SimplePluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, Record, Signature, Span, SyntaxShape, Value as NuValue,
+};
+
+use crate::format::{input_bytes, try_parse, ParseError};
+use crate::nu_plist::NuPlistPlugin;
+use crate::schema::Schema;
+
+pub struct PlistValidate;
+
+impl SimplePluginCommand for PlistValidate {
+    type Plugin = NuPlistPlugin;
+
+    fn name(&self) -> &str {
+        "plist validate"
+    }
+
+    fn usage(&self) -> &str {
+        "Check that the input is a well-formed plist document"
+    }
+
+    fn extra_usage(&self) -> &str {
+        r#"Rather than failing on the first parse error, a record is returned with
+`valid`, the detected `format` and a table of `errors`. Each error has a message
+and its location: a line and column for OpenStep documents, a byte offset for
+binary documents along with the index of the `object` being read, and a line,
+column and offset plus the `element` they happened at for XML.
+
+With --schema the parsed document is also checked against a schema record, and
+any mismatches are listed in `violations` with their path and a message. See
+`plist schema` for inferring a schema from known-good documents.
+
+A schema record can have these columns, all optional:
+  type: a type name or list of them (string, int, real, bool, date, data, dict, array, uid)
+  required: whether the key must be present in its dictionary
+  keys: a record of schemas for the entries of a dictionary
+  additional: whether a dictionary may have keys not listed in `keys` (default true)
+  items: the schema of every element of an array
+  min, max: the allowed range of a number
+  values: a list of allowed values
+A type name on its own can be used in place of a schema record."#
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        vec![Example {
+            example: "ls *.plist | each { |f| open --raw $f.name | plist validate | insert file $f.name }",
+            description: "Check every plist in a directory",
+            result: None,
+        },
+        Example {
+            example: "open --raw com.example.agent.plist | plist validate --schema { keys: { Label: { type: string, required: true }, ProgramArguments: { type: array, items: string } } }",
+            description: "Check a launchd job against a schema",
+            result: None,
+        }]
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(PluginCommand::name(self))
+            .named(
+                "schema",
+                SyntaxShape::Record(vec![]),
+                "A schema the document must match",
+                Some('s'),
+            )
+            .category(Category::Formats)
+    }
+
+    fn run(
+        &self,
+        _plugin: &NuPlistPlugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: &NuValue,
+    ) -> Result<NuValue, LabeledError> {
+        let schema = call
+            .get_flag::<NuValue>("schema")?
+            .map(|schema| Schema::from_value(&schema))
+            .transpose()?;
+        let bytes = input_bytes(input, call.head)?;
+        Ok(validate(bytes, schema.as_ref(), call.head))
+    }
+}
+
+/// Parses a document and checks it against the schema, if any, returning the
+/// record `plist validate` gives.
+fn validate(bytes: &[u8], schema: Option<&Schema>, span: Span) -> NuValue {
+    let (format, result) = try_parse(bytes);
+
+    let mut errors = Vec::new();
+    let mut violations = Vec::new();
+    match result {
+        Ok(plist) => {
+            if let Some(schema) = schema {
+                schema.check(&plist, &mut Vec::new(), &mut violations);
+            }
+        }
+        Err(error) => errors.push(diagnostic(&error, span)),
+    }
+
+    let mut record = Record::new();
+    record.push(
+        "valid",
+        NuValue::bool(errors.is_empty() && violations.is_empty(), span),
+    );
+    record.push("format", NuValue::string(format.as_str(), span));
+    record.push("errors", NuValue::list(errors, span));
+    if schema.is_some() {
+        let violations = violations
+            .into_iter()
+            .map(|violation| violation.into_value(span))
+            .collect();
+        record.push("violations", NuValue::list(violations, span));
+    }
+    NuValue::record(record, span)
+}
+
+fn diagnostic(error: &ParseError, span: Span) -> NuValue {
+    let optional = |value: Option<i64>| match value {
+        Some(value) => NuValue::int(value, span),
+        None => NuValue::nothing(span),
+    };
+    let mut record = Record::new();
+    record.push("message", NuValue::string(error.message.clone(), span));
+    record.push("line", optional(error.line.map(|l| l as i64)));
+    record.push("column", optional(error.column.map(|c| c as i64)));
+    record.push("offset", optional(error.offset.map(|o| o as i64)));
+    record.push("object", optional(error.object.map(|o| o as i64)));
+    record.push(
+        "element",
+        match &error.element {
+            Some(element) => NuValue::string(element.clone(), span),
+            None => NuValue::nothing(span),
+        },
+    );
+    NuValue::record(record, span)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::format::{plist_bytes, PlistFormat};
+    use plist::Value as PlistValue;
+
+    #[test]
+    fn test_valid_binary() {
+        let span = Span::test_data();
+        let bytes = plist_bytes(&PlistValue::Boolean(true), PlistFormat::Binary, span)
+            .expect("failed to write");
+        let result = validate(&bytes, None, span);
+        let result = result.as_record().expect("expected a record");
+        assert_eq!(result.get("valid"), Some(&NuValue::test_bool(true)));
+        assert_eq!(result.get("format"), Some(&NuValue::test_string("binary")));
+        assert_eq!(result.get("errors"), Some(&NuValue::test_list(vec![])));
+        assert_eq!(result.get("violations"), None);
+    }
+
+    #[test]
+    fn test_invalid_openstep() {
+        let span = Span::test_data();
+        let result = validate(b"{\n  a = 1;\n  b = ;\n}", None, span);
+        let result = result.as_record().expect("expected a record");
+        assert_eq!(result.get("valid"), Some(&NuValue::test_bool(false)));
+        assert_eq!(
+            result.get("format"),
+            Some(&NuValue::test_string("openstep"))
+        );
+        let Some(NuValue::List { vals: errors, .. }) = result.get("errors") else {
+            panic!("expected a list of errors");
+        };
+        assert_eq!(errors.len(), 1);
+        let error = errors[0].as_record().expect("expected a record");
+        assert_eq!(
+            error.columns().collect::<Vec<_>>(),
+            vec!["message", "line", "column", "offset", "object", "element"]
+        );
+        assert!(matches!(error.get("message"), Some(NuValue::String { .. })));
+        assert_eq!(error.get("line"), Some(&NuValue::test_int(3)));
+        assert!(matches!(error.get("column"), Some(NuValue::Int { .. })));
+        for column in ["offset", "object", "element"] {
+            assert_eq!(error.get(column), Some(&NuValue::nothing(span)));
+        }
+    }
+}
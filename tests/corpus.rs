@@ -0,0 +1,138 @@
+//! Real-world plists checked against golden expectations. Each fixture in
+//! `tests/fixtures` has a `.golden.xml` file holding the XML plist it should
+//! read as. Run with `UPDATE_GOLDEN=1` to rewrite the golden files after an
+//! intended change.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nu_plugin_plist::{
+    convert_plist_value, decode_archive, parse_plist, plist_bytes, DecodeOptions, PlistFormat,
+};
+use nu_protocol::{Span, Value as NuValue};
+use plist::Value as PlistValue;
+
+const FIXTURES: [(&str, PlistFormat); 4] = [
+    ("Info.plist", PlistFormat::Xml),
+    ("profile.mobileconfig", PlistFormat::Xml),
+    ("archive.plist", PlistFormat::Binary),
+    ("Library.xml", PlistFormat::Xml),
+];
+
+fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+fn read_fixture(name: &str) -> (PlistValue, PlistFormat) {
+    let bytes = fs::read(fixture_path(name)).expect("failed to read fixture");
+    parse_plist(&bytes, Span::test_data()).expect("failed to parse fixture")
+}
+
+fn get<'a>(value: &'a NuValue, path: &[&str]) -> &'a NuValue {
+    path.iter().fold(value, |value, key| {
+        let record = value.as_record().expect("expected a record");
+        record.get(key).expect("missing key")
+    })
+}
+
+#[test]
+fn fixtures_match_golden() {
+    let span = Span::test_data();
+    for (name, format) in FIXTURES {
+        let (plist_val, read_format) = read_fixture(name);
+        assert_eq!(read_format, format, "{name} was read as the wrong format");
+
+        let xml = plist_bytes(&plist_val, PlistFormat::Xml, span).expect("failed to write");
+        let golden = fixture_path(&format!("{name}.golden.xml"));
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&golden, &xml).expect("failed to write golden file");
+        }
+        let expected = fs::read_to_string(&golden).expect("failed to read golden file");
+        assert_eq!(String::from_utf8_lossy(&xml), expected, "{name} differs");
+
+        // XML has no UIDs, so archives only keep them in binary
+        for format in [format, PlistFormat::Binary] {
+            let bytes = plist_bytes(&plist_val, format, span).expect("failed to write");
+            let (read, _) = parse_plist(&bytes, span).expect("failed to read");
+            assert_eq!(
+                read,
+                plist_val,
+                "{name} changed through {}",
+                format.as_str()
+            );
+        }
+    }
+}
+
+#[test]
+fn info_plist() {
+    let span = Span::test_data();
+    let (plist_val, _) = read_fixture("Info.plist");
+    let info = convert_plist_value(&plist_val, span).expect("failed to convert");
+    assert_eq!(
+        get(&info, &["CFBundleIdentifier"]),
+        &NuValue::string("com.example.app", span)
+    );
+    assert_eq!(
+        get(&info, &["UIDeviceFamily"]),
+        &NuValue::list(vec![NuValue::int(1, span), NuValue::int(2, span)], span)
+    );
+    assert_eq!(
+        get(&info, &["LSRequiresIPhoneOS"]),
+        &NuValue::bool(true, span)
+    );
+}
+
+#[test]
+fn mobileconfig() {
+    let span = Span::test_data();
+    let (plist_val, _) = read_fixture("profile.mobileconfig");
+    let profile = convert_plist_value(&plist_val, span).expect("failed to convert");
+    assert_eq!(
+        get(&profile, &["PayloadDescription"]),
+        &NuValue::string("Wi-Fi and certificate settings for Example & Co.", span)
+    );
+    let payloads = get(&profile, &["PayloadContent"])
+        .as_list()
+        .expect("expected a list");
+    assert_eq!(payloads.len(), 2);
+    assert!(matches!(
+        get(&payloads[1], &["PayloadContent"]),
+        NuValue::Binary { val, .. } if val.len() == 36
+    ));
+}
+
+#[test]
+fn keyed_archive() {
+    let span = Span::test_data();
+    let (plist_val, _) = read_fixture("archive.plist");
+    let root = decode_archive(&plist_val, &DecodeOptions::default(), None, span)
+        .expect("failed to decode");
+    assert_eq!(get(&root, &["title"]), &NuValue::string("Groceries", span));
+    assert_eq!(
+        get(&root, &["items"]),
+        &NuValue::list(
+            vec![NuValue::string("milk", span), NuValue::string("eggs", span)],
+            span
+        )
+    );
+    assert_eq!(get(&root, &["count"]), &NuValue::int(2, span));
+}
+
+#[test]
+fn itunes_library() {
+    let span = Span::test_data();
+    let (plist_val, _) = read_fixture("Library.xml");
+    let library = convert_plist_value(&plist_val, span).expect("failed to convert");
+    assert_eq!(
+        get(&library, &["Tracks", "1236", "Name"]),
+        &NuValue::string("Café Olé", span)
+    );
+    let NuValue::Date { val, .. } = get(&library, &["Tracks", "1234", "Date Added"]) else {
+        panic!("expected a date");
+    };
+    assert_eq!(val.to_rfc3339(), "2019-03-02T18:05:40+00:00");
+}
@@ -0,0 +1,252 @@
+//! End-to-end tests running commands through the plugin protocol, rather than
+//! calling the conversion functions directly.
+
+use nu_plugin::Plugin;
+use nu_plugin_plist::NuPlistPlugin;
+use nu_plugin_test_support::PluginTest;
+use nu_protocol::{record, PipelineData, ShellError, Span, Value};
+
+const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>Label</key>
+	<string>com.example.agent</string>
+	<key>RunAtLoad</key>
+	<true/>
+	<key>Interval</key>
+	<real>1.5</real>
+	<key>Created</key>
+	<date>2024-01-01T00:00:00Z</date>
+</dict>
+</plist>"#;
+
+fn plugin_test() -> PluginTest {
+    PluginTest::new("plist", NuPlistPlugin.into()).expect("failed to start the plugin")
+}
+
+fn input(value: Value) -> PipelineData {
+    PipelineData::Value(value, None)
+}
+
+fn eval_with(source: &str, value: Value) -> Result<Value, ShellError> {
+    plugin_test()
+        .eval_with(source, input(value))?
+        .into_value(Span::test_data())
+}
+
+fn labeled(error: ShellError) -> nu_protocol::LabeledError {
+    match error {
+        ShellError::LabeledError(error) => *error,
+        other => panic!("expected a labeled error, got {other:?}"),
+    }
+}
+
+#[test]
+fn from_plist_xml() -> Result<(), ShellError> {
+    let value = eval_with("from plist --dates-as string", Value::test_string(XML))?;
+    assert_eq!(
+        value,
+        Value::test_record(record! {
+            "Label" => Value::test_string("com.example.agent"),
+            "RunAtLoad" => Value::test_bool(true),
+            "Interval" => Value::test_float(1.5),
+            "Created" => Value::test_string("2024-01-01T00:00:00Z"),
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn binary_round_trip() -> Result<(), ShellError> {
+    let mut test = plugin_test();
+    let bytes = test
+        .eval("{ name: agent, count: 3, data: 0x[01ff] } | to plist --binary")?
+        .into_value(Span::test_data())?;
+    let Value::Binary { val, .. } = &bytes else {
+        panic!("expected binary output, got {bytes:?}");
+    };
+    assert!(val.starts_with(b"bplist00"));
+
+    let value = test
+        .eval_with("from plist", input(bytes))?
+        .into_value(Span::test_data())?;
+    assert_eq!(
+        value,
+        Value::test_record(record! {
+            "name" => Value::test_string("agent"),
+            "count" => Value::test_int(3),
+            "data" => Value::test_binary(vec![0x01, 0xff]),
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn tagged_round_trip() -> Result<(), ShellError> {
+    let mut test = plugin_test();
+    let tagged = test
+        .eval_with("from plist --tagged", input(Value::test_string(XML)))?
+        .into_value(Span::test_data())?;
+    let written = test
+        .eval_with("to plist --from-tagged --binary", input(tagged.clone()))?
+        .into_value(Span::test_data())?;
+    let read = test
+        .eval_with("from plist --tagged", input(written))?
+        .into_value(Span::test_data())?;
+    assert_eq!(read, tagged);
+    Ok(())
+}
+
+#[test]
+fn root_array_streams() -> Result<(), ShellError> {
+    let xml = "<plist><array><integer>1</integer><integer>2</integer></array></plist>";
+    let output = plugin_test().eval_with("from plist", input(Value::test_string(xml)))?;
+    assert!(matches!(output, PipelineData::ListStream(..)));
+    assert_eq!(
+        output.into_value(Span::test_data())?,
+        Value::test_list(vec![Value::test_int(1), Value::test_int(2)])
+    );
+    Ok(())
+}
+
+#[test]
+fn list_input_decodes_each_item() -> Result<(), ShellError> {
+    let value = plugin_test()
+        .eval("['<string>a</string>' '{ b = 1; }'] | from plist")?
+        .into_value(Span::test_data())?;
+    assert_eq!(
+        value,
+        Value::test_list(vec![
+            Value::test_string("a"),
+            Value::test_record(record! { "b" => Value::test_string("1") }),
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn list_items_that_fail_become_errors() -> Result<(), ShellError> {
+    // A list literal is piped in as a single value, while the rows of a root
+    // array arrive as a stream, and both give an error in place of the
+    // broken item
+    for source in [
+        "['<string>a</string>' '<plist><array>' '<string>c</string>'] | from plist",
+        r#"'("<string>a</string>", "<plist><array>", "<string>c</string>")' | from plist | from plist"#,
+    ] {
+        let value = plugin_test().eval(source)?.into_value(Span::test_data())?;
+        let Value::List { vals, .. } = value else {
+            panic!("expected a list from {source}, got {value:?}");
+        };
+        assert_eq!(vals.len(), 3);
+        assert_eq!(vals[0], Value::test_string("a"));
+        assert!(matches!(vals[1], Value::Error { .. }));
+        assert_eq!(vals[2], Value::test_string("c"));
+    }
+    Ok(())
+}
+
+#[test]
+fn parse_errors_have_a_code() {
+    let error = eval_with("from plist", Value::test_string("<plist><array>"))
+        .expect_err("expected a parse error");
+    let error = labeled(error);
+    assert_eq!(error.code.as_deref(), Some("nu_plugin_plist::parse_error"));
+}
+
+#[test]
+fn flag_errors_point_at_the_flag() {
+    let mut test = plugin_test();
+    let error = test
+        .eval("{ a: 1 } | to plist --format yaml")
+        .and_then(|output| output.into_value(Span::test_data()))
+        .expect_err("expected an unknown format error");
+    let error = labeled(error);
+    assert!(error.labels[0].text.contains("Unknown format yaml"));
+    assert_eq!(
+        test.engine_state().get_span_contents(error.labels[0].span),
+        b"yaml"
+    );
+}
+
+#[test]
+fn conflicting_flags() {
+    let error = eval_with("from plist --lazy --tagged", Value::test_string(XML))
+        .expect_err("expected an error");
+    assert!(labeled(error).labels[0]
+        .text
+        .contains("--tagged can't be used with --lazy"));
+}
+
+#[test]
+fn binary_conflicts_with_format() {
+    let error = eval_with("to plist --binary --format xml", Value::test_int(1))
+        .expect_err("expected an error");
+    assert!(labeled(error).labels[0]
+        .text
+        .contains("--binary can't be combined with other --format values"));
+}
+
+#[test]
+fn per_item_names_the_invalid_item() -> Result<(), ShellError> {
+    let mut test = plugin_test();
+    let value = test
+        .eval("[{ Label: a } { Label: b } { Label: c }] | to plist --per-item --format openstep")?
+        .into_value(Span::test_data())?;
+    let Value::List { vals, .. } = &value else {
+        panic!("expected a list, got {value:?}");
+    };
+    assert_eq!(vals.len(), 3);
+    assert!(vals[1].as_str()?.contains("Label = b;"));
+
+    // The whole command fails, naming the item that can't be written
+    let error = test
+        .eval("[{ Label: a } {|| 2 } { Label: c }] | to plist --per-item")
+        .and_then(|output| output.into_value(Span::test_data()))
+        .expect_err("expected an error for the closure");
+    let error = labeled(error);
+    assert_eq!(
+        error.code.as_deref(),
+        Some("nu_plugin_plist::unsupported_type")
+    );
+    assert!(error.labels[0].text.contains("The closure at 1"));
+    Ok(())
+}
+
+#[test]
+fn update_existing_key() -> Result<(), ShellError> {
+    let xml = "<plist><dict><key>Build</key><integer>1</integer><key>Name</key><string>a</string></dict></plist>";
+    let value = eval_with(
+        "plist update Build {|v| $v + 1 } | from plist",
+        Value::test_string(xml),
+    )?;
+    assert_eq!(
+        value,
+        Value::test_record(record! {
+            "Build" => Value::test_int(2),
+            "Name" => Value::test_string("a"),
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn update_missing_key() {
+    let xml = "<plist><dict><key>Build</key><integer>1</integer></dict></plist>";
+    let error = eval_with(
+        "plist update Version {|v| $v + 1 }",
+        Value::test_string(xml),
+    )
+    .expect_err("expected a missing key error");
+    let error = labeled(error);
+    assert_eq!(error.code.as_deref(), Some("nu_plugin_plist::not_found"));
+    assert!(error.labels[0].text.contains("Key Version not found"));
+}
+
+#[test]
+fn examples() -> Result<(), ShellError> {
+    // Examples without a result, such as those reading files, are skipped
+    for command in NuPlistPlugin.commands() {
+        plugin_test().test_examples(&command.examples())?;
+    }
+    Ok(())
+}
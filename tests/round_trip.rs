@@ -0,0 +1,146 @@
+//! Property tests checking that values survive a trip through `to plist` and
+//! `from plist`, in both directions and in both XML and binary form.
+
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, FixedOffset};
+use nu_plugin_plist::{
+    convert_nu_value, convert_plist_value, parse_plist, plist_bytes, PlistFormat,
+};
+use nu_protocol::{Record, Span, Value as NuValue};
+use plist::{Date as PlistDate, Dictionary, Uid, Value as PlistValue};
+use proptest::prelude::*;
+
+/// Text XML 1.0 can hold, without carriage returns that readers turn into
+/// line feeds.
+const TEXT: &str = "[a-zA-Z0-9 \t\n<>&\"'éß€😀]{0,12}";
+
+/// Keys that aren't taken for the special records `from plist` writes, such as
+/// `CF$UID`.
+const KEY: &str = "[a-zA-Z][a-zA-Z0-9 _-]{0,8}";
+
+/// Whole seconds between 1900 and 2100, as XML plists don't keep fractions.
+fn date_seconds() -> impl Strategy<Value = i64> {
+    -2_208_988_800i64..4_102_444_800
+}
+
+fn plist_date(seconds: i64) -> PlistDate {
+    let offset = Duration::from_secs(seconds.unsigned_abs());
+    match seconds {
+        0.. => SystemTime::UNIX_EPOCH + offset,
+        _ => SystemTime::UNIX_EPOCH - offset,
+    }
+    .into()
+}
+
+/// Plist values of every type. UIDs only exist in binary plists, so they are
+/// left out unless `uids` is set.
+fn plist_value(uids: bool) -> impl Strategy<Value = PlistValue> {
+    let mut leaves = vec![
+        any::<bool>().prop_map(PlistValue::Boolean).boxed(),
+        any::<i64>()
+            .prop_map(|i| PlistValue::Integer(i.into()))
+            .boxed(),
+        any::<u64>()
+            .prop_map(|u| PlistValue::Integer(u.into()))
+            .boxed(),
+        any::<f64>()
+            .prop_filter("finite", |r| r.is_finite())
+            .prop_map(PlistValue::Real)
+            .boxed(),
+        TEXT.prop_map(PlistValue::String).boxed(),
+        prop::collection::vec(any::<u8>(), 0..32)
+            .prop_map(PlistValue::Data)
+            .boxed(),
+        date_seconds()
+            .prop_map(|seconds| PlistValue::Date(plist_date(seconds)))
+            .boxed(),
+    ];
+    if uids {
+        leaves.push(
+            any::<u32>()
+                .prop_map(|uid| PlistValue::Uid(Uid::new(uid.into())))
+                .boxed(),
+        );
+    }
+    let leaf = prop::strategy::Union::new(leaves);
+    leaf.prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(PlistValue::Array),
+            prop::collection::btree_map(KEY, inner, 0..8).prop_map(|entries| {
+                PlistValue::Dictionary(entries.into_iter().collect::<Dictionary>())
+            }),
+        ]
+    })
+}
+
+/// Nu values of the types that have a plist equivalent.
+fn nu_value() -> impl Strategy<Value = NuValue> {
+    let span = Span::test_data();
+    let leaf = prop_oneof![
+        any::<bool>().prop_map(move |b| NuValue::bool(b, span)),
+        any::<i64>().prop_map(move |i| NuValue::int(i, span)),
+        any::<f64>()
+            .prop_filter("finite", |f| f.is_finite())
+            .prop_map(move |f| NuValue::float(f, span)),
+        TEXT.prop_map(move |s| NuValue::string(s, span)),
+        prop::collection::vec(any::<u8>(), 0..32).prop_map(move |b| NuValue::binary(b, span)),
+        (date_seconds(), -12i32..=14).prop_map(move |(seconds, hours)| {
+            let offset = FixedOffset::east_opt(hours * 3600).expect("valid offset");
+            let date = DateTime::from_timestamp(seconds, 0).expect("valid date");
+            NuValue::date(date.with_timezone(&offset), span)
+        }),
+    ];
+    leaf.prop_recursive(4, 64, 8, move |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(move |v| NuValue::list(v, span)),
+            prop::collection::btree_map(KEY, inner, 1..8).prop_map(move |entries| {
+                let mut record = Record::new();
+                for (key, value) in entries {
+                    record.push(key, value);
+                }
+                NuValue::record(record, span)
+            }),
+        ]
+    })
+}
+
+fn write_and_read(plist_val: &PlistValue, format: PlistFormat) -> PlistValue {
+    let span = Span::test_data();
+    let bytes = plist_bytes(plist_val, format, span).expect("failed to write");
+    let (read, read_format) = parse_plist(&bytes, span).expect("failed to read");
+    assert_eq!(read_format, format);
+    read
+}
+
+proptest! {
+    #[test]
+    fn xml_round_trip(plist_val in plist_value(false)) {
+        prop_assert_eq!(write_and_read(&plist_val, PlistFormat::Xml), plist_val);
+    }
+
+    #[test]
+    fn binary_round_trip(plist_val in plist_value(true)) {
+        prop_assert_eq!(write_and_read(&plist_val, PlistFormat::Binary), plist_val);
+    }
+
+    #[test]
+    fn plist_to_nu_and_back(plist_val in plist_value(true)) {
+        let nu_val = convert_plist_value(&plist_val, Span::test_data()).expect("failed to convert");
+        prop_assert_eq!(convert_nu_value(&nu_val).expect("failed to convert"), plist_val);
+    }
+
+    #[test]
+    fn nu_to_plist_and_back(nu_val in nu_value(), binary in any::<bool>()) {
+        let format = match binary {
+            true => PlistFormat::Binary,
+            false => PlistFormat::Xml,
+        };
+        let plist_val = convert_nu_value(&nu_val).expect("failed to convert");
+        let read = write_and_read(&plist_val, format);
+        prop_assert_eq!(
+            convert_plist_value(&read, Span::test_data()).expect("failed to convert"),
+            nu_val
+        );
+    }
+}